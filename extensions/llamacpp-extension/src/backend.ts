@@ -13,8 +13,13 @@ import {
   getSupportedFeaturesFromRust,
   isCudaInstalledFromRust,
   isMoltenVKInstalledFromRust,
+  listCustomBackends,
+  CustomBackend,
 } from '@janhq/tauri-plugin-llamacpp-api'
 
+// Version slot used for user-registered backends, e.g. "custom/rocm-gfx1100"
+export const CUSTOM_BACKEND_VERSION = 'custom'
+
 /*
  * Reads currently installed backends in janDataFolderPath
  *
@@ -39,7 +44,9 @@ async function fetchRemoteSupportedBackends(
 ): Promise<{ version: string; backend: string }[]> {
   // Pull the latest releases from the repo
   const { releases } = await _fetchGithubReleases('janhq', 'llama.cpp')
-  releases.sort((a, b) => b.tag_name.localeCompare(a.tag_name))
+  releases.sort((a, b) =>
+    b.tag_name.localeCompare(a.tag_name, undefined, { numeric: true })
+  )
   releases.splice(10) // keep only the latest 10 releases
 
   // Walk the assets and keep only those that match a supported backend
@@ -126,10 +133,28 @@ export async function getBackendDir(
   return backendDir
 }
 
+/*
+ * Reads user-registered backends that live outside the backends folder
+ */
+export async function getCustomBackends(): Promise<CustomBackend[]> {
+  try {
+    return await listCustomBackends(await getJanDataFolderPath())
+  } catch (e) {
+    console.warn(`Failed to read custom backends: ${String(e)}`)
+    return []
+  }
+}
+
 export async function getBackendExePath(
   backend: string,
   version: string
 ): Promise<string> {
+  if (version === CUSTOM_BACKEND_VERSION) {
+    const custom = (await getCustomBackends()).find((b) => b.name === backend)
+    if (!custom)
+      throw new Error(`Custom backend '${backend}' is not registered`)
+    return custom.server_path
+  }
   const exe_name = IS_WINDOWS ? 'llama-server.exe' : 'llama-server'
   const backendDir = await getBackendDir(backend, version)
   let exePath: string
@@ -146,6 +171,10 @@ export async function isBackendInstalled(
   backend: string,
   version: string
 ): Promise<boolean> {
+  if (version === CUSTOM_BACKEND_VERSION) {
+    const custom = (await getCustomBackends()).find((b) => b.name === backend)
+    return !!custom && (await fs.existsSync(custom.server_path))
+  }
   const exePath = await getBackendExePath(backend, version)
   const result = await fs.existsSync(exePath)
   return result
@@ -35,6 +35,7 @@ import {
   isBackendInstalled,
   getBackendExePath,
   getBackendDir,
+  getCustomBackends,
 } from './backend'
 import { invoke } from '@tauri-apps/api/core'
 import {
@@ -215,12 +216,11 @@ export default class llamacpp_extension extends AIEngine {
 
       try {
         version_backends = await listSupportedBackends()
+        // Already sorted newest first by Rust (BackendVersion ordering)
         if (version_backends.length === 0) {
           throw new Error(
             'No supported backend binaries found for this system. Backend selection and auto-update will be unavailable.'
           )
-        } else {
-          version_backends.sort((a, b) => b.version.localeCompare(a.version))
         }
       } catch (error) {
         throw new Error(
@@ -504,7 +504,8 @@ export default class llamacpp_extension extends AIEngine {
     // Use Rust logic to prioritize backends
     const result = await prioritizeBackends(
       version_backends,
-      hasEnoughGpuMemory
+      hasEnoughGpuMemory,
+      await getCustomBackends()
     )
     return result.backend_string
   }
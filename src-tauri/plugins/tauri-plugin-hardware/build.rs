@@ -1,4 +1,12 @@
-const COMMANDS: &[&str] = &["get_system_info", "get_system_usage"];
+const COMMANDS: &[&str] = &[
+    "get_system_info",
+    "refresh_system_info",
+    "get_system_usage",
+    "start_usage_monitor",
+    "stop_usage_monitor",
+    "get_usage_history",
+    "get_process_usage",
+];
 
 fn main() {
     tauri_plugin::Builder::new(COMMANDS).build();
@@ -1,11 +1,34 @@
 import { invoke } from '@tauri-apps/api/core'
+import { listen, type UnlistenFn } from '@tauri-apps/api/event'
 
 // Types
+export interface CpuCore {
+  package: number;
+  core_id: number;
+  core_type: 'performance' | 'efficiency';
+  cpus: number[];
+}
+
+export interface CpuGroup {
+  id: number;
+  cpus: number[];
+}
+
+export interface CpuTopology {
+  cores: CpuCore[];
+  numa_nodes: CpuGroup[];
+  l3_groups: CpuGroup[];
+}
+
 export interface CpuStaticInfo {
   name: string;
   core_count: number;
+  logical_core_count: number;
+  performance_core_count: number;
+  efficiency_core_count: number;
   arch: string;
   extensions: string[];
+  topology: CpuTopology;
 }
 
 export interface GpuInfo {
@@ -26,24 +49,93 @@ export interface SystemInfo {
   gpus: GpuInfo[];
 }
 
+export interface SystemInfoChange {
+  added: GpuInfo[];
+  removed: GpuInfo[];
+  changed: GpuInfo[];
+  system_info: SystemInfo;
+}
+
+export const SYSTEM_INFO_CHANGED_EVENT = 'hardware://system-info-changed';
+
 export interface GpuUsage {
   uuid: string;
   used_memory: number;
   total_memory: number;
+  utilization?: number | null;
+  temperature?: number | null;
+  power_draw?: number | null;
+  graphics_clock?: number | null;
+  memory_clock?: number | null;
 }
 
 export interface SystemUsage {
   cpu: number;
+  cpu_per_core: number[];
   used_memory: number;
   total_memory: number;
+  used_swap: number;
+  total_swap: number;
   gpus: GpuUsage[];
 }
 
+export interface ProcessUsage {
+  pid: number;
+  name: string;
+  memory: number;
+  cpu: number;
+  gpu_memory: number;
+  total_memory: number;
+  total_cpu: number;
+  total_gpu_memory: number;
+  children: ProcessUsage[];
+}
+
+export interface UsageSample extends SystemUsage {
+  timestamp: number;
+}
+
+export const USAGE_EVENT = 'hardware://usage';
+
 // Hardware commands
 export async function getSystemInfo(): Promise<SystemInfo> {
   return await invoke('plugin:hardware|get_system_info');
 }
 
+export async function refreshSystemInfo(): Promise<SystemInfo> {
+  return await invoke('plugin:hardware|refresh_system_info');
+}
+
+export async function onSystemInfoChanged(
+  handler: (change: SystemInfoChange) => void
+): Promise<UnlistenFn> {
+  return await listen<SystemInfoChange>(SYSTEM_INFO_CHANGED_EVENT, (event) =>
+    handler(event.payload)
+  );
+}
+
 export async function getSystemUsage(): Promise<SystemUsage> {
   return await invoke('plugin:hardware|get_system_usage');
 }
+
+export async function getProcessUsage(pids: number[]): Promise<ProcessUsage[]> {
+  return await invoke('plugin:hardware|get_process_usage', { pids });
+}
+
+export async function startUsageMonitor(intervalMs?: number): Promise<void> {
+  return await invoke('plugin:hardware|start_usage_monitor', { intervalMs });
+}
+
+export async function stopUsageMonitor(): Promise<boolean> {
+  return await invoke('plugin:hardware|stop_usage_monitor');
+}
+
+export async function getUsageHistory(): Promise<UsageSample[]> {
+  return await invoke('plugin:hardware|get_usage_history');
+}
+
+export async function onUsageSample(
+  handler: (sample: UsageSample) => void
+): Promise<UnlistenFn> {
+  return await listen<UsageSample>(USAGE_EVENT, (event) => handler(event.payload));
+}
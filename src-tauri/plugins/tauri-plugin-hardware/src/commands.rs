@@ -1,92 +1,206 @@
 use crate::{
-    types::{CpuStaticInfo, SystemInfo, SystemUsage},
+    monitor::{self, UsageSampler, DEFAULT_MONITOR_INTERVAL_MS},
+    process,
+    types::{
+        CpuStaticInfo, GpuInfo, ProcessUsage, SystemInfo, SystemInfoChange, SystemUsage,
+        UsageSample,
+    },
     vendor::{nvidia, vulkan},
     SYSTEM_INFO,
 };
+use std::time::Duration;
 use sysinfo::System;
+use tauri::{AppHandle, Emitter, Runtime};
+
+pub const SYSTEM_INFO_CHANGED_EVENT: &str = "hardware://system-info-changed";
 
 #[tauri::command]
 pub fn get_system_info() -> SystemInfo {
+    let cached = SYSTEM_INFO
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    if let Some(info) = cached {
+        return info;
+    }
     SYSTEM_INFO
-        .get_or_init(|| {
-            let mut system = System::new();
-            system.refresh_memory();
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(probe_system_info)
+        .clone()
+}
+
+/// Re-probe CPU, NVIDIA and Vulkan devices and replace the cached system info.
+/// Emits `hardware://system-info-changed` when GPUs were added, removed or
+/// changed (e.g. a driver update) compared to the previous probe.
+#[tauri::command]
+pub fn refresh_system_info<R: Runtime>(app: AppHandle<R>) -> SystemInfo {
+    // A driver installed since startup should be picked up
+    nvidia::retry_nvml_init();
+    let info = probe_system_info();
+
+    let previous = SYSTEM_INFO
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .replace(info.clone());
+    if let Some(change) = previous.and_then(|previous| diff_system_info(&previous, &info)) {
+        log::info!(
+            "GPU devices changed: {} added, {} removed, {} changed",
+            change.added.len(),
+            change.removed.len(),
+            change.changed.len()
+        );
+        if let Err(e) = app.emit(SYSTEM_INFO_CHANGED_EVENT, &change) {
+            log::warn!("Failed to emit system info change: {}", e);
+        }
+    }
+    info
+}
+
+/// GPUs that differ between two probes, matched by uuid
+pub(crate) fn diff_system_info(
+    previous: &SystemInfo,
+    current: &SystemInfo,
+) -> Option<SystemInfoChange> {
+    let find = |gpus: &[GpuInfo], uuid: &str| gpus.iter().find(|gpu| gpu.uuid == uuid).cloned();
+
+    let added: Vec<GpuInfo> = current
+        .gpus
+        .iter()
+        .filter(|gpu| find(&previous.gpus, &gpu.uuid).is_none())
+        .cloned()
+        .collect();
+    let removed: Vec<GpuInfo> = previous
+        .gpus
+        .iter()
+        .filter(|gpu| find(&current.gpus, &gpu.uuid).is_none())
+        .cloned()
+        .collect();
+    // Same device, but e.g. NVML became available or the driver was updated
+    let changed: Vec<GpuInfo> = current
+        .gpus
+        .iter()
+        .filter(|gpu| {
+            find(&previous.gpus, &gpu.uuid).is_some_and(|old| {
+                old.driver_version != gpu.driver_version
+                    || old.total_memory != gpu.total_memory
+                    || old.nvidia_info.is_some() != gpu.nvidia_info.is_some()
+                    || old.vulkan_info.is_some() != gpu.vulkan_info.is_some()
+            })
+        })
+        .cloned()
+        .collect();
 
-            let mut gpu_map = std::collections::HashMap::new();
-            for gpu in nvidia::get_nvidia_gpus() {
+    if added.is_empty() && removed.is_empty() && changed.is_empty() {
+        return None;
+    }
+    Some(SystemInfoChange {
+        added,
+        removed,
+        changed,
+        system_info: current.clone(),
+    })
+}
+
+fn probe_system_info() -> SystemInfo {
+    let mut system = System::new();
+    system.refresh_memory();
+
+    let mut gpu_map = std::collections::HashMap::new();
+    for gpu in nvidia::get_nvidia_gpus() {
+        gpu_map.insert(gpu.uuid.clone(), gpu);
+    }
+
+    let vulkan_gpus = vulkan::get_vulkan_gpus();
+    log::info!("Vulkan GPU detection returned {} GPUs", vulkan_gpus.len());
+
+    for gpu in vulkan_gpus {
+        log::info!(
+            "Processing Vulkan GPU: {} (vendor: {:?}, uuid: {})",
+            gpu.name,
+            gpu.vendor,
+            gpu.uuid
+        );
+        match gpu_map.get_mut(&gpu.uuid) {
+            // for existing NVIDIA GPUs, add Vulkan info
+            Some(nvidia_gpu) => {
+                nvidia_gpu.vulkan_info = gpu.vulkan_info;
+            }
+            None => {
                 gpu_map.insert(gpu.uuid.clone(), gpu);
             }
+        }
+    }
 
-            let vulkan_gpus = vulkan::get_vulkan_gpus();
-            log::info!("Vulkan GPU detection returned {} GPUs", vulkan_gpus.len());
-
-            for gpu in vulkan_gpus {
-                log::info!(
-                    "Processing Vulkan GPU: {} (vendor: {:?}, uuid: {})",
-                    gpu.name,
-                    gpu.vendor,
-                    gpu.uuid
-                );
-                match gpu_map.get_mut(&gpu.uuid) {
-                    // for existing NVIDIA GPUs, add Vulkan info
-                    Some(nvidia_gpu) => {
-                        nvidia_gpu.vulkan_info = gpu.vulkan_info;
-                    }
-                    None => {
-                        gpu_map.insert(gpu.uuid.clone(), gpu);
-                    }
-                }
-            }
+    log::info!("Total GPUs in system info: {}", gpu_map.len());
+    for (uuid, gpu) in &gpu_map {
+        log::info!(
+            "  GPU: {} (uuid: {}, vendor: {:?})",
+            gpu.name,
+            uuid,
+            gpu.vendor
+        );
+    }
 
-            log::info!("Total GPUs in system info: {}", gpu_map.len());
-            for (uuid, gpu) in &gpu_map {
-                log::info!("  GPU: {} (uuid: {}, vendor: {:?})", gpu.name, uuid, gpu.vendor);
-            }
+    let os_type = if cfg!(target_os = "windows") {
+        "windows"
+    } else if cfg!(target_os = "macos") {
+        "macos"
+    } else if cfg!(target_os = "linux") {
+        "linux"
+    } else {
+        "unknown"
+    };
+    let os_name = System::long_os_version().unwrap_or("Unknown".to_string());
 
-            let os_type = if cfg!(target_os = "windows") {
-                "windows"
-            } else if cfg!(target_os = "macos") {
-                "macos"
-            } else if cfg!(target_os = "linux") {
-                "linux"
-            } else {
-                "unknown"
-            };
-            let os_name = System::long_os_version().unwrap_or("Unknown".to_string());
-
-            SystemInfo {
-                cpu: CpuStaticInfo::new(),
-                os_type: os_type.to_string(),
-                os_name,
-                total_memory: system.total_memory() / 1024 / 1024, // bytes to MiB
-                gpus: gpu_map.into_values().collect(),
-            }
-        })
-        .clone()
+    SystemInfo {
+        cpu: CpuStaticInfo::new(),
+        os_type: os_type.to_string(),
+        os_name,
+        total_memory: system.total_memory() / 1024 / 1024, // bytes to MiB
+        gpus: gpu_map.into_values().collect(),
+    }
 }
 
 #[tauri::command]
 pub fn get_system_usage() -> SystemUsage {
-    let mut system = System::new();
-    system.refresh_memory();
+    // Reuse the monitor's latest sample instead of blocking for a fresh one
+    if let Some(sample) = monitor::latest_sample() {
+        return sample.usage;
+    }
 
     // need to refresh 2 times to get CPU usage
-    system.refresh_cpu_all();
+    let mut sampler = UsageSampler::new();
     std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
-    system.refresh_cpu_all();
-
-    let cpus = system.cpus();
-    let cpu_usage =
-        cpus.iter().map(|cpu| cpu.cpu_usage()).sum::<f32>() / (cpus.len().max(1) as f32);
-
-    SystemUsage {
-        cpu: cpu_usage,
-        used_memory: system.used_memory() / 1024 / 1024, // bytes to MiB,
-        total_memory: system.total_memory() / 1024 / 1024, // bytes to MiB,
-        gpus: get_system_info()
-            .gpus
-            .iter()
-            .map(|gpu| gpu.get_usage())
-            .collect(),
-    }
+    sampler.sample()
+}
+
+/// Sample usage in the background and emit `hardware://usage` events
+#[tauri::command]
+pub fn start_usage_monitor<R: Runtime>(
+    app: AppHandle<R>,
+    interval_ms: Option<u64>,
+) -> Result<(), String> {
+    let interval = Duration::from_millis(interval_ms.unwrap_or(DEFAULT_MONITOR_INTERVAL_MS));
+    monitor::start(app, interval)
+}
+
+#[tauri::command]
+pub fn stop_usage_monitor() -> bool {
+    monitor::stop()
+}
+
+#[tauri::command]
+pub fn get_usage_history() -> Vec<UsageSample> {
+    monitor::history()
+}
+
+/// RSS, CPU and GPU memory of the given processes (e.g. llama-server sessions
+/// and MCP servers), each including its child process tree. Runs off the main
+/// thread since the first call waits for a second CPU sample.
+#[tauri::command]
+pub async fn get_process_usage(pids: Vec<u32>) -> Result<Vec<ProcessUsage>, String> {
+    tauri::async_runtime::spawn_blocking(move || process::get_process_usage(&pids))
+        .await
+        .map_err(|e| format!("Failed to read process usage: {}", e))
 }
@@ -1,6 +1,6 @@
 use sysinfo::System;
 
-use crate::types::CpuStaticInfo;
+use crate::types::{CpuStaticInfo, CpuTopology};
 
 impl CpuStaticInfo {
     pub fn new() -> Self {
@@ -21,14 +21,57 @@ impl CpuStaticInfo {
             .unwrap_or("unknown")
             .to_string();
 
+        let core_count = System::physical_core_count().unwrap_or(0);
+        let topology = CpuStaticInfo::get_topology();
+        let (performance_core_count, efficiency_core_count) =
+            CpuStaticInfo::get_core_types(&topology).unwrap_or((core_count, 0));
+
         CpuStaticInfo {
             name,
-            core_count: System::physical_core_count().unwrap_or(0),
+            core_count,
+            logical_core_count: system.cpus().len(),
+            performance_core_count,
+            efficiency_core_count,
             arch: System::cpu_arch(),
             extensions: CpuStaticInfo::get_extensions(),
+            topology,
         }
     }
 
+    #[cfg(target_os = "linux")]
+    fn get_topology() -> CpuTopology {
+        read_cpu_topology(std::path::Path::new(SYS_DEVICES_ROOT)).unwrap_or_default()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn get_topology() -> CpuTopology {
+        CpuTopology::default()
+    }
+
+    /// Physical (performance, efficiency) core counts, if known
+    #[cfg(target_os = "macos")]
+    fn get_core_types(_topology: &CpuTopology) -> Option<(usize, usize)> {
+        // Apple Silicon reports performance levels; Intel Macs have only one
+        let performance = sysctl_usize("hw.perflevel0.physicalcpu")?;
+        let efficiency = sysctl_usize("hw.perflevel1.physicalcpu").unwrap_or(0);
+        Some((performance, efficiency))
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn get_core_types(topology: &CpuTopology) -> Option<(usize, usize)> {
+        use crate::types::CoreType;
+
+        if topology.cores.is_empty() {
+            return None;
+        }
+        let efficiency = topology
+            .cores
+            .iter()
+            .filter(|core| core.core_type == CoreType::Efficiency)
+            .count();
+        Some((topology.cores.len() - efficiency, efficiency))
+    }
+
     // TODO: see if we need to check for all CPU extensions
     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
     fn get_extensions() -> Vec<String> {
@@ -128,3 +171,168 @@ impl CpuStaticInfo {
         vec![]
     }
 }
+
+#[cfg(target_os = "macos")]
+fn sysctl_usize(name: &str) -> Option<usize> {
+    let name = std::ffi::CString::new(name).ok()?;
+    let mut value: libc::c_int = 0;
+    let mut size = std::mem::size_of::<libc::c_int>();
+    let ret = unsafe {
+        libc::sysctlbyname(
+            name.as_ptr(),
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut size,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    (ret == 0 && value > 0).then_some(value as usize)
+}
+
+#[cfg(target_os = "linux")]
+pub const SYS_DEVICES_ROOT: &str = "/sys/devices";
+
+/// Parse kernel CPU lists such as `0-3,8,10-11`
+pub fn parse_cpu_list(list: &str) -> Vec<usize> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((start, end)) => {
+                if let (Ok(start), Ok(end)) = (start.parse::<usize>(), end.parse::<usize>()) {
+                    cpus.extend(start..=end);
+                }
+            }
+            None => cpus.extend(range.parse::<usize>().ok()),
+        }
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    cpus
+}
+
+/// Read core, NUMA and L3 layout from `<sys_devices>/system/cpu`. Offline
+/// CPUs have no `topology` directory and are left out.
+///
+/// Hybrid Intel CPUs list their P- and E-cores in `cpu_core/cpus` and
+/// `cpu_atom/cpus`; ARM big.LITTLE systems report a per-CPU `cpu_capacity`
+/// where only the big cores reach the maximum.
+#[cfg(target_os = "linux")]
+pub(crate) fn read_cpu_topology(sys_devices: &std::path::Path) -> Option<CpuTopology> {
+    use crate::types::{CoreType, CpuCore, CpuGroup};
+    use crate::vendor::sysfs::{read_trimmed, read_u64};
+    use std::collections::BTreeMap;
+
+    let cpu_root = sys_devices.join("system/cpu");
+    let mut cpus: Vec<usize> = std::fs::read_dir(&cpu_root)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("cpu")?
+                .parse()
+                .ok()
+        })
+        .filter(|cpu| {
+            cpu_root
+                .join(format!("cpu{}/topology/core_id", cpu))
+                .exists()
+        })
+        .collect();
+    if cpus.is_empty() {
+        return None;
+    }
+    cpus.sort_unstable();
+
+    let atom_cpus = read_trimmed(&sys_devices.join("cpu_atom/cpus"))
+        .map(|list| parse_cpu_list(&list))
+        .unwrap_or_default();
+    let capacities: BTreeMap<usize, u64> = cpus
+        .iter()
+        .filter_map(|&cpu| {
+            read_u64(&cpu_root.join(format!("cpu{}/cpu_capacity", cpu))).map(|c| (cpu, c))
+        })
+        .collect();
+    let max_capacity = capacities.values().copied().max();
+
+    let mut cores: Vec<CpuCore> = Vec::new();
+    let mut numa_nodes: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+    let mut l3_groups: Vec<CpuGroup> = Vec::new();
+
+    for &cpu in &cpus {
+        let dir = cpu_root.join(format!("cpu{}", cpu));
+        let read_id = |file: &str| {
+            read_trimmed(&dir.join("topology").join(file)).and_then(|id| id.parse::<u32>().ok())
+        };
+
+        // SMT siblings share a physical core; core_id alone repeats across
+        // clusters on some ARM kernels
+        if !cores.iter().any(|core| core.cpus.contains(&cpu)) {
+            let mut siblings = read_trimmed(&dir.join("topology/thread_siblings_list"))
+                .map(|list| parse_cpu_list(&list))
+                .unwrap_or_default();
+            siblings.retain(|sibling| cpus.contains(sibling));
+            if !siblings.contains(&cpu) {
+                siblings = vec![cpu];
+            }
+
+            let efficiency = atom_cpus.contains(&cpu)
+                || matches!(
+                    (capacities.get(&cpu), max_capacity),
+                    (Some(capacity), Some(max)) if *capacity < max
+                );
+            cores.push(CpuCore {
+                package: read_id("physical_package_id").unwrap_or(0),
+                core_id: read_id("core_id").unwrap_or(0),
+                core_type: if efficiency {
+                    CoreType::Efficiency
+                } else {
+                    CoreType::Performance
+                },
+                cpus: siblings,
+            });
+        }
+
+        // cpuN/nodeM links to the NUMA node the CPU belongs to
+        if let Ok(entries) = std::fs::read_dir(&dir) {
+            for entry in entries.flatten() {
+                let node = entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|name| name.strip_prefix("node")?.parse::<u32>().ok());
+                if let Some(node) = node {
+                    numa_nodes.entry(node).or_default().push(cpu);
+                }
+            }
+        }
+
+        let Ok(caches) = std::fs::read_dir(dir.join("cache")) else {
+            continue;
+        };
+        for cache in caches.flatten().map(|entry| entry.path()) {
+            if read_trimmed(&cache.join("level")).as_deref() != Some("3") {
+                continue;
+            }
+            let Some(shared) = read_trimmed(&cache.join("shared_cpu_list")) else {
+                continue;
+            };
+            let shared = parse_cpu_list(&shared);
+            if !l3_groups.iter().any(|group| group.cpus == shared) {
+                let id = read_trimmed(&cache.join("id"))
+                    .and_then(|id| id.parse().ok())
+                    .unwrap_or(l3_groups.len() as u32);
+                l3_groups.push(CpuGroup { id, cpus: shared });
+            }
+        }
+    }
+
+    Some(CpuTopology {
+        cores,
+        numa_nodes: numa_nodes
+            .into_iter()
+            .map(|(id, cpus)| CpuGroup { id, cpus })
+            .collect(),
+        l3_groups,
+    })
+}
@@ -20,6 +20,7 @@ impl GpuInfo {
         match self.vendor {
             Vendor::NVIDIA => self.get_usage_nvidia(),
             Vendor::AMD => self.get_usage_amd(),
+            Vendor::Intel => self.get_usage_intel(),
             _ => self.get_usage_unsupported(),
         }
     }
@@ -29,6 +30,11 @@ impl GpuInfo {
             uuid: self.uuid.clone(),
             used_memory: 0,
             total_memory: 0,
+            utilization: None,
+            temperature: None,
+            power_draw: None,
+            graphics_clock: None,
+            memory_clock: None,
         }
     }
 }
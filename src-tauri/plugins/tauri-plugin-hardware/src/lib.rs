@@ -2,25 +2,35 @@ mod commands;
 mod constants;
 pub mod cpu;
 pub mod gpu;
+mod monitor;
+mod process;
 mod types;
 pub mod vendor;
 
+pub use commands::SYSTEM_INFO_CHANGED_EVENT;
 pub use constants::*;
+pub use monitor::USAGE_EVENT;
 pub use types::*;
 
-use std::sync::OnceLock;
+use std::sync::RwLock;
 use tauri::Runtime;
 
-static SYSTEM_INFO: OnceLock<SystemInfo> = OnceLock::new();
+/// Probed on first use and replaced by `refresh_system_info`
+static SYSTEM_INFO: RwLock<Option<SystemInfo>> = RwLock::new(None);
 
-pub use commands::get_system_info;
+pub use commands::{get_process_usage, get_system_info, refresh_system_info};
 
 /// Initialize the hardware plugin
 pub fn init<R: Runtime>() -> tauri::plugin::TauriPlugin<R> {
     tauri::plugin::Builder::new("hardware")
         .invoke_handler(tauri::generate_handler![
             commands::get_system_info,
-            commands::get_system_usage
+            commands::refresh_system_info,
+            commands::get_system_usage,
+            commands::start_usage_monitor,
+            commands::stop_usage_monitor,
+            commands::get_usage_history,
+            commands::get_process_usage
         ])
         .build()
 }
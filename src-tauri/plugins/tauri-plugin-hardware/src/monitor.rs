@@ -0,0 +1,182 @@
+use crate::{
+    commands::get_system_info,
+    types::{SystemUsage, UsageSample},
+};
+use std::collections::VecDeque;
+use std::sync::{mpsc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use sysinfo::System;
+use tauri::{AppHandle, Emitter, Runtime};
+
+pub const USAGE_EVENT: &str = "hardware://usage";
+/// Number of recent samples kept for `get_usage_history`
+pub const USAGE_HISTORY_LEN: usize = 120;
+pub const DEFAULT_MONITOR_INTERVAL_MS: u64 = 1000;
+
+static MONITOR: Mutex<Option<MonitorHandle>> = Mutex::new(None);
+static HISTORY: Mutex<VecDeque<UsageSample>> = Mutex::new(VecDeque::new());
+
+struct MonitorHandle {
+    interval: Duration,
+    stop_tx: mpsc::Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl MonitorHandle {
+    fn stop(self) {
+        let _ = self.stop_tx.send(());
+        if self.thread.join().is_err() {
+            log::error!("Usage monitor thread panicked");
+        }
+    }
+}
+
+/// Keeps a single `System` alive between refreshes, so CPU usage is measured
+/// against the previous sample instead of sleeping on every call.
+pub struct UsageSampler {
+    system: System,
+}
+
+impl UsageSampler {
+    pub fn new() -> Self {
+        let mut system = System::new();
+        // CPU usage is computed relative to the previous refresh
+        system.refresh_cpu_all();
+        UsageSampler { system }
+    }
+
+    pub fn sample(&mut self) -> SystemUsage {
+        self.system.refresh_cpu_all();
+        self.system.refresh_memory();
+
+        let cpu_per_core: Vec<f32> = self
+            .system
+            .cpus()
+            .iter()
+            .map(|cpu| cpu.cpu_usage())
+            .collect();
+        let cpu = cpu_per_core.iter().sum::<f32>() / (cpu_per_core.len().max(1) as f32);
+
+        SystemUsage {
+            cpu,
+            cpu_per_core,
+            // bytes to MiB
+            used_memory: self.system.used_memory() / 1024 / 1024,
+            total_memory: self.system.total_memory() / 1024 / 1024,
+            used_swap: self.system.used_swap() / 1024 / 1024,
+            total_swap: self.system.total_swap() / 1024 / 1024,
+            gpus: get_system_info()
+                .gpus
+                .iter()
+                .map(|gpu| gpu.get_usage())
+                .collect(),
+        }
+    }
+}
+
+impl Default for UsageSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Start sampling every `interval` on a background thread. A running monitor
+/// with a different interval is restarted; the same interval is a no-op.
+pub fn start<R: Runtime>(app: AppHandle<R>, interval: Duration) -> Result<(), String> {
+    let interval = interval.max(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    let mut monitor = MONITOR.lock().map_err(|e| e.to_string())?;
+    if let Some(handle) = monitor.as_ref() {
+        if handle.interval == interval {
+            return Ok(());
+        }
+    }
+    if let Some(handle) = monitor.take() {
+        handle.stop();
+    }
+
+    let (stop_tx, stop_rx) = mpsc::channel();
+    let thread = std::thread::Builder::new()
+        .name("hardware-usage-monitor".to_string())
+        .spawn(move || run(app, interval, stop_rx))
+        .map_err(|e| format!("Failed to start usage monitor: {}", e))?;
+    log::info!("Started usage monitor ({} ms)", interval.as_millis());
+
+    *monitor = Some(MonitorHandle {
+        interval,
+        stop_tx,
+        thread,
+    });
+    Ok(())
+}
+
+/// Stop the background monitor. Returns `false` if it was not running.
+pub fn stop() -> bool {
+    let handle = match MONITOR.lock() {
+        Ok(mut monitor) => monitor.take(),
+        Err(e) => {
+            log::error!("Usage monitor lock poisoned: {}", e);
+            return false;
+        }
+    };
+    match handle {
+        Some(handle) => {
+            handle.stop();
+            log::info!("Stopped usage monitor");
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn is_running() -> bool {
+    MONITOR
+        .lock()
+        .map(|monitor| monitor.is_some())
+        .unwrap_or(false)
+}
+
+/// Recent samples, oldest first
+pub fn history() -> Vec<UsageSample> {
+    HISTORY
+        .lock()
+        .map(|history| history.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Most recent sample, only while the monitor is running
+pub fn latest_sample() -> Option<UsageSample> {
+    if !is_running() {
+        return None;
+    }
+    HISTORY.lock().ok()?.back().cloned()
+}
+
+fn run<R: Runtime>(app: AppHandle<R>, interval: Duration, stop_rx: mpsc::Receiver<()>) {
+    let mut sampler = UsageSampler::new();
+
+    // Waiting first also gives the CPU counters a baseline to diff against
+    while let Err(mpsc::RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+        let sample = UsageSample {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            usage: sampler.sample(),
+        };
+
+        if let Ok(mut history) = HISTORY.lock() {
+            push_bounded(&mut history, sample.clone(), USAGE_HISTORY_LEN);
+        }
+        if let Err(e) = app.emit(USAGE_EVENT, &sample) {
+            log::warn!("Failed to emit usage sample: {}", e);
+        }
+    }
+}
+
+pub(crate) fn push_bounded<T>(buffer: &mut VecDeque<T>, item: T, capacity: usize) {
+    while buffer.len() >= capacity.max(1) {
+        buffer.pop_front();
+    }
+    buffer.push_back(item);
+}
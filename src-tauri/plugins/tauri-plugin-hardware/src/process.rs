@@ -0,0 +1,156 @@
+use crate::{types::ProcessUsage, vendor::nvidia};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System};
+
+/// Kept between calls so process CPU usage is measured since the previous query
+static PROCESS_SYSTEM: Mutex<Option<System>> = Mutex::new(None);
+
+/// Snapshot of one process, before it is attributed to a tree
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProcessEntry {
+    pub name: String,
+    pub parent: Option<u32>,
+    /// Resident memory in bytes
+    pub memory: u64,
+    pub cpu: f32,
+}
+
+/// Resource usage of each requested process and its descendants. Unknown
+/// PIDs (e.g. a server that already exited) are skipped.
+pub fn get_process_usage(pids: &[u32]) -> Vec<ProcessUsage> {
+    let table = snapshot_processes();
+    let gpu_memory = nvidia::get_nvidia_process_memory();
+    let gpu_memory_of = |pid: u32| gpu_memory.get(&pid).copied().unwrap_or(0) + drm_memory(pid);
+
+    pids.iter()
+        .filter(|pid| table.contains_key(pid))
+        .map(|&pid| build_process_tree(pid, &table, &gpu_memory_of))
+        .collect()
+}
+
+fn snapshot_processes() -> HashMap<u32, ProcessEntry> {
+    let refresh = |system: &mut System| {
+        system.refresh_processes_specifics(
+            ProcessesToUpdate::All,
+            true,
+            // Linux threads would otherwise show up as child processes
+            ProcessRefreshKind::nothing()
+                .with_cpu()
+                .with_memory()
+                .without_tasks(),
+        );
+    };
+
+    let mut guard = PROCESS_SYSTEM.lock().unwrap_or_else(|e| e.into_inner());
+    let system = match guard.as_mut() {
+        Some(system) => {
+            refresh(system);
+            system
+        }
+        None => {
+            // CPU usage needs two refreshes to have something to diff against
+            let mut system = System::new();
+            refresh(&mut system);
+            std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+            refresh(&mut system);
+            guard.insert(system)
+        }
+    };
+
+    system
+        .processes()
+        .iter()
+        .filter(|(_, process)| process.thread_kind().is_none())
+        .map(|(pid, process)| {
+            (
+                pid.as_u32(),
+                ProcessEntry {
+                    name: process.name().to_string_lossy().to_string(),
+                    parent: process.parent().map(|parent| parent.as_u32()),
+                    memory: process.memory(),
+                    cpu: process.cpu_usage(),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Device-local memory held through DRM clients (amdgpu, i915, xe), in bytes
+#[cfg(target_os = "linux")]
+fn drm_memory(pid: u32) -> u64 {
+    use crate::vendor::sysfs::{read_process_drm_clients, PROC_ROOT};
+
+    read_process_drm_clients(std::path::Path::new(PROC_ROOT), pid)
+        .iter()
+        .map(|client| client.local_memory())
+        .sum()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn drm_memory(_pid: u32) -> u64 {
+    0
+}
+
+/// Attribute `pid` and all of its descendants in `table`
+pub(crate) fn build_process_tree(
+    pid: u32,
+    table: &HashMap<u32, ProcessEntry>,
+    gpu_memory_of: &dyn Fn(u32) -> u64,
+) -> ProcessUsage {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (&child, entry) in table {
+        if let Some(parent) = entry.parent {
+            children.entry(parent).or_default().push(child);
+        }
+    }
+    for pids in children.values_mut() {
+        pids.sort_unstable();
+    }
+
+    let mut visited = HashSet::new();
+    build_node(pid, table, &children, gpu_memory_of, &mut visited).0
+}
+
+/// Returns the node plus its subtree totals in bytes (memory, GPU memory)
+fn build_node(
+    pid: u32,
+    table: &HashMap<u32, ProcessEntry>,
+    children: &HashMap<u32, Vec<u32>>,
+    gpu_memory_of: &dyn Fn(u32) -> u64,
+    visited: &mut HashSet<u32>,
+) -> (ProcessUsage, u64, u64) {
+    visited.insert(pid);
+    let entry = table.get(&pid).cloned().unwrap_or_default();
+    let gpu_memory = gpu_memory_of(pid);
+
+    let mut total_memory = entry.memory;
+    let mut total_gpu_memory = gpu_memory;
+    let mut total_cpu = entry.cpu;
+    let mut nodes = Vec::new();
+    for &child in children.get(&pid).into_iter().flatten() {
+        // PID reuse can make the parent links inconsistent
+        if visited.contains(&child) {
+            continue;
+        }
+        let (node, memory, gpu) = build_node(child, table, children, gpu_memory_of, visited);
+        total_memory += memory;
+        total_gpu_memory += gpu;
+        total_cpu += node.total_cpu;
+        nodes.push(node);
+    }
+
+    let usage = ProcessUsage {
+        pid,
+        name: entry.name,
+        // bytes to MiB
+        memory: entry.memory / 1024 / 1024,
+        cpu: entry.cpu,
+        gpu_memory: gpu_memory / 1024 / 1024,
+        total_memory: total_memory / 1024 / 1024,
+        total_cpu,
+        total_gpu_memory: total_gpu_memory / 1024 / 1024,
+        children: nodes,
+    };
+    (usage, total_memory, total_gpu_memory)
+}
@@ -1,5 +1,8 @@
 use crate::commands::*;
-use crate::types::CpuStaticInfo;
+use crate::monitor::{self, push_bounded, UsageSampler};
+use crate::process::{build_process_tree, ProcessEntry};
+use crate::types::{CpuStaticInfo, GpuInfo, SystemInfo, Vendor};
+use std::collections::{HashMap, VecDeque};
 use tauri::test::mock_app;
 
 #[test]
@@ -14,6 +17,154 @@ fn test_system_usage() {
     println!("System Usage Info: {:?}", usage);
 }
 
+#[test]
+fn test_usage_sampler() {
+    let mut sampler = UsageSampler::new();
+    std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    let usage = sampler.sample();
+
+    assert!(!usage.cpu_per_core.is_empty());
+    assert!(usage
+        .cpu_per_core
+        .iter()
+        .all(|core| (0.0..=100.0).contains(core)));
+    assert!(usage.used_memory <= usage.total_memory);
+    assert!(usage.used_swap <= usage.total_swap);
+}
+
+#[test]
+fn test_push_bounded_keeps_most_recent() {
+    let mut buffer = VecDeque::new();
+    for i in 0..5 {
+        push_bounded(&mut buffer, i, 3);
+    }
+    assert_eq!(buffer, VecDeque::from(vec![2, 3, 4]));
+}
+
+#[test]
+fn test_usage_monitor_start_stop() {
+    let app = mock_app();
+    // Intervals below the sysinfo minimum are clamped
+    start_usage_monitor(app.handle().clone(), Some(0)).unwrap();
+    assert!(monitor::is_running());
+    // Starting again with the same interval keeps the running monitor
+    start_usage_monitor(app.handle().clone(), Some(0)).unwrap();
+
+    std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL * 3);
+    let history = get_usage_history();
+    assert!(!history.is_empty());
+    assert!(history.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+    assert!(monitor::latest_sample().is_some());
+
+    assert!(stop_usage_monitor());
+    assert!(!stop_usage_monitor());
+    assert!(monitor::latest_sample().is_none());
+    // History survives a stop so the UI can still draw it
+    assert!(!get_usage_history().is_empty());
+}
+
+fn fake_gpu(uuid: &str, driver_version: &str) -> GpuInfo {
+    GpuInfo {
+        name: format!("GPU {}", uuid),
+        total_memory: 8192,
+        vendor: Vendor::AMD,
+        uuid: uuid.to_string(),
+        driver_version: driver_version.to_string(),
+        nvidia_info: None,
+        vulkan_info: None,
+    }
+}
+
+fn fake_system_info(gpus: Vec<GpuInfo>) -> SystemInfo {
+    SystemInfo {
+        cpu: CpuStaticInfo::new(),
+        os_type: "linux".to_string(),
+        os_name: "Linux".to_string(),
+        total_memory: 16384,
+        gpus,
+    }
+}
+
+#[test]
+fn test_diff_system_info() {
+    let before = fake_system_info(vec![fake_gpu("a", "1.0"), fake_gpu("b", "1.0")]);
+    assert!(diff_system_info(&before, &before.clone()).is_none());
+
+    let after = fake_system_info(vec![fake_gpu("b", "1.1"), fake_gpu("c", "1.0")]);
+    let change = diff_system_info(&before, &after).unwrap();
+    let uuids = |gpus: &[GpuInfo]| gpus.iter().map(|gpu| gpu.uuid.clone()).collect::<Vec<_>>();
+    assert_eq!(uuids(&change.added), vec!["c"]);
+    assert_eq!(uuids(&change.removed), vec!["a"]);
+    assert_eq!(uuids(&change.changed), vec!["b"]);
+    assert_eq!(change.system_info.gpus.len(), 2);
+}
+
+#[test]
+fn test_refresh_system_info() {
+    let app = mock_app();
+    let refreshed = refresh_system_info(app.handle().clone());
+    let cached = get_system_info();
+    assert_eq!(refreshed.gpus.len(), cached.gpus.len());
+    assert_eq!(refreshed.cpu.name, cached.cpu.name);
+}
+
+fn fake_process(name: &str, parent: Option<u32>, memory_mib: u64, cpu: f32) -> ProcessEntry {
+    ProcessEntry {
+        name: name.to_string(),
+        parent,
+        memory: memory_mib * 1024 * 1024,
+        cpu,
+    }
+}
+
+#[test]
+fn test_build_process_tree() {
+    let table = HashMap::from([
+        (1, fake_process("init", None, 10, 0.0)),
+        (100, fake_process("llama-server", Some(1), 2048, 150.0)),
+        (101, fake_process("llama-helper", Some(100), 64, 5.0)),
+        (102, fake_process("sh", Some(100), 2, 0.5)),
+        (103, fake_process("worker", Some(102), 30, 1.0)),
+        (200, fake_process("mcp-server", Some(1), 100, 2.0)),
+    ]);
+    let gpu_memory_of = |pid: u32| if pid == 100 { 4096 * 1024 * 1024 } else { 0 };
+
+    let usage = build_process_tree(100, &table, &gpu_memory_of);
+    assert_eq!(usage.name, "llama-server");
+    assert_eq!(usage.memory, 2048);
+    assert_eq!(usage.gpu_memory, 4096);
+    assert_eq!(usage.total_memory, 2048 + 64 + 2 + 30);
+    assert_eq!(usage.total_gpu_memory, 4096);
+    assert_eq!(usage.total_cpu, 156.5);
+    let children: Vec<u32> = usage.children.iter().map(|child| child.pid).collect();
+    assert_eq!(children, vec![101, 102]);
+    assert_eq!(usage.children[1].children[0].name, "worker");
+}
+
+#[test]
+fn test_build_process_tree_survives_parent_cycles() {
+    // Reused PIDs can produce inconsistent parent links
+    let table = HashMap::from([
+        (10, fake_process("a", Some(11), 1, 0.0)),
+        (11, fake_process("b", Some(10), 1, 0.0)),
+    ]);
+    let usage = build_process_tree(10, &table, &|_| 0);
+    assert_eq!(usage.total_memory, 2);
+    assert_eq!(usage.children.len(), 1);
+    assert!(usage.children[0].children.is_empty());
+}
+
+#[test]
+fn test_get_process_usage() {
+    let pid = std::process::id();
+    let usage = tauri::async_runtime::block_on(get_process_usage(vec![pid, u32::MAX])).unwrap();
+    // Unknown PIDs are skipped
+    assert_eq!(usage.len(), 1);
+    assert_eq!(usage[0].pid, pid);
+    assert!(usage[0].memory > 0);
+    assert!(usage[0].total_memory >= usage[0].memory);
+}
+
 #[cfg(test)]
 mod cpu_tests {
     use super::*;
@@ -156,4 +307,172 @@ mod cpu_tests {
         assert!(json_str.contains("arch"));
         assert!(json_str.contains("extensions"));
     }
+
+    #[test]
+    fn test_core_type_counts() {
+        let cpu_info = CpuStaticInfo::new();
+        assert!(cpu_info.logical_core_count >= cpu_info.core_count);
+        assert!(cpu_info.performance_core_count > 0);
+        if !cpu_info.topology.cores.is_empty() {
+            assert_eq!(
+                cpu_info.performance_core_count + cpu_info.efficiency_core_count,
+                cpu_info.topology.cores.len()
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        use crate::cpu::parse_cpu_list;
+
+        assert_eq!(parse_cpu_list("0-3,8,10-11\n"), vec![0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(parse_cpu_list("5"), vec![5]);
+        assert_eq!(parse_cpu_list("2,0-1,1"), vec![0, 1, 2]);
+        assert!(parse_cpu_list("").is_empty());
+        assert!(parse_cpu_list("x-y").is_empty());
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod cpu_topology_tests {
+    use crate::cpu::read_cpu_topology;
+    use crate::types::{CoreType, CpuGroup};
+    use std::fs;
+    use std::path::Path;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn fake_cpu(root: &Path, cpu: usize, core_id: u32, siblings: &str, l3: Option<(u32, &str)>) {
+        let dir = root.join(format!("system/cpu/cpu{}", cpu));
+        write(&dir.join("topology/core_id"), &format!("{}\n", core_id));
+        write(&dir.join("topology/physical_package_id"), "0\n");
+        write(&dir.join("topology/thread_siblings_list"), siblings);
+        if let Some((id, shared)) = l3 {
+            write(&dir.join("cache/index3/level"), "3\n");
+            write(&dir.join("cache/index3/id"), &id.to_string());
+            write(&dir.join("cache/index3/shared_cpu_list"), shared);
+        }
+        write(&dir.join("cache/index0/level"), "1\n");
+        write(&dir.join("cache/index0/shared_cpu_list"), siblings);
+    }
+
+    #[test]
+    fn test_hybrid_intel_topology() {
+        let root = tempfile::tempdir().unwrap();
+        // 2 P-cores with HT (0,1 and 2,3) plus 2 E-cores (4, 5)
+        for (cpu, core_id, siblings) in [
+            (0, 0, "0-1"),
+            (1, 0, "0-1"),
+            (2, 4, "2-3"),
+            (3, 4, "2-3"),
+            (4, 8, "4"),
+            (5, 9, "5"),
+        ] {
+            fake_cpu(root.path(), cpu, core_id, siblings, Some((0, "0-5")));
+            fs::create_dir_all(root.path().join(format!("system/cpu/cpu{}/node0", cpu))).unwrap();
+        }
+        write(&root.path().join("cpu_core/cpus"), "0-3\n");
+        write(&root.path().join("cpu_atom/cpus"), "4-5\n");
+        // Offline CPUs have no topology
+        fs::create_dir_all(root.path().join("system/cpu/cpu6")).unwrap();
+        fs::create_dir_all(root.path().join("system/cpu/cpufreq")).unwrap();
+
+        let topology = read_cpu_topology(root.path()).unwrap();
+        assert_eq!(topology.cores.len(), 4);
+        assert_eq!(topology.cores[0].cpus, vec![0, 1]);
+        assert_eq!(topology.cores[1].core_id, 4);
+        assert_eq!(topology.cores[1].core_type, CoreType::Performance);
+        assert_eq!(topology.cores[2].cpus, vec![4]);
+        assert_eq!(topology.cores[2].core_type, CoreType::Efficiency);
+        assert_eq!(topology.cores[3].core_type, CoreType::Efficiency);
+        assert_eq!(
+            topology.numa_nodes,
+            vec![CpuGroup {
+                id: 0,
+                cpus: vec![0, 1, 2, 3, 4, 5]
+            }]
+        );
+        assert_eq!(topology.l3_groups.len(), 1);
+    }
+
+    #[test]
+    fn test_multi_ccd_numa_topology() {
+        let root = tempfile::tempdir().unwrap();
+        // Two CCDs of two SMT-less cores each, one NUMA node per CCD
+        for cpu in 0..4 {
+            let (ccd, shared) = if cpu < 2 { (0, "0-1") } else { (1, "2-3") };
+            fake_cpu(
+                root.path(),
+                cpu,
+                cpu as u32,
+                &cpu.to_string(),
+                Some((ccd, shared)),
+            );
+            fs::create_dir_all(
+                root.path()
+                    .join(format!("system/cpu/cpu{}/node{}", cpu, ccd)),
+            )
+            .unwrap();
+        }
+
+        let topology = read_cpu_topology(root.path()).unwrap();
+        assert_eq!(topology.cores.len(), 4);
+        assert!(topology
+            .cores
+            .iter()
+            .all(|core| core.core_type == CoreType::Performance));
+        assert_eq!(
+            topology.l3_groups,
+            vec![
+                CpuGroup {
+                    id: 0,
+                    cpus: vec![0, 1]
+                },
+                CpuGroup {
+                    id: 1,
+                    cpus: vec![2, 3]
+                }
+            ]
+        );
+        assert_eq!(topology.numa_nodes.len(), 2);
+        assert_eq!(topology.numa_nodes[1].cpus, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_big_little_capacity_topology() {
+        let root = tempfile::tempdir().unwrap();
+        for (cpu, capacity) in [(0, "446"), (1, "446"), (2, "1024"), (3, "1024")] {
+            // Older ARM kernels repeat core_id across clusters
+            fake_cpu(root.path(), cpu, (cpu % 2) as u32, &cpu.to_string(), None);
+            write(
+                &root
+                    .path()
+                    .join(format!("system/cpu/cpu{}/cpu_capacity", cpu)),
+                capacity,
+            );
+        }
+
+        let topology = read_cpu_topology(root.path()).unwrap();
+        let types: Vec<CoreType> = topology.cores.iter().map(|core| core.core_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                CoreType::Efficiency,
+                CoreType::Efficiency,
+                CoreType::Performance,
+                CoreType::Performance
+            ]
+        );
+        assert!(topology.l3_groups.is_empty());
+        assert!(topology.numa_nodes.is_empty());
+    }
+
+    #[test]
+    fn test_missing_cpu_root() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(read_cpu_topology(root.path()).is_none());
+    }
 }
@@ -5,9 +5,48 @@ use crate::vendor::{nvidia::NvidiaInfo, vulkan::VulkanInfo};
 #[derive(Clone, Serialize, Debug)]
 pub struct CpuStaticInfo {
     pub name: String,
+    /// Physical cores
     pub core_count: usize,
+    /// Hardware threads, SMT siblings included
+    pub logical_core_count: usize,
+    /// Physical performance cores; equal to `core_count` on non-hybrid CPUs
+    pub performance_core_count: usize,
+    /// Physical efficiency cores (Intel E-cores, Apple/ARM little cores)
+    pub efficiency_core_count: usize,
     pub arch: String,
     pub extensions: Vec<String>,
+    pub topology: CpuTopology,
+}
+
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CoreType {
+    Performance,
+    Efficiency,
+}
+
+/// A physical core and the logical CPUs (SMT siblings) running on it
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct CpuCore {
+    pub package: u32,
+    pub core_id: u32,
+    pub core_type: CoreType,
+    pub cpus: Vec<usize>,
+}
+
+/// Logical CPUs sharing a NUMA node or an L3 cache
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct CpuGroup {
+    pub id: u32,
+    pub cpus: Vec<usize>,
+}
+
+/// Per-core layout. Only Linux exposes it; elsewhere every list is empty.
+#[derive(Clone, Serialize, Debug, Default, PartialEq)]
+pub struct CpuTopology {
+    pub cores: Vec<CpuCore>,
+    pub numa_nodes: Vec<CpuGroup>,
+    pub l3_groups: Vec<CpuGroup>,
 }
 
 #[derive(Debug, Clone)]
@@ -57,17 +96,64 @@ pub struct SystemInfo {
     pub gpus: Vec<GpuInfo>,
 }
 
+/// Payload of `hardware://system-info-changed`
+#[derive(Serialize, Clone, Debug)]
+pub struct SystemInfoChange {
+    pub added: Vec<GpuInfo>,
+    pub removed: Vec<GpuInfo>,
+    pub changed: Vec<GpuInfo>,
+    pub system_info: SystemInfo,
+}
+
 #[derive(Serialize, Clone, Debug)]
 pub struct GpuUsage {
     pub uuid: String,
     pub used_memory: u64,
     pub total_memory: u64,
+    /// Busy percentage, when the driver reports it
+    pub utilization: Option<u32>,
+    /// Degrees Celsius
+    pub temperature: Option<u32>,
+    /// Watts
+    pub power_draw: Option<f32>,
+    /// MHz
+    pub graphics_clock: Option<u32>,
+    /// MHz
+    pub memory_clock: Option<u32>,
 }
 
 #[derive(Serialize, Clone, Debug)]
 pub struct SystemUsage {
     pub cpu: f32,
+    pub cpu_per_core: Vec<f32>,
     pub used_memory: u64,
     pub total_memory: u64,
+    pub used_swap: u64,
+    pub total_swap: u64,
     pub gpus: Vec<GpuUsage>,
 }
+
+#[derive(Serialize, Clone, Debug)]
+pub struct UsageSample {
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub usage: SystemUsage,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ProcessUsage {
+    pub pid: u32,
+    pub name: String,
+    /// Resident memory in MiB
+    pub memory: u64,
+    /// Percent of a single core, so it can exceed 100
+    pub cpu: f32,
+    /// GPU memory in MiB, where the driver reports it per process
+    pub gpu_memory: u64,
+    /// This process and all of its descendants
+    pub total_memory: u64,
+    pub total_cpu: f32,
+    pub total_gpu_memory: u64,
+    pub children: Vec<ProcessUsage>,
+}
@@ -27,12 +27,16 @@ const COMMANDS: &[&str] = &[
     "is_cuda_installed",
     "find_latest_version_for_backend",
     "prioritize_backends",
-    "parse_backend_version",
     "check_backend_for_updates",
     "remove_old_backend_versions",
     "validate_backend_string",
     "should_migrate_backend",
     "handle_setting_update",
+    // custom backends
+    "list_custom_backends",
+    "register_custom_backend",
+    "validate_custom_backend",
+    "remove_custom_backend",
 ];
 
 fn main() {
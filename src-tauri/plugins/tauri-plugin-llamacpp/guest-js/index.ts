@@ -12,6 +12,7 @@ import {
   BestBackendResult,
   UpdateCheckResult,
   SettingUpdateResult,
+  CustomBackend,
 } from './types'
 
 // Helpers
@@ -52,6 +53,8 @@ export function normalizeLlamacppConfig(config: any): LlamacppConfig {
     ctx_size: asNumber(config.ctx_size),
     threads: asNumber(config.threads),
     threads_batch: asNumber(config.threads_batch),
+    cpu_mask: asString(config.cpu_mask),
+    numa: asString(config.numa),
     n_predict: asNumber(config.n_predict),
     batch_size: asNumber(config.batch_size),
     ubatch_size: asNumber(config.ubatch_size),
@@ -321,20 +324,16 @@ export async function findLatestVersionForBackend(
 
 export async function prioritizeBackends(
   versionBackends: BackendVersion[],
-  hasEnoughGpuMemory: boolean
+  hasEnoughGpuMemory: boolean,
+  customBackends?: CustomBackend[]
 ): Promise<BestBackendResult> {
   return invoke('plugin:llamacpp|prioritize_backends', {
     versionBackends,
     hasEnoughGpuMemory,
+    customBackends,
   })
 }
 
-export async function parseBackendVersion(
-  versionString: string
-): Promise<number> {
-  return invoke('plugin:llamacpp|parse_backend_version', { versionString })
-}
-
 export async function checkBackendForUpdates(
   currentBackendString: string,
   versionBackends: BackendVersion[]
@@ -385,4 +384,46 @@ export async function handleSettingUpdate(
   })
 }
 
+// custom backend functions
+
+export async function listCustomBackends(
+  janDataFolderPath: string
+): Promise<CustomBackend[]> {
+  return invoke('plugin:llamacpp|list_custom_backends', { janDataFolderPath })
+}
+
+export async function registerCustomBackend(
+  janDataFolderPath: string,
+  name: string,
+  path: string,
+  features: string[]
+): Promise<CustomBackend> {
+  return invoke('plugin:llamacpp|register_custom_backend', {
+    janDataFolderPath,
+    name,
+    path,
+    features,
+  })
+}
+
+export async function validateCustomBackend(
+  janDataFolderPath: string,
+  name: string
+): Promise<CustomBackend> {
+  return invoke('plugin:llamacpp|validate_custom_backend', {
+    janDataFolderPath,
+    name,
+  })
+}
+
+export async function removeCustomBackend(
+  janDataFolderPath: string,
+  name: string
+): Promise<boolean> {
+  return invoke('plugin:llamacpp|remove_custom_backend', {
+    janDataFolderPath,
+    name,
+  })
+}
+
 export * from './types'
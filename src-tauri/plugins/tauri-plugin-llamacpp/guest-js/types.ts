@@ -44,6 +44,8 @@ export type LlamacppConfig = {
   ctx_size: number
   threads: number
   threads_batch: number
+  cpu_mask: string
+  numa: string
   n_predict: number
   batch_size: number
   ubatch_size: number
@@ -72,6 +74,10 @@ export type ModelPlan = {
   offloadMmproj?: boolean
   batchSize: number
   mode: 'GPU' | 'Hybrid' | 'CPU' | 'Unsupported'
+  threads: number
+  threadsBatch: number
+  cpuMask?: string
+  numa?: string
 }
 
 export interface DownloadItem {
@@ -167,6 +173,15 @@ export interface UpdateCheckResult {
   target_backend?: string
 }
 
+export interface CustomBackend {
+  name: string
+  path: string
+  server_path: string
+  features: string[]
+  version?: string | null
+  devices: { id: string; name: string; mem: number; free: number }[]
+}
+
 export interface SettingUpdateResult {
   backend_type_updated: boolean
   effective_backend_type?: string
@@ -17,6 +17,8 @@ pub struct LlamacppConfig {
     pub ctx_size: i32,
     pub threads: i32,
     pub threads_batch: i32,
+    pub cpu_mask: String,
+    pub numa: String,
     pub n_predict: i32,
     pub batch_size: i32,
     pub ubatch_size: i32,
@@ -185,6 +187,16 @@ impl ArgumentBuilder {
             self.args.push("--threads-batch".to_string());
             self.args.push(self.config.threads_batch.to_string());
         }
+
+        if !self.config.cpu_mask.is_empty() {
+            self.args.push("--cpu-mask".to_string());
+            self.args.push(self.config.cpu_mask.clone());
+        }
+
+        if !self.config.numa.is_empty() && self.config.numa != "none" {
+            self.args.push("--numa".to_string());
+            self.args.push(self.config.numa.clone());
+        }
     }
 
     fn add_batch_settings(&mut self) {
@@ -354,6 +366,8 @@ mod tests {
             ctx_size: 2048,
             threads: 0,
             threads_batch: 0,
+            cpu_mask: String::new(),
+            numa: String::new(),
             n_predict: 0,
             batch_size: 0,
             ubatch_size: 0,
@@ -426,5 +440,30 @@ mod tests {
         assert!(!args.contains(&"--device".to_string()));
         assert!(!args.contains(&"--chat-template".to_string()));
         assert!(!args.contains(&"--override-tensor".to_string()));
+        assert!(!args.contains(&"--cpu-mask".to_string()));
+        assert!(!args.contains(&"--numa".to_string()));
+    }
+
+    #[test]
+    fn test_cpu_affinity_settings() {
+        let mut config = default_config();
+        config.threads = 8;
+        config.cpu_mask = "0xff".to_string();
+        config.numa = "distribute".to_string();
+
+        let builder = ArgumentBuilder::new(config, false, "/test/data".to_string()).unwrap();
+        let args = builder.build("test", "/path", 8080, None);
+
+        let mask = args.iter().position(|arg| arg == "--cpu-mask").unwrap();
+        assert_eq!(args[mask + 1], "0xff");
+        let numa = args.iter().position(|arg| arg == "--numa").unwrap();
+        assert_eq!(args[numa + 1], "distribute");
+
+        let mut config = default_config();
+        config.numa = "none".to_string();
+        let builder = ArgumentBuilder::new(config, false, "/test/data".to_string()).unwrap();
+        assert!(!builder
+            .build("test", "/path", 8080, None)
+            .contains(&"--numa".to_string()));
     }
 }
@@ -3,6 +3,9 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 
+use crate::backend_version::BackendVersion;
+use crate::custom_backend::{custom_backend_category, CustomBackend, CUSTOM_BACKEND_VERSION};
+
 #[tauri::command]
 pub fn map_old_backend_to_new(old_backend: String) -> String {
     let is_windows = old_backend.starts_with("win-");
@@ -282,12 +285,9 @@ pub async fn list_supported_backends(
 
     // Sort newest version first; if versions tie, sort by backend name
     merged.sort_by(|a, b| {
-        let version_cmp = b.version.cmp(&a.version);
-        if version_cmp == std::cmp::Ordering::Equal {
-            a.backend.cmp(&b.backend)
-        } else {
-            version_cmp
-        }
+        BackendVersion::parse(&b.version)
+            .cmp(&BackendVersion::parse(&a.version))
+            .then_with(|| a.backend.cmp(&b.backend))
     });
 
     Ok(merged)
@@ -551,7 +551,8 @@ pub fn find_latest_version_for_backend(
     }
 
     // Sort by version (newest first)
-    matching_backends.sort_by(|a, b| b.version.cmp(&a.version));
+    matching_backends
+        .sort_by(|a, b| BackendVersion::parse(&b.version).cmp(&BackendVersion::parse(&a.version)));
 
     // Return the full string including the original asset name
     Some(format!(
@@ -564,11 +565,31 @@ pub fn find_latest_version_for_backend(
 pub async fn prioritize_backends(
     version_backends: Vec<BackendInfo>,
     has_enough_gpu_memory: bool,
+    custom_backends: Option<Vec<CustomBackend>>,
 ) -> Result<BestBackendResult, String> {
-    if version_backends.is_empty() {
+    let custom_backends = custom_backends.unwrap_or_default();
+    if version_backends.is_empty() && custom_backends.is_empty() {
         return Err("No backends available".to_string());
     }
 
+    // Custom backends go first so a user's own build wins within its category
+    let candidates: Vec<(BackendInfo, Option<String>)> = custom_backends
+        .iter()
+        .map(|custom| {
+            (
+                BackendInfo {
+                    version: CUSTOM_BACKEND_VERSION.to_string(),
+                    backend: custom.name.clone(),
+                },
+                custom_backend_category(&custom.features),
+            )
+        })
+        .chain(version_backends.iter().map(|vb| {
+            let category = get_backend_category(&vb.backend);
+            (vb.clone(), category)
+        }))
+        .collect();
+
     // Priority list based on GPU memory and platform
     // On macOS, always prioritize Vulkan over CPU backends since MoltenVK works well
     // even with GPUs that have less than 6GB VRAM (like AMD Radeon Pro 5300 with 4GB)
@@ -577,6 +598,8 @@ pub async fn prioritize_backends(
         "cuda-cu13.0",
         "cuda-cu12.0",
         "cuda-cu11.7",
+        "rocm",
+        "sycl",
         "metal",  // Prefer native Metal first on macOS
         "vulkan", // MoltenVK fallback for wider GPU support
         "arm64",
@@ -594,6 +617,8 @@ pub async fn prioritize_backends(
             "cuda-cu13.0",
             "cuda-cu12.0",
             "cuda-cu11.7",
+            "rocm",
+            "sycl",
             "vulkan",
             "common_cpus",
             "avx512",
@@ -608,6 +633,8 @@ pub async fn prioritize_backends(
             "cuda-cu13.0",
             "cuda-cu12.0",
             "cuda-cu11.7",
+            "rocm",
+            "sycl",
             "common_cpus",
             "avx512",
             "avx2",
@@ -621,12 +648,10 @@ pub async fn prioritize_backends(
 
     // Find best matching backend
     for priority_category in backend_priorities {
-        let matching_backends: Vec<&BackendInfo> = version_backends
+        let matching_backends: Vec<&BackendInfo> = candidates
             .iter()
-            .filter(|vb| {
-                let category = get_backend_category(&vb.backend);
-                category.as_deref() == Some(priority_category)
-            })
+            .filter(|(_, category)| category.as_deref() == Some(priority_category))
+            .map(|(vb, _)| vb)
             .collect();
 
         if !matching_backends.is_empty() {
@@ -647,7 +672,7 @@ pub async fn prioritize_backends(
     }
 
     // Fallback to newest version
-    let fallback = &version_backends[0];
+    let fallback = version_backends.first().unwrap_or(&candidates[0].0);
     log::info!("Fallback to: {}/{}", fallback.version, fallback.backend);
 
     Ok(BestBackendResult {
@@ -700,13 +725,6 @@ fn get_backend_category(backend_string: &str) -> Option<String> {
     None
 }
 
-#[tauri::command]
-pub fn parse_backend_version(version_string: String) -> u32 {
-    // Remove any leading non-digit characters
-    let numeric = version_string.trim_start_matches(|c: char| !c.is_ascii_digit());
-    numeric.parse::<u32>().unwrap_or(0)
-}
-
 #[tauri::command]
 pub async fn check_backend_for_updates(
     current_backend_string: String,
@@ -747,8 +765,7 @@ pub async fn check_backend_for_updates(
     let latest_version = target_parts[0];
 
     // Check if update is needed
-    if parse_backend_version(latest_version.to_string())
-        > parse_backend_version(current_version.to_string())
+    if BackendVersion::parse(latest_version).is_newer_than(&BackendVersion::parse(current_version))
     {
         log::info!(
             "New update available: {} -> {}",
@@ -789,6 +806,7 @@ pub async fn remove_old_backend_versions(
 
     let version_dirs = fs::read_dir(&backends_path)
         .map_err(|e| format!("Failed to read backends directory: {}", e))?;
+    let latest = BackendVersion::parse(&latest_version);
 
     for version_entry in version_dirs {
         let version_entry =
@@ -800,8 +818,9 @@ pub async fn remove_old_backend_versions(
             None => continue,
         };
 
-        // Skip the latest version
-        if version_name == latest_version {
+        // Only remove versions strictly older than the latest one; keep the latest,
+        // anything newer and other flavors of the same release
+        if !latest.is_newer_than(&BackendVersion::parse(&version_name)) {
             continue;
         }
 
@@ -1205,17 +1224,6 @@ mod tests {
         assert_eq!(result[2].backend, "backend-b");
     }
 
-    // --- Tests for parse_backend_version ---
-    #[test]
-    fn test_parse_backend_version() {
-        assert_eq!(parse_backend_version("b7523".to_string()), 7523);
-        assert_eq!(parse_backend_version("b7524".to_string()), 7524);
-        assert_eq!(parse_backend_version("7525".to_string()), 7525);
-        assert_eq!(parse_backend_version("v100".to_string()), 100);
-        assert_eq!(parse_backend_version("invalid".to_string()), 0);
-        // Note: "v1.0.0" would fail to parse as u32 due to dots, returning 0
-        assert_eq!(parse_backend_version("v1.0.0".to_string()), 0);
-    }
     // --- Filesystem Integration Tests ---
 
     #[tokio::test]
@@ -1386,6 +1394,77 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_backend_versions_compare_numerically() {
+        let backend = "linux-common_cpus-x64";
+        let available = vec![
+            BackendInfo {
+                version: "b1000".into(),
+                backend: backend.into(),
+            },
+            BackendInfo {
+                version: "b999".into(),
+                backend: backend.into(),
+            },
+        ];
+
+        let sorted = list_supported_backends(available.clone(), vec![])
+            .await
+            .unwrap();
+        assert_eq!(sorted[0].version, "b1000");
+        assert_eq!(
+            find_latest_version_for_backend(available.clone(), backend.to_string()),
+            Some(format!("b1000/{}", backend))
+        );
+
+        let result = check_backend_for_updates(format!("b999/{}", backend), available)
+            .await
+            .unwrap();
+        assert!(result.update_needed);
+        assert_eq!(result.new_version, "b1000");
+    }
+
+    #[tokio::test]
+    async fn test_check_backend_for_updates_ignores_fork_flavor() {
+        let current = "v1.2.3/linux-vulkan-x64".to_string();
+        let available = vec![BackendInfo {
+            version: "v1.2.3-rocm".into(),
+            backend: "linux-vulkan-x64".into(),
+        }];
+
+        let result = check_backend_for_updates(current, available).await.unwrap();
+        assert!(!result.update_needed);
+    }
+
+    #[tokio::test]
+    async fn test_remove_old_backend_versions_keeps_newer() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path();
+        let exe_name = if cfg!(target_os = "windows") {
+            "llama-server.exe"
+        } else {
+            "llama-server"
+        };
+        for version in ["b999", "b1000", "b1001"] {
+            let bin = root.join(version).join("backend-a");
+            fs::create_dir_all(&bin).unwrap();
+            File::create(bin.join(exe_name)).unwrap();
+        }
+
+        let removed = remove_old_backend_versions(
+            root.to_string_lossy().to_string(),
+            "b1000".to_string(),
+            "backend-a".to_string(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(removed.len(), 1);
+        assert!(!root.join("b999").join("backend-a").exists());
+        assert!(root.join("b1000").join("backend-a").exists());
+        assert!(root.join("b1001").join("backend-a").exists());
+    }
+
     #[tokio::test]
     async fn test_check_backend_for_updates_already_latest() {
         let current = "b7524/linux-common_cpus-x64".to_string();
@@ -1422,6 +1501,77 @@ mod tests {
         assert!(result.is_err());
     }
 
+    // --- Tests for prioritize_backends ---
+
+    fn custom_backend(name: &str, features: &[&str]) -> CustomBackend {
+        CustomBackend {
+            name: name.to_string(),
+            path: format!("/opt/{}", name),
+            server_path: format!("/opt/{}/llama-server", name),
+            features: features.iter().map(|f| f.to_string()).collect(),
+            version: None,
+            devices: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prioritize_backends_prefers_custom_in_category() {
+        let bundled = vec![
+            BackendInfo {
+                version: "b7524".into(),
+                backend: "linux-vulkan-x64".into(),
+            },
+            BackendInfo {
+                version: "b7524".into(),
+                backend: "linux-common_cpus-x64".into(),
+            },
+        ];
+
+        let result = prioritize_backends(
+            bundled.clone(),
+            true,
+            Some(vec![custom_backend("rocm-gfx1100", &["rocm"])]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.backend_string, "custom/rocm-gfx1100");
+        assert_eq!(result.version, CUSTOM_BACKEND_VERSION);
+
+        // Unknown features never outrank a categorized bundled backend
+        let result = prioritize_backends(
+            bundled.clone(),
+            true,
+            Some(vec![custom_backend("mystery", &["experimental"])]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.backend_string, "b7524/linux-vulkan-x64");
+
+        // Same category: the custom build wins
+        let result = prioritize_backends(
+            bundled,
+            true,
+            Some(vec![custom_backend("my-vulkan", &["vulkan"])]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.backend_string, "custom/my-vulkan");
+    }
+
+    #[tokio::test]
+    async fn test_prioritize_backends_only_custom() {
+        let result = prioritize_backends(
+            vec![],
+            false,
+            Some(vec![custom_backend("mystery", &["experimental"])]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.backend_string, "custom/mystery");
+
+        assert!(prioritize_backends(vec![], false, None).await.is_err());
+    }
+
     // --- Tests for should_migrate_backend ---
 
     #[test]
@@ -0,0 +1,278 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Version of a llama.cpp backend release.
+///
+/// Understands upstream build tags (`b6325`, `6325`), semver tags used by
+/// forks (`v1.2.3`, `1.2`), pre-releases (`v1.2.3-rc.1`) and fork suffixes
+/// (`b6325-rocm`, `v1.2.3-ik`). Build numbers and semver are different
+/// schemes and cannot really be compared; to keep ordering total, build
+/// numbers sort above semver and unparseable versions sort below both.
+#[derive(Debug, Clone)]
+pub struct BackendVersion {
+    raw: String,
+    scheme: VersionScheme,
+    /// `rc.1`, `beta`, ... — sorts below the matching release
+    pre_release: Option<String>,
+    /// Fork/flavor tag such as `rocm` — does not make a version newer
+    suffix: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum VersionScheme {
+    Unknown,
+    Semver([u64; 3]),
+    Build(u64),
+}
+
+const PRE_RELEASE_TAGS: &[&str] = &["rc", "alpha", "beta", "pre", "preview", "dev"];
+
+impl BackendVersion {
+    pub fn parse(version: &str) -> Self {
+        let raw = version.trim().to_string();
+        // Build metadata (`+abc`) never affects ordering
+        let without_meta = raw.split('+').next().unwrap_or_default();
+        let (core, tag) = match without_meta.split_once('-') {
+            Some((core, tag)) => (core, Some(tag)),
+            None => (without_meta, None),
+        };
+
+        let scheme = parse_scheme(core);
+        let (pre_release, suffix) = match tag.filter(|tag| !tag.is_empty()) {
+            Some(tag) if scheme != VersionScheme::Unknown && is_pre_release(tag) => {
+                (Some(tag.to_ascii_lowercase()), None)
+            }
+            Some(tag) => (None, Some(tag.to_ascii_lowercase())),
+            None => (None, None),
+        };
+
+        BackendVersion {
+            raw,
+            scheme,
+            pre_release,
+            suffix,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+
+    /// llama.cpp build number, if this is a `b<N>` style version
+    pub fn build_number(&self) -> Option<u64> {
+        match self.scheme {
+            VersionScheme::Build(number) => Some(number),
+            _ => None,
+        }
+    }
+
+    pub fn suffix(&self) -> Option<&str> {
+        self.suffix.as_deref()
+    }
+
+    /// True when `self` is a newer release than `other`, ignoring fork suffixes.
+    /// Used for update checks, where `b6325-rocm` is not an upgrade over `b6325`.
+    pub fn is_newer_than(&self, other: &BackendVersion) -> bool {
+        self.cmp_release(other) == Ordering::Greater
+    }
+
+    fn cmp_release(&self, other: &BackendVersion) -> Ordering {
+        self.scheme
+            .cmp(&other.scheme)
+            .then_with(|| cmp_pre_release(&self.pre_release, &other.pre_release))
+    }
+}
+
+fn parse_scheme(core: &str) -> VersionScheme {
+    if let Some(number) = core
+        .strip_prefix(['b', 'B'])
+        .filter(|digits| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()))
+        .and_then(|digits| digits.parse().ok())
+    {
+        return VersionScheme::Build(number);
+    }
+    if !core.is_empty() && core.chars().all(|c| c.is_ascii_digit()) {
+        if let Ok(number) = core.parse() {
+            return VersionScheme::Build(number);
+        }
+    }
+
+    let dotted = core.strip_prefix(['v', 'V']).unwrap_or(core);
+    let parts: Vec<&str> = dotted.split('.').collect();
+    if parts.len() > 3 || parts.iter().any(|part| part.is_empty()) {
+        return VersionScheme::Unknown;
+    }
+    let mut numbers = [0u64; 3];
+    for (slot, part) in numbers.iter_mut().zip(&parts) {
+        match part.parse() {
+            Ok(number) => *slot = number,
+            Err(_) => return VersionScheme::Unknown,
+        }
+    }
+    VersionScheme::Semver(numbers)
+}
+
+fn is_pre_release(tag: &str) -> bool {
+    let tag = tag.to_ascii_lowercase();
+    PRE_RELEASE_TAGS.iter().any(|pre| {
+        tag.strip_prefix(pre).is_some_and(|rest| {
+            rest.is_empty()
+                || rest.starts_with(['.', '-'])
+                || rest.starts_with(|c: char| c.is_ascii_digit())
+        })
+    })
+}
+
+/// A release sorts above any of its pre-releases; pre-releases compare
+/// dot-separated identifiers, numerically where possible.
+fn cmp_pre_release(a: &Option<String>, b: &Option<String>) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => {
+            let mut a_parts = a.split(['.', '-']);
+            let mut b_parts = b.split(['.', '-']);
+            loop {
+                match (a_parts.next(), b_parts.next()) {
+                    (None, None) => return Ordering::Equal,
+                    (None, Some(_)) => return Ordering::Less,
+                    (Some(_), None) => return Ordering::Greater,
+                    (Some(x), Some(y)) => {
+                        let ordering = match (x.parse::<u64>(), y.parse::<u64>()) {
+                            (Ok(x), Ok(y)) => x.cmp(&y),
+                            (Ok(_), Err(_)) => Ordering::Less,
+                            (Err(_), Ok(_)) => Ordering::Greater,
+                            (Err(_), Err(_)) => x.cmp(y),
+                        };
+                        if ordering != Ordering::Equal {
+                            return ordering;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Ord for BackendVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cmp_release(other)
+            // Upstream builds sort above fork flavors of the same release
+            .then_with(|| match (&self.suffix, &other.suffix) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => a.cmp(b),
+            })
+            .then_with(|| self.raw.cmp(&other.raw))
+    }
+}
+
+impl PartialOrd for BackendVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for BackendVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for BackendVersion {}
+
+impl fmt::Display for BackendVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(version: &str) -> BackendVersion {
+        BackendVersion::parse(version)
+    }
+
+    #[test]
+    fn test_parse_build_numbers() {
+        assert_eq!(v("b6325").build_number(), Some(6325));
+        assert_eq!(v("6325").build_number(), Some(6325));
+        assert_eq!(v("b6325-rocm").build_number(), Some(6325));
+        assert_eq!(v("b6325-rocm").suffix(), Some("rocm"));
+        assert_eq!(v("v1.2.3").build_number(), None);
+        assert_eq!(v("b").build_number(), None);
+    }
+
+    #[test]
+    fn test_build_numbers_sort_numerically() {
+        assert!(v("b1000") > v("b999"));
+        assert!(v("b7524") > v("b7523"));
+        assert!(v("b1000").is_newer_than(&v("b999")));
+        assert!(!v("b999").is_newer_than(&v("b1000")));
+    }
+
+    #[test]
+    fn test_semver_ordering() {
+        assert!(v("v1.10.0") > v("v1.9.9"));
+        assert!(!v("1.2").is_newer_than(&v("v1.2.0")));
+        assert!(!v("v1.2.0").is_newer_than(&v("1.2")));
+        assert!(v("v2") > v("v1.99.99"));
+        assert!(v("v1.2.3") > v("v1.2.3-rc.2"));
+        assert!(v("v1.2.3-rc.10") > v("v1.2.3-rc.2"));
+        assert!(v("v1.2.3-beta") > v("v1.2.3-alpha.5"));
+        assert!(v("v1.2.3").is_newer_than(&v("v1.2.3-rc1")));
+    }
+
+    #[test]
+    fn test_fork_suffixes() {
+        let upstream = v("v1.2.3");
+        let fork = v("v1.2.3-rocm");
+        assert_eq!(fork.suffix(), Some("rocm"));
+        // A fork flavor of the same release is not an update
+        assert!(!fork.is_newer_than(&upstream));
+        assert!(!upstream.is_newer_than(&fork));
+        // ...but ordering is still total and deterministic
+        assert!(upstream > fork);
+        assert!(v("v1.2.4-rocm").is_newer_than(&upstream));
+        assert!(v("b6326-ik").is_newer_than(&v("b6325")));
+    }
+
+    #[test]
+    fn test_cross_scheme_ordering() {
+        assert!(v("b1") > v("v99.0.0"));
+        assert!(v("v0.0.1") > v("nightly"));
+        assert!(!v("nightly").is_newer_than(&v("latest")));
+    }
+
+    #[test]
+    fn test_sorting_mixed_versions() {
+        let mut versions: Vec<BackendVersion> = [
+            "b999",
+            "v1.2.3-rocm",
+            "b1000",
+            "b1000-rocm",
+            "garbage",
+            "v1.2.3",
+        ]
+        .iter()
+        .map(|s| v(s))
+        .collect();
+        versions.sort_by(|a, b| b.cmp(a));
+        let sorted: Vec<&str> = versions.iter().map(|v| v.as_str()).collect();
+        assert_eq!(
+            sorted,
+            vec![
+                "b1000",
+                "b1000-rocm",
+                "b999",
+                "v1.2.3",
+                "v1.2.3-rocm",
+                "garbage"
+            ]
+        );
+    }
+}
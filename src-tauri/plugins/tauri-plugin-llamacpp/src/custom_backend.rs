@@ -0,0 +1,396 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::device::{get_devices_from_backend, get_version_from_backend, DeviceInfo};
+
+/// Version slot used for custom backends in `version/backend` strings, e.g. `custom/rocm-gfx1100`.
+pub const CUSTOM_BACKEND_VERSION: &str = "custom";
+
+const REGISTRY_FILE: &str = "custom_backends.json";
+
+/// A self-built llama.cpp backend living outside `<data>/llamacpp/backends`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CustomBackend {
+    pub name: String,
+    /// Directory (or binary) the user pointed us at
+    pub path: String,
+    /// Resolved `llama-server` executable
+    pub server_path: String,
+    /// Declared capabilities, e.g. `["rocm"]`, `["cuda-12"]`, `["openblas"]`
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Build reported by `llama-server --version` on the last probe
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Devices reported by `llama-server --list-devices` on the last probe
+    #[serde(default)]
+    pub devices: Vec<DeviceInfo>,
+}
+
+fn registry_path(jan_data_folder_path: &str) -> PathBuf {
+    PathBuf::from(jan_data_folder_path)
+        .join("llamacpp")
+        .join(REGISTRY_FILE)
+}
+
+fn load_registry(jan_data_folder_path: &str) -> Result<Vec<CustomBackend>, String> {
+    let path = registry_path(jan_data_folder_path);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read custom backend registry: {}", e))?;
+    serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse custom backend registry: {}", e))
+}
+
+fn save_registry(jan_data_folder_path: &str, backends: &[CustomBackend]) -> Result<(), String> {
+    let path = registry_path(jan_data_folder_path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create registry directory: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(backends)
+        .map_err(|e| format!("Failed to serialize custom backend registry: {}", e))?;
+    fs::write(&path, content).map_err(|e| format!("Failed to write custom backend registry: {}", e))
+}
+
+fn validate_custom_backend_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && !name.contains("..");
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid custom backend name '{}': use letters, digits, '-', '_' or '.'",
+            name
+        ))
+    }
+}
+
+/// Accept either the `llama-server` binary itself or a build/install directory
+/// containing it at `build/bin/`, `bin/` or the root.
+fn resolve_server_path(path: &Path) -> Option<PathBuf> {
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+    let exe_name = if cfg!(target_os = "windows") {
+        "llama-server.exe"
+    } else {
+        "llama-server"
+    };
+    [
+        path.join("build").join("bin").join(exe_name),
+        path.join("bin").join(exe_name),
+        path.join(exe_name),
+    ]
+    .into_iter()
+    .find(|candidate| candidate.is_file())
+}
+
+/// Run the server binary to fill in `version` and `devices`. A build that
+/// cannot report its version is treated as broken; an empty device list is
+/// fine (CPU-only builds).
+async fn probe_custom_backend(backend: &mut CustomBackend) -> Result<(), String> {
+    let version = get_version_from_backend(&backend.server_path, HashMap::new())
+        .await
+        .map_err(|e| {
+            format!(
+                "llama-server at {} failed to run: {}",
+                backend.server_path, e
+            )
+        })?;
+    let devices = match get_devices_from_backend(&backend.server_path, HashMap::new()).await {
+        Ok(devices) => devices,
+        Err(e) => {
+            log::warn!(
+                "Could not list devices for custom backend {}: {}",
+                backend.name,
+                e
+            );
+            Vec::new()
+        }
+    };
+    backend.version = Some(version);
+    backend.devices = devices;
+    Ok(())
+}
+
+/// Map declared features to the categories used by `prioritize_backends`.
+pub fn custom_backend_category(features: &[String]) -> Option<String> {
+    features.iter().find_map(|feature| {
+        let feature = feature.to_ascii_lowercase();
+        let category = match feature.as_str() {
+            "cuda-13" | "cuda13" | "cu13.0" => "cuda-cu13.0",
+            "cuda-12" | "cuda12" | "cu12.0" | "cuda" => "cuda-cu12.0",
+            "cuda-11" | "cuda11" | "cu11.7" => "cuda-cu11.7",
+            "rocm" | "hip" | "hipblas" => "rocm",
+            "sycl" | "oneapi" => "sycl",
+            "metal" => "metal",
+            "vulkan" => "vulkan",
+            "cpu" | "openblas" | "blas" | "common_cpus" => "common_cpus",
+            "avx512" => "avx512",
+            "avx2" => "avx2",
+            "avx" => "avx",
+            "noavx" => "noavx",
+            _ => return None,
+        };
+        Some(category.to_string())
+    })
+}
+
+#[tauri::command]
+pub async fn list_custom_backends(
+    jan_data_folder_path: String,
+) -> Result<Vec<CustomBackend>, String> {
+    load_registry(&jan_data_folder_path)
+}
+
+/// Register (or replace) a custom backend after checking that its
+/// `llama-server` actually runs.
+#[tauri::command]
+pub async fn register_custom_backend(
+    jan_data_folder_path: String,
+    name: String,
+    path: String,
+    features: Vec<String>,
+) -> Result<CustomBackend, String> {
+    validate_custom_backend_name(&name)?;
+    let server_path = resolve_server_path(Path::new(&path))
+        .ok_or_else(|| format!("No llama-server executable found at {}", path))?;
+
+    let mut backend = CustomBackend {
+        name,
+        path,
+        server_path: server_path.to_string_lossy().to_string(),
+        features,
+        version: None,
+        devices: Vec::new(),
+    };
+    probe_custom_backend(&mut backend).await?;
+
+    let mut registry = load_registry(&jan_data_folder_path)?;
+    registry.retain(|existing| existing.name != backend.name);
+    registry.push(backend.clone());
+    save_registry(&jan_data_folder_path, &registry)?;
+
+    log::info!(
+        "Registered custom backend {} ({}) at {}",
+        backend.name,
+        backend.version.as_deref().unwrap_or("unknown"),
+        backend.server_path
+    );
+    Ok(backend)
+}
+
+/// Re-probe a registered backend, e.g. after the user rebuilt it.
+#[tauri::command]
+pub async fn validate_custom_backend(
+    jan_data_folder_path: String,
+    name: String,
+) -> Result<CustomBackend, String> {
+    let mut registry = load_registry(&jan_data_folder_path)?;
+    let backend = registry
+        .iter_mut()
+        .find(|backend| backend.name == name)
+        .ok_or_else(|| format!("Custom backend '{}' is not registered", name))?;
+
+    backend.server_path = resolve_server_path(Path::new(&backend.path))
+        .ok_or_else(|| format!("No llama-server executable found at {}", backend.path))?
+        .to_string_lossy()
+        .to_string();
+    probe_custom_backend(backend).await?;
+
+    let backend = backend.clone();
+    save_registry(&jan_data_folder_path, &registry)?;
+    Ok(backend)
+}
+
+/// Forget a custom backend. The files on disk are left untouched.
+#[tauri::command]
+pub async fn remove_custom_backend(
+    jan_data_folder_path: String,
+    name: String,
+) -> Result<bool, String> {
+    let mut registry = load_registry(&jan_data_folder_path)?;
+    let before = registry.len();
+    registry.retain(|backend| backend.name != name);
+    if registry.len() == before {
+        return Ok(false);
+    }
+    save_registry(&jan_data_folder_path, &registry)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn custom(name: &str, features: &[&str]) -> CustomBackend {
+        CustomBackend {
+            name: name.to_string(),
+            path: format!("/opt/{}", name),
+            server_path: format!("/opt/{}/bin/llama-server", name),
+            features: features.iter().map(|f| f.to_string()).collect(),
+            version: None,
+            devices: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_validate_custom_backend_name() {
+        assert!(validate_custom_backend_name("rocm-gfx1100").is_ok());
+        assert!(validate_custom_backend_name("openblas_v0.3").is_ok());
+        assert!(validate_custom_backend_name("").is_err());
+        assert!(validate_custom_backend_name("../escape").is_err());
+        assert!(validate_custom_backend_name("with space").is_err());
+        assert!(validate_custom_backend_name("a/b").is_err());
+    }
+
+    #[test]
+    fn test_custom_backend_category() {
+        let features = |list: &[&str]| list.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            custom_backend_category(&features(&["ROCm"])),
+            Some("rocm".to_string())
+        );
+        assert_eq!(
+            custom_backend_category(&features(&["flash-attn", "cuda-12"])),
+            Some("cuda-cu12.0".to_string())
+        );
+        assert_eq!(
+            custom_backend_category(&features(&["openblas"])),
+            Some("common_cpus".to_string())
+        );
+        assert_eq!(custom_backend_category(&features(&["experimental"])), None);
+        assert_eq!(custom_backend_category(&[]), None);
+    }
+
+    #[test]
+    fn test_registry_roundtrip() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_dir = temp_dir.path().to_string_lossy().to_string();
+
+        assert!(load_registry(&data_dir).unwrap().is_empty());
+
+        let backends = vec![custom("rocm", &["rocm"]), custom("blas", &["openblas"])];
+        save_registry(&data_dir, &backends).unwrap();
+        assert!(temp_dir
+            .path()
+            .join("llamacpp")
+            .join(REGISTRY_FILE)
+            .exists());
+
+        let loaded = load_registry(&data_dir).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].name, "rocm");
+        assert_eq!(loaded[1].features, vec!["openblas".to_string()]);
+    }
+
+    #[test]
+    fn test_resolve_server_path() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(resolve_server_path(temp_dir.path()).is_none());
+
+        let exe_name = if cfg!(target_os = "windows") {
+            "llama-server.exe"
+        } else {
+            "llama-server"
+        };
+        let bin_dir = temp_dir.path().join("build").join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let exe = bin_dir.join(exe_name);
+        fs::write(&exe, b"").unwrap();
+
+        assert_eq!(resolve_server_path(temp_dir.path()), Some(exe.clone()));
+        // Pointing directly at the binary works too
+        assert_eq!(resolve_server_path(&exe), Some(exe));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_register_validate_and_remove_custom_backend() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let data_dir = temp_dir.path().join("data").to_string_lossy().to_string();
+        let build_dir = temp_dir.path().join("llama.cpp").join("build").join("bin");
+        fs::create_dir_all(&build_dir).unwrap();
+
+        // Fake llama-server that answers --version and --list-devices
+        let exe = build_dir.join("llama-server");
+        fs::write(
+            &exe,
+            "#!/bin/sh\n\
+             if [ \"$1\" = \"--version\" ]; then echo 'version: 6325 (e92d53b2)' >&2; exit 0; fi\n\
+             echo 'Available devices:'\n\
+             echo 'ROCm0: AMD Radeon RX 7900 XTX (24560 MiB, 24000 MiB free)'\n",
+        )
+        .unwrap();
+        fs::set_permissions(&exe, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let source_dir = temp_dir
+            .path()
+            .join("llama.cpp")
+            .to_string_lossy()
+            .to_string();
+        let backend = register_custom_backend(
+            data_dir.clone(),
+            "rocm-gfx1100".to_string(),
+            source_dir,
+            vec!["rocm".to_string()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(backend.version.as_deref(), Some("6325 (e92d53b2)"));
+        assert_eq!(backend.devices.len(), 1);
+        assert_eq!(backend.devices[0].id, "ROCm0");
+
+        let listed = list_custom_backends(data_dir.clone()).await.unwrap();
+        assert_eq!(listed.len(), 1);
+
+        let revalidated = validate_custom_backend(data_dir.clone(), "rocm-gfx1100".to_string())
+            .await
+            .unwrap();
+        assert_eq!(revalidated.server_path, exe.to_string_lossy());
+
+        assert!(
+            remove_custom_backend(data_dir.clone(), "rocm-gfx1100".to_string())
+                .await
+                .unwrap()
+        );
+        assert!(
+            !remove_custom_backend(data_dir.clone(), "rocm-gfx1100".to_string())
+                .await
+                .unwrap()
+        );
+        assert!(list_custom_backends(data_dir).await.unwrap().is_empty());
+        // The user's build is left alone
+        assert!(exe.exists());
+    }
+
+    #[tokio::test]
+    async fn test_register_custom_backend_rejects_missing_binary() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_dir = temp_dir.path().to_string_lossy().to_string();
+        let result = register_custom_backend(
+            data_dir.clone(),
+            "missing".to_string(),
+            temp_dir
+                .path()
+                .join("nowhere")
+                .to_string_lossy()
+                .to_string(),
+            vec![],
+        )
+        .await;
+        assert!(result.is_err());
+        assert!(list_custom_backends(data_dir).await.unwrap().is_empty());
+    }
+}
@@ -73,6 +73,67 @@ pub async fn get_devices_from_backend(
     parse_device_output(&stdout)
 }
 
+/// Run `llama-server --version` and return the reported build, e.g. `6325 (e92d53b2)`.
+///
+/// Used to check that a freshly installed backend actually starts on this machine.
+pub async fn get_version_from_backend(
+    backend_path: &str,
+    envs: HashMap<String, String>,
+) -> ServerResult<String> {
+    log::info!("Getting version from server at path: {:?}", backend_path);
+
+    let bin_path = validate_binary_path(backend_path)?;
+
+    let mut command = Command::new(&bin_path);
+    command.arg("--version");
+    command.envs(envs);
+
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+    setup_windows_process_flags(&mut command);
+    add_cuda_paths(&mut command);
+    setup_library_path(bin_path.parent(), &mut command);
+
+    let output = timeout(Duration::from_secs(30), command.output())
+        .await
+        .map_err(|_| {
+            LlamacppError::new(
+                ErrorCode::InternalError,
+                "Timeout waiting for server version".into(),
+                None,
+            )
+        })?
+        .map_err(ServerError::Io)?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        log::error!("llama-server --version failed: {}", stderr);
+        return Err(LlamacppError::from_stderr(&stderr).into());
+    }
+
+    // llama.cpp prints the build info to stderr, but some forks use stdout
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_version_output(&stderr)
+        .or_else(|| parse_version_output(&stdout))
+        .ok_or_else(|| {
+            LlamacppError::new(
+                ErrorCode::InternalError,
+                "Could not read version from llama-server output".into(),
+                Some(format!("stdout: {stdout}\nstderr: {stderr}")),
+            )
+            .into()
+        })
+}
+
+fn parse_version_output(output: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        line.trim()
+            .strip_prefix("version:")
+            .map(|rest| rest.trim().to_string())
+            .filter(|rest| !rest.is_empty())
+    })
+}
+
 fn parse_device_output(output: &str) -> ServerResult<Vec<DeviceInfo>> {
     let mut devices = Vec::new();
     let mut found_devices_section = false;
@@ -384,6 +445,25 @@ Some footer text
         assert_eq!(result[2].mem, 8000);
     }
 
+    #[test]
+    fn test_parse_version_output() {
+        let output =
+            "version: 6325 (e92d53b2)\nbuilt with cc (Ubuntu 13.3.0) for x86_64-linux-gnu\n";
+        assert_eq!(
+            parse_version_output(output),
+            Some("6325 (e92d53b2)".to_string())
+        );
+
+        let output = "ggml_vulkan: Found 1 Vulkan devices:\n  version: b7524-ik (abc)\n";
+        assert_eq!(
+            parse_version_output(output),
+            Some("b7524-ik (abc)".to_string())
+        );
+
+        assert_eq!(parse_version_output("version:\n"), None);
+        assert_eq!(parse_version_output("no version here"), None);
+    }
+
     #[test]
     fn test_parse_device_output_no_devices_section() {
         let output = "Some output without Available devices section";
@@ -3,7 +3,7 @@ use crate::gguf::utils::estimate_kv_cache_internal;
 use crate::gguf::utils::read_gguf_metadata_internal;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use tauri_plugin_hardware::get_system_info;
+use tauri_plugin_hardware::{get_system_info, CoreType, CpuCore, CpuStaticInfo};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -14,6 +14,20 @@ pub struct ModelPlan {
     pub offload_mmproj: bool,
     pub batch_size: u64,
     pub mode: ModelMode,
+    #[serde(flatten)]
+    pub cpu: CpuPlan,
+}
+
+/// llama.cpp threading recommended for the CPU topology
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CpuPlan {
+    pub threads: u64,
+    pub threads_batch: u64,
+    /// Hex `--cpu-mask`, only when some cores should be left out
+    pub cpu_mask: Option<String>,
+    /// `--numa` strategy on multi-node systems
+    pub numa: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -131,6 +145,7 @@ pub async fn plan_model_load(
             batch_size: 64,
             mode: ModelMode::Unsupported,
             offload_mmproj: false,
+            cpu: recommend_cpu_plan(&sys_info.cpu, &ModelMode::Unsupported),
         });
     }
     if mmproj_size > 0 {
@@ -303,8 +318,10 @@ pub async fn plan_model_load(
         offload_mmproj = false;
     }
 
-    log::info!("Planned model load params: GPU Layers: {}, max_ctx_len: {}, kv_cache offload: {}, offload mmproj: {}, batch_size: {}",
-        gpu_layers, max_ctx_len, !no_offload_kv_cache, offload_mmproj, batch_size);
+    let cpu = recommend_cpu_plan(&sys_info.cpu, &mode);
+
+    log::info!("Planned model load params: GPU Layers: {}, max_ctx_len: {}, kv_cache offload: {}, offload mmproj: {}, batch_size: {}, threads: {}/{}",
+        gpu_layers, max_ctx_len, !no_offload_kv_cache, offload_mmproj, batch_size, cpu.threads, cpu.threads_batch);
     Ok(ModelPlan {
         gpu_layers,
         max_context_length: max_ctx_len,
@@ -312,5 +329,245 @@ pub async fn plan_model_load(
         offload_mmproj,
         batch_size,
         mode,
+        cpu,
     })
 }
+
+/// Token generation is memory bound and runs best with one thread per
+/// physical performance core; E-cores and SMT siblings only add sync stalls.
+/// Prompt processing is compute bound, so SMT siblings of those cores are
+/// used for `threads_batch`. With the whole model on the GPU the CPU only
+/// drives the backend, so threads stay within one L3 domain (CCD) and NUMA
+/// placement does not matter.
+pub fn recommend_cpu_plan(cpu: &CpuStaticInfo, mode: &ModelMode) -> CpuPlan {
+    let topology = &cpu.topology;
+    let mut cores: Vec<&CpuCore> = topology
+        .cores
+        .iter()
+        .filter(|core| core.core_type == CoreType::Performance)
+        .collect();
+
+    if *mode == ModelMode::GPU && topology.l3_groups.len() > 1 {
+        let in_group = |core: &CpuCore, cpus: &[usize]| core.cpus.iter().all(|c| cpus.contains(c));
+        if let Some(group) = topology
+            .l3_groups
+            .iter()
+            .find(|group| cores.iter().any(|core| in_group(core, &group.cpus)))
+        {
+            cores.retain(|core| in_group(core, &group.cpus));
+        }
+    }
+
+    // Without per-core topology (macOS, Windows) fall back to the counts
+    if cores.is_empty() {
+        let threads = cpu.performance_core_count.max(1) as u64;
+        let threads_batch = if cpu.efficiency_core_count == 0 && *mode != ModelMode::GPU {
+            (cpu.logical_core_count as u64).max(threads)
+        } else {
+            threads
+        };
+        return CpuPlan {
+            threads,
+            threads_batch,
+            cpu_mask: None,
+            numa: None,
+        };
+    }
+
+    let threads = cores.len() as u64;
+    let logical: Vec<usize> = cores
+        .iter()
+        .flat_map(|core| core.cpus.iter().copied())
+        .collect();
+    let threads_batch = if *mode == ModelMode::GPU {
+        threads
+    } else {
+        logical.len() as u64
+    };
+    let cpu_mask = (cores.len() < topology.cores.len()).then(|| cpu_mask_hex(&logical));
+    let numa = (topology.numa_nodes.len() > 1 && *mode != ModelMode::GPU)
+        .then(|| "distribute".to_string());
+
+    CpuPlan {
+        threads,
+        threads_batch,
+        cpu_mask,
+        numa,
+    }
+}
+
+/// `--cpu-mask` value with one bit per logical CPU, e.g. `0xf0f`
+fn cpu_mask_hex(cpus: &[usize]) -> String {
+    let Some(&max) = cpus.iter().max() else {
+        return "0x0".to_string();
+    };
+    let mut nibbles = vec![0u8; max / 4 + 1];
+    for &cpu in cpus {
+        nibbles[cpu / 4] |= 1 << (cpu % 4);
+    }
+    let hex: String = nibbles
+        .iter()
+        .rev()
+        .map(|nibble| format!("{:x}", nibble))
+        .collect();
+    format!("0x{}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tauri_plugin_hardware::{CpuGroup, CpuTopology};
+
+    fn core(core_id: u32, core_type: CoreType, cpus: &[usize]) -> CpuCore {
+        CpuCore {
+            package: 0,
+            core_id,
+            core_type,
+            cpus: cpus.to_vec(),
+        }
+    }
+
+    fn cpu_info(topology: CpuTopology) -> CpuStaticInfo {
+        let efficiency = topology
+            .cores
+            .iter()
+            .filter(|core| core.core_type == CoreType::Efficiency)
+            .count();
+        CpuStaticInfo {
+            name: "test".to_string(),
+            core_count: topology.cores.len(),
+            logical_core_count: topology.cores.iter().map(|core| core.cpus.len()).sum(),
+            performance_core_count: topology.cores.len() - efficiency,
+            efficiency_core_count: efficiency,
+            arch: "x86_64".to_string(),
+            extensions: vec![],
+            topology,
+        }
+    }
+
+    fn group(id: u32, cpus: std::ops::Range<usize>) -> CpuGroup {
+        CpuGroup {
+            id,
+            cpus: cpus.collect(),
+        }
+    }
+
+    #[test]
+    fn test_cpu_mask_hex() {
+        assert_eq!(cpu_mask_hex(&[0, 1, 2, 3]), "0xf");
+        assert_eq!(cpu_mask_hex(&[0, 2, 8]), "0x105");
+        assert_eq!(cpu_mask_hex(&[]), "0x0");
+        assert_eq!(cpu_mask_hex(&[130]), format!("0x4{}", "0".repeat(32)));
+    }
+
+    #[test]
+    fn test_hybrid_cpu_uses_performance_cores() {
+        // 2 P-cores with HT, 4 E-cores
+        let info = cpu_info(CpuTopology {
+            cores: vec![
+                core(0, CoreType::Performance, &[0, 1]),
+                core(4, CoreType::Performance, &[2, 3]),
+                core(8, CoreType::Efficiency, &[4]),
+                core(9, CoreType::Efficiency, &[5]),
+                core(10, CoreType::Efficiency, &[6]),
+                core(11, CoreType::Efficiency, &[7]),
+            ],
+            numa_nodes: vec![group(0, 0..8)],
+            l3_groups: vec![group(0, 0..8)],
+        });
+
+        let plan = recommend_cpu_plan(&info, &ModelMode::CPU);
+        assert_eq!(plan.threads, 2);
+        assert_eq!(plan.threads_batch, 4);
+        assert_eq!(plan.cpu_mask.as_deref(), Some("0xf"));
+        assert_eq!(plan.numa, None);
+    }
+
+    #[test]
+    fn test_multi_ccd_cpu() {
+        // 2 CCDs x 2 cores with SMT, one NUMA node per CCD
+        let info = cpu_info(CpuTopology {
+            cores: vec![
+                core(0, CoreType::Performance, &[0, 4]),
+                core(1, CoreType::Performance, &[1, 5]),
+                core(2, CoreType::Performance, &[2, 6]),
+                core(3, CoreType::Performance, &[3, 7]),
+            ],
+            numa_nodes: vec![
+                CpuGroup {
+                    id: 0,
+                    cpus: vec![0, 1, 4, 5],
+                },
+                CpuGroup {
+                    id: 1,
+                    cpus: vec![2, 3, 6, 7],
+                },
+            ],
+            l3_groups: vec![
+                CpuGroup {
+                    id: 0,
+                    cpus: vec![0, 1, 4, 5],
+                },
+                CpuGroup {
+                    id: 1,
+                    cpus: vec![2, 3, 6, 7],
+                },
+            ],
+        });
+
+        let plan = recommend_cpu_plan(&info, &ModelMode::Hybrid);
+        assert_eq!(plan.threads, 4);
+        assert_eq!(plan.threads_batch, 8);
+        assert_eq!(plan.cpu_mask, None);
+        assert_eq!(plan.numa.as_deref(), Some("distribute"));
+
+        // Fully offloaded: stay on the first CCD
+        let plan = recommend_cpu_plan(&info, &ModelMode::GPU);
+        assert_eq!(plan.threads, 2);
+        assert_eq!(plan.threads_batch, 2);
+        assert_eq!(plan.cpu_mask.as_deref(), Some("0x33"));
+        assert_eq!(plan.numa, None);
+    }
+
+    #[test]
+    fn test_without_topology() {
+        let mut info = cpu_info(CpuTopology::default());
+        info.core_count = 10;
+        info.logical_core_count = 10;
+        info.performance_core_count = 8;
+        info.efficiency_core_count = 2;
+        let plan = recommend_cpu_plan(&info, &ModelMode::CPU);
+        assert_eq!(plan.threads, 8);
+        assert_eq!(plan.threads_batch, 8);
+        assert_eq!(plan.cpu_mask, None);
+
+        info.performance_core_count = 8;
+        info.efficiency_core_count = 0;
+        info.logical_core_count = 16;
+        let plan = recommend_cpu_plan(&info, &ModelMode::CPU);
+        assert_eq!(plan.threads_batch, 16);
+    }
+
+    #[test]
+    fn test_model_plan_serialization() {
+        let plan = ModelPlan {
+            gpu_layers: 10,
+            max_context_length: 4096,
+            no_offload_kv_cache: false,
+            offload_mmproj: false,
+            batch_size: 512,
+            mode: ModelMode::Hybrid,
+            cpu: CpuPlan {
+                threads: 6,
+                threads_batch: 12,
+                cpu_mask: Some("0xfff".to_string()),
+                numa: None,
+            },
+        };
+        let json = serde_json::to_value(&plan).unwrap();
+        assert_eq!(json["threads"], 6);
+        assert_eq!(json["threadsBatch"], 12);
+        assert_eq!(json["cpuMask"], "0xfff");
+        assert!(json["numa"].is_null());
+    }
+}
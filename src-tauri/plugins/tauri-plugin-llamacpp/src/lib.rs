@@ -5,8 +5,10 @@ use tauri::{
 
 mod args;
 mod backend;
+mod backend_version;
 pub mod cleanup;
 mod commands;
+mod custom_backend;
 mod device;
 mod error;
 mod gguf;
@@ -14,6 +16,7 @@ mod path;
 mod process;
 pub mod state;
 pub use cleanup::cleanup_llama_processes;
+pub use device::get_version_from_backend;
 pub use state::LLamaBackendSession;
 
 /// Initializes the plugin.
@@ -49,12 +52,16 @@ pub fn init<R: Runtime>() -> TauriPlugin<R> {
             backend::is_moltenvk_installed,
             backend::find_latest_version_for_backend,
             backend::prioritize_backends,
-            backend::parse_backend_version,
             backend::check_backend_for_updates,
             backend::remove_old_backend_versions,
             backend::validate_backend_string,
             backend::should_migrate_backend,
-            backend::handle_setting_update
+            backend::handle_setting_update,
+            // Custom backends
+            custom_backend::list_custom_backends,
+            custom_backend::register_custom_backend,
+            custom_backend::validate_custom_backend,
+            custom_backend::remove_custom_backend
         ])
         .setup(|app, _api| {
             // Initialize and manage the plugin state
@@ -200,7 +200,11 @@ pub fn change_app_data_folder<R: Runtime>(
     configuration.data_folder = new_data_folder;
 
     // Save the updated configuration
-    update_app_configuration(app_handle, configuration)
+    update_app_configuration(app_handle.clone(), configuration)?;
+
+    // Threads in the new folder are encrypted with the keys copied along with them
+    crate::core::threads::encryption::load_keys(&new_data_folder_path)?;
+    crate::core::threads::watcher::restart_if_running(&app_handle)
 }
 
 #[tauri::command]
@@ -3,6 +3,18 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AppConfiguration {
     pub data_folder: String,
+    /// Where desktop threads are stored; mobile always uses SQLite
+    #[serde(default)]
+    pub threads_storage: ThreadsStorage,
+    /// Days deleted threads and messages stay in the trash; 0 keeps them until it is emptied
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: u32,
+    /// Whether changes made to the threads folder outside the app emit `threads://changed`
+    #[serde(default)]
+    pub watch_threads_folder: bool,
+    /// Shared folder threads are synced through with other devices
+    #[serde(default)]
+    pub threads_sync_folder: Option<String>,
     // Add other fields as needed
 }
 
@@ -10,7 +22,24 @@ impl AppConfiguration {
     pub fn default() -> Self {
         Self {
             data_folder: String::from("./data"), // Set a default value for the data_folder
-                                                 // Add other fields with default values as needed
+            // Add other fields with default values as needed
+            threads_storage: ThreadsStorage::default(),
+            trash_retention_days: default_trash_retention_days(),
+            watch_threads_folder: false,
+            threads_sync_folder: None,
         }
     }
 }
+
+fn default_trash_retention_days() -> u32 {
+    30
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ThreadsStorage {
+    /// `thread.json` + `messages.jsonl` per thread directory
+    #[default]
+    Files,
+    Sqlite,
+}
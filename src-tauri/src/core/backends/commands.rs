@@ -0,0 +1,257 @@
+use super::helpers::*;
+use super::models::{BackendAsset, InstallBackendOptions, InstalledBackendInfo};
+use crate::core::app::commands::get_jan_data_folder_path;
+use crate::core::downloads::helpers::{
+    _convert_headers, _download_files_internal, _get_client_for_item, err_to_string,
+};
+use crate::core::downloads::models::DownloadItem;
+use crate::core::filesystem::commands::decompress;
+use crate::core::state::AppState;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tauri::{Runtime, State};
+use tokio_util::sync::CancellationToken;
+
+/// Download, verify, extract and smoke-test a llama.cpp backend.
+///
+/// The new build is staged next to the final directory and only swapped in
+/// after `llama-server --version` succeeds, so a failed install never
+/// leaves a half-extracted backend behind or breaks the existing one.
+/// Progress is reported on the usual `download-{task_id}` channel and the
+/// task can be stopped with `cancel_download_task`.
+#[tauri::command]
+pub async fn install_backend<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    state: State<'_, AppState>,
+    version: String,
+    backend: String,
+    options: Option<InstallBackendOptions>,
+) -> Result<InstalledBackendInfo, String> {
+    validate_path_component("version", &version)?;
+    validate_path_component("name", &backend)?;
+    let options = options.unwrap_or_default();
+
+    let jan_data_folder = get_jan_data_folder_path(app.clone());
+    let version_dir = backends_root(&jan_data_folder).join(&version);
+    let target_dir = version_dir.join(&backend);
+    let staging_dir = version_dir.join(format!(".{backend}.installing"));
+    let downloads_dir = backend_downloads_dir(&jan_data_folder);
+    tokio::fs::create_dir_all(&downloads_dir)
+        .await
+        .map_err(err_to_string)?;
+
+    let task_id = format!("llamacpp-{version}-{backend}").replace('.', "-");
+    let cancel_token = CancellationToken::new();
+    {
+        let mut download_manager = state.download_manager.lock().await;
+        if let Some(existing_token) = download_manager.cancel_tokens.remove(&task_id) {
+            log::info!("Cancelling existing backend install: {task_id}");
+            existing_token.cancel();
+        }
+        download_manager
+            .cancel_tokens
+            .insert(task_id.clone(), cancel_token.clone());
+    }
+
+    let assets = resolve_backend_assets(&version, &backend, &options);
+    let result = async {
+        let archives = fetch_backend_assets(
+            app.clone(),
+            &version,
+            &assets,
+            &options,
+            &downloads_dir,
+            &task_id,
+            cancel_token.clone(),
+        )
+        .await?;
+        if cancel_token.is_cancelled() {
+            return Err("Backend install cancelled".to_string());
+        }
+        stage_backend(app.clone(), &assets, &archives, &staging_dir).await
+    }
+    .await;
+
+    {
+        let mut download_manager = state.download_manager.lock().await;
+        download_manager.cancel_tokens.remove(&task_id);
+    }
+
+    let server_version = match result {
+        Ok(server_version) => server_version,
+        Err(e) => {
+            log::error!("Failed to install backend {version}/{backend}: {e}");
+            let _ = std::fs::remove_dir_all(&staging_dir);
+            return Err(e);
+        }
+    };
+
+    if let Err(e) = replace_backend_dir(&staging_dir, &target_dir) {
+        let _ = std::fs::remove_dir_all(&staging_dir);
+        return Err(e);
+    }
+
+    // The cudart archive is shared between versions, so only drop the main one.
+    let _ = std::fs::remove_file(downloads_dir.join(&assets[0].name));
+
+    let server_path = find_server_binary(&target_dir)
+        .ok_or_else(|| "llama-server missing after install".to_string())?;
+    log::info!("Installed backend {version}/{backend} ({server_version})");
+
+    Ok(InstalledBackendInfo {
+        version,
+        backend,
+        path: target_dir.to_string_lossy().to_string(),
+        server_path: server_path.to_string_lossy().to_string(),
+        server_version,
+    })
+}
+
+/// Fetch every asset into `downloads_dir`, verifying its SHA-256, and return the
+/// archive paths in the same order as `assets`.
+async fn fetch_backend_assets<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    version: &str,
+    assets: &[BackendAsset],
+    options: &InstallBackendOptions,
+    downloads_dir: &Path,
+    task_id: &str,
+    cancel_token: CancellationToken,
+) -> Result<Vec<PathBuf>, String> {
+    let probe_item = DownloadItem {
+        url: assets[0].url.clone(),
+        save_path: String::new(),
+        proxy: options.proxy.clone(),
+        sha256: None,
+        size: None,
+        model_id: None,
+    };
+    let header_map = _convert_headers(&options.headers).map_err(err_to_string)?;
+    let client = _get_client_for_item(&probe_item, &header_map)?;
+
+    let release_digests = if assets.iter().any(|asset| is_remote_url(&asset.url)) {
+        fetch_release_digests(&client, version)
+            .await
+            .unwrap_or_else(|e| {
+                log::warn!("Could not read release digests for {version}: {e}");
+                HashMap::new()
+            })
+    } else {
+        HashMap::new()
+    };
+
+    let mut archives = Vec::with_capacity(assets.len());
+    let mut remote_items = Vec::new();
+    for (index, asset) in assets.iter().enumerate() {
+        let explicit = if index == 0 {
+            options.sha256.as_deref().map(str::to_ascii_lowercase)
+        } else {
+            None
+        };
+        let sha256 = match explicit.or_else(|| release_digests.get(&asset.name).cloned()) {
+            Some(digest) => Some(digest),
+            None => fetch_sidecar_digest(&client, asset).await,
+        };
+        if sha256.is_none() {
+            if !options.allow_unverified {
+                return Err(format!(
+                    "No SHA-256 checksum available for {}. Provide one or allow unverified installs.",
+                    asset.name
+                ));
+            }
+            log::warn!("Installing {} without checksum verification", asset.name);
+        }
+
+        let archive_path = downloads_dir.join(&asset.name);
+        if is_remote_url(&asset.url) {
+            remote_items.push(DownloadItem {
+                url: asset.url.clone(),
+                save_path: archive_path.to_string_lossy().to_string(),
+                proxy: options.proxy.clone(),
+                sha256,
+                size: None,
+                model_id: None,
+            });
+        } else {
+            copy_from_mirror(asset, &archive_path, sha256.as_deref(), &cancel_token).await?;
+        }
+        archives.push(archive_path);
+    }
+
+    if !remote_items.is_empty() {
+        _download_files_internal(
+            app,
+            &remote_items,
+            &options.headers,
+            task_id,
+            true,
+            cancel_token,
+        )
+        .await?;
+    }
+    Ok(archives)
+}
+
+async fn copy_from_mirror(
+    asset: &BackendAsset,
+    archive_path: &Path,
+    sha256: Option<&str>,
+    cancel_token: &CancellationToken,
+) -> Result<(), String> {
+    let source = local_mirror_path(&asset.url);
+    log::info!(
+        "Copying {} from local mirror {}",
+        asset.name,
+        source.display()
+    );
+    tokio::fs::copy(&source, archive_path)
+        .await
+        .map_err(|e| format!("Failed to read {} from mirror: {e}", source.display()))?;
+
+    if let Some(expected) = sha256 {
+        let computed =
+            jan_utils::crypto::compute_file_sha256_with_cancellation(archive_path, cancel_token)
+                .await?;
+        if computed != expected {
+            let _ = tokio::fs::remove_file(archive_path).await;
+            return Err(format!(
+                "Hash verification failed for {}. Expected: {expected}, Computed: {computed}",
+                asset.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Extract the archives into a fresh staging directory and make sure the
+/// resulting `llama-server` runs. Returns the reported server version.
+async fn stage_backend<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    assets: &[BackendAsset],
+    archives: &[PathBuf],
+    staging_dir: &Path,
+) -> Result<String, String> {
+    if staging_dir.exists() {
+        std::fs::remove_dir_all(staging_dir).map_err(err_to_string)?;
+    }
+
+    for (asset, archive) in assets.iter().zip(archives) {
+        let output_dir = match &asset.extract_subdir {
+            Some(subdir) => staging_dir.join(subdir),
+            None => staging_dir.to_path_buf(),
+        };
+        let archive = archive.to_string_lossy().to_string();
+        let output_dir = output_dir.to_string_lossy().to_string();
+        let app = app.clone();
+        tokio::task::spawn_blocking(move || decompress(app, &archive, &output_dir))
+            .await
+            .map_err(err_to_string)?
+            .map_err(|e| format!("Failed to extract {}: {e}", asset.name))?;
+    }
+
+    let server_path = find_server_binary(staging_dir)
+        .ok_or_else(|| "Archive does not contain llama-server".to_string())?;
+    tauri_plugin_llamacpp::get_version_from_backend(&server_path.to_string_lossy(), HashMap::new())
+        .await
+        .map_err(|e| format!("llama-server failed to start: {e}"))
+}
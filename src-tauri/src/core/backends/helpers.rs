@@ -0,0 +1,243 @@
+use super::models::{BackendAsset, BackendSource, InstallBackendOptions};
+use crate::core::downloads::helpers::err_to_string;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const GITHUB_RELEASES_API: &str = "https://api.github.com/repos/janhq/llama.cpp/releases";
+const GITHUB_DOWNLOAD_URL: &str = "https://github.com/janhq/llama.cpp/releases/download";
+const CDN_DOWNLOAD_URL: &str = "https://catalog.jan.ai/llama.cpp/releases";
+
+pub fn backends_root(jan_data_folder: &Path) -> PathBuf {
+    jan_data_folder.join("llamacpp").join("backends")
+}
+
+/// Archives are kept here between attempts so interrupted downloads can resume.
+pub fn backend_downloads_dir(jan_data_folder: &Path) -> PathBuf {
+    backends_root(jan_data_folder).join(".downloads")
+}
+
+/// Reject anything that could escape `backends/<version>/<backend>`.
+pub fn validate_path_component(kind: &str, value: &str) -> Result<(), String> {
+    if value.is_empty()
+        || value == "."
+        || value.contains("..")
+        || value.contains('/')
+        || value.contains('\\')
+    {
+        return Err(format!("Invalid backend {kind}: '{value}'"));
+    }
+    Ok(())
+}
+
+pub fn backend_asset_name(version: &str, backend: &str) -> String {
+    format!("llama-{version}-bin-{backend}.tar.gz")
+}
+
+/// CUDA backends need the matching cudart runtime shipped next to `llama-server`.
+pub fn cudart_asset_name(backend: &str) -> Option<String> {
+    let cuda_version = if backend.contains("cuda-11") || backend.contains("cu11.7") {
+        "11.7"
+    } else if backend.contains("cuda-12") || backend.contains("cu12.0") {
+        "12.0"
+    } else if backend.contains("cuda-13") || backend.contains("cu13.0") {
+        "13.0"
+    } else {
+        return None;
+    };
+    let platform = if backend.starts_with("win-") {
+        "win"
+    } else {
+        "linux"
+    };
+    Some(format!(
+        "cudart-llama-bin-{platform}-cu{cuda_version}-x64.tar.gz"
+    ))
+}
+
+pub fn render_url_template(template: &str, version: &str, backend: &str, asset: &str) -> String {
+    template
+        .replace("{version}", version)
+        .replace("{backend}", backend)
+        .replace("{asset}", asset)
+}
+
+pub fn default_asset_url(source: BackendSource, version: &str, asset: &str) -> String {
+    match source {
+        BackendSource::Github => format!("{GITHUB_DOWNLOAD_URL}/{version}/{asset}"),
+        BackendSource::Cdn => format!("{CDN_DOWNLOAD_URL}/{version}/{asset}"),
+    }
+}
+
+pub fn is_remote_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// Map a non-http mirror location (plain path or `file://` URL) to a path.
+pub fn local_mirror_path(url: &str) -> PathBuf {
+    PathBuf::from(url.strip_prefix("file://").unwrap_or(url))
+}
+
+/// Every archive needed to install `backend` at `version`, in extraction order.
+pub fn resolve_backend_assets(
+    version: &str,
+    backend: &str,
+    options: &InstallBackendOptions,
+) -> Vec<BackendAsset> {
+    let asset_url = |asset: &str| match &options.url_template {
+        Some(template) => render_url_template(template, version, backend, asset),
+        None => default_asset_url(options.source, version, asset),
+    };
+
+    let main = backend_asset_name(version, backend);
+    let mut assets = vec![BackendAsset {
+        url: asset_url(&main),
+        name: main,
+        extract_subdir: None,
+    }];
+    if let Some(cudart) = cudart_asset_name(backend) {
+        assets.push(BackendAsset {
+            url: asset_url(&cudart),
+            name: cudart,
+            extract_subdir: Some("build/bin".to_string()),
+        });
+    }
+    assets
+}
+
+/// Extract a SHA-256 digest from a GitHub `digest` field (`sha256:<hex>`), a bare
+/// hex string, or `sha256sum`-style output (`<hex>  <file>`). When the text lists
+/// several files, the line naming `asset_name` wins.
+pub fn parse_sha256_digest(text: &str, asset_name: &str) -> Option<String> {
+    let is_digest = |s: &str| s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit());
+    let mut first = None;
+
+    for line in text.lines() {
+        let mut parts = line.split_whitespace();
+        let Some(token) = parts.next() else {
+            continue;
+        };
+        let token = token.strip_prefix("sha256:").unwrap_or(token);
+        if !is_digest(token) {
+            continue;
+        }
+        let digest = token.to_ascii_lowercase();
+        match parts.next().map(|name| name.trim_start_matches('*')) {
+            Some(name) if name == asset_name => return Some(digest),
+            Some(_) => {}
+            None => return Some(digest),
+        }
+        first.get_or_insert(digest);
+    }
+    first
+}
+
+/// Asset digests published on the GitHub release for `version`.
+pub async fn fetch_release_digests(
+    client: &reqwest::Client,
+    version: &str,
+) -> Result<HashMap<String, String>, String> {
+    let url = format!("{GITHUB_RELEASES_API}/tags/{version}");
+    let release: serde_json::Value = client
+        .get(&url)
+        .header("User-Agent", "jan-app")
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .await
+        .map_err(err_to_string)?
+        .error_for_status()
+        .map_err(err_to_string)?
+        .json()
+        .await
+        .map_err(err_to_string)?;
+
+    Ok(release_digests_from_json(&release))
+}
+
+pub fn release_digests_from_json(release: &serde_json::Value) -> HashMap<String, String> {
+    release
+        .get("assets")
+        .and_then(|assets| assets.as_array())
+        .map(|assets| {
+            assets
+                .iter()
+                .filter_map(|asset| {
+                    let name = asset.get("name")?.as_str()?;
+                    let digest = parse_sha256_digest(asset.get("digest")?.as_str()?, name)?;
+                    Some((name.to_string(), digest))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Look for a `<asset>.sha256` file next to the archive on the mirror.
+pub async fn fetch_sidecar_digest(
+    client: &reqwest::Client,
+    asset: &BackendAsset,
+) -> Option<String> {
+    let sidecar = format!("{}.sha256", asset.url);
+    let text = if is_remote_url(&sidecar) {
+        let response = client.get(&sidecar).send().await.ok()?;
+        if !response.status().is_success() {
+            return None;
+        }
+        response.text().await.ok()?
+    } else {
+        tokio::fs::read_to_string(local_mirror_path(&sidecar))
+            .await
+            .ok()?
+    };
+    parse_sha256_digest(&text, &asset.name)
+}
+
+/// Locate `llama-server` inside an extracted backend directory.
+pub fn find_server_binary(backend_dir: &Path) -> Option<PathBuf> {
+    let exe_name = if cfg!(windows) {
+        "llama-server.exe"
+    } else {
+        "llama-server"
+    };
+    [
+        backend_dir.join("build").join("bin").join(exe_name),
+        backend_dir.join(exe_name),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+/// Move `staging` into place at `target`. An existing install is only removed once
+/// the new one is in place, and is restored if the move fails.
+pub fn replace_backend_dir(staging: &Path, target: &Path) -> Result<(), String> {
+    let previous = target.with_file_name(format!(
+        ".{}.previous",
+        target
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default()
+    ));
+    if previous.exists() {
+        std::fs::remove_dir_all(&previous).map_err(err_to_string)?;
+    }
+
+    let had_previous = target.exists();
+    if had_previous {
+        std::fs::rename(target, &previous)
+            .map_err(|e| format!("Failed to move existing backend aside: {e}"))?;
+    }
+
+    if let Err(e) = std::fs::rename(staging, target) {
+        if had_previous {
+            if let Err(restore_err) = std::fs::rename(&previous, target) {
+                log::error!("Failed to restore previous backend install: {restore_err}");
+            }
+        }
+        return Err(format!("Failed to move backend into place: {e}"));
+    }
+
+    if had_previous {
+        if let Err(e) = std::fs::remove_dir_all(&previous) {
+            log::warn!("Failed to remove previous backend install: {e}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use crate::core::downloads::models::ProxyConfig;
+
+/// Where `install_backend` fetches release assets from when no template is given.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendSource {
+    #[default]
+    Github,
+    Cdn,
+}
+
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct InstallBackendOptions {
+    pub source: BackendSource,
+    /// Mirror override, e.g. `https://mirror.local/llama.cpp/{version}/{asset}`.
+    /// Supports `{version}`, `{backend}` and `{asset}` placeholders. A template
+    /// that is not an http(s) URL is treated as a local directory/file path.
+    pub url_template: Option<String>,
+    /// Expected SHA-256 of the backend archive. When omitted the digest is
+    /// looked up from the release metadata or a `<asset>.sha256` sidecar.
+    pub sha256: Option<String>,
+    /// Install even if no checksum could be found for an asset.
+    pub allow_unverified: bool,
+    pub proxy: Option<ProxyConfig>,
+    pub headers: std::collections::HashMap<String, String>,
+}
+
+/// A single archive that has to be fetched and extracted for a backend.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackendAsset {
+    pub name: String,
+    pub url: String,
+    /// Directory inside the backend folder the archive is extracted into.
+    pub extract_subdir: Option<String>,
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct InstalledBackendInfo {
+    pub version: String,
+    pub backend: String,
+    pub path: String,
+    pub server_path: String,
+    /// Build string reported by `llama-server --version`
+    pub server_version: String,
+}
@@ -0,0 +1,191 @@
+use super::helpers::*;
+use super::models::*;
+use crate::core::app::commands::get_jan_data_folder_path;
+use std::fs;
+use std::path::PathBuf;
+use tauri::test::mock_app;
+
+// Fresh scratch directory under the mock app's data folder
+fn test_dir(name: &str) -> PathBuf {
+    let app = mock_app();
+    let dir = get_jan_data_folder_path(app.handle().clone()).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_validate_path_component() {
+    assert!(validate_path_component("version", "b7524").is_ok());
+    assert!(validate_path_component("name", "linux-cuda-12-common_cpus-x64").is_ok());
+    assert!(validate_path_component("version", "").is_err());
+    assert!(validate_path_component("version", "..").is_err());
+    assert!(validate_path_component("name", "../evil").is_err());
+    assert!(validate_path_component("name", "a/b").is_err());
+    assert!(validate_path_component("name", "a\\b").is_err());
+}
+
+#[test]
+fn test_cudart_asset_name() {
+    assert_eq!(
+        cudart_asset_name("win-cuda-12-common_cpus-x64"),
+        Some("cudart-llama-bin-win-cu12.0-x64.tar.gz".to_string())
+    );
+    assert_eq!(
+        cudart_asset_name("linux-cuda-11-common_cpus-x64"),
+        Some("cudart-llama-bin-linux-cu11.7-x64.tar.gz".to_string())
+    );
+    assert_eq!(
+        cudart_asset_name("linux-cuda-13-common_cpus-x64"),
+        Some("cudart-llama-bin-linux-cu13.0-x64.tar.gz".to_string())
+    );
+    assert_eq!(cudart_asset_name("linux-vulkan-x64"), None);
+    assert_eq!(cudart_asset_name("macos-arm64"), None);
+}
+
+#[test]
+fn test_resolve_backend_assets_default_sources() {
+    let options = InstallBackendOptions::default();
+    let assets = resolve_backend_assets("b7524", "linux-vulkan-x64", &options);
+    assert_eq!(
+        assets,
+        vec![BackendAsset {
+            name: "llama-b7524-bin-linux-vulkan-x64.tar.gz".to_string(),
+            url: "https://github.com/janhq/llama.cpp/releases/download/b7524/llama-b7524-bin-linux-vulkan-x64.tar.gz".to_string(),
+            extract_subdir: None,
+        }]
+    );
+
+    let options = InstallBackendOptions {
+        source: BackendSource::Cdn,
+        ..Default::default()
+    };
+    let assets = resolve_backend_assets("b7524", "win-cuda-12-common_cpus-x64", &options);
+    assert_eq!(assets.len(), 2);
+    assert_eq!(
+        assets[0].url,
+        "https://catalog.jan.ai/llama.cpp/releases/b7524/llama-b7524-bin-win-cuda-12-common_cpus-x64.tar.gz"
+    );
+    assert_eq!(assets[1].name, "cudart-llama-bin-win-cu12.0-x64.tar.gz");
+    assert_eq!(assets[1].extract_subdir.as_deref(), Some("build/bin"));
+}
+
+#[test]
+fn test_resolve_backend_assets_url_template() {
+    let options = InstallBackendOptions {
+        url_template: Some("https://mirror.local/{version}/{backend}/{asset}".to_string()),
+        ..Default::default()
+    };
+    let assets = resolve_backend_assets("b7524", "linux-vulkan-x64", &options);
+    assert_eq!(
+        assets[0].url,
+        "https://mirror.local/b7524/linux-vulkan-x64/llama-b7524-bin-linux-vulkan-x64.tar.gz"
+    );
+    assert!(is_remote_url(&assets[0].url));
+
+    let options = InstallBackendOptions {
+        url_template: Some("file:///mnt/mirror/{asset}".to_string()),
+        ..Default::default()
+    };
+    let assets = resolve_backend_assets("b7524", "linux-vulkan-x64", &options);
+    assert!(!is_remote_url(&assets[0].url));
+    assert_eq!(
+        local_mirror_path(&assets[0].url),
+        std::path::PathBuf::from("/mnt/mirror/llama-b7524-bin-linux-vulkan-x64.tar.gz")
+    );
+}
+
+#[test]
+fn test_parse_sha256_digest() {
+    let digest = "a".repeat(64);
+    let other = "b".repeat(64);
+
+    assert_eq!(
+        parse_sha256_digest(&format!("sha256:{digest}"), "x.tar.gz"),
+        Some(digest.clone())
+    );
+    assert_eq!(
+        parse_sha256_digest(&digest.to_uppercase(), "x.tar.gz"),
+        Some(digest.clone())
+    );
+    // sha256sum output listing several files picks the matching one
+    let listing = format!("{other}  y.tar.gz\n{digest} *x.tar.gz\n");
+    assert_eq!(
+        parse_sha256_digest(&listing, "x.tar.gz"),
+        Some(digest.clone())
+    );
+    // falls back to the first digest when no name matches
+    assert_eq!(parse_sha256_digest(&listing, "z.tar.gz"), Some(other));
+
+    assert_eq!(parse_sha256_digest("sha256:1234", "x.tar.gz"), None);
+    assert_eq!(parse_sha256_digest("", "x.tar.gz"), None);
+}
+
+#[test]
+fn test_release_digests_from_json() {
+    let digest = "c".repeat(64);
+    let release = serde_json::json!({
+        "tag_name": "b7524",
+        "assets": [
+            { "name": "llama-b7524-bin-linux-vulkan-x64.tar.gz", "digest": format!("sha256:{digest}") },
+            { "name": "no-digest.tar.gz", "digest": null },
+            { "name": "other.tar.gz" }
+        ]
+    });
+    let digests = release_digests_from_json(&release);
+    assert_eq!(digests.len(), 1);
+    assert_eq!(
+        digests.get("llama-b7524-bin-linux-vulkan-x64.tar.gz"),
+        Some(&digest)
+    );
+}
+
+#[test]
+fn test_find_server_binary() {
+    let dir = test_dir("test_find_server_binary");
+    assert!(find_server_binary(&dir).is_none());
+
+    let exe_name = if cfg!(windows) {
+        "llama-server.exe"
+    } else {
+        "llama-server"
+    };
+    let bin_dir = dir.join("build").join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    fs::write(bin_dir.join(exe_name), b"").unwrap();
+    assert_eq!(find_server_binary(&dir), Some(bin_dir.join(exe_name)));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_replace_backend_dir_swaps_existing_install() {
+    let dir = test_dir("test_replace_backend_dir_swap");
+    let target = dir.join("linux-vulkan-x64");
+    let staging = dir.join(".linux-vulkan-x64.installing");
+
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("old"), b"old").unwrap();
+    fs::create_dir_all(&staging).unwrap();
+    fs::write(staging.join("new"), b"new").unwrap();
+
+    replace_backend_dir(&staging, &target).unwrap();
+    assert!(target.join("new").exists());
+    assert!(!target.join("old").exists());
+    assert!(!staging.exists());
+    assert!(!dir.join(".linux-vulkan-x64.previous").exists());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_replace_backend_dir_keeps_existing_install_on_failure() {
+    let dir = test_dir("test_replace_backend_dir_rollback");
+    let target = dir.join("linux-vulkan-x64");
+    fs::create_dir_all(&target).unwrap();
+    fs::write(target.join("old"), b"old").unwrap();
+
+    // Missing staging directory makes the final rename fail
+    let staging = dir.join(".linux-vulkan-x64.installing");
+    assert!(replace_backend_dir(&staging, &target).is_err());
+    assert!(target.join("old").exists());
+    let _ = fs::remove_dir_all(&dir);
+}
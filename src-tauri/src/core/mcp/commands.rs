@@ -17,7 +17,7 @@ use crate::core::{
     mcp::models::ToolWithServer,
     state::{RunningServiceEnum, SharedMcpServers},
 };
-use std::{fs, time::Duration};
+use std::{collections::HashMap, fs, time::Duration};
 
 async fn tool_call_timeout(state: &State<'_, AppState>) -> Duration {
     state
@@ -150,6 +150,14 @@ pub async fn get_connected_servers(
     Ok(servers_map.keys().cloned().collect())
 }
 
+/// PIDs of running stdio MCP servers, keyed by server name
+#[tauri::command]
+pub async fn get_mcp_server_pids(
+    state: State<'_, AppState>,
+) -> Result<HashMap<String, u32>, String> {
+    Ok(state.mcp_server_pids.lock().await.clone())
+}
+
 /// Retrieves all available tools from all MCP servers with server information
 ///
 /// # Arguments
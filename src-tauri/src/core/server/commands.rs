@@ -2,6 +2,7 @@ use tauri::{AppHandle, Manager, Runtime, State};
 use tauri_plugin_llamacpp::state::LlamacppState;
 
 use crate::core::server::proxy;
+use crate::core::server::structured_output::StructuredOutputConfig;
 use crate::core::state::AppState;
 
 #[derive(serde::Deserialize)]
@@ -12,6 +13,8 @@ pub struct StartServerConfig {
     pub api_key: String,
     pub trusted_hosts: Vec<String>,
     pub proxy_timeout: u64,
+    #[serde(default)]
+    pub structured_output: StructuredOutputConfig,
 }
 
 #[tauri::command]
@@ -27,6 +30,7 @@ pub async fn start_server<R: Runtime>(
         api_key,
         trusted_hosts,
         proxy_timeout,
+        structured_output,
     } = config;
     let server_handle = state.server_handle.clone();
     let plugin_state: State<LlamacppState> = app_handle.state();
@@ -41,6 +45,7 @@ pub async fn start_server<R: Runtime>(
         api_key,
         vec![trusted_hosts],
         proxy_timeout,
+        structured_output,
     )
     .await
     .map_err(|e| e.to_string())?;
@@ -1,2 +1,7 @@
 pub mod commands;
 pub mod proxy;
+pub mod structured_output;
+pub mod tool_calls;
+
+#[cfg(test)]
+mod tests;
@@ -12,6 +12,8 @@ use std::sync::Arc;
 use tauri_plugin_llamacpp::LLamaBackendSession;
 use tokio::sync::Mutex;
 
+use super::structured_output::{self, SchemaError, StructuredOutputConfig, StructuredOutputPlan};
+use super::tool_calls::{self, ToolCallFormat, ToolCallStreamRewriter};
 use crate::core::state::ServerHandle;
 
 /// Configuration for the proxy server
@@ -20,6 +22,9 @@ struct ProxyConfig {
     prefix: String,
     proxy_api_key: String,
     trusted_hosts: Vec<Vec<String>>,
+    structured_output: StructuredOutputConfig,
+    /// Text tool call format of each running llama-server, keyed by session pid
+    tool_call_formats: Arc<Mutex<HashMap<i32, ToolCallFormat>>>,
 }
 
 /// Determines the final destination path based on the original request path
@@ -295,7 +300,11 @@ async fn proxy_request(
 
     let target_port: Option<i32>;
     let session_api_key: Option<String>;
-    let buffered_body: Option<Bytes>;
+    let mut buffered_body: Option<Bytes>;
+    let mut structured_output_plan: Option<StructuredOutputPlan> = None;
+    let mut is_streaming = false;
+    let mut tool_call_format: Option<ToolCallFormat> = None;
+    let mut session_identity: Option<(i32, String, String)> = None;
     let original_path = parts.uri.path();
     let destination_path = get_destination_path(original_path, &config.prefix);
 
@@ -325,7 +334,7 @@ async fn proxy_request(
             buffered_body = Some(body_bytes.clone());
 
             match serde_json::from_slice::<serde_json::Value>(&body_bytes) {
-                Ok(json_body) => {
+                Ok(mut json_body) => {
                     if let Some(model_id) = json_body.get("model").and_then(|v| v.as_str()) {
                         log::debug!("Extracted model_id: {model_id}");
                         let sessions_guard = sessions.lock().await;
@@ -353,6 +362,11 @@ async fn proxy_request(
                         {
                             target_port = Some(session.info.port);
                             session_api_key = Some(session.info.api_key.clone());
+                            session_identity = Some((
+                                session.info.pid,
+                                session.info.model_id.clone(),
+                                session.info.model_path.clone(),
+                            ));
                             log::debug!("Found session for model_id {model_id}");
                         } else {
                             log::warn!("No running session found for model_id: {model_id}");
@@ -370,6 +384,67 @@ async fn proxy_request(
                                 )))
                                 .unwrap());
                         }
+                        drop(sessions_guard);
+
+                        if destination_path == "/chat/completions" {
+                            is_streaming = json_body
+                                .get("stream")
+                                .and_then(|v| v.as_bool())
+                                .unwrap_or(false);
+                            match structured_output::prepare_chat_request(
+                                &mut json_body,
+                                &config.structured_output,
+                            ) {
+                                Ok(Some(plan)) => {
+                                    log::debug!(
+                                        "Enforcing structured output for {destination_path}"
+                                    );
+                                    match serde_json::to_vec(&json_body) {
+                                        Ok(bytes) => buffered_body = Some(Bytes::from(bytes)),
+                                        Err(e) => {
+                                            log::error!("Failed to serialize rewritten body: {e}");
+                                        }
+                                    }
+                                    structured_output_plan = Some(plan);
+                                }
+                                Ok(None) => {}
+                                Err(e) => {
+                                    log::warn!("Rejected structured output request: {e}");
+                                    let mut error_response =
+                                        Response::builder().status(StatusCode::BAD_REQUEST);
+                                    error_response = add_cors_headers_with_host_and_origin(
+                                        error_response,
+                                        &host_header,
+                                        &origin_header,
+                                        &config.trusted_hosts,
+                                    );
+                                    return Ok(error_response
+                                        .header(hyper::header::CONTENT_TYPE, "application/json")
+                                        .body(Body::from(e.to_error_body()))
+                                        .unwrap());
+                                }
+                            }
+
+                            let has_tools = json_body
+                                .get("tools")
+                                .and_then(|t| t.as_array())
+                                .is_some_and(|t| !t.is_empty());
+                            if let (true, Some((pid, model_id, model_path)), Some(port)) =
+                                (has_tools, &session_identity, target_port)
+                            {
+                                tool_call_format = Some(
+                                    resolve_tool_call_format(
+                                        &client,
+                                        &config.tool_call_formats,
+                                        *pid,
+                                        port,
+                                        session_api_key.as_deref().unwrap_or_default(),
+                                        &[model_id.as_str(), model_path.as_str()],
+                                    )
+                                    .await,
+                                );
+                            }
+                        }
                     } else {
                         log::warn!(
                             "POST body for {destination_path} is missing 'model' field or it's not a string"
@@ -570,7 +645,11 @@ async fn proxy_request(
     let mut outbound_req = client.request(method.clone(), &upstream_url);
 
     for (name, value) in headers.iter() {
-        if name != hyper::header::HOST && name != hyper::header::AUTHORIZATION {
+        // The body may have been rewritten, so let the client compute its length
+        if name != hyper::header::HOST
+            && name != hyper::header::AUTHORIZATION
+            && name != hyper::header::CONTENT_LENGTH
+        {
             outbound_req = outbound_req.header(name, value);
         }
     }
@@ -600,6 +679,55 @@ async fn proxy_request(
             .unwrap());
     };
 
+    if let Some(plan) = structured_output_plan.as_ref().filter(|plan| plan.validate) {
+        let response = match send_with_schema_validation(
+            outbound_req_with_body,
+            plan,
+            config.structured_output.max_retries,
+            tool_call_format,
+        )
+        .await
+        {
+            Ok((status, response_headers, bytes)) => {
+                let mut builder = Response::builder().status(status);
+                for (name, value) in response_headers.iter() {
+                    if !is_cors_header(name.as_str()) && name != hyper::header::CONTENT_LENGTH {
+                        builder = builder.header(name, value);
+                    }
+                }
+                add_cors_headers_with_host_and_origin(
+                    builder,
+                    &host_header,
+                    &origin_header,
+                    &config.trusted_hosts,
+                )
+                .body(Body::from(bytes))
+            }
+            Err(ValidationFailure::Upstream(error_msg)) => {
+                log::error!("{error_msg}");
+                add_cors_headers_with_host_and_origin(
+                    Response::builder().status(StatusCode::BAD_GATEWAY),
+                    &host_header,
+                    &origin_header,
+                    &config.trusted_hosts,
+                )
+                .body(Body::from(error_msg))
+            }
+            Err(ValidationFailure::Schema(e)) => {
+                log::error!("{e}");
+                add_cors_headers_with_host_and_origin(
+                    Response::builder().status(StatusCode::BAD_GATEWAY),
+                    &host_header,
+                    &origin_header,
+                    &config.trusted_hosts,
+                )
+                .header(hyper::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(e.to_error_body()))
+            }
+        };
+        return Ok(response.unwrap());
+    }
+
     match outbound_req_with_body.send().await {
         Ok(response) => {
             let status = response.status();
@@ -620,6 +748,29 @@ async fn proxy_request(
                 &config.trusted_hosts,
             );
 
+            let tool_call_format = tool_call_format.filter(|_| status.is_success());
+            if let (Some(format), false) = (tool_call_format, is_streaming) {
+                let bytes = match response.bytes().await {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        log::error!("Failed to read model response: {e}");
+                        return Ok(builder
+                            .status(StatusCode::BAD_GATEWAY)
+                            .body(Body::from(format!("Failed to read model response: {e}")))
+                            .unwrap());
+                    }
+                };
+                let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+                    Ok(mut json) if tool_calls::rewrite_completion(format, &mut json) => {
+                        log::debug!("Recovered text tool calls using {format:?} format");
+                        Body::from(json.to_string())
+                    }
+                    _ => Body::from(bytes),
+                };
+                return Ok(builder.body(body).unwrap());
+            }
+
+            let mut rewriter = tool_call_format.map(ToolCallStreamRewriter::new);
             let mut stream = response.bytes_stream();
             let (mut sender, body) = hyper::Body::channel();
 
@@ -627,6 +778,13 @@ async fn proxy_request(
                 while let Some(chunk_result) = stream.next().await {
                     match chunk_result {
                         Ok(chunk) => {
+                            let chunk = match rewriter.as_mut() {
+                                Some(rewriter) => Bytes::from(rewriter.process(&chunk)),
+                                None => chunk,
+                            };
+                            if chunk.is_empty() {
+                                continue;
+                            }
                             if sender.send_data(chunk).await.is_err() {
                                 log::debug!("Client disconnected during streaming");
                                 break;
@@ -638,6 +796,12 @@ async fn proxy_request(
                         }
                     }
                 }
+                if let Some(rewriter) = rewriter.as_mut() {
+                    let remaining = rewriter.finish();
+                    if !remaining.is_empty() {
+                        let _ = sender.send_data(Bytes::from(remaining)).await;
+                    }
+                }
                 log::debug!("Streaming complete to client");
             });
 
@@ -658,6 +822,114 @@ async fn proxy_request(
     }
 }
 
+/// Why `send_with_schema_validation` returned no completion
+enum ValidationFailure {
+    /// The model could not be reached or its response could not be read
+    Upstream(String),
+    /// No attempt produced output matching the requested schemas
+    Schema(SchemaError),
+}
+
+/// Send a non-streaming completion request and check the result against the requested
+/// schemas, regenerating it up to `max_retries` times when it does not match.
+async fn send_with_schema_validation(
+    request: reqwest::RequestBuilder,
+    plan: &StructuredOutputPlan,
+    max_retries: u32,
+    tool_call_format: Option<ToolCallFormat>,
+) -> Result<(StatusCode, hyper::HeaderMap, Bytes), ValidationFailure> {
+    let mut attempt = 0;
+    loop {
+        let attempt_request = request.try_clone().ok_or_else(|| {
+            ValidationFailure::Upstream("Request body cannot be replayed for validation".into())
+        })?;
+        let response = attempt_request.send().await.map_err(|e| {
+            ValidationFailure::Upstream(format!("Proxy request to model failed: {e}"))
+        })?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = response.bytes().await.map_err(|e| {
+            ValidationFailure::Upstream(format!("Failed to read model response: {e}"))
+        })?;
+
+        if !status.is_success() {
+            return Ok((status, headers, bytes));
+        }
+
+        let mut json = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+            Ok(json) => json,
+            Err(e) => {
+                return Err(ValidationFailure::Upstream(format!(
+                    "Model response is not valid JSON: {e}"
+                )))
+            }
+        };
+        let bytes = match tool_call_format {
+            Some(format) if tool_calls::rewrite_completion(format, &mut json) => {
+                Bytes::from(json.to_string())
+            }
+            _ => bytes,
+        };
+
+        match plan.validate_response(&json) {
+            Ok(()) => return Ok((status, headers, bytes)),
+            Err(e) if attempt < max_retries => {
+                attempt += 1;
+                log::warn!(
+                    "Structured output validation failed, retrying ({attempt}/{max_retries}): {e}"
+                );
+            }
+            Err(e) => {
+                return Err(ValidationFailure::Schema(SchemaError {
+                    message: format!(
+                        "Model output did not match the requested schema after {} attempt(s): {}",
+                        attempt + 1,
+                        e.message
+                    ),
+                    ..e
+                }))
+            }
+        }
+    }
+}
+
+/// Resolve the text tool call format of a running session, querying its chat template
+/// from llama-server once and caching the result per process.
+async fn resolve_tool_call_format(
+    client: &Client,
+    cache: &Mutex<HashMap<i32, ToolCallFormat>>,
+    pid: i32,
+    port: i32,
+    api_key: &str,
+    model_names: &[&str],
+) -> ToolCallFormat {
+    if let Some(format) = cache.lock().await.get(&pid) {
+        return *format;
+    }
+
+    let chat_template = match client
+        .get(format!("http://127.0.0.1:{port}/props"))
+        .bearer_auth(api_key)
+        .send()
+        .await
+    {
+        Ok(response) => response
+            .json::<serde_json::Value>()
+            .await
+            .ok()
+            .and_then(|props| props.get("chat_template")?.as_str().map(String::from)),
+        Err(e) => {
+            log::debug!("Failed to read chat template from llama-server: {e}");
+            None
+        }
+    };
+
+    let format = ToolCallFormat::detect(chat_template.as_deref(), model_names);
+    log::debug!("Using {format:?} tool call format for session {pid}");
+    cache.lock().await.insert(pid, format);
+    format
+}
+
 fn add_cors_headers_with_host_and_origin(
     builder: hyper::http::response::Builder,
     _host: &str,
@@ -699,6 +971,7 @@ pub async fn start_server(
     proxy_api_key: String,
     trusted_hosts: Vec<Vec<String>>,
     proxy_timeout: u64,
+    structured_output: StructuredOutputConfig,
 ) -> Result<u16, Box<dyn std::error::Error + Send + Sync>> {
     let mut handle_guard = server_handle.lock().await;
     if handle_guard.is_some() {
@@ -713,6 +986,8 @@ pub async fn start_server(
         prefix,
         proxy_api_key,
         trusted_hosts,
+        structured_output,
+        tool_call_formats: Arc::new(Mutex::new(HashMap::new())),
     };
 
     let client = Client::builder()
@@ -0,0 +1,1169 @@
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Keywords that only annotate a schema and never constrain the output
+const ANNOTATION_KEYWORDS: &[&str] = &[
+    "$schema",
+    "$id",
+    "$comment",
+    "$defs",
+    "definitions",
+    "title",
+    "description",
+    "default",
+    "examples",
+    "deprecated",
+    "readOnly",
+    "writeOnly",
+];
+
+/// Keywords that are translated into grammar rules
+const GRAMMAR_KEYWORDS: &[&str] = &[
+    "$ref",
+    "type",
+    "const",
+    "enum",
+    "anyOf",
+    "oneOf",
+    "properties",
+    "required",
+    "additionalProperties",
+    "items",
+    "prefixItems",
+    "minItems",
+    "maxItems",
+    "minLength",
+    "maxLength",
+    "format",
+];
+
+/// Keywords a grammar cannot express. They are only accepted when the output is validated,
+/// and rejected otherwise.
+const VALIDATION_KEYWORDS: &[&str] = &[
+    "minimum",
+    "maximum",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+    "multipleOf",
+    "uniqueItems",
+];
+
+const SUPPORTED_FORMATS: &[&str] = &["date", "time", "date-time", "uuid"];
+
+const SPACE_RULE: &str = r#"| " " | "\n" [ \t]{0,20}"#;
+
+/// Settings controlling how the proxy enforces structured output
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct StructuredOutputConfig {
+    /// Validate non-streaming completions against the requested `response_format` schema.
+    /// Without it, schemas using keywords a grammar cannot express are rejected. Strict tool
+    /// call arguments of non-streaming requests are always validated.
+    #[serde(default)]
+    pub validate: bool,
+    /// Number of times a completion is regenerated when it fails validation
+    #[serde(default)]
+    pub max_retries: u32,
+}
+
+/// Error raised for schemas that cannot be enforced or outputs that do not match them
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaError {
+    pub path: String,
+    pub message: String,
+}
+
+impl SchemaError {
+    fn new(path: &str, message: impl Into<String>) -> Self {
+        Self {
+            path: path.to_string(),
+            message: message.into(),
+        }
+    }
+
+    fn prefixed(mut self, prefix: &str) -> Self {
+        self.path = if self.path.is_empty() {
+            prefix.to_string()
+        } else {
+            format!("{prefix}.{}", self.path)
+        };
+        self
+    }
+
+    /// Render the error as an OpenAI-compatible error body
+    pub fn to_error_body(&self) -> String {
+        json!({
+            "error": {
+                "message": self.to_string(),
+                "type": "invalid_request_error",
+                "param": self.path,
+                "code": null,
+            }
+        })
+        .to_string()
+    }
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// Schemas extracted from a chat completion request that the response has to satisfy
+#[derive(Debug, Default, Clone)]
+pub struct StructuredOutputPlan {
+    pub response_schema: Option<Value>,
+    pub tool_schemas: HashMap<String, Value>,
+    /// Check the completion against the schemas once it is generated
+    pub validate: bool,
+}
+
+impl StructuredOutputPlan {
+    pub fn is_empty(&self) -> bool {
+        self.response_schema.is_none() && self.tool_schemas.is_empty()
+    }
+
+    /// Check the message content and strict tool call arguments of every choice
+    pub fn validate_response(&self, response: &Value) -> Result<(), SchemaError> {
+        let choices = response
+            .get("choices")
+            .and_then(|c| c.as_array())
+            .ok_or_else(|| SchemaError::new("choices", "response has no choices"))?;
+
+        for (i, choice) in choices.iter().enumerate() {
+            let path = format!("choices[{i}].message");
+            let Some(message) = choice.get("message") else {
+                continue;
+            };
+
+            let tool_calls = message
+                .get("tool_calls")
+                .and_then(|t| t.as_array())
+                .filter(|t| !t.is_empty());
+
+            if let Some(tool_calls) = tool_calls {
+                for (j, call) in tool_calls.iter().enumerate() {
+                    let call_path = format!("{path}.tool_calls[{j}].function.arguments");
+                    let name = call
+                        .pointer("/function/name")
+                        .and_then(|n| n.as_str())
+                        .unwrap_or_default();
+                    let Some(schema) = self.tool_schemas.get(name) else {
+                        continue;
+                    };
+                    let arguments = call
+                        .pointer("/function/arguments")
+                        .and_then(|a| a.as_str())
+                        .unwrap_or("{}");
+                    let value: Value = serde_json::from_str(arguments).map_err(|e| {
+                        SchemaError::new(&call_path, format!("arguments are not valid JSON: {e}"))
+                    })?;
+                    validate_against_schema(schema, &value).map_err(|e| e.prefixed(&call_path))?;
+                }
+            } else if let Some(schema) = &self.response_schema {
+                let content_path = format!("{path}.content");
+                let content = message
+                    .get("content")
+                    .and_then(|c| c.as_str())
+                    .ok_or_else(|| SchemaError::new(&content_path, "content is missing"))?;
+                let value: Value = serde_json::from_str(content).map_err(|e| {
+                    SchemaError::new(&content_path, format!("content is not valid JSON: {e}"))
+                })?;
+                validate_against_schema(schema, &value).map_err(|e| e.prefixed(&content_path))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rewrite the `response_format` of a chat completion request into a GBNF grammar and
+/// check the schemas of strict tools.
+///
+/// The grammar constrains the message content. Strict tool call arguments cannot be
+/// constrained alongside llama-server's own tool call handling, so they are validated once the
+/// completion is generated instead, which is only possible for non-streaming requests. Streamed
+/// tool call arguments are passed through unchecked.
+///
+/// Returns `Ok(None)` when the request does not ask for structured output.
+pub fn prepare_chat_request(
+    body: &mut Value,
+    config: &StructuredOutputConfig,
+) -> Result<Option<StructuredOutputPlan>, SchemaError> {
+    let mut plan = StructuredOutputPlan::default();
+    let streaming = body
+        .get("stream")
+        .and_then(|s| s.as_bool())
+        .unwrap_or(false);
+    let validate_content = config.validate && !streaming;
+
+    if let Some(response_format) = body.get("response_format").cloned() {
+        let schema = match response_format.get("type").and_then(|t| t.as_str()) {
+            Some("text") => None,
+            Some("json_object") => Some(
+                response_format
+                    .get("schema")
+                    .cloned()
+                    .unwrap_or_else(|| json!({ "type": "object", "additionalProperties": true })),
+            ),
+            Some("json_schema") => Some(
+                response_format
+                    .pointer("/json_schema/schema")
+                    .cloned()
+                    .ok_or_else(|| {
+                        SchemaError::new("response_format.json_schema.schema", "schema is required")
+                    })?,
+            ),
+            Some(other) => {
+                return Err(SchemaError::new(
+                    "response_format.type",
+                    format!("unsupported response format '{other}'"),
+                ))
+            }
+            None => return Err(SchemaError::new("response_format.type", "type is required")),
+        };
+
+        if let Some(schema) = schema {
+            if body.get("grammar").is_some() {
+                return Err(SchemaError::new(
+                    "grammar",
+                    "grammar cannot be combined with a JSON response_format",
+                ));
+            }
+            let grammar = schema_to_grammar(&schema, validate_content)
+                .map_err(|e| e.prefixed("response_format.json_schema.schema"))?;
+            if let Some(obj) = body.as_object_mut() {
+                obj.remove("response_format");
+                obj.insert("grammar".to_string(), Value::String(grammar));
+            }
+            plan.response_schema = Some(schema);
+            plan.validate = validate_content;
+        }
+    }
+
+    if let Some(tools) = body.get("tools").and_then(|t| t.as_array()) {
+        for (i, tool) in tools.iter().enumerate() {
+            let Some(function) = tool.get("function") else {
+                continue;
+            };
+            let strict = function
+                .get("strict")
+                .and_then(|s| s.as_bool())
+                .unwrap_or(false);
+            if !strict {
+                continue;
+            }
+            let path = format!("tools[{i}].function");
+            let name = function
+                .get("name")
+                .and_then(|n| n.as_str())
+                .ok_or_else(|| SchemaError::new(&path, "name is required"))?;
+            let parameters = function
+                .get("parameters")
+                .cloned()
+                .unwrap_or_else(|| json!({ "type": "object", "properties": {} }));
+            // Only checks that the schema can be enforced, the arguments are validated
+            schema_to_grammar(&parameters, !streaming)
+                .map_err(|e| e.prefixed(&format!("{path}.parameters")))?;
+            plan.tool_schemas.insert(name.to_string(), parameters);
+            plan.validate |= !streaming;
+        }
+    }
+
+    Ok(if plan.is_empty() { None } else { Some(plan) })
+}
+
+/// Convert a JSON schema into a GBNF grammar understood by llama-server.
+///
+/// Keywords that cannot be enforced by the grammar are rejected instead of being silently
+/// dropped, unless `validated` is set because the output is checked against the schema too.
+pub fn schema_to_grammar(schema: &Value, validated: bool) -> Result<String, SchemaError> {
+    let mut builder = GrammarBuilder::new(schema, validated);
+    let root = builder.visit(schema, "root", "")?;
+    if root != "root" {
+        builder.add_rule("root", &root);
+    }
+    Ok(builder.format())
+}
+
+struct GrammarBuilder<'a> {
+    root: &'a Value,
+    /// Keywords the grammar does not express are enforced by validating the output
+    validated: bool,
+    rules: Vec<(String, String)>,
+    refs: HashMap<String, String>,
+}
+
+impl<'a> GrammarBuilder<'a> {
+    fn new(root: &'a Value, validated: bool) -> Self {
+        let mut builder = Self {
+            root,
+            validated,
+            rules: Vec::new(),
+            refs: HashMap::new(),
+        };
+        builder.add_rule("space", SPACE_RULE);
+        builder
+    }
+
+    fn format(&self) -> String {
+        let mut rules: Vec<_> = self.rules.iter().collect();
+        rules.sort_by_key(|(name, _)| name != "root");
+        rules
+            .iter()
+            .map(|(name, body)| format!("{name} ::= {body}\n"))
+            .collect()
+    }
+
+    fn add_rule(&mut self, name: &str, body: &str) -> String {
+        let base = sanitize_rule_name(name);
+        let mut candidate = base.clone();
+        let mut counter = 0;
+        loop {
+            match self.rules.iter().find(|(n, _)| *n == candidate) {
+                None => {
+                    self.rules.push((candidate.clone(), body.to_string()));
+                    return candidate;
+                }
+                Some((_, existing)) if existing == body => return candidate,
+                Some(_) => {
+                    counter += 1;
+                    candidate = format!("{base}{counter}");
+                }
+            }
+        }
+    }
+
+    fn add_primitive(&mut self, name: &str) -> String {
+        let body = match name {
+            "boolean" => r#"("true" | "false") space"#,
+            "null" => r#""null" space"#,
+            "integral-part" => r#"[0] | [1-9] [0-9]{0,15}"#,
+            "decimal-part" => r#"[0-9]{1,16}"#,
+            "integer" => r#"("-"? integral-part) space"#,
+            "number" => {
+                r#"("-"? integral-part) ("." decimal-part)? ([eE] [-+]? integral-part)? space"#
+            }
+            "char" => r#"[^"\\\x7F\x00-\x1F] | [\\] (["\\bfnrt] | "u" [0-9a-fA-F]{4})"#,
+            "string" => r#""\"" char* "\"" space"#,
+            "value" => "object | array | string | number | boolean | null",
+            "object" => {
+                r#""{" space ( string ":" space value ("," space string ":" space value)* )? "}" space"#
+            }
+            "array" => r#""[" space ( value ("," space value)* )? "]" space"#,
+            "date" => {
+                r#"[0-9]{4} "-" ( "0" [1-9] | "1" [0-2] ) "-" ( "0" [1-9] | [1-2] [0-9] | "3" [0-1] )"#
+            }
+            "time" => {
+                r#"([01] [0-9] | "2" [0-3]) ":" [0-5] [0-9] ":" [0-5] [0-9] ( "." [0-9]{3} )? ( "Z" | ( "+" | "-" ) ( [01] [0-9] | "2" [0-3] ) ":" [0-5] [0-9] )"#
+            }
+            "date-time" => r#"date "T" time"#,
+            "date-string" => r#""\"" date "\"" space"#,
+            "time-string" => r#""\"" time "\"" space"#,
+            "date-time-string" => r#""\"" date-time "\"" space"#,
+            "uuid" => {
+                r#""\"" [0-9a-fA-F]{8} "-" [0-9a-fA-F]{4} "-" [0-9a-fA-F]{4} "-" [0-9a-fA-F]{4} "-" [0-9a-fA-F]{12} "\"" space"#
+            }
+            _ => unreachable!("unknown primitive {name}"),
+        };
+        let deps: &[&str] = match name {
+            "integer" => &["integral-part"],
+            "number" => &["integral-part", "decimal-part"],
+            "string" => &["char"],
+            "value" => &["object", "array", "string", "number", "boolean", "null"],
+            "object" => &["string", "value"],
+            "array" => &["value"],
+            "date-time" => &["date", "time"],
+            "date-string" => &["date"],
+            "time-string" => &["time"],
+            "date-time-string" => &["date-time"],
+            _ => &[],
+        };
+        let rule = self.add_rule(name, body);
+        for dep in deps {
+            if !self.rules.iter().any(|(n, _)| n == dep) {
+                self.add_primitive(dep);
+            }
+        }
+        rule
+    }
+
+    fn visit(&mut self, schema: &Value, name: &str, path: &str) -> Result<String, SchemaError> {
+        let obj = match schema {
+            Value::Bool(true) => return Ok(self.add_primitive("value")),
+            Value::Bool(false) => {
+                return Err(SchemaError::new(path, "a false schema cannot be satisfied"))
+            }
+            Value::Object(obj) => obj,
+            _ => {
+                return Err(SchemaError::new(
+                    path,
+                    "schema must be an object or a boolean",
+                ))
+            }
+        };
+
+        for key in obj.keys() {
+            let key = key.as_str();
+            if !ANNOTATION_KEYWORDS.contains(&key)
+                && !GRAMMAR_KEYWORDS.contains(&key)
+                && !VALIDATION_KEYWORDS.contains(&key)
+            {
+                return Err(SchemaError::new(
+                    path,
+                    format!("unsupported schema keyword '{key}'"),
+                ));
+            }
+            if VALIDATION_KEYWORDS.contains(&key) && !self.validated {
+                return Err(SchemaError::new(
+                    path,
+                    format!(
+                        "keyword '{key}' is only enforced by validating the output, which is not \
+                         enabled for this request"
+                    ),
+                ));
+            }
+        }
+
+        if let Some(reference) = obj.get("$ref") {
+            if let Some(extra) = obj
+                .keys()
+                .find(|k| *k != "$ref" && !ANNOTATION_KEYWORDS.contains(&k.as_str()))
+            {
+                return Err(SchemaError::new(
+                    path,
+                    format!("keyword '{extra}' cannot be combined with '$ref'"),
+                ));
+            }
+            let reference = reference
+                .as_str()
+                .ok_or_else(|| SchemaError::new(path, "'$ref' must be a string"))?;
+            return self.visit_ref(reference, path);
+        }
+
+        self.check_alternative_siblings(obj, path)?;
+
+        if let Some(value) = obj.get("const") {
+            let body = format!("{} space", json_literal(value));
+            return Ok(self.add_rule(name, &body));
+        }
+
+        if let Some(values) = obj.get("enum") {
+            let values = values
+                .as_array()
+                .filter(|v| !v.is_empty())
+                .ok_or_else(|| SchemaError::new(path, "'enum' must be a non-empty array"))?;
+            let alternatives: Vec<String> = values.iter().map(json_literal).collect();
+            let body = format!("({}) space", alternatives.join(" | "));
+            return Ok(self.add_rule(name, &body));
+        }
+
+        for keyword in ["anyOf", "oneOf"] {
+            if let Some(variants) = obj.get(keyword) {
+                let variants = variants
+                    .as_array()
+                    .filter(|v| !v.is_empty())
+                    .ok_or_else(|| {
+                        SchemaError::new(path, format!("'{keyword}' must be a non-empty array"))
+                    })?;
+                let mut alternatives = Vec::with_capacity(variants.len());
+                for (i, variant) in variants.iter().enumerate() {
+                    alternatives.push(self.visit(
+                        variant,
+                        &format!("{name}-{i}"),
+                        &join_path(path, &format!("{keyword}[{i}]")),
+                    )?);
+                }
+                return Ok(self.add_rule(name, &alternatives.join(" | ")));
+            }
+        }
+
+        match obj.get("type") {
+            Some(Value::String(ty)) => self.visit_type(obj, ty, name, path),
+            Some(Value::Array(types)) => {
+                let mut alternatives = Vec::with_capacity(types.len());
+                for ty in types {
+                    let ty = ty
+                        .as_str()
+                        .ok_or_else(|| SchemaError::new(path, "'type' entries must be strings"))?;
+                    alternatives.push(self.visit_type(obj, ty, &format!("{name}-{ty}"), path)?);
+                }
+                Ok(self.add_rule(name, &alternatives.join(" | ")))
+            }
+            Some(_) => Err(SchemaError::new(
+                path,
+                "'type' must be a string or an array",
+            )),
+            None if obj.contains_key("properties") || obj.contains_key("additionalProperties") => {
+                self.visit_type(obj, "object", name, path)
+            }
+            None if obj.contains_key("items") || obj.contains_key("prefixItems") => {
+                self.visit_type(obj, "array", name, path)
+            }
+            None => Ok(self.add_primitive("value")),
+        }
+    }
+
+    /// The rules for `const`, `enum`, `anyOf` and `oneOf` ignore every other keyword, so those
+    /// are rejected unless the output is validated. A `type` that all the values of `const` or
+    /// `enum` have is redundant and allowed.
+    fn check_alternative_siblings(
+        &self,
+        obj: &serde_json::Map<String, Value>,
+        path: &str,
+    ) -> Result<(), SchemaError> {
+        if self.validated {
+            return Ok(());
+        }
+        let Some(keyword) = ["const", "enum", "anyOf", "oneOf"]
+            .into_iter()
+            .find(|keyword| obj.contains_key(*keyword))
+        else {
+            return Ok(());
+        };
+        let values: Vec<&Value> = match keyword {
+            "const" => obj.get("const").into_iter().collect(),
+            "enum" => obj
+                .get("enum")
+                .and_then(|values| values.as_array())
+                .map(|values| values.iter().collect())
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        let redundant_type = |ty: &Value| {
+            !values.is_empty()
+                && values.iter().all(|value| match ty {
+                    Value::String(ty) => check_type(ty, value, path).is_ok(),
+                    Value::Array(types) => types.iter().any(|ty| {
+                        ty.as_str()
+                            .is_some_and(|ty| check_type(ty, value, path).is_ok())
+                    }),
+                    _ => false,
+                })
+        };
+        let extra = obj.iter().find(|(key, value)| {
+            let key = key.as_str();
+            key != keyword
+                && !ANNOTATION_KEYWORDS.contains(&key)
+                && !(key == "type" && redundant_type(value))
+        });
+        match extra {
+            Some((extra, _)) => Err(SchemaError::new(
+                path,
+                format!("keyword '{extra}' cannot be combined with '{keyword}'"),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_ref(&mut self, reference: &str, path: &str) -> Result<String, SchemaError> {
+        if let Some(rule) = self.refs.get(reference) {
+            return Ok(rule.clone());
+        }
+        let pointer = reference.strip_prefix('#').ok_or_else(|| {
+            SchemaError::new(
+                path,
+                format!("only local references are supported, got '{reference}'"),
+            )
+        })?;
+        let root = self.root;
+        let target = root.pointer(pointer).ok_or_else(|| {
+            SchemaError::new(path, format!("reference '{reference}' cannot be resolved"))
+        })?;
+
+        let name = pointer
+            .rsplit('/')
+            .next()
+            .filter(|n| !n.is_empty())
+            .unwrap_or("ref");
+        // Reserve the rule name first so recursive schemas terminate
+        let rule = self.add_rule(&format!("ref-{name}"), &format!("<pending {reference}>"));
+        self.refs.insert(reference.to_string(), rule.clone());
+        let body = self.visit(target, &format!("{rule}-def"), &join_path(path, "$ref"))?;
+        if let Some(entry) = self.rules.iter_mut().find(|(n, _)| *n == rule) {
+            entry.1 = body;
+        }
+        Ok(rule)
+    }
+
+    fn visit_type(
+        &mut self,
+        obj: &serde_json::Map<String, Value>,
+        ty: &str,
+        name: &str,
+        path: &str,
+    ) -> Result<String, SchemaError> {
+        match ty {
+            "object" => self.visit_object(obj, name, path),
+            "array" => self.visit_array(obj, name, path),
+            "string" => self.visit_string(obj, name, path),
+            "number" | "integer" | "boolean" | "null" => Ok(self.add_primitive(ty)),
+            other => Err(SchemaError::new(path, format!("unknown type '{other}'"))),
+        }
+    }
+
+    fn visit_object(
+        &mut self,
+        obj: &serde_json::Map<String, Value>,
+        name: &str,
+        path: &str,
+    ) -> Result<String, SchemaError> {
+        let empty = serde_json::Map::new();
+        let properties = match obj.get("properties") {
+            Some(Value::Object(props)) => props,
+            Some(_) => return Err(SchemaError::new(path, "'properties' must be an object")),
+            None => &empty,
+        };
+        let required: HashSet<&str> = match obj.get("required") {
+            Some(Value::Array(items)) => items.iter().filter_map(|v| v.as_str()).collect(),
+            Some(_) => return Err(SchemaError::new(path, "'required' must be an array")),
+            None => HashSet::new(),
+        };
+        if let Some(missing) = required.iter().find(|r| !properties.contains_key(**r)) {
+            return Err(SchemaError::new(
+                path,
+                format!("required property '{missing}' is not declared in 'properties'"),
+            ));
+        }
+
+        let mut required_kvs = Vec::new();
+        let mut optional_kvs = Vec::new();
+        for (key, prop_schema) in properties {
+            let value_rule = self.visit(
+                prop_schema,
+                &format!("{name}-{key}"),
+                &join_path(path, &format!("properties.{key}")),
+            )?;
+            let kv_body = format!(
+                "{} space \":\" space {value_rule}",
+                gbnf_literal(&Value::String(key.clone()).to_string())
+            );
+            let kv_rule = self.add_rule(&format!("{name}-{key}-kv"), &kv_body);
+            if required.contains(key.as_str()) {
+                required_kvs.push(kv_rule);
+            } else {
+                optional_kvs.push((kv_rule, false));
+            }
+        }
+
+        let additional = match obj.get("additionalProperties") {
+            None | Some(Value::Bool(false)) => None,
+            Some(Value::Bool(true)) => Some(self.add_primitive("value")),
+            Some(schema @ Value::Object(_)) => Some(self.visit(
+                schema,
+                &format!("{name}-additional"),
+                &join_path(path, "additionalProperties"),
+            )?),
+            Some(_) => {
+                return Err(SchemaError::new(
+                    path,
+                    "'additionalProperties' must be a boolean or a schema",
+                ))
+            }
+        };
+        if let Some(value_rule) = additional {
+            self.add_primitive("string");
+            let kv_rule = self.add_rule(
+                &format!("{name}-additional-kv"),
+                &format!("string \":\" space {value_rule}"),
+            );
+            optional_kvs.push((kv_rule, true));
+        }
+
+        let mut body = String::from("\"{\" space");
+        if !required_kvs.is_empty() {
+            body.push(' ');
+            body.push_str(&required_kvs.join(" \",\" space "));
+        }
+        if !optional_kvs.is_empty() {
+            let alternatives: Vec<String> = (0..optional_kvs.len())
+                .map(|i| optional_chain(&optional_kvs[i..]))
+                .collect();
+            if required_kvs.is_empty() {
+                body.push_str(&format!(" ( {} )?", alternatives.join(" | ")));
+            } else {
+                body.push_str(&format!(
+                    " ( \",\" space ( {} ) )?",
+                    alternatives.join(" | ")
+                ));
+            }
+        }
+        body.push_str(" \"}\" space");
+        Ok(self.add_rule(name, &body))
+    }
+
+    fn visit_array(
+        &mut self,
+        obj: &serde_json::Map<String, Value>,
+        name: &str,
+        path: &str,
+    ) -> Result<String, SchemaError> {
+        if let Some(prefix) = obj.get("prefixItems") {
+            let prefix = prefix
+                .as_array()
+                .ok_or_else(|| SchemaError::new(path, "'prefixItems' must be an array"))?;
+            if obj.contains_key("items") {
+                return Err(SchemaError::new(
+                    path,
+                    "'items' cannot be combined with 'prefixItems'",
+                ));
+            }
+            let mut items = Vec::with_capacity(prefix.len());
+            for (i, item) in prefix.iter().enumerate() {
+                items.push(self.visit(
+                    item,
+                    &format!("{name}-tuple-{i}"),
+                    &join_path(path, &format!("prefixItems[{i}]")),
+                )?);
+            }
+            let body = if items.is_empty() {
+                "\"[\" space \"]\" space".to_string()
+            } else {
+                format!("\"[\" space {} \"]\" space", items.join(" \",\" space "))
+            };
+            return Ok(self.add_rule(name, &body));
+        }
+
+        let item = match obj.get("items") {
+            Some(items) => self.visit(items, &format!("{name}-item"), &join_path(path, "items"))?,
+            None => self.add_primitive("value"),
+        };
+        let min_items = get_count(obj, "minItems", path)?.unwrap_or(0);
+        let max_items = get_count(obj, "maxItems", path)?;
+        if max_items.is_some_and(|max| max < min_items) {
+            return Err(SchemaError::new(
+                path,
+                "'maxItems' is smaller than 'minItems'",
+            ));
+        }
+
+        let body = match (min_items, max_items) {
+            (_, Some(0)) => "\"[\" space \"]\" space".to_string(),
+            (0, max) => format!(
+                "\"[\" space ( {item} ( \",\" space {item} ){} )? \"]\" space",
+                repetition(0, max.map(|m| m - 1))
+            ),
+            (min, max) => format!(
+                "\"[\" space {item} ( \",\" space {item} ){} \"]\" space",
+                repetition(min - 1, max.map(|m| m - 1))
+            ),
+        };
+        Ok(self.add_rule(name, &body))
+    }
+
+    fn visit_string(
+        &mut self,
+        obj: &serde_json::Map<String, Value>,
+        name: &str,
+        path: &str,
+    ) -> Result<String, SchemaError> {
+        if let Some(format) = obj.get("format") {
+            let format = format
+                .as_str()
+                .ok_or_else(|| SchemaError::new(path, "'format' must be a string"))?;
+            if !SUPPORTED_FORMATS.contains(&format) {
+                return Err(SchemaError::new(
+                    path,
+                    format!("unsupported string format '{format}'"),
+                ));
+            }
+            if obj.contains_key("minLength") || obj.contains_key("maxLength") {
+                return Err(SchemaError::new(
+                    path,
+                    "'format' cannot be combined with 'minLength' or 'maxLength'",
+                ));
+            }
+            return Ok(match format {
+                "uuid" => self.add_primitive("uuid"),
+                other => self.add_primitive(&format!("{other}-string")),
+            });
+        }
+
+        let min_length = get_count(obj, "minLength", path)?.unwrap_or(0);
+        let max_length = get_count(obj, "maxLength", path)?;
+        if min_length == 0 && max_length.is_none() {
+            return Ok(self.add_primitive("string"));
+        }
+        if max_length.is_some_and(|max| max < min_length) {
+            return Err(SchemaError::new(
+                path,
+                "'maxLength' is smaller than 'minLength'",
+            ));
+        }
+        self.add_primitive("char");
+        let body = format!(
+            "\"\\\"\" char{} \"\\\"\" space",
+            repetition(min_length, max_length)
+        );
+        Ok(self.add_rule(name, &body))
+    }
+}
+
+/// Build the tail of an object rule where every key-value pair is optional
+fn optional_chain(kvs: &[(String, bool)]) -> String {
+    let mut parts = Vec::with_capacity(kvs.len());
+    for (i, (kv, repeated)) in kvs.iter().enumerate() {
+        let comma_ref = format!("( \",\" space {kv} )");
+        if i == 0 {
+            if *repeated {
+                parts.push(format!("{kv} {comma_ref}*"));
+            } else {
+                parts.push(kv.clone());
+            }
+        } else {
+            parts.push(format!("{comma_ref}{}", if *repeated { "*" } else { "?" }));
+        }
+    }
+    parts.join(" ")
+}
+
+fn repetition(min: u64, max: Option<u64>) -> String {
+    match max {
+        Some(max) => format!("{{{min},{max}}}"),
+        None => format!("{{{min},}}"),
+    }
+}
+
+fn get_count(
+    obj: &serde_json::Map<String, Value>,
+    key: &str,
+    path: &str,
+) -> Result<Option<u64>, SchemaError> {
+    match obj.get(key) {
+        None => Ok(None),
+        Some(value) => value.as_u64().map(Some).ok_or_else(|| {
+            SchemaError::new(path, format!("'{key}' must be a non-negative integer"))
+        }),
+    }
+}
+
+fn join_path(path: &str, segment: &str) -> String {
+    if path.is_empty() {
+        segment.to_string()
+    } else {
+        format!("{path}.{segment}")
+    }
+}
+
+fn sanitize_rule_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+/// Quote a literal so it can be embedded in a GBNF rule
+fn gbnf_literal(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_literal(value: &Value) -> String {
+    gbnf_literal(&value.to_string())
+}
+
+/// Validate a value against a schema using the subset of JSON Schema accepted by
+/// [`schema_to_grammar`].
+pub fn validate_against_schema(schema: &Value, value: &Value) -> Result<(), SchemaError> {
+    validate_node(schema, schema, value, "")
+}
+
+fn validate_node(
+    root: &Value,
+    schema: &Value,
+    value: &Value,
+    path: &str,
+) -> Result<(), SchemaError> {
+    let obj = match schema {
+        Value::Bool(true) => return Ok(()),
+        Value::Bool(false) => return Err(SchemaError::new(path, "no value is allowed here")),
+        Value::Object(obj) => obj,
+        _ => {
+            return Err(SchemaError::new(
+                path,
+                "schema must be an object or a boolean",
+            ))
+        }
+    };
+
+    if let Some(reference) = obj.get("$ref").and_then(|r| r.as_str()) {
+        let target = reference
+            .strip_prefix('#')
+            .and_then(|pointer| root.pointer(pointer))
+            .ok_or_else(|| SchemaError::new(path, format!("cannot resolve '{reference}'")))?;
+        return validate_node(root, target, value, path);
+    }
+
+    if let Some(expected) = obj.get("const") {
+        if expected != value {
+            return Err(SchemaError::new(path, format!("expected {expected}")));
+        }
+    }
+
+    if let Some(values) = obj.get("enum").and_then(|e| e.as_array()) {
+        if !values.contains(value) {
+            return Err(SchemaError::new(
+                path,
+                "value is not one of the allowed values",
+            ));
+        }
+    }
+
+    if let Some(variants) = obj.get("anyOf").and_then(|v| v.as_array()) {
+        if !variants
+            .iter()
+            .any(|variant| validate_node(root, variant, value, path).is_ok())
+        {
+            return Err(SchemaError::new(
+                path,
+                "value does not match any schema in 'anyOf'",
+            ));
+        }
+    }
+
+    if let Some(variants) = obj.get("oneOf").and_then(|v| v.as_array()) {
+        let matches = variants
+            .iter()
+            .filter(|variant| validate_node(root, variant, value, path).is_ok())
+            .count();
+        if matches != 1 {
+            return Err(SchemaError::new(
+                path,
+                format!("value matches {matches} schemas in 'oneOf', expected exactly 1"),
+            ));
+        }
+    }
+
+    match obj.get("type") {
+        Some(Value::String(ty)) => check_type(ty, value, path)?,
+        Some(Value::Array(types))
+            if !types
+                .iter()
+                .filter_map(|t| t.as_str())
+                .any(|ty| check_type(ty, value, path).is_ok()) =>
+        {
+            return Err(SchemaError::new(
+                path,
+                format!("expected one of the types {}", Value::Array(types.clone())),
+            ));
+        }
+        _ => {}
+    }
+
+    match value {
+        Value::Object(map) => validate_object(root, obj, map, path),
+        Value::Array(items) => validate_array(root, obj, items, path),
+        Value::String(text) => validate_string(obj, text, path),
+        Value::Number(number) => validate_number(obj, number.as_f64().unwrap_or_default(), path),
+        _ => Ok(()),
+    }
+}
+
+fn check_type(ty: &str, value: &Value, path: &str) -> Result<(), SchemaError> {
+    let matches = match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        _ => false,
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(SchemaError::new(path, format!("expected {ty}")))
+    }
+}
+
+fn validate_object(
+    root: &Value,
+    obj: &serde_json::Map<String, Value>,
+    map: &serde_json::Map<String, Value>,
+    path: &str,
+) -> Result<(), SchemaError> {
+    let empty = serde_json::Map::new();
+    let properties = obj
+        .get("properties")
+        .and_then(|p| p.as_object())
+        .unwrap_or(&empty);
+
+    if let Some(required) = obj.get("required").and_then(|r| r.as_array()) {
+        for key in required.iter().filter_map(|k| k.as_str()) {
+            if !map.contains_key(key) {
+                return Err(SchemaError::new(
+                    path,
+                    format!("missing required property '{key}'"),
+                ));
+            }
+        }
+    }
+
+    for (key, value) in map {
+        let child_path = join_path(path, key);
+        match properties.get(key) {
+            Some(prop_schema) => validate_node(root, prop_schema, value, &child_path)?,
+            None => match obj.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    return Err(SchemaError::new(
+                        path,
+                        format!("unexpected property '{key}'"),
+                    ))
+                }
+                Some(schema @ Value::Object(_)) => validate_node(root, schema, value, &child_path)?,
+                _ => {}
+            },
+        }
+    }
+    Ok(())
+}
+
+fn validate_array(
+    root: &Value,
+    obj: &serde_json::Map<String, Value>,
+    items: &[Value],
+    path: &str,
+) -> Result<(), SchemaError> {
+    if let Some(min) = obj.get("minItems").and_then(|m| m.as_u64()) {
+        if (items.len() as u64) < min {
+            return Err(SchemaError::new(
+                path,
+                format!("expected at least {min} items"),
+            ));
+        }
+    }
+    if let Some(max) = obj.get("maxItems").and_then(|m| m.as_u64()) {
+        if (items.len() as u64) > max {
+            return Err(SchemaError::new(
+                path,
+                format!("expected at most {max} items"),
+            ));
+        }
+    }
+
+    let prefix = obj
+        .get("prefixItems")
+        .and_then(|p| p.as_array())
+        .map(|p| p.as_slice())
+        .unwrap_or_default();
+    if !prefix.is_empty() && items.len() != prefix.len() {
+        return Err(SchemaError::new(
+            path,
+            format!("expected exactly {} items", prefix.len()),
+        ));
+    }
+    for (i, item) in items.iter().enumerate() {
+        let item_path = format!("{path}[{i}]");
+        if let Some(item_schema) = prefix.get(i) {
+            validate_node(root, item_schema, item, &item_path)?;
+        } else if let Some(item_schema) = obj.get("items") {
+            validate_node(root, item_schema, item, &item_path)?;
+        }
+    }
+
+    if obj.get("uniqueItems").and_then(|u| u.as_bool()) == Some(true) {
+        for (i, item) in items.iter().enumerate() {
+            if items[..i].contains(item) {
+                return Err(SchemaError::new(path, "items must be unique"));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn validate_string(
+    obj: &serde_json::Map<String, Value>,
+    text: &str,
+    path: &str,
+) -> Result<(), SchemaError> {
+    let length = text.chars().count() as u64;
+    if let Some(min) = obj.get("minLength").and_then(|m| m.as_u64()) {
+        if length < min {
+            return Err(SchemaError::new(
+                path,
+                format!("expected at least {min} characters"),
+            ));
+        }
+    }
+    if let Some(max) = obj.get("maxLength").and_then(|m| m.as_u64()) {
+        if length > max {
+            return Err(SchemaError::new(
+                path,
+                format!("expected at most {max} characters"),
+            ));
+        }
+    }
+    let valid_format = match obj.get("format").and_then(|f| f.as_str()) {
+        Some("date") => chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d").is_ok(),
+        Some("date-time") => chrono::DateTime::parse_from_rfc3339(text).is_ok(),
+        Some("time") => chrono::DateTime::parse_from_rfc3339(&format!("1970-01-01T{text}")).is_ok(),
+        Some("uuid") => uuid::Uuid::parse_str(text).is_ok(),
+        _ => true,
+    };
+    if !valid_format {
+        return Err(SchemaError::new(
+            path,
+            "string does not match the requested format",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_number(
+    obj: &serde_json::Map<String, Value>,
+    number: f64,
+    path: &str,
+) -> Result<(), SchemaError> {
+    let bound = |key: &str| obj.get(key).and_then(|v| v.as_f64());
+    if let Some(min) = bound("minimum") {
+        if number < min {
+            return Err(SchemaError::new(path, format!("expected a value >= {min}")));
+        }
+    }
+    if let Some(max) = bound("maximum") {
+        if number > max {
+            return Err(SchemaError::new(path, format!("expected a value <= {max}")));
+        }
+    }
+    if let Some(min) = bound("exclusiveMinimum") {
+        if number <= min {
+            return Err(SchemaError::new(path, format!("expected a value > {min}")));
+        }
+    }
+    if let Some(max) = bound("exclusiveMaximum") {
+        if number >= max {
+            return Err(SchemaError::new(path, format!("expected a value < {max}")));
+        }
+    }
+    if let Some(divisor) = bound("multipleOf").filter(|d| *d > 0.0) {
+        let quotient = number / divisor;
+        if (quotient - quotient.round()).abs() > 1e-9 {
+            return Err(SchemaError::new(
+                path,
+                format!("expected a multiple of {divisor}"),
+            ));
+        }
+    }
+    Ok(())
+}
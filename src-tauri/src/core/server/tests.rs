@@ -0,0 +1,608 @@
+use super::structured_output::*;
+use super::tool_calls::*;
+use serde_json::{json, Value};
+
+#[test]
+fn test_schema_to_grammar_object_with_required_and_optional() {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "name": { "type": "string" },
+            "age": { "type": "integer" }
+        },
+        "required": ["name"]
+    });
+
+    let grammar = schema_to_grammar(&schema, false).unwrap();
+
+    assert!(grammar.starts_with("root ::= "));
+    assert!(grammar.contains(r#"root-name-kv ::= "\"name\"" space ":" space string"#));
+    assert!(grammar.contains(r#"root-age-kv ::= "\"age\"" space ":" space integer"#));
+    assert!(grammar
+        .contains(r#"root ::= "{" space root-name-kv ( "," space ( root-age-kv ) )? "}" space"#));
+    assert!(grammar.contains("space ::= "));
+}
+
+#[test]
+fn test_schema_to_grammar_enum_and_const() {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "unit": { "enum": ["celsius", "fahrenheit"] },
+            "kind": { "const": "weather" }
+        },
+        "required": ["unit", "kind"]
+    });
+
+    let grammar = schema_to_grammar(&schema, false).unwrap();
+
+    assert!(grammar.contains(r#"root-unit ::= ("\"celsius\"" | "\"fahrenheit\"") space"#));
+    assert!(grammar.contains(r#"root-kind ::= "\"weather\"" space"#));
+}
+
+#[test]
+fn test_schema_to_grammar_array_bounds() {
+    let schema = json!({
+        "type": "array",
+        "items": { "type": "number" },
+        "minItems": 1,
+        "maxItems": 3
+    });
+
+    let grammar = schema_to_grammar(&schema, false).unwrap();
+
+    assert!(grammar.contains(r#"root ::= "[" space number ( "," space number ){0,2} "]" space"#));
+}
+
+#[test]
+fn test_schema_to_grammar_recursive_ref() {
+    let schema = json!({
+        "$ref": "#/$defs/node",
+        "$defs": {
+            "node": {
+                "type": "object",
+                "properties": {
+                    "children": { "type": "array", "items": { "$ref": "#/$defs/node" } }
+                }
+            }
+        }
+    });
+
+    let grammar = schema_to_grammar(&schema, false).unwrap();
+
+    assert!(grammar.contains("root ::= ref-node\n"));
+    assert!(grammar.contains("ref-node ::= ref-node-def\n"));
+    assert!(
+        grammar.contains("ref-node-def-children-item ::= ref-node")
+            || grammar.contains("ref-node-def-children ::= \"[\" space ( ref-node")
+    );
+    assert!(!grammar.contains("<pending"));
+}
+
+#[test]
+fn test_schema_to_grammar_rejects_unsupported_keywords() {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "code": { "type": "string", "pattern": "^[A-Z]+$" }
+        }
+    });
+
+    let err = schema_to_grammar(&schema, false).unwrap_err();
+    assert_eq!(err.path, "properties.code");
+    assert!(err.message.contains("pattern"));
+
+    let err =
+        schema_to_grammar(&json!({ "type": "string", "format": "email" }), false).unwrap_err();
+    assert!(err.message.contains("email"));
+
+    let err = schema_to_grammar(&json!({ "$ref": "https://example.com/schema.json" }), false)
+        .unwrap_err();
+    assert!(err.message.contains("local references"));
+}
+
+#[test]
+fn test_prepare_chat_request_rewrites_response_format() {
+    let mut body = json!({
+        "model": "test",
+        "messages": [],
+        "response_format": {
+            "type": "json_schema",
+            "json_schema": {
+                "name": "answer",
+                "schema": { "type": "object", "properties": { "ok": { "type": "boolean" } }, "required": ["ok"] }
+            }
+        }
+    });
+
+    let plan = prepare_chat_request(&mut body, &StructuredOutputConfig::default())
+        .unwrap()
+        .unwrap();
+
+    assert!(body.get("response_format").is_none());
+    assert!(body["grammar"].as_str().unwrap().contains("root ::="));
+    assert!(plan.response_schema.is_some());
+    assert!(plan.tool_schemas.is_empty());
+}
+
+#[test]
+fn test_prepare_chat_request_without_structured_output() {
+    let mut body = json!({
+        "model": "test",
+        "messages": [],
+        "response_format": { "type": "text" },
+        "tools": [{ "type": "function", "function": { "name": "lookup", "parameters": { "type": "object", "not": {} } } }]
+    });
+    let original = body.clone();
+
+    assert!(
+        prepare_chat_request(&mut body, &StructuredOutputConfig::default())
+            .unwrap()
+            .is_none()
+    );
+    assert_eq!(body, original);
+}
+
+#[test]
+fn test_prepare_chat_request_rejects_invalid_strict_tool() {
+    let mut body = json!({
+        "model": "test",
+        "messages": [],
+        "tools": [{
+            "type": "function",
+            "function": {
+                "name": "lookup",
+                "strict": true,
+                "parameters": { "type": "object", "properties": { "q": { "type": "string" } }, "allOf": [] }
+            }
+        }]
+    });
+
+    let err = prepare_chat_request(&mut body, &StructuredOutputConfig::default()).unwrap_err();
+    assert_eq!(err.path, "tools[0].function.parameters");
+    assert!(err.message.contains("allOf"));
+    assert!(err.to_error_body().contains("invalid_request_error"));
+}
+
+#[test]
+fn test_prepare_chat_request_rejects_grammar_conflict() {
+    let mut body = json!({
+        "model": "test",
+        "grammar": "root ::= \"x\"",
+        "response_format": { "type": "json_object" }
+    });
+
+    let err = prepare_chat_request(&mut body, &StructuredOutputConfig::default()).unwrap_err();
+    assert_eq!(err.path, "grammar");
+}
+
+#[test]
+fn test_validate_against_schema() {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "count": { "type": "integer", "minimum": 0 },
+            "tags": { "type": "array", "items": { "type": "string" }, "uniqueItems": true },
+            "when": { "type": "string", "format": "date" }
+        },
+        "required": ["count"],
+        "additionalProperties": false
+    });
+
+    assert!(validate_against_schema(
+        &schema,
+        &json!({ "count": 2, "tags": ["a", "b"], "when": "2024-02-29" })
+    )
+    .is_ok());
+
+    let err = validate_against_schema(&schema, &json!({ "tags": [] })).unwrap_err();
+    assert!(err.message.contains("count"));
+
+    let err = validate_against_schema(&schema, &json!({ "count": -1 })).unwrap_err();
+    assert_eq!(err.path, "count");
+
+    let err =
+        validate_against_schema(&schema, &json!({ "count": 1, "tags": ["a", "a"] })).unwrap_err();
+    assert_eq!(err.path, "tags");
+
+    let err = validate_against_schema(&schema, &json!({ "count": 1, "extra": true })).unwrap_err();
+    assert!(err.message.contains("extra"));
+
+    let err =
+        validate_against_schema(&schema, &json!({ "count": 1, "when": "yesterday" })).unwrap_err();
+    assert_eq!(err.path, "when");
+}
+
+#[test]
+fn test_plan_validates_content_and_tool_arguments() {
+    let mut body = json!({
+        "model": "test",
+        "response_format": {
+            "type": "json_schema",
+            "json_schema": { "schema": { "type": "object", "properties": { "ok": { "type": "boolean" } }, "required": ["ok"] } }
+        },
+        "tools": [{
+            "type": "function",
+            "function": {
+                "name": "lookup",
+                "strict": true,
+                "parameters": { "type": "object", "properties": { "q": { "type": "string" } }, "required": ["q"] }
+            }
+        }]
+    });
+    let plan = prepare_chat_request(&mut body, &StructuredOutputConfig::default())
+        .unwrap()
+        .unwrap();
+
+    let valid_content =
+        json!({ "choices": [{ "message": { "role": "assistant", "content": "{\"ok\": true}" } }] });
+    assert!(plan.validate_response(&valid_content).is_ok());
+
+    let invalid_content = json!({ "choices": [{ "message": { "role": "assistant", "content": "sure! {\"ok\": true}" } }] });
+    let err = plan.validate_response(&invalid_content).unwrap_err();
+    assert_eq!(err.path, "choices[0].message.content");
+
+    let invalid_call = json!({ "choices": [{ "message": {
+        "role": "assistant",
+        "content": null,
+        "tool_calls": [{ "type": "function", "function": { "name": "lookup", "arguments": "{\"query\": \"x\"}" } }]
+    } }] });
+    let err = plan.validate_response(&invalid_call).unwrap_err();
+    assert_eq!(
+        err.path,
+        "choices[0].message.tool_calls[0].function.arguments"
+    );
+}
+
+#[test]
+fn test_schema_to_grammar_requires_validation_for_validation_keywords() {
+    let schema = json!({ "type": "integer", "minimum": 0 });
+
+    let err = schema_to_grammar(&schema, false).unwrap_err();
+    assert!(err.message.contains("minimum"));
+    assert!(schema_to_grammar(&schema, true).is_ok());
+
+    let mut body = json!({
+        "model": "test",
+        "response_format": { "type": "json_schema", "json_schema": { "schema": schema } }
+    });
+    let err =
+        prepare_chat_request(&mut body.clone(), &StructuredOutputConfig::default()).unwrap_err();
+    assert_eq!(err.path, "response_format.json_schema.schema");
+
+    let config = StructuredOutputConfig {
+        validate: true,
+        max_retries: 1,
+    };
+    let plan = prepare_chat_request(&mut body, &config).unwrap().unwrap();
+    assert!(plan.validate);
+}
+
+#[test]
+fn test_schema_to_grammar_rejects_siblings_of_alternatives() {
+    let schema = json!({ "type": "string", "enum": ["a", "bcde"], "maxLength": 3 });
+    let err = schema_to_grammar(&schema, false).unwrap_err();
+    assert!(err.message.contains("maxLength"));
+    assert!(schema_to_grammar(&schema, true).is_ok());
+
+    let err = schema_to_grammar(&json!({ "type": "integer", "enum": ["a"] }), false).unwrap_err();
+    assert!(err.message.contains("type"));
+
+    let err = schema_to_grammar(
+        &json!({ "anyOf": [{ "type": "string" }], "minLength": 1 }),
+        false,
+    )
+    .unwrap_err();
+    assert!(err.message.contains("anyOf"));
+
+    assert!(schema_to_grammar(
+        &json!({ "type": "string", "const": "a", "description": "fixed" }),
+        false
+    )
+    .is_ok());
+}
+
+#[test]
+fn test_plan_validates_strict_tools_unless_streaming() {
+    let mut body = json!({
+        "model": "test",
+        "tools": [{
+            "type": "function",
+            "function": {
+                "name": "lookup",
+                "strict": true,
+                "parameters": {
+                    "type": "object",
+                    "properties": { "limit": { "type": "integer", "maximum": 10 } }
+                }
+            }
+        }]
+    });
+    let config = StructuredOutputConfig::default();
+
+    let plan = prepare_chat_request(&mut body.clone(), &config)
+        .unwrap()
+        .unwrap();
+    assert!(plan.validate);
+
+    body["stream"] = json!(true);
+    let err = prepare_chat_request(&mut body, &config).unwrap_err();
+    assert!(err.message.contains("maximum"));
+}
+
+fn sse_chunk(content: &str) -> String {
+    let chunk = json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion.chunk",
+        "created": 1,
+        "model": "test",
+        "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }]
+    });
+    format!("data: {chunk}\n\n")
+}
+
+fn sse_finish() -> String {
+    let chunk = json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion.chunk",
+        "created": 1,
+        "model": "test",
+        "choices": [{ "index": 0, "delta": {}, "finish_reason": "stop" }]
+    });
+    format!("data: {chunk}\n\n")
+}
+
+fn collect_events(output: &str) -> Vec<Value> {
+    output
+        .split("\n\n")
+        .filter_map(|event| event.strip_prefix("data: "))
+        .filter(|data| *data != "[DONE]")
+        .map(|data| serde_json::from_str(data).unwrap())
+        .collect()
+}
+
+fn run_stream(format: ToolCallFormat, pieces: &[&str]) -> Vec<Value> {
+    let mut rewriter = ToolCallStreamRewriter::new(format);
+    let mut output = Vec::new();
+    for piece in pieces {
+        output.extend(rewriter.process(sse_chunk(piece).as_bytes()));
+    }
+    output.extend(rewriter.process(sse_finish().as_bytes()));
+    output.extend(rewriter.process(b"data: [DONE]\n\n"));
+    output.extend(rewriter.finish());
+    collect_events(&String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn test_detect_format() {
+    assert_eq!(
+        ToolCallFormat::detect(Some("{% for tool in tools %}<tool_call>{% endfor %}"), &[]),
+        ToolCallFormat::Hermes
+    );
+    assert_eq!(
+        ToolCallFormat::detect(Some("[AVAILABLE_TOOLS]...[TOOL_CALLS]"), &["qwen"]),
+        ToolCallFormat::Mistral
+    );
+    assert_eq!(
+        ToolCallFormat::detect(None, &["Meta-Llama-3.1-8B-Instruct-Q4_K_M"]),
+        ToolCallFormat::Llama3
+    );
+    assert_eq!(
+        ToolCallFormat::detect(Some("{{ messages }}"), &["Qwen2.5-7B-Instruct"]),
+        ToolCallFormat::Hermes
+    );
+    assert_eq!(
+        ToolCallFormat::detect(None, &["gemma-3-4b"]),
+        ToolCallFormat::Generic
+    );
+}
+
+#[test]
+fn test_parse_hermes() {
+    let content = "Let me check.\n<tool_call>\n{\"name\": \"get_weather\", \"arguments\": {\"city\": \"Paris\"}}\n</tool_call>";
+    let (remaining, calls) = parse_tool_calls(ToolCallFormat::Hermes, content).unwrap();
+
+    assert_eq!(remaining, "Let me check.");
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].name, "get_weather");
+    assert_eq!(calls[0].arguments, r#"{"city":"Paris"}"#);
+}
+
+#[test]
+fn test_parse_qwen_multiple_calls() {
+    let content = "<tool_call>\n{\"name\": \"a\", \"arguments\": {}}\n</tool_call>\n<tool_call>\n{\"name\": \"b\", \"arguments\": \"{\\\"x\\\":1}\"}\n</tool_call>";
+    let (remaining, calls) = parse_tool_calls(ToolCallFormat::Hermes, content).unwrap();
+
+    assert!(remaining.is_empty());
+    assert_eq!(
+        calls.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+        ["a", "b"]
+    );
+    assert_eq!(calls[1].arguments, r#"{"x":1}"#);
+}
+
+#[test]
+fn test_parse_mistral() {
+    let content = "[TOOL_CALLS][{\"name\": \"search\", \"arguments\": {\"q\": \"rust\"}, \"id\": \"abc123def\"}]";
+    let (remaining, calls) = parse_tool_calls(ToolCallFormat::Mistral, content).unwrap();
+
+    assert!(remaining.is_empty());
+    assert_eq!(calls[0].name, "search");
+    assert_eq!(calls[0].id.as_deref(), Some("abc123def"));
+
+    let content = "[TOOL_CALLS]search[ARGS]{\"q\": \"rust\"}[TOOL_CALLS]open[ARGS]{\"url\": \"x\"}";
+    let (_, calls) = parse_tool_calls(ToolCallFormat::Mistral, content).unwrap();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[1].name, "open");
+    assert_eq!(calls[1].arguments, r#"{"url":"x"}"#);
+}
+
+#[test]
+fn test_parse_llama3() {
+    let content = "<|python_tag|>{\"name\": \"lookup\", \"parameters\": {\"id\": 7}}";
+    let (_, calls) = parse_tool_calls(ToolCallFormat::Llama3, content).unwrap();
+    assert_eq!(calls[0].name, "lookup");
+    assert_eq!(calls[0].arguments, r#"{"id":7}"#);
+
+    let content = "{\"name\": \"a\", \"parameters\": {}}; {\"name\": \"b\", \"parameters\": {}}";
+    let (_, calls) = parse_tool_calls(ToolCallFormat::Llama3, content).unwrap();
+    assert_eq!(calls.len(), 2);
+
+    assert!(parse_tool_calls(ToolCallFormat::Llama3, "{\"answer\": 42}").is_none());
+    assert!(parse_tool_calls(ToolCallFormat::Generic, "Just text").is_none());
+}
+
+#[test]
+fn test_rewrite_completion() {
+    let mut response = json!({
+        "choices": [{
+            "index": 0,
+            "message": {
+                "role": "assistant",
+                "content": "<tool_call>{\"name\": \"get_time\", \"arguments\": {}}</tool_call>"
+            },
+            "finish_reason": "stop"
+        }]
+    });
+
+    assert!(rewrite_completion(ToolCallFormat::Generic, &mut response));
+    let message = &response["choices"][0]["message"];
+    assert!(message["content"].is_null());
+    assert_eq!(message["tool_calls"][0]["function"]["name"], "get_time");
+    assert_eq!(message["tool_calls"][0]["type"], "function");
+    assert_eq!(response["choices"][0]["finish_reason"], "tool_calls");
+
+    let mut plain =
+        json!({ "choices": [{ "message": { "role": "assistant", "content": "Hello" } }] });
+    assert!(!rewrite_completion(ToolCallFormat::Generic, &mut plain));
+}
+
+#[test]
+fn test_stream_rewriter_hermes_split_across_chunks() {
+    let events = run_stream(
+        ToolCallFormat::Hermes,
+        &[
+            "Sure",
+            ". <tool",
+            "_call>{\"name\": \"get_weather\", ",
+            "\"arguments\": {\"city\": \"Oslo\"}}</tool_call>",
+        ],
+    );
+
+    let text: String = events
+        .iter()
+        .filter_map(|e| {
+            e.pointer("/choices/0/delta/content")
+                .and_then(|c| c.as_str())
+        })
+        .collect();
+    assert_eq!(text, "Sure. ");
+
+    let calls: Vec<&Value> = events
+        .iter()
+        .filter_map(|e| e.pointer("/choices/0/delta/tool_calls/0"))
+        .collect();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0]["index"], 0);
+    assert_eq!(calls[0]["function"]["name"], "get_weather");
+    assert_eq!(calls[0]["function"]["arguments"], r#"{"city":"Oslo"}"#);
+
+    let finish = events.iter().rev().find_map(|e| {
+        e.pointer("/choices/0/finish_reason")
+            .and_then(|f| f.as_str())
+    });
+    assert_eq!(finish, Some("tool_calls"));
+}
+
+#[test]
+fn test_stream_rewriter_mistral_and_plain_text() {
+    let events = run_stream(
+        ToolCallFormat::Mistral,
+        &["[TOOL", "_CALLS][{\"name\": \"ping\", \"arguments\": {}}]"],
+    );
+    assert_eq!(
+        events
+            .iter()
+            .filter(|e| e.pointer("/choices/0/delta/tool_calls").is_some())
+            .count(),
+        1
+    );
+
+    let events = run_stream(ToolCallFormat::Generic, &["Hello", " world"]);
+    let text: String = events
+        .iter()
+        .filter_map(|e| {
+            e.pointer("/choices/0/delta/content")
+                .and_then(|c| c.as_str())
+        })
+        .collect();
+    assert_eq!(text, "Hello world");
+    assert!(events
+        .iter()
+        .all(|e| e.pointer("/choices/0/finish_reason") != Some(&json!("tool_calls"))));
+}
+
+#[test]
+fn test_stream_rewriter_llama3_bare_json() {
+    let events = run_stream(
+        ToolCallFormat::Llama3,
+        &["{\"name\": \"lookup\", ", "\"parameters\": {\"id\": 1}}"],
+    );
+    let calls: Vec<&Value> = events
+        .iter()
+        .filter_map(|e| e.pointer("/choices/0/delta/tool_calls/0"))
+        .collect();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0]["function"]["arguments"], r#"{"id":1}"#);
+}
+
+fn streamed_text(output: Vec<u8>) -> String {
+    collect_events(&String::from_utf8(output).unwrap())
+        .iter()
+        .filter_map(|e| {
+            e.pointer("/choices/0/delta/content")
+                .and_then(|c| c.as_str())
+        })
+        .collect()
+}
+
+#[test]
+fn test_stream_rewriter_character_split_across_chunks() {
+    let event = sse_chunk("é");
+    let split = event.find('é').unwrap() + 1;
+    let mut rewriter = ToolCallStreamRewriter::new(ToolCallFormat::Generic);
+    let mut output = rewriter.process(&event.as_bytes()[..split]);
+    output.extend(rewriter.process(&event.as_bytes()[split..]));
+    output.extend(rewriter.finish());
+    assert_eq!(streamed_text(output), "é");
+}
+
+#[test]
+fn test_stream_rewriter_generic_json_reply() {
+    // JSON that cannot be a tool call is streamed as soon as that is clear
+    let mut rewriter = ToolCallStreamRewriter::new(ToolCallFormat::Generic);
+    let output = rewriter.process(sse_chunk("{\"name\": \"Oslo\", ").as_bytes());
+    assert!(output.is_empty());
+    let output = rewriter.process(sse_chunk("\"country\": \"NO\"").as_bytes());
+    assert_eq!(
+        streamed_text(output),
+        "{\"name\": \"Oslo\", \"country\": \"NO\""
+    );
+    let output = rewriter.process(sse_chunk("}").as_bytes());
+    assert_eq!(streamed_text(output), "}");
+
+    let mut rewriter = ToolCallStreamRewriter::new(ToolCallFormat::Generic);
+    let output = rewriter.process(sse_chunk("{\"answer\": 42}").as_bytes());
+    assert_eq!(streamed_text(output), "{\"answer\": 42}");
+
+    let events = run_stream(
+        ToolCallFormat::Generic,
+        &["{\"name\": \"lookup\", \"par", "ameters\": {\"id\": 1}}"],
+    );
+    let calls: Vec<&Value> = events
+        .iter()
+        .filter_map(|e| e.pointer("/choices/0/delta/tool_calls/0"))
+        .collect();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0]["function"]["name"], "lookup");
+}
@@ -0,0 +1,627 @@
+use serde_json::{json, Value};
+
+const HERMES_START: &str = "<tool_call>";
+const HERMES_END: &str = "</tool_call>";
+const MISTRAL_MARKER: &str = "[TOOL_CALLS]";
+const MISTRAL_ARGS: &str = "[ARGS]";
+const LLAMA3_PYTHON_TAG: &str = "<|python_tag|>";
+/// Keys a bare JSON tool call may have
+const BARE_CALL_KEYS: [&str; 5] = ["name", "arguments", "parameters", "id", "type"];
+
+/// Text formats models use to emit tool calls when llama-server cannot parse them natively
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolCallFormat {
+    /// `<tool_call>{"name": ..., "arguments": ...}</tool_call>`, used by Hermes and Qwen
+    Hermes,
+    /// `[TOOL_CALLS][{"name": ..., "arguments": ...}]` or `[TOOL_CALLS]name[ARGS]{...}`
+    Mistral,
+    /// `<|python_tag|>{"name": ..., "parameters": ...}` or a bare JSON object
+    Llama3,
+    /// Unknown model family, every format is tried in turn
+    Generic,
+}
+
+impl ToolCallFormat {
+    /// Detect the format from the markers a chat template renders tool calls with
+    pub fn from_chat_template(template: &str) -> Option<Self> {
+        if template.contains(HERMES_START) {
+            Some(Self::Hermes)
+        } else if template.contains(MISTRAL_MARKER) {
+            Some(Self::Mistral)
+        } else if template.contains(LLAMA3_PYTHON_TAG) || template.contains("ipython") {
+            Some(Self::Llama3)
+        } else {
+            None
+        }
+    }
+
+    /// Guess the format from a model id or GGUF file name
+    pub fn from_model_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase().replace(['_', ' '], "-");
+        if name.contains("hermes") || name.contains("qwen") {
+            Some(Self::Hermes)
+        } else if name.contains("mistral") || name.contains("mixtral") || name.contains("devstral")
+        {
+            Some(Self::Mistral)
+        } else if name.contains("llama-3") || name.contains("llama3") {
+            Some(Self::Llama3)
+        } else {
+            None
+        }
+    }
+
+    /// Resolve the format, preferring the chat template over the model name
+    pub fn detect(chat_template: Option<&str>, model_names: &[&str]) -> Self {
+        chat_template
+            .and_then(Self::from_chat_template)
+            .or_else(|| model_names.iter().find_map(|n| Self::from_model_name(n)))
+            .unwrap_or(Self::Generic)
+    }
+
+    fn uses_hermes(self) -> bool {
+        matches!(self, Self::Hermes | Self::Generic)
+    }
+
+    fn uses_mistral(self) -> bool {
+        matches!(self, Self::Mistral | Self::Generic)
+    }
+
+    fn uses_llama3(self) -> bool {
+        matches!(self, Self::Llama3 | Self::Generic)
+    }
+
+    fn markers(self) -> Vec<&'static str> {
+        let mut markers = Vec::new();
+        if self.uses_hermes() {
+            markers.push(HERMES_START);
+        }
+        if self.uses_mistral() {
+            markers.push(MISTRAL_MARKER);
+        }
+        if self.uses_llama3() {
+            markers.push(LLAMA3_PYTHON_TAG);
+        }
+        markers
+    }
+}
+
+type ToolCallParser = fn(&str) -> Option<(String, Vec<ParsedToolCall>)>;
+
+/// A tool call recovered from plain text content
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedToolCall {
+    pub id: Option<String>,
+    pub name: String,
+    /// JSON-encoded arguments, as OpenAI clients expect
+    pub arguments: String,
+}
+
+impl ParsedToolCall {
+    fn from_json(value: &Value) -> Option<Self> {
+        let name = value.get("name")?.as_str()?.to_string();
+        let arguments = match value.get("arguments").or_else(|| value.get("parameters")) {
+            Some(Value::String(s)) => s.clone(),
+            Some(args) => args.to_string(),
+            None => "{}".to_string(),
+        };
+        let id = value.get("id").and_then(|i| i.as_str()).map(String::from);
+        Some(Self {
+            id,
+            name,
+            arguments,
+        })
+    }
+
+    fn to_openai(&self, index: Option<usize>) -> Value {
+        let id = self
+            .id
+            .clone()
+            .unwrap_or_else(|| format!("call_{}", uuid::Uuid::new_v4().simple()));
+        let mut call = json!({
+            "id": id,
+            "type": "function",
+            "function": { "name": self.name, "arguments": self.arguments },
+        });
+        if let Some(index) = index {
+            call["index"] = json!(index);
+        }
+        call
+    }
+}
+
+/// Extract tool calls from message content.
+///
+/// Returns the content left over once the tool call blocks are removed, or `None`
+/// when the content does not contain any tool call in the given format.
+pub fn parse_tool_calls(
+    format: ToolCallFormat,
+    content: &str,
+) -> Option<(String, Vec<ParsedToolCall>)> {
+    let parsers: [(bool, ToolCallParser); 3] = [
+        (format.uses_hermes(), parse_hermes),
+        (format.uses_mistral(), parse_mistral),
+        (format.uses_llama3(), parse_llama3),
+    ];
+    parsers
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .find_map(|(_, parser)| parser(content).filter(|(_, calls)| !calls.is_empty()))
+}
+
+fn parse_hermes(content: &str) -> Option<(String, Vec<ParsedToolCall>)> {
+    let mut calls = Vec::new();
+    let mut remaining = String::new();
+    let mut rest = content;
+    while let Some(start) = rest.find(HERMES_START) {
+        remaining.push_str(&rest[..start]);
+        let after = &rest[start + HERMES_START.len()..];
+        let (block, next) = match after.find(HERMES_END) {
+            Some(end) => (&after[..end], &after[end + HERMES_END.len()..]),
+            None => (after, ""),
+        };
+        let value: Value = serde_json::from_str(block.trim()).ok()?;
+        calls.push(ParsedToolCall::from_json(&value)?);
+        rest = next;
+    }
+    remaining.push_str(rest);
+    Some((remaining.trim().to_string(), calls))
+}
+
+fn parse_mistral(content: &str) -> Option<(String, Vec<ParsedToolCall>)> {
+    let start = content.find(MISTRAL_MARKER)?;
+    let remaining = content[..start].trim().to_string();
+    let mut calls = Vec::new();
+
+    for segment in content[start..].split(MISTRAL_MARKER).skip(1) {
+        let segment = segment.trim();
+        if segment.starts_with('[') || segment.starts_with('{') {
+            let value = first_json_value(segment)?.0;
+            match value {
+                Value::Array(items) => {
+                    for item in &items {
+                        calls.push(ParsedToolCall::from_json(item)?);
+                    }
+                }
+                item => calls.push(ParsedToolCall::from_json(&item)?),
+            }
+        } else {
+            // Newer templates emit `name[ARGS]{...}` per call
+            let mut rest = segment;
+            while let Some(args_at) = rest.find(MISTRAL_ARGS) {
+                let name = rest[..args_at].trim().to_string();
+                let args_text = rest[args_at + MISTRAL_ARGS.len()..].trim_start();
+                let (arguments, consumed) = first_json_value(args_text)?;
+                calls.push(ParsedToolCall {
+                    id: None,
+                    name,
+                    arguments: arguments.to_string(),
+                });
+                rest = args_text[consumed..].trim_start();
+            }
+        }
+    }
+    Some((remaining, calls))
+}
+
+fn parse_llama3(content: &str) -> Option<(String, Vec<ParsedToolCall>)> {
+    let trimmed = content.trim();
+    let (remaining, body) = match trimmed.find(LLAMA3_PYTHON_TAG) {
+        Some(at) => (
+            trimmed[..at].trim().to_string(),
+            &trimmed[at + LLAMA3_PYTHON_TAG.len()..],
+        ),
+        None => (String::new(), trimmed),
+    };
+
+    let mut calls = Vec::new();
+    let mut rest = body.trim();
+    while !rest.is_empty() {
+        let (value, consumed) = first_json_value(rest)?;
+        let call = ParsedToolCall::from_json(&value)?;
+        // Bare JSON objects only count as calls when they carry a parameters object
+        if value.get("parameters").is_none() && value.get("arguments").is_none() {
+            return None;
+        }
+        calls.push(call);
+        rest = rest[consumed..]
+            .trim_start()
+            .trim_start_matches(';')
+            .trim_start();
+    }
+    Some((remaining, calls))
+}
+
+/// Whether `text`, the start of a reply beginning with `{`, may still turn out to be a bare
+/// `{"name": ..., "arguments": ...}` tool call
+fn may_be_bare_call(text: &str) -> bool {
+    let mut stream = serde_json::Deserializer::from_str(text).into_iter::<Value>();
+    match stream.next() {
+        None => true,
+        Some(Ok(value)) => {
+            value.get("name").is_some_and(Value::is_string)
+                && (value.get("arguments").is_some() || value.get("parameters").is_some())
+        }
+        Some(Err(e)) if e.is_eof() => top_level_keys(text).iter().all(|(key, complete)| {
+            BARE_CALL_KEYS.iter().any(|known| {
+                if *complete {
+                    known == key
+                } else {
+                    known.starts_with(key.as_str())
+                }
+            })
+        }),
+        Some(Err(_)) => false,
+    }
+}
+
+/// Keys of the outermost object of a JSON prefix, each with whether it is complete
+fn top_level_keys(text: &str) -> Vec<(String, bool)> {
+    let mut keys = Vec::new();
+    let mut depth = 0usize;
+    let mut expect_key = false;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut key: Option<String> = None;
+    for c in text.chars() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+                continue;
+            } else if c == '"' {
+                in_string = false;
+                if let Some(key) = key.take() {
+                    keys.push((key, true));
+                }
+                continue;
+            }
+            if let Some(key) = key.as_mut() {
+                key.push(c);
+            }
+            continue;
+        }
+        match c {
+            '{' | '[' => {
+                depth += 1;
+                expect_key = depth == 1;
+            }
+            '}' | ']' => depth = depth.saturating_sub(1),
+            ',' => expect_key = depth == 1,
+            '"' => {
+                in_string = true;
+                if expect_key {
+                    key = Some(String::new());
+                }
+                expect_key = false;
+            }
+            _ => {}
+        }
+    }
+    if let Some(key) = key {
+        keys.push((key, false));
+    }
+    keys
+}
+
+/// Parse the leading JSON value of `text`, returning it with the number of bytes consumed
+fn first_json_value(text: &str) -> Option<(Value, usize)> {
+    let mut stream = serde_json::Deserializer::from_str(text).into_iter::<Value>();
+    let value = stream.next()?.ok()?;
+    Some((value, stream.byte_offset()))
+}
+
+/// Rewrite a non-streaming chat completion so text tool calls become `tool_calls`.
+///
+/// Returns `true` when the response was modified.
+pub fn rewrite_completion(format: ToolCallFormat, response: &mut Value) -> bool {
+    let Some(choices) = response.get_mut("choices").and_then(|c| c.as_array_mut()) else {
+        return false;
+    };
+    let mut modified = false;
+    for choice in choices {
+        let Some(message) = choice.get_mut("message") else {
+            continue;
+        };
+        let has_native_calls = message
+            .get("tool_calls")
+            .and_then(|t| t.as_array())
+            .is_some_and(|t| !t.is_empty());
+        if has_native_calls {
+            continue;
+        }
+        let Some(content) = message.get("content").and_then(|c| c.as_str()) else {
+            continue;
+        };
+        let Some((remaining, calls)) = parse_tool_calls(format, content) else {
+            continue;
+        };
+        message["content"] = if remaining.is_empty() {
+            Value::Null
+        } else {
+            Value::String(remaining)
+        };
+        message["tool_calls"] =
+            Value::Array(calls.iter().map(|call| call.to_openai(None)).collect());
+        choice["finish_reason"] = json!("tool_calls");
+        modified = true;
+    }
+    modified
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamMode {
+    /// Content is forwarded as it arrives
+    Text,
+    /// Inside a `<tool_call>` block, waiting for the closing tag
+    InBlock,
+    /// A reply starting with `{`, held while it may still be a bare JSON tool call
+    BareCall,
+    /// Tool calls without a closing marker, held until the stream ends
+    HoldToEnd,
+}
+
+/// Rewrites a server-sent event stream of chat completion chunks, turning tool calls
+/// written as text into `tool_calls` deltas.
+pub struct ToolCallStreamRewriter {
+    format: ToolCallFormat,
+    mode: StreamMode,
+    /// Raw bytes of an event that has not been terminated yet. Kept undecoded, as a chunk may
+    /// end in the middle of a UTF-8 character.
+    pending_event: Vec<u8>,
+    /// Content that has been received but not forwarded yet
+    content: String,
+    /// Whether any non-whitespace content has been forwarded
+    started: bool,
+    emitted_calls: usize,
+    template: Option<Value>,
+    finished: bool,
+}
+
+impl ToolCallStreamRewriter {
+    pub fn new(format: ToolCallFormat) -> Self {
+        Self {
+            format,
+            mode: StreamMode::Text,
+            pending_event: Vec::new(),
+            content: String::new(),
+            started: false,
+            emitted_calls: 0,
+            template: None,
+            finished: false,
+        }
+    }
+
+    /// Process a chunk of upstream bytes and return the bytes to forward to the client
+    pub fn process(&mut self, chunk: &[u8]) -> Vec<u8> {
+        // Drop the `\r` of `\r\n` line endings, which may be split across chunks too
+        let start = self.pending_event.len().saturating_sub(1);
+        let mut received = self.pending_event.split_off(start);
+        received.extend_from_slice(chunk);
+        for (i, byte) in received.iter().enumerate() {
+            if !(*byte == b'\r' && received.get(i + 1) == Some(&b'\n')) {
+                self.pending_event.push(*byte);
+            }
+        }
+
+        let mut output = String::new();
+        while let Some(end) = self
+            .pending_event
+            .windows(2)
+            .position(|pair| pair == b"\n\n")
+        {
+            let event: Vec<u8> = self.pending_event.drain(..end + 2).collect();
+            self.process_event(&String::from_utf8_lossy(&event), &mut output);
+        }
+        output.into_bytes()
+    }
+
+    /// Flush anything still buffered once the upstream stream has ended
+    pub fn finish(&mut self) -> Vec<u8> {
+        let mut output = String::new();
+        let event = String::from_utf8_lossy(&std::mem::take(&mut self.pending_event)).into_owned();
+        if !event.trim().is_empty() {
+            self.process_event(&event, &mut output);
+        }
+        self.flush(&mut output);
+        output.into_bytes()
+    }
+
+    fn process_event(&mut self, event: &str, output: &mut String) {
+        let data = event
+            .lines()
+            .find_map(|line| line.strip_prefix("data:"))
+            .map(str::trim);
+
+        let Some(data) = data else {
+            output.push_str(event);
+            return;
+        };
+        if data == "[DONE]" {
+            self.flush(output);
+            output.push_str(event);
+            return;
+        }
+        let Ok(mut chunk) = serde_json::from_str::<Value>(data) else {
+            output.push_str(event);
+            return;
+        };
+
+        let mut template = chunk.clone();
+        if let Some(obj) = template.as_object_mut() {
+            obj.remove("usage");
+            obj.remove("timings");
+        }
+        self.template = Some(template);
+
+        let content = chunk
+            .pointer("/choices/0/delta/content")
+            .and_then(|c| c.as_str())
+            .map(String::from);
+        let finish_reason = chunk
+            .pointer("/choices/0/finish_reason")
+            .filter(|f| !f.is_null())
+            .cloned();
+
+        if let Some(content) = &content {
+            self.content.push_str(content);
+            self.advance(output);
+        }
+
+        if finish_reason.is_some() {
+            self.flush(output);
+            if self.emitted_calls > 0 {
+                chunk["choices"][0]["finish_reason"] = json!("tool_calls");
+            }
+        }
+
+        // Content has been re-emitted above, forward the chunk only if it carries more
+        let mut forward = finish_reason.is_some() || chunk.get("usage").is_some();
+        match chunk
+            .pointer_mut("/choices/0/delta")
+            .and_then(|d| d.as_object_mut())
+        {
+            Some(delta) => {
+                if content.is_some() {
+                    delta.remove("content");
+                }
+                forward |= !delta.is_empty();
+            }
+            None => forward = true,
+        }
+        if forward {
+            push_event(output, &chunk);
+        }
+    }
+
+    /// Forward as much buffered content as possible without splitting a tool call
+    fn advance(&mut self, output: &mut String) {
+        loop {
+            match self.mode {
+                StreamMode::Text => {
+                    if !self.started {
+                        let trimmed = self.content.trim_start();
+                        if trimmed.is_empty() {
+                            return;
+                        }
+                        if self.format.uses_llama3() && trimmed.starts_with('{') {
+                            self.mode = StreamMode::BareCall;
+                            continue;
+                        }
+                    }
+
+                    let markers = self.format.markers();
+                    let found = markers
+                        .iter()
+                        .filter_map(|m| self.content.find(m).map(|at| (at, *m)))
+                        .min_by_key(|(at, _)| *at);
+                    if let Some((at, marker)) = found {
+                        let text: String = self.content.drain(..at).collect();
+                        self.emit_text(&text, output);
+                        self.mode = if marker == HERMES_START {
+                            StreamMode::InBlock
+                        } else {
+                            StreamMode::HoldToEnd
+                        };
+                        continue;
+                    }
+
+                    // Keep back a suffix that could be the beginning of a marker
+                    let hold = markers
+                        .iter()
+                        .map(|m| partial_marker_len(&self.content, m))
+                        .max()
+                        .unwrap_or(0);
+                    let forward = self.content.len() - hold;
+                    let text: String = self.content.drain(..forward).collect();
+                    self.emit_text(&text, output);
+                    return;
+                }
+                StreamMode::InBlock => {
+                    let Some(end) = self.content.find(HERMES_END) else {
+                        return;
+                    };
+                    let block: String = self.content.drain(..end + HERMES_END.len()).collect();
+                    match parse_hermes(&block) {
+                        Some((_, calls)) if !calls.is_empty() => self.emit_calls(&calls, output),
+                        _ => self.emit_text(&block, output),
+                    }
+                    self.mode = StreamMode::Text;
+                }
+                StreamMode::BareCall => {
+                    if may_be_bare_call(self.content.trim_start()) {
+                        return;
+                    }
+                    // Not a tool call after all, so it is forwarded as text from now on
+                    self.started = true;
+                    self.mode = StreamMode::Text;
+                }
+                StreamMode::HoldToEnd => return,
+            }
+        }
+    }
+
+    fn flush(&mut self, output: &mut String) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        let content = std::mem::take(&mut self.content);
+        if content.is_empty() {
+            return;
+        }
+        match self.mode {
+            StreamMode::Text => self.emit_text(&content, output),
+            StreamMode::InBlock | StreamMode::BareCall | StreamMode::HoldToEnd => {
+                match parse_tool_calls(self.format, &content) {
+                    Some((remaining, calls)) => {
+                        self.emit_text(&remaining, output);
+                        self.emit_calls(&calls, output);
+                    }
+                    None => self.emit_text(&content, output),
+                }
+            }
+        }
+    }
+
+    fn emit_text(&mut self, text: &str, output: &mut String) {
+        if text.is_empty() {
+            return;
+        }
+        self.started = true;
+        if let Some(chunk) = self.delta_chunk(json!({ "content": text })) {
+            push_event(output, &chunk);
+        }
+    }
+
+    fn emit_calls(&mut self, calls: &[ParsedToolCall], output: &mut String) {
+        self.started = true;
+        for call in calls {
+            let delta = json!({ "tool_calls": [call.to_openai(Some(self.emitted_calls))] });
+            self.emitted_calls += 1;
+            if let Some(chunk) = self.delta_chunk(delta) {
+                push_event(output, &chunk);
+            }
+        }
+    }
+
+    fn delta_chunk(&self, delta: Value) -> Option<Value> {
+        let mut chunk = self.template.clone()?;
+        chunk["choices"] = json!([{ "index": 0, "delta": delta, "finish_reason": null }]);
+        Some(chunk)
+    }
+}
+
+fn push_event(output: &mut String, chunk: &Value) {
+    output.push_str("data: ");
+    output.push_str(&chunk.to_string());
+    output.push_str("\n\n");
+}
+
+/// Length of the longest suffix of `text` that is a proper prefix of `marker`
+fn partial_marker_len(text: &str, marker: &str) -> usize {
+    (1..marker.len())
+        .rev()
+        .find(|&len| text.ends_with(&marker[..len]))
+        .unwrap_or(0)
+}
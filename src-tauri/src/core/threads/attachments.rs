@@ -0,0 +1,602 @@
+/*!
+   Content-Addressed Attachment Store
+
+   Files attached to messages are stored once in `attachments/<first two hex digits>/<sha256>`
+   in the Jan data folder, whichever thread they are attached to. Messages refer to them with
+   `attachment://<sha256>` URLs anywhere in their content.
+
+   `attachments/index.json` records the sniffed MIME type, size and original name of every file,
+   and the messages referencing it. The message commands keep those references up to date;
+   trashed messages keep theirs until they are deleted permanently. The index is kept in memory
+   and read again only when the file changed, so saving a message that refers to no attachment
+   costs no I/O, and one that does only writes the index when its references changed.
+   `gc_attachments` recounts the references from all stored messages, since data written by
+   other means is not tracked, and deletes files nothing refers to. Attachments are not
+   encrypted.
+*/
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+use tauri::Runtime;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use super::commands::{list_messages, list_threads};
+use super::constants::{
+    ATTACHMENTS_DIR, ATTACHMENTS_INDEX_FILE, ATTACHMENTS_TMP_DIR, MESSAGES_FILE,
+};
+use super::helpers::{read_messages_from_path, write_file_atomic};
+use super::trash::{self, now_secs, TrashKind};
+use crate::core::app::commands::get_jan_data_folder_path;
+
+pub const ATTACHMENT_URL_PREFIX: &str = "attachment://";
+const HASH_LEN: usize = 64;
+/// Bytes read to tell text from binary files
+const SNIFF_LEN: usize = 8192;
+/// Unreferenced files put more recently than this are kept by `gc_attachments`, as the
+/// message referring to them may not be saved yet
+pub const DEFAULT_GC_MIN_AGE_SECS: u64 = 24 * 60 * 60;
+
+/// Serializes read-modify-write cycles of index.json, and keeps the index of each data folder
+/// as last read or written
+static INDEX_LOCK: Mutex<BTreeMap<PathBuf, LoadedIndex>> = Mutex::new(BTreeMap::new());
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct IndexEntry {
+    mime_type: String,
+    size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    /// Seconds since the epoch of the last `put_attachment` of this file
+    added_at: u64,
+    /// Ids of the messages referencing the file, by thread id
+    #[serde(default)]
+    references: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl IndexEntry {
+    fn ref_count(&self) -> usize {
+        self.references.values().map(BTreeSet::len).sum()
+    }
+}
+
+type Index = BTreeMap<String, IndexEntry>;
+
+struct LoadedIndex {
+    /// Length and modification time of index.json when it was read or written
+    stamp: Option<(u64, SystemTime)>,
+    index: Index,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Attachment {
+    /// SHA-256 of the content, in lowercase hex
+    pub id: String,
+    /// `attachment://<id>`, to put in message content
+    pub url: String,
+    pub path: String,
+    pub mime_type: String,
+    pub size: u64,
+    pub name: Option<String>,
+    pub ref_count: usize,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct GcReport {
+    pub removed: usize,
+    pub freed_bytes: u64,
+    /// Attachments whose recorded references did not match the stored messages
+    pub recounted: usize,
+}
+
+fn attachments_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join(ATTACHMENTS_DIR)
+}
+
+fn index_path(data_dir: &Path) -> PathBuf {
+    attachments_dir(data_dir).join(ATTACHMENTS_INDEX_FILE)
+}
+
+fn is_hash(id: &str) -> bool {
+    id.len() == HASH_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+fn blob_path(data_dir: &Path, id: &str) -> PathBuf {
+    attachments_dir(data_dir).join(&id[..2]).join(id)
+}
+
+fn read_index(data_dir: &Path) -> Result<Index, String> {
+    let path = index_path(data_dir);
+    if !path.exists() {
+        return Ok(Index::new());
+    }
+    let data = fs::read(&path).map_err(|e| format!("Failed to read attachment index: {}", e))?;
+    serde_json::from_slice(&data).map_err(|e| format!("Invalid attachment index: {}", e))
+}
+
+fn write_index(data_dir: &Path, index: &Index) -> Result<(), String> {
+    let data = serde_json::to_vec_pretty(index).map_err(|e| e.to_string())?;
+    write_file_atomic(&index_path(data_dir), &data)
+}
+
+fn index_stamp(data_dir: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(index_path(data_dir)).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// Run `f` on the index, reading it again if index.json changed since it was last used, and
+/// save it if `f` returns true along with its result
+fn with_index<T>(data_dir: &Path, f: impl FnOnce(&mut Index) -> (T, bool)) -> Result<T, String> {
+    let mut indexes = INDEX_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let stamp = index_stamp(data_dir);
+    if indexes
+        .get(data_dir)
+        .map_or(true, |loaded| loaded.stamp != stamp)
+    {
+        let index = read_index(data_dir)?;
+        indexes.insert(data_dir.to_path_buf(), LoadedIndex { stamp, index });
+    }
+    let loaded = indexes
+        .get_mut(data_dir)
+        .ok_or("Attachment index not loaded")?;
+    let (result, changed) = f(&mut loaded.index);
+    if changed {
+        if let Err(e) = write_index(data_dir, &loaded.index) {
+            // The index in memory no longer matches the file
+            indexes.remove(data_dir);
+            return Err(e);
+        }
+        loaded.stamp = index_stamp(data_dir);
+    }
+    Ok(result)
+}
+
+/// Run `update` on the index and save it if it returns true
+fn update_index(data_dir: &Path, update: impl FnOnce(&mut Index) -> bool) -> Result<(), String> {
+    with_index(data_dir, |index| ((), update(index)))
+}
+
+fn to_attachment(data_dir: &Path, id: &str, entry: &IndexEntry) -> Attachment {
+    Attachment {
+        id: id.to_string(),
+        url: format!("{}{}", ATTACHMENT_URL_PREFIX, id),
+        path: blob_path(data_dir, id).to_string_lossy().to_string(),
+        mime_type: entry.mime_type.clone(),
+        size: entry.size,
+        name: entry.name.clone(),
+        ref_count: entry.ref_count(),
+    }
+}
+
+/// MIME type from the magic bytes of a file. Files without a known signature are
+/// `text/plain` if they start with UTF-8 text, `application/octet-stream` otherwise.
+pub fn sniff_mime_type(path: &Path) -> Result<String, String> {
+    if let Some(kind) = infer::get_from_path(path).map_err(|e| e.to_string())? {
+        return Ok(kind.mime_type().to_string());
+    }
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    fs::File::open(path)
+        .and_then(|file| file.take(SNIFF_LEN as u64).read_to_end(&mut head))
+        .map_err(|e| e.to_string())?;
+    // A character may be cut off at the end of the sample
+    let is_text = match std::str::from_utf8(&head) {
+        Ok(text) => !text.contains('\0'),
+        Err(e) => e.error_len().is_none(),
+    };
+    let mime_type = match is_text {
+        true => "text/plain",
+        false => "application/octet-stream",
+    };
+    Ok(mime_type.to_string())
+}
+
+/// Ids of the attachments a message refers to with `attachment://` URLs
+pub fn message_attachment_ids(message: &Value) -> HashSet<String> {
+    fn walk(value: &Value, ids: &mut HashSet<String>) {
+        match value {
+            Value::String(text) => {
+                for (start, _) in text.match_indices(ATTACHMENT_URL_PREFIX) {
+                    let rest = &text[start + ATTACHMENT_URL_PREFIX.len()..];
+                    if let Some(id) = rest.get(..HASH_LEN).filter(|id| is_hash(id)) {
+                        ids.insert(id.to_string());
+                    }
+                }
+            }
+            Value::Array(values) => values.iter().for_each(|value| walk(value, ids)),
+            Value::Object(map) => map.values().for_each(|value| walk(value, ids)),
+            _ => {}
+        }
+    }
+
+    let mut ids = HashSet::new();
+    walk(message, &mut ids);
+    ids
+}
+
+/// Copy a file into the store, unless a file with the same content is there already
+pub async fn put_file(
+    data_dir: &Path,
+    source: &Path,
+    name: Option<String>,
+) -> Result<Attachment, String> {
+    if !source.is_file() {
+        return Err(format!("Not a file: {}", source.display()));
+    }
+    let name = name.or_else(|| {
+        source
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+    });
+    put(data_dir, name, |tmp_path| {
+        fs::copy(source, tmp_path)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to copy attachment: {}", e))
+    })
+    .await
+}
+
+/// Store `data` as a file, unless a file with the same content is there already
+pub async fn put_data(
+    data_dir: &Path,
+    data: &[u8],
+    name: Option<String>,
+) -> Result<Attachment, String> {
+    put(data_dir, name, |tmp_path| {
+        fs::write(tmp_path, data).map_err(|e| format!("Failed to write attachment: {}", e))
+    })
+    .await
+}
+
+/// Store the file `write` creates at the given temporary path
+async fn put(
+    data_dir: &Path,
+    name: Option<String>,
+    write: impl FnOnce(&Path) -> Result<(), String>,
+) -> Result<Attachment, String> {
+    let tmp_dir = attachments_dir(data_dir).join(ATTACHMENTS_TMP_DIR);
+    fs::create_dir_all(&tmp_dir).map_err(|e| e.to_string())?;
+
+    // Hash the copy, so the stored content matches its name even if the source changes
+    let tmp_path = tmp_dir.join(Uuid::new_v4().to_string());
+    let result = async {
+        write(&tmp_path)?;
+        let id = jan_utils::crypto::compute_file_sha256_with_cancellation(
+            &tmp_path,
+            &CancellationToken::new(),
+        )
+        .await?;
+        let mime_type = sniff_mime_type(&tmp_path)?;
+        let size = fs::metadata(&tmp_path).map_err(|e| e.to_string())?.len();
+        Ok::<_, String>((id, mime_type, size))
+    }
+    .await;
+    let (id, mime_type, size) = match result {
+        Ok(file) => file,
+        Err(e) => {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+    };
+
+    let blob = blob_path(data_dir, &id);
+    let store_blob = || {
+        if blob.exists() {
+            let _ = fs::remove_file(&tmp_path);
+        } else {
+            fs::create_dir_all(blob.parent().ok_or("Invalid attachment path")?)
+                .map_err(|e| e.to_string())?;
+            fs::rename(&tmp_path, &blob)
+                .map_err(|e| format!("Failed to store attachment: {}", e))?;
+        }
+        Ok::<_, String>(())
+    };
+    // The file is stored with the index locked, so that `gc_attachments` cannot see it unindexed
+    let stored = with_index(data_dir, |index| {
+        if let Err(e) = store_blob() {
+            return (Err(e), false);
+        }
+        let entry = index.entry(id.clone()).or_insert_with(|| IndexEntry {
+            mime_type,
+            size,
+            name: None,
+            added_at: 0,
+            references: BTreeMap::new(),
+        });
+        entry.added_at = now_secs();
+        if entry.name.is_none() {
+            entry.name = name;
+        }
+        (Ok(to_attachment(data_dir, &id, entry)), true)
+    })
+    .and_then(|stored| stored);
+    if stored.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    stored
+}
+
+/// An attachment of the store, if it exists
+pub fn get(data_dir: &Path, id: &str) -> Result<Option<Attachment>, String> {
+    if !is_hash(id) {
+        return Err("Invalid attachment id".to_string());
+    }
+    with_index(data_dir, |index| {
+        let attachment = index
+            .get(id)
+            .filter(|_| blob_path(data_dir, id).exists())
+            .map(|entry| to_attachment(data_dir, id, entry));
+        (attachment, false)
+    })
+}
+
+/// Record the attachments a saved message refers to, replacing the ones it referred to before.
+/// Only the entries of attachments added or dropped are changed, and the index is not written
+/// when there are none.
+pub fn set_message_references(
+    data_dir: &Path,
+    thread_id: &str,
+    message_id: &str,
+    ids: &HashSet<String>,
+) -> Result<(), String> {
+    if ids.is_empty() && !index_path(data_dir).exists() {
+        return Ok(());
+    }
+
+    update_index(data_dir, |index| {
+        let recorded: HashSet<String> = index
+            .iter()
+            .filter(|(_, entry)| {
+                entry
+                    .references
+                    .get(thread_id)
+                    .is_some_and(|messages| messages.contains(message_id))
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        let mut changed = false;
+        for id in recorded.difference(ids) {
+            let Some(entry) = index.get_mut(id) else {
+                continue;
+            };
+            if let Some(messages) = entry.references.get_mut(thread_id) {
+                messages.remove(message_id);
+                if messages.is_empty() {
+                    entry.references.remove(thread_id);
+                }
+            }
+            changed = true;
+        }
+        // References to files not in the store are not recorded
+        for id in ids.difference(&recorded) {
+            if let Some(entry) = index.get_mut(id) {
+                entry
+                    .references
+                    .entry(thread_id.to_string())
+                    .or_default()
+                    .insert(message_id.to_string());
+                changed = true;
+            }
+        }
+        changed
+    })
+}
+
+/// Drop the references of a permanently deleted message, or of all messages of a thread
+/// when `message_id` is not given
+pub fn release_references(
+    data_dir: &Path,
+    thread_id: &str,
+    message_id: Option<&str>,
+) -> Result<(), String> {
+    if !index_path(data_dir).exists() {
+        return Ok(());
+    }
+    update_index(data_dir, |index| {
+        let mut changed = false;
+        for entry in index.values_mut() {
+            let Some(messages) = entry.references.get_mut(thread_id) else {
+                continue;
+            };
+            changed |= match message_id {
+                Some(message_id) => messages.remove(message_id),
+                None => !messages.is_empty(),
+            };
+            if message_id.is_none() || messages.is_empty() {
+                entry.references.remove(thread_id);
+            }
+        }
+        changed
+    })
+}
+
+pub async fn on_message_saved<R: Runtime>(app: &tauri::AppHandle<R>, message: &Value) {
+    let (Some(thread_id), Some(message_id)) = (
+        message.get("thread_id").and_then(|id| id.as_str()),
+        message.get("id").and_then(|id| id.as_str()),
+    ) else {
+        return;
+    };
+    let (thread_id, message_id) = (thread_id.to_string(), message_id.to_string());
+    let ids = message_attachment_ids(message);
+    let data_dir = get_jan_data_folder_path(app.clone());
+    // The index lock is a blocking one, and the index may have to be read or written
+    let result = tokio::task::spawn_blocking(move || {
+        set_message_references(&data_dir, &thread_id, &message_id, &ids)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+    if let Err(e) = result {
+        log::warn!("Failed to update attachment references: {}", e);
+    }
+}
+
+pub async fn on_messages_deleted<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    thread_id: &str,
+    message_id: Option<&str>,
+) {
+    let (thread_id, message_id) = (thread_id.to_string(), message_id.map(str::to_string));
+    let data_dir = get_jan_data_folder_path(app.clone());
+    let result = tokio::task::spawn_blocking(move || {
+        release_references(&data_dir, &thread_id, message_id.as_deref())
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result);
+    if let Err(e) = result {
+        log::warn!("Failed to update attachment references: {}", e);
+    }
+}
+
+/// Messages of every thread and of the trash
+async fn all_messages<R: Runtime>(app: &tauri::AppHandle<R>) -> Result<Vec<Value>, String> {
+    let mut messages = Vec::new();
+    for thread in list_threads(app.clone(), None).await? {
+        if let Some(thread_id) = thread.get("id").and_then(|id| id.as_str()) {
+            messages.extend(list_messages(app.clone(), thread_id.to_string()).await?);
+        }
+    }
+    for entry in trash::list_entries(app)? {
+        match entry.kind {
+            TrashKind::Thread => {
+                let path = trash::trashed_thread_dir(app, &entry).join(MESSAGES_FILE);
+                messages.extend(read_messages_from_path(&path)?);
+            }
+            TrashKind::Message => messages.push(trash::read_trashed_message(app, &entry)?),
+        }
+    }
+    Ok(messages)
+}
+
+fn is_older_than(path: &Path, min_age: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age >= min_age)
+}
+
+/// Recount the references of every attachment from the stored messages, then delete the
+/// files that are unreferenced and were last put at least `min_age_secs` ago, along with
+/// files missing from the index and leftovers of interrupted puts
+pub async fn collect_garbage<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    min_age_secs: u64,
+) -> Result<GcReport, String> {
+    let data_dir = get_jan_data_folder_path(app.clone());
+    let root = attachments_dir(&data_dir);
+    if !root.exists() {
+        return Ok(GcReport::default());
+    }
+
+    // References recorded while the messages are read are newer than the count
+    let before = with_index(&data_dir, |index| (index.clone(), false))?;
+    let mut references: BTreeMap<String, BTreeMap<String, BTreeSet<String>>> = BTreeMap::new();
+    for message in all_messages(app).await? {
+        let (Some(thread_id), Some(message_id)) = (
+            message.get("thread_id").and_then(|id| id.as_str()),
+            message.get("id").and_then(|id| id.as_str()),
+        ) else {
+            continue;
+        };
+        for id in message_attachment_ids(&message) {
+            references
+                .entry(id)
+                .or_default()
+                .entry(thread_id.to_string())
+                .or_default()
+                .insert(message_id.to_string());
+        }
+    }
+
+    let mut report = GcReport::default();
+    let cutoff = now_secs().saturating_sub(min_age_secs);
+    let min_age = Duration::from_secs(min_age_secs);
+    with_index(&data_dir, |index| {
+        index.retain(|id, entry| {
+            let blob = blob_path(&data_dir, id);
+            if !blob.exists() {
+                return false;
+            }
+            let counted = references.remove(id).unwrap_or_default();
+            let updated =
+                before.get(id).map(|before| &before.references) != Some(&entry.references);
+            if updated {
+                return true;
+            }
+            if counted != entry.references {
+                entry.references = counted;
+                report.recounted += 1;
+            }
+            if entry.ref_count() > 0 || entry.added_at > cutoff {
+                return true;
+            }
+            match fs::remove_file(&blob) {
+                Ok(()) => {
+                    report.removed += 1;
+                    report.freed_bytes += entry.size;
+                    false
+                }
+                Err(e) => {
+                    log::warn!("Failed to delete attachment {}: {}", id, e);
+                    true
+                }
+            }
+        });
+        // Swept with the index locked, so that files being put are not taken for strays
+        let swept = remove_unindexed(&root, index, min_age, &mut report);
+        (swept, true)
+    })??;
+    Ok(report)
+}
+
+/// Delete files without an index entry, e.g. when the app stopped between storing a file and
+/// recording it, and copies left by interrupted puts, once they are at least `min_age` old
+fn remove_unindexed(
+    root: &Path,
+    index: &Index,
+    min_age: Duration,
+    report: &mut GcReport,
+) -> Result<(), String> {
+    for dir in fs::read_dir(root).map_err(|e| e.to_string())? {
+        let dir = dir.map_err(|e| e.to_string())?.path();
+        if !dir.is_dir() {
+            continue;
+        }
+        let is_tmp = dir
+            .file_name()
+            .is_some_and(|name| name == ATTACHMENTS_TMP_DIR);
+        for file in fs::read_dir(&dir).map_err(|e| e.to_string())? {
+            let path = file.map_err(|e| e.to_string())?.path();
+            let indexed = !is_tmp
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|id| index.contains_key(id));
+            if indexed || !is_older_than(&path, min_age) {
+                continue;
+            }
+            let size = fs::metadata(&path)
+                .map(|metadata| metadata.len())
+                .unwrap_or(0);
+            if fs::remove_file(&path).is_ok() && !is_tmp {
+                report.removed += 1;
+                report.freed_bytes += size;
+            }
+        }
+        if !is_tmp {
+            let _ = fs::remove_dir(&dir);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,172 @@
+/*!
+   Message Branching
+
+   Messages may carry a `parent_id` naming the message they answer, which turns a thread into a
+   tree: regenerating a reply or editing a prompt adds a sibling instead of replacing the
+   original. `parent_id: null` marks a root. Messages without the field keep the old linear
+   model and continue the message stored before them, so existing threads are one branch.
+
+   The active branch ends at the leaf reached by starting from the thread's
+   `metadata.active_message_id` (or the newest message) and following the newest child.
+   Branching only relies on the message JSON, so it works the same for messages.jsonl and SQLite.
+*/
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Thread metadata key of the message the active branch passes through
+pub const ACTIVE_MESSAGE_KEY: &str = "active_message_id";
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct MessageBranch {
+    /// Messages of the active branch, from the root to the leaf
+    pub messages: Vec<Value>,
+    /// For each message on the branch that has alternatives, the ids of all messages
+    /// sharing its parent, itself included, oldest first
+    pub siblings: HashMap<String, Vec<String>>,
+}
+
+fn message_id(message: &Value) -> Option<&str> {
+    message.get("id").and_then(|id| id.as_str())
+}
+
+/// Index of the parent of every message, `None` for roots. A `parent_id` that names no
+/// message of the thread makes the message a root.
+pub fn parent_indices(messages: &[Value]) -> Vec<Option<usize>> {
+    let positions: HashMap<&str, usize> = messages
+        .iter()
+        .enumerate()
+        .filter_map(|(i, message)| Some((message_id(message)?, i)))
+        .collect();
+
+    messages
+        .iter()
+        .enumerate()
+        .map(|(i, message)| match message.get("parent_id") {
+            Some(Value::String(parent)) => positions.get(parent.as_str()).copied(),
+            Some(_) => None,
+            None => i.checked_sub(1),
+        })
+        .collect()
+}
+
+/// Indices of the messages from the root down to `index`
+fn path_to(parents: &[Option<usize>], index: usize) -> Vec<usize> {
+    let mut path = vec![index];
+    let mut current = index;
+    // A malformed file can contain cycles; no path is longer than the thread
+    while let Some(parent) = parents[current] {
+        if path.len() > parents.len() || path.contains(&parent) {
+            break;
+        }
+        path.push(parent);
+        current = parent;
+    }
+    path.reverse();
+    path
+}
+
+fn children_of(parents: &[Option<usize>]) -> (Vec<usize>, HashMap<usize, Vec<usize>>) {
+    let mut roots = Vec::new();
+    let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, parent) in parents.iter().enumerate() {
+        match parent {
+            Some(parent) => children.entry(*parent).or_default().push(i),
+            None => roots.push(i),
+        }
+    }
+    (roots, children)
+}
+
+/// Resolve the active branch of a thread from its messages in storage order
+pub fn active_branch(messages: Vec<Value>, active_message_id: Option<&str>) -> MessageBranch {
+    let Some(last) = messages.len().checked_sub(1) else {
+        return MessageBranch::default();
+    };
+    let parents = parent_indices(&messages);
+    let (roots, children) = children_of(&parents);
+
+    let start = active_message_id
+        .and_then(|id| messages.iter().position(|m| message_id(m) == Some(id)))
+        .unwrap_or(last);
+    let mut leaf = start;
+    let mut steps = 0;
+    while let Some(newest) = children.get(&leaf).and_then(|c| c.last()) {
+        steps += 1;
+        if steps > messages.len() {
+            break;
+        }
+        leaf = *newest;
+    }
+
+    let path = path_to(&parents, leaf);
+    let mut siblings = HashMap::new();
+    for &i in &path {
+        let group = match parents[i] {
+            Some(parent) => &children[&parent],
+            None => &roots,
+        };
+        if group.len() > 1 {
+            if let Some(id) = message_id(&messages[i]) {
+                let ids = group
+                    .iter()
+                    .filter_map(|&j| message_id(&messages[j]).map(str::to_string))
+                    .collect();
+                siblings.insert(id.to_string(), ids);
+            }
+        }
+    }
+
+    let mut messages: Vec<Option<Value>> = messages.into_iter().map(Some).collect();
+    MessageBranch {
+        messages: path.iter().filter_map(|&i| messages[i].take()).collect(),
+        siblings,
+    }
+}
+
+/// Copies of the messages from the root down to `message_id`, with new ids and linked by
+/// explicit `parent_id`s
+pub fn fork_messages(messages: &[Value], message_id: &str) -> Result<Vec<Value>, String> {
+    let index = messages
+        .iter()
+        .position(|m| self::message_id(m) == Some(message_id))
+        .ok_or_else(|| format!("Message not found: {}", message_id))?;
+    let parents = parent_indices(messages);
+
+    let mut parent = Value::Null;
+    Ok(path_to(&parents, index)
+        .into_iter()
+        .map(|i| {
+            let mut message = messages[i].clone();
+            let id = Value::String(Uuid::new_v4().to_string());
+            message["id"] = id.clone();
+            message["parent_id"] = std::mem::replace(&mut parent, id);
+            message
+        })
+        .collect())
+}
+
+/// Before the message at `index` is removed, attach its children to its own parent so
+/// their branches stay reachable. Returns the indices of the messages that changed.
+pub fn reparent_children(messages: &mut [Value], index: usize) -> Vec<usize> {
+    let parents = parent_indices(messages);
+    let new_parent = parents[index]
+        .and_then(|parent| message_id(&messages[parent]))
+        .map(|id| Value::String(id.to_string()))
+        .unwrap_or(Value::Null);
+
+    // Implicit children continue the message stored before them, which after the removal
+    // is the removed message's predecessor; that is only its parent in a linear stretch
+    let predecessor_is_parent = parents[index] == index.checked_sub(1);
+    let mut changed = Vec::new();
+    for (i, parent) in parents.iter().enumerate() {
+        let explicit = messages[i].get("parent_id").is_some();
+        if *parent == Some(index) && (explicit || !predecessor_is_parent) {
+            messages[i]["parent_id"] = new_parent.clone();
+            changed.push(i);
+        }
+    }
+    changed
+}
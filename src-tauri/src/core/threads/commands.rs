@@ -1,62 +1,98 @@
-use std::fs::{self, File};
-use std::io::Write;
+use std::collections::HashMap;
+use std::fs;
 use tauri::Runtime;
 use uuid::Uuid;
 
-#[cfg(any(target_os = "android", target_os = "ios"))]
+use crate::core::app::commands::{
+    get_app_configurations, get_jan_data_folder_path, update_app_configuration,
+};
+use crate::core::app::models::ThreadsStorage;
+
+use super::attachments::{self, Attachment, GcReport, DEFAULT_GC_MIN_AGE_SECS};
+use super::branches::{
+    active_branch, fork_messages, reparent_children, MessageBranch, ACTIVE_MESSAGE_KEY,
+};
 use super::db;
+use super::encryption::{
+    self, read_json_file, write_json_file, EncryptionStatus, KeySource, RewriteReport,
+};
+use super::events::{self, ChangeKind};
+use super::export::{
+    export_threads_to_path, import_archive, ExportFormat, ExportSummary, ImportSummary,
+};
 use super::helpers::{
-    get_lock_for_thread, read_messages_from_file, should_use_sqlite, update_thread_metadata,
-    write_messages_to_file,
+    append_message_to_file, get_lock_for_thread, read_messages_from_file, read_messages_from_path,
+    set_desktop_sqlite_enabled, should_use_sqlite, update_thread_metadata, write_messages_to_file,
+};
+use super::importers::{import_history, HistoryImportReport, ImportSource};
+use super::migration::{
+    export_sqlite_to_files, files_schema_is_current, migrate_files_to_sqlite,
+    sqlite_schema_is_current, upgrade_files_schema, upgrade_sqlite_schema,
+    verify_files_against_sqlite, ExportReport, MigrationReport, SchemaUpgradeReport,
+    VerificationReport,
 };
+use super::models::{normalize_assistant, normalize_message, normalize_thread};
+use super::pagination::{
+    count_messages_in_file, read_messages_page, MessageOrder, MessagesPage, PageQuery,
+    DEFAULT_PAGE_SIZE,
+};
+use super::search::{self, SearchFilters, SearchHit};
+use super::sync::{self, SyncReport};
+use super::thread_index::{self, apply_query, IndexedThread, ThreadQuery, ThreadSortKey};
+use super::trash::{self, TrashEntry, TrashKind};
+use super::watcher;
 use super::{
-    constants::THREADS_FILE,
+    constants::{MESSAGES_FILE, THREADS_FILE},
     utils::{
         ensure_data_dirs, ensure_thread_dir_exists, get_data_dir, get_messages_path,
         get_thread_dir, get_thread_metadata_path,
     },
 };
 
-/// Lists all threads by reading their metadata from the threads directory or database.
-/// Returns a vector of thread metadata as JSON values.
+/// Lists threads, most recently updated first unless `query` sorts them otherwise.
+/// `query` can also filter them and select a page of the result.
+/// With file storage, the thread index is read instead of every thread.json.
 #[tauri::command]
 pub async fn list_threads<R: Runtime>(
     app_handle: tauri::AppHandle<R>,
+    query: Option<ThreadQuery>,
 ) -> Result<Vec<serde_json::Value>, String> {
-    if should_use_sqlite() {
-        // Use SQLite on mobile platforms
-        #[cfg(any(target_os = "android", target_os = "ios"))]
-        return db::db_list_threads(app_handle).await;
-    }
-
-    // Use file-based storage on desktop
-    ensure_data_dirs(app_handle.clone())?;
-    let data_dir = get_data_dir(app_handle.clone());
-    let mut threads = Vec::new();
-
-    if !data_dir.exists() {
-        return Ok(threads);
-    }
-
-    for entry in fs::read_dir(&data_dir).map_err(|e| e.to_string())? {
-        let entry = entry.map_err(|e| e.to_string())?;
-        let path = entry.path();
-        if path.is_dir() {
-            let thread_metadata_path = path.join(THREADS_FILE);
-            if thread_metadata_path.exists() {
-                let data = fs::read_to_string(&thread_metadata_path).map_err(|e| e.to_string())?;
-                match serde_json::from_str(&data) {
-                    Ok(thread) => threads.push(thread),
-                    Err(e) => {
-                        println!("Failed to parse thread file: {e}");
-                        continue; // skip invalid thread files
-                    }
+    let query = query.unwrap_or_default();
+    let threads = if should_use_sqlite() {
+        let counts =
+            if query.sort_by == Some(ThreadSortKey::MessageCount) || query.min_messages.is_some() {
+                db::db_count_messages_by_thread(app_handle.clone()).await?
+            } else {
+                HashMap::new()
+            };
+        db::db_list_threads(app_handle)
+            .await?
+            .into_iter()
+            .map(|thread| {
+                let id = thread.get("id").and_then(|id| id.as_str());
+                let message_count = id.and_then(|id| counts.get(id)).copied();
+                IndexedThread {
+                    thread,
+                    message_count: message_count.unwrap_or(0),
                 }
-            }
-        }
-    }
+            })
+            .collect()
+    } else {
+        thread_index::list_indexed_threads(&app_handle).await?
+    };
+    Ok(apply_query(threads, &query))
+}
 
-    Ok(threads)
+/// Reads every thread.json again to rebuild the thread index of the file storage.
+/// Returns the number of indexed threads.
+#[tauri::command]
+pub async fn rebuild_thread_index<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+) -> Result<usize, String> {
+    if should_use_sqlite() {
+        return Err("The thread index is only used with file storage".to_string());
+    }
+    thread_index::rebuild_thread_index(&app_handle).await
 }
 
 /// Creates a new thread, assigns it a unique ID, and persists its metadata.
@@ -66,22 +102,44 @@ pub async fn create_thread<R: Runtime>(
     app_handle: tauri::AppHandle<R>,
     mut thread: serde_json::Value,
 ) -> Result<serde_json::Value, String> {
+    // The database keeps an id given by the caller; files always get a new one
+    if !should_use_sqlite() || thread.get("id").and_then(|id| id.as_str()).is_none() {
+        thread["id"] = serde_json::Value::String(Uuid::new_v4().to_string());
+    }
+    insert_thread(app_handle, thread).await
+}
+
+/// Persists a new thread under the id it already has, e.g. a thread received from another
+/// device. Fails if a thread with that id exists.
+pub async fn insert_thread<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    thread: serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let thread = normalize_thread(thread)?;
+    let thread_id = thread
+        .get("id")
+        .and_then(|id| id.as_str())
+        .ok_or("Missing thread id")?
+        .to_string();
     if should_use_sqlite() {
-        #[cfg(any(target_os = "android", target_os = "ios"))]
-        return db::db_create_thread(app_handle, thread).await;
+        let thread = db::db_create_thread(app_handle.clone(), thread).await?;
+        events::on_thread_changed(&app_handle, ChangeKind::Created, &thread_id);
+        return Ok(thread);
     }
 
     // Use file-based storage on desktop
     ensure_data_dirs(app_handle.clone())?;
-    let uuid = Uuid::new_v4().to_string();
-    thread["id"] = serde_json::Value::String(uuid.clone());
-    let thread_dir = get_thread_dir(app_handle.clone(), &uuid);
+    let thread_dir = get_thread_dir(app_handle.clone(), &thread_id);
     if !thread_dir.exists() {
         fs::create_dir_all(&thread_dir).map_err(|e| e.to_string())?;
     }
-    let path = get_thread_metadata_path(app_handle.clone(), &uuid);
-    let data = serde_json::to_string_pretty(&thread).map_err(|e| e.to_string())?;
-    fs::write(path, data).map_err(|e| e.to_string())?;
+    let path = get_thread_metadata_path(app_handle.clone(), &thread_id);
+    if path.exists() {
+        return Err("A thread with this id already exists".to_string());
+    }
+    write_json_file(&path, &thread)?;
+    thread_index::on_thread_saved(&app_handle, &thread, Some(0)).await;
+    events::on_thread_changed(&app_handle, ChangeKind::Created, &thread_id);
     Ok(thread)
 }
 
@@ -92,42 +150,78 @@ pub async fn modify_thread<R: Runtime>(
     app_handle: tauri::AppHandle<R>,
     thread: serde_json::Value,
 ) -> Result<(), String> {
+    let thread = normalize_thread(thread)?;
+    let thread_id = thread
+        .get("id")
+        .and_then(|id| id.as_str())
+        .ok_or("Missing thread id")?
+        .to_string();
     if should_use_sqlite() {
-        #[cfg(any(target_os = "android", target_os = "ios"))]
-        return db::db_modify_thread(app_handle, thread).await;
+        db::db_modify_thread(app_handle.clone(), thread).await?;
+        events::on_thread_changed(&app_handle, ChangeKind::Modified, &thread_id);
+        return Ok(());
     }
 
     // Use file-based storage on desktop
-    let thread_id = thread
-        .get("id")
-        .and_then(|id| id.as_str())
-        .ok_or("Missing thread id")?;
-    let thread_dir = get_thread_dir(app_handle.clone(), thread_id);
+    let thread_dir = get_thread_dir(app_handle.clone(), &thread_id);
     if !thread_dir.exists() {
         return Err("Thread directory does not exist".to_string());
     }
-    let path = get_thread_metadata_path(app_handle.clone(), thread_id);
-    let data = serde_json::to_string_pretty(&thread).map_err(|e| e.to_string())?;
-    fs::write(path, data).map_err(|e| e.to_string())?;
+    let path = get_thread_metadata_path(app_handle.clone(), &thread_id);
+    write_json_file(&path, &thread)?;
+    thread_index::on_thread_saved(&app_handle, &thread, None).await;
+    events::on_thread_changed(&app_handle, ChangeKind::Modified, &thread_id);
     Ok(())
 }
 
-/// Deletes a thread and all its associated files by removing its directory.
+/// Moves a thread with its messages and files to the trash.
 #[tauri::command]
 pub async fn delete_thread<R: Runtime>(
     app_handle: tauri::AppHandle<R>,
     thread_id: String,
 ) -> Result<(), String> {
     if should_use_sqlite() {
-        #[cfg(any(target_os = "android", target_os = "ios"))]
-        return db::db_delete_thread(app_handle, &thread_id).await;
+        if let Ok(thread) = db::db_get_thread(app_handle.clone(), &thread_id).await {
+            let messages = db::db_list_messages(app_handle.clone(), &thread_id).await?;
+            trash::trash_thread_data(&app_handle, &thread, &messages)?;
+            db::db_delete_thread(app_handle.clone(), &thread_id).await?;
+        }
+    } else {
+        // Use file-based storage on desktop
+        let lock = get_lock_for_thread(&thread_id).await;
+        let _guard = lock.lock().await;
+        let thread_dir = get_thread_dir(app_handle.clone(), &thread_id);
+        if thread_dir.exists() {
+            let thread = get_thread(app_handle.clone(), thread_id.clone()).await.ok();
+            trash::trash_thread_dir(&app_handle, &thread_id, thread.as_ref(), &thread_dir)?;
+        }
     }
 
-    // Use file-based storage on desktop
-    let thread_dir = get_thread_dir(app_handle.clone(), &thread_id);
-    if thread_dir.exists() {
-        let _ = fs::remove_dir_all(thread_dir);
+    search::on_thread_deleted(&app_handle, &thread_id).await;
+    thread_index::on_thread_deleted(&app_handle, &thread_id).await;
+    events::on_thread_changed(&app_handle, ChangeKind::Deleted, &thread_id);
+    Ok(())
+}
+
+/// Deletes a thread and all its associated files without keeping it in the trash.
+pub async fn delete_thread_permanently<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    thread_id: String,
+) -> Result<(), String> {
+    if should_use_sqlite() {
+        db::db_delete_thread(app_handle.clone(), &thread_id).await?;
+    } else {
+        // Use file-based storage on desktop
+        let thread_dir = get_thread_dir(app_handle.clone(), &thread_id);
+        if thread_dir.exists() {
+            let _ = fs::remove_dir_all(thread_dir);
+        }
     }
+
+    search::on_thread_deleted(&app_handle, &thread_id).await;
+    attachments::on_messages_deleted(&app_handle, &thread_id, None).await;
+    thread_index::on_thread_deleted(&app_handle, &thread_id).await;
+    events::on_thread_changed(&app_handle, ChangeKind::Deleted, &thread_id);
     Ok(())
 }
 
@@ -139,14 +233,62 @@ pub async fn list_messages<R: Runtime>(
     thread_id: String,
 ) -> Result<Vec<serde_json::Value>, String> {
     if should_use_sqlite() {
-        #[cfg(any(target_os = "android", target_os = "ios"))]
         return db::db_list_messages(app_handle, &thread_id).await;
     }
 
     // Use file-based storage on desktop
+    // Reading may quarantine corrupt lines, which rewrites the file
+    let lock = get_lock_for_thread(&thread_id).await;
+    let _guard = lock.lock().await;
     read_messages_from_file(app_handle, &thread_id)
 }
 
+/// Lists one page of a thread's messages: at most `limit` of those strictly between
+/// `after_id` and `before_id`. With `order = "desc"` the newest of them come first.
+#[tauri::command]
+pub async fn list_messages_page<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    thread_id: String,
+    before_id: Option<String>,
+    after_id: Option<String>,
+    limit: Option<usize>,
+    order: Option<MessageOrder>,
+) -> Result<MessagesPage, String> {
+    let query = PageQuery {
+        before_id,
+        after_id,
+        limit: limit.unwrap_or(DEFAULT_PAGE_SIZE),
+        order: order.unwrap_or_default(),
+    };
+
+    if should_use_sqlite() {
+        return db::db_list_messages_page(app_handle, &thread_id, &query).await;
+    }
+
+    // Use file-based storage on desktop
+    let lock = get_lock_for_thread(&thread_id).await;
+    let _guard = lock.lock().await;
+    let page = read_messages_page(&get_messages_path(app_handle.clone(), &thread_id), &query)?;
+    events::on_messages_quarantined(&app_handle, &thread_id, page.quarantined);
+    Ok(page)
+}
+
+/// Returns the number of messages in a thread without loading them.
+#[tauri::command]
+pub async fn count_messages<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    thread_id: String,
+) -> Result<usize, String> {
+    if should_use_sqlite() {
+        return db::db_count_messages(app_handle, &thread_id).await;
+    }
+
+    // Use file-based storage on desktop
+    let lock = get_lock_for_thread(&thread_id).await;
+    let _guard = lock.lock().await;
+    count_messages_in_file(&get_messages_path(app_handle, &thread_id))
+}
+
 /// Appends a new message to a thread's messages.jsonl file.
 /// Uses a per-thread async lock to prevent race conditions and ensure file consistency.
 #[tauri::command]
@@ -154,9 +296,18 @@ pub async fn create_message<R: Runtime>(
     app_handle: tauri::AppHandle<R>,
     mut message: serde_json::Value,
 ) -> Result<serde_json::Value, String> {
+    if message.get("id").is_none() {
+        let uuid = Uuid::new_v4().to_string();
+        message["id"] = serde_json::Value::String(uuid);
+    }
+    let message = normalize_message(message)?;
+
     if should_use_sqlite() {
-        #[cfg(any(target_os = "android", target_os = "ios"))]
-        return db::db_create_message(app_handle, message).await;
+        let message = db::db_create_message(app_handle.clone(), message).await?;
+        search::on_message_saved(&app_handle, &message).await;
+        attachments::on_message_saved(&app_handle, &message).await;
+        events::on_message_saved(&app_handle, ChangeKind::Created, &message);
+        return Ok(message);
     }
 
     // Use file-based storage on desktop
@@ -169,11 +320,6 @@ pub async fn create_message<R: Runtime>(
     };
     let path = get_messages_path(app_handle.clone(), &thread_id);
 
-    if message.get("id").is_none() {
-        let uuid = Uuid::new_v4().to_string();
-        message["id"] = serde_json::Value::String(uuid);
-    }
-
     // Acquire per-thread lock before writing
     {
         let lock = get_lock_for_thread(&thread_id).await;
@@ -182,19 +328,13 @@ pub async fn create_message<R: Runtime>(
         // Ensure directory exists right before file operations to handle race conditions
         ensure_thread_dir_exists(app_handle.clone(), &thread_id)?;
 
-        let mut file: File = fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(path)
-            .map_err(|e| e.to_string())?;
-
-        let data = serde_json::to_string(&message).map_err(|e| e.to_string())?;
-        writeln!(file, "{data}").map_err(|e| e.to_string())?;
-
-        // Explicitly flush to ensure data is written before returning
-        file.flush().map_err(|e| e.to_string())?;
+        append_message_to_file(&message, &path)?;
     }
+    thread_index::on_message_count_changed(&app_handle, &thread_id, 1).await;
 
+    search::on_message_saved(&app_handle, &message).await;
+    attachments::on_message_saved(&app_handle, &message).await;
+    events::on_message_saved(&app_handle, ChangeKind::Created, &message);
     Ok(message)
 }
 
@@ -206,9 +346,13 @@ pub async fn modify_message<R: Runtime>(
     app_handle: tauri::AppHandle<R>,
     message: serde_json::Value,
 ) -> Result<serde_json::Value, String> {
+    let message = normalize_message(message)?;
     if should_use_sqlite() {
-        #[cfg(any(target_os = "android", target_os = "ios"))]
-        return db::db_modify_message(app_handle, message).await;
+        let message = db::db_modify_message(app_handle.clone(), message).await?;
+        search::on_message_saved(&app_handle, &message).await;
+        attachments::on_message_saved(&app_handle, &message).await;
+        events::on_message_saved(&app_handle, ChangeKind::Modified, &message);
+        return Ok(message);
     }
 
     // Use file-based storage on desktop
@@ -227,21 +371,24 @@ pub async fn modify_message<R: Runtime>(
         let _guard = lock.lock().await;
 
         let mut messages = read_messages_from_file(app_handle.clone(), thread_id)?;
-        if let Some(index) = messages
+        let index = messages
             .iter()
             .position(|m| m.get("id").and_then(|v| v.as_str()) == Some(message_id))
-        {
-            messages[index] = message.clone();
+            .ok_or_else(|| format!("Message not found: {}", message_id))?;
+        messages[index] = message.clone();
 
-            // Rewrite all messages
-            let path = get_messages_path(app_handle.clone(), thread_id);
-            write_messages_to_file(&messages, &path)?;
-        }
+        // Rewrite all messages
+        let path = get_messages_path(app_handle.clone(), thread_id);
+        write_messages_to_file(&messages, &path)?;
     }
+
+    search::on_message_saved(&app_handle, &message).await;
+    attachments::on_message_saved(&app_handle, &message).await;
+    events::on_message_saved(&app_handle, ChangeKind::Modified, &message);
     Ok(message)
 }
 
-/// Deletes a message from a thread's messages.jsonl file by message ID.
+/// Moves a message to the trash and removes it from the thread's messages.jsonl file.
 /// Rewrites the entire messages.jsonl file for the thread.
 /// Uses a per-thread async lock to prevent race conditions and ensure file consistency.
 #[tauri::command]
@@ -250,28 +397,159 @@ pub async fn delete_message<R: Runtime>(
     thread_id: String,
     message_id: String,
 ) -> Result<(), String> {
+    let is_deleted =
+        |m: &serde_json::Value| m.get("id").and_then(|v| v.as_str()) == Some(message_id.as_str());
+
     if should_use_sqlite() {
-        #[cfg(any(target_os = "android", target_os = "ios"))]
-        return db::db_delete_message(app_handle, &thread_id, &message_id).await;
+        let mut messages = db::db_list_messages(app_handle.clone(), &thread_id).await?;
+        if let Some(index) = messages.iter().position(is_deleted) {
+            let created_at =
+                db::db_message_created_at(app_handle.clone(), &thread_id, &message_id).await?;
+            trash::trash_message(&app_handle, &thread_id, &messages, index, created_at)?;
+            // Replies to the deleted message move up to its parent
+            for i in reparent_children(&mut messages, index) {
+                db::db_modify_message(app_handle.clone(), messages[i].clone()).await?;
+            }
+        }
+        db::db_delete_message(app_handle.clone(), &thread_id, &message_id).await?;
+        search::on_message_deleted(&app_handle, &message_id).await;
+        events::on_message_changed(&app_handle, ChangeKind::Deleted, &thread_id, &message_id);
+        return Ok(());
     }
 
     // Use file-based storage on desktop
     // Acquire per-thread lock before modifying
+    let removed;
     {
         let lock = get_lock_for_thread(&thread_id).await;
         let _guard = lock.lock().await;
 
         let mut messages = read_messages_from_file(app_handle.clone(), &thread_id)?;
-        messages.retain(|m| m.get("id").and_then(|v| v.as_str()) != Some(message_id.as_str()));
+        if let Some(index) = messages.iter().position(is_deleted) {
+            trash::trash_message(&app_handle, &thread_id, &messages, index, None)?;
+            reparent_children(&mut messages, index);
+        }
+        let count = messages.len();
+        messages.retain(|m| !is_deleted(m));
+        removed = count - messages.len();
 
-        // Rewrite remaining messages
-        let path = get_messages_path(app_handle.clone(), &thread_id);
-        write_messages_to_file(&messages, &path)?;
+        // Rewrite remaining messages, unless the message was not there
+        if removed > 0 {
+            let path = get_messages_path(app_handle.clone(), &thread_id);
+            write_messages_to_file(&messages, &path)?;
+        }
+    }
+    if removed > 0 {
+        thread_index::on_message_count_changed(&app_handle, &thread_id, -(removed as i64)).await;
     }
 
+    search::on_message_deleted(&app_handle, &message_id).await;
+    events::on_message_changed(&app_handle, ChangeKind::Deleted, &thread_id, &message_id);
     Ok(())
 }
 
+/// Retrieves a thread's metadata.
+#[tauri::command]
+pub async fn get_thread<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    thread_id: String,
+) -> Result<serde_json::Value, String> {
+    if should_use_sqlite() {
+        return db::db_get_thread(app_handle, &thread_id).await;
+    }
+
+    // Use file-based storage on desktop
+    let path = get_thread_metadata_path(app_handle, &thread_id);
+    if !path.exists() {
+        return Err("Thread not found".to_string());
+    }
+    read_json_file(&path)
+}
+
+/// Lists the messages of a thread's active branch, from the first message to the leaf,
+/// together with the alternatives of every message on it that has siblings.
+#[tauri::command]
+pub async fn list_message_branch<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    thread_id: String,
+) -> Result<MessageBranch, String> {
+    let thread = get_thread(app_handle.clone(), thread_id.clone()).await?;
+    let active_message_id = thread
+        .get("metadata")
+        .and_then(|metadata| metadata.get(ACTIVE_MESSAGE_KEY))
+        .and_then(|id| id.as_str());
+    let messages = list_messages(app_handle, thread_id).await?;
+    Ok(active_branch(messages, active_message_id))
+}
+
+/// Switches a thread's active branch to the one through `message_id`, continuing along the
+/// newest replies below it. Returns the new active branch.
+#[tauri::command]
+pub async fn set_active_message<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    thread_id: String,
+    message_id: String,
+) -> Result<MessageBranch, String> {
+    let messages = list_messages(app_handle.clone(), thread_id.clone()).await?;
+    if !messages
+        .iter()
+        .any(|m| m.get("id").and_then(|id| id.as_str()) == Some(message_id.as_str()))
+    {
+        return Err(format!("Message not found: {}", message_id));
+    }
+
+    let mut thread = get_thread(app_handle.clone(), thread_id).await?;
+    if !thread.get("metadata").is_some_and(|m| m.is_object()) {
+        thread["metadata"] = serde_json::json!({});
+    }
+    thread["metadata"][ACTIVE_MESSAGE_KEY] = serde_json::Value::String(message_id.clone());
+    modify_thread(app_handle, thread).await?;
+    Ok(active_branch(messages, Some(&message_id)))
+}
+
+/// Creates a new thread that starts with a copy of the branch leading to `at_message_id`.
+/// The copy remembers its origin in `metadata.forked_from`.
+#[tauri::command]
+pub async fn fork_thread<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    thread_id: String,
+    at_message_id: String,
+) -> Result<serde_json::Value, String> {
+    let mut thread = get_thread(app_handle.clone(), thread_id.clone()).await?;
+    let messages = list_messages(app_handle.clone(), thread_id.clone()).await?;
+    let forked = fork_messages(&messages, &at_message_id)?;
+
+    if let Some(thread) = thread.as_object_mut() {
+        thread.remove("id");
+    }
+    if !thread.get("metadata").is_some_and(|m| m.is_object()) {
+        thread["metadata"] = serde_json::json!({});
+    }
+    if let Some(metadata) = thread["metadata"].as_object_mut() {
+        metadata.remove(ACTIVE_MESSAGE_KEY);
+        metadata.insert(
+            "forked_from".to_string(),
+            serde_json::json!({ "thread_id": thread_id, "message_id": at_message_id }),
+        );
+    }
+    let created = create_thread(app_handle.clone(), thread).await?;
+    let new_id = created
+        .get("id")
+        .and_then(|id| id.as_str())
+        .ok_or("Missing thread id")?
+        .to_string();
+
+    // A fork that fails halfway is removed again rather than left incomplete
+    for mut message in forked {
+        message["thread_id"] = serde_json::Value::String(new_id.clone());
+        if let Err(e) = create_message(app_handle.clone(), message).await {
+            let _ = delete_thread_permanently(app_handle.clone(), new_id).await;
+            return Err(e);
+        }
+    }
+    Ok(created)
+}
+
 /// Retrieves the first assistant associated with a thread.
 /// Returns an error if the thread or assistant is not found.
 #[tauri::command]
@@ -280,7 +558,6 @@ pub async fn get_thread_assistant<R: Runtime>(
     thread_id: String,
 ) -> Result<serde_json::Value, String> {
     if should_use_sqlite() {
-        #[cfg(any(target_os = "android", target_os = "ios"))]
         return db::db_get_thread_assistant(app_handle, &thread_id).await;
     }
 
@@ -289,8 +566,7 @@ pub async fn get_thread_assistant<R: Runtime>(
     if !path.exists() {
         return Err("Thread not found".to_string());
     }
-    let data = fs::read_to_string(&path).map_err(|e| e.to_string())?;
-    let thread: serde_json::Value = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    let thread: serde_json::Value = read_json_file(&path)?;
     if let Some(assistants) = thread.get("assistants").and_then(|a| a.as_array()) {
         if let Some(first) = assistants.first() {
             Ok(first.clone())
@@ -310,9 +586,12 @@ pub async fn create_thread_assistant<R: Runtime>(
     thread_id: String,
     assistant: serde_json::Value,
 ) -> Result<serde_json::Value, String> {
+    let assistant = normalize_assistant(assistant)?;
     if should_use_sqlite() {
-        #[cfg(any(target_os = "android", target_os = "ios"))]
-        return db::db_create_thread_assistant(app_handle, &thread_id, assistant).await;
+        let assistant =
+            db::db_create_thread_assistant(app_handle.clone(), &thread_id, assistant).await?;
+        events::on_thread_changed(&app_handle, ChangeKind::Modified, &thread_id);
+        return Ok(assistant);
     }
 
     // Use file-based storage on desktop
@@ -320,16 +599,15 @@ pub async fn create_thread_assistant<R: Runtime>(
     if !path.exists() {
         return Err("Thread not found".to_string());
     }
-    let mut thread: serde_json::Value = {
-        let data = fs::read_to_string(&path).map_err(|e| e.to_string())?;
-        serde_json::from_str(&data).map_err(|e| e.to_string())?
-    };
+    let mut thread: serde_json::Value = read_json_file(&path)?;
     if let Some(assistants) = thread.get_mut("assistants").and_then(|a| a.as_array_mut()) {
         assistants.push(assistant.clone());
     } else {
         thread["assistants"] = serde_json::Value::Array(vec![assistant.clone()]);
     }
-    update_thread_metadata(app_handle, &thread_id, &thread)?;
+    update_thread_metadata(app_handle.clone(), &thread_id, &thread)?;
+    thread_index::on_thread_saved(&app_handle, &thread, None).await;
+    events::on_thread_changed(&app_handle, ChangeKind::Modified, &thread_id);
     Ok(assistant)
 }
 
@@ -341,9 +619,12 @@ pub async fn modify_thread_assistant<R: Runtime>(
     thread_id: String,
     assistant: serde_json::Value,
 ) -> Result<serde_json::Value, String> {
+    let assistant = normalize_assistant(assistant)?;
     if should_use_sqlite() {
-        #[cfg(any(target_os = "android", target_os = "ios"))]
-        return db::db_modify_thread_assistant(app_handle, &thread_id, assistant).await;
+        let assistant =
+            db::db_modify_thread_assistant(app_handle.clone(), &thread_id, assistant).await?;
+        events::on_thread_changed(&app_handle, ChangeKind::Modified, &thread_id);
+        return Ok(assistant);
     }
 
     // Use file-based storage on desktop
@@ -351,10 +632,7 @@ pub async fn modify_thread_assistant<R: Runtime>(
     if !path.exists() {
         return Err("Thread not found".to_string());
     }
-    let mut thread: serde_json::Value = {
-        let data = fs::read_to_string(&path).map_err(|e| e.to_string())?;
-        serde_json::from_str(&data).map_err(|e| e.to_string())?
-    };
+    let mut thread: serde_json::Value = read_json_file(&path)?;
     let assistant_id = assistant
         .get("id")
         .and_then(|v| v.as_str())
@@ -368,8 +646,501 @@ pub async fn modify_thread_assistant<R: Runtime>(
             .position(|a| a.get("id").and_then(|v| v.as_str()) == Some(assistant_id))
         {
             assistants[index] = assistant.clone();
-            update_thread_metadata(app_handle, &thread_id, &thread)?;
+            update_thread_metadata(app_handle.clone(), &thread_id, &thread)?;
+            thread_index::on_thread_saved(&app_handle, &thread, None).await;
+            events::on_thread_changed(&app_handle, ChangeKind::Modified, &thread_id);
         }
     }
     Ok(assistant)
 }
+
+/// Returns the backend threads are currently read from and written to.
+#[tauri::command]
+pub fn get_threads_storage() -> ThreadsStorage {
+    if should_use_sqlite() {
+        ThreadsStorage::Sqlite
+    } else {
+        ThreadsStorage::Files
+    }
+}
+
+/// Switches the desktop thread storage backend and persists the choice.
+/// Moving to SQLite migrates the thread files (resuming an interrupted migration) and only
+/// switches once every thread's message count matches; moving back exports the database
+/// to the file layout.
+#[tauri::command]
+pub async fn set_threads_storage<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    storage: ThreadsStorage,
+) -> Result<(), String> {
+    if cfg!(any(target_os = "android", target_os = "ios")) {
+        return Err("Thread storage cannot be changed on mobile".to_string());
+    }
+
+    if get_threads_storage() != storage {
+        let threads_dir = get_data_dir(app_handle.clone());
+        let pool = db::ensure_database(&app_handle).await?;
+        match storage {
+            ThreadsStorage::Sqlite => {
+                let report = migrate_files_to_sqlite(&pool, &threads_dir).await?;
+                if !report.failed.is_empty() {
+                    return Err(format!(
+                        "Failed to migrate {} threads, keeping file storage",
+                        report.failed.len()
+                    ));
+                }
+                let verification = verify_files_against_sqlite(&pool, &threads_dir).await?;
+                if !verification.is_ok() {
+                    return Err(format!(
+                        "Message counts differ for {} threads after migration, keeping file storage",
+                        verification.mismatches.len()
+                    ));
+                }
+            }
+            ThreadsStorage::Files => {
+                export_sqlite_to_files(&pool, &threads_dir, true).await?;
+            }
+        }
+    }
+
+    let mut configuration = get_app_configurations(app_handle.clone());
+    configuration.threads_storage = storage;
+    update_app_configuration(app_handle.clone(), configuration)?;
+    set_desktop_sqlite_enabled(storage == ThreadsStorage::Sqlite);
+    log::info!("Threads storage set to {:?}", storage);
+    thread_index::invalidate(&app_handle).await;
+    events::on_all_threads_changed(&app_handle);
+    Ok(())
+}
+
+/// Turns reporting changes made to the threads folder outside the app on or off and
+/// persists the choice.
+#[tauri::command]
+pub async fn set_watch_threads_folder<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    enabled: bool,
+) -> Result<(), String> {
+    if enabled {
+        watcher::start(&app_handle)?;
+    } else {
+        watcher::stop();
+    }
+    let mut configuration = get_app_configurations(app_handle.clone());
+    configuration.watch_threads_folder = enabled;
+    update_app_configuration(app_handle, configuration)
+}
+
+/// Sets the shared folder threads are synced through, or turns syncing off with `None`.
+#[tauri::command]
+pub async fn set_threads_sync_folder<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    folder: Option<String>,
+) -> Result<(), String> {
+    if let Some(folder) = &folder {
+        if !std::path::Path::new(folder).is_dir() {
+            return Err(format!("Sync folder {} does not exist", folder));
+        }
+    }
+    let mut configuration = get_app_configurations(app_handle.clone());
+    configuration.threads_sync_folder = folder;
+    update_app_configuration(app_handle, configuration)
+}
+
+/// Exchanges thread changes with other devices through the sync folder.
+#[tauri::command]
+pub async fn sync_threads<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+) -> Result<SyncReport, String> {
+    let folder = get_app_configurations(app_handle.clone())
+        .threads_sync_folder
+        .ok_or("No sync folder is set")?;
+    sync::sync_with_folder(&app_handle, std::path::Path::new(&folder)).await
+}
+
+/// Copies file-based threads into SQLite without switching backends.
+/// Threads finished by an earlier, interrupted run are skipped.
+#[tauri::command]
+pub async fn migrate_threads_to_sqlite<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+) -> Result<MigrationReport, String> {
+    let pool = db::ensure_database(&app_handle).await?;
+    migrate_files_to_sqlite(&pool, &get_data_dir(app_handle)).await
+}
+
+/// Compares the message count of every file-based thread with the database.
+#[tauri::command]
+pub async fn verify_threads_migration<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+) -> Result<VerificationReport, String> {
+    let pool = db::ensure_database(&app_handle).await?;
+    verify_files_against_sqlite(&pool, &get_data_dir(app_handle)).await
+}
+
+/// Writes every thread stored in SQLite to `target_dir` as thread.json + messages.jsonl.
+#[tauri::command]
+pub async fn export_threads_to_jsonl<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    target_dir: String,
+) -> Result<ExportReport, String> {
+    let pool = db::ensure_database(&app_handle).await?;
+    export_sqlite_to_files(&pool, std::path::Path::new(&target_dir), false).await
+}
+
+/// Upgrades thread and message records written by older versions of Jan to the current
+/// schema. Records that fail validation are reported and left alone. Always scans every
+/// record; startup uses `upgrade_thread_schema_if_needed` instead.
+#[tauri::command]
+pub async fn upgrade_thread_schema<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+) -> Result<SchemaUpgradeReport, String> {
+    let report = if should_use_sqlite() {
+        let pool = db::ensure_database(&app_handle).await?;
+        let data_dir = get_jan_data_folder_path(app_handle.clone());
+        upgrade_sqlite_schema(&pool, &data_dir).await?
+    } else {
+        upgrade_files_schema(&get_data_dir(app_handle.clone())).await?
+    };
+    if report.upgraded_threads + report.upgraded_messages > 0 {
+        thread_index::invalidate(&app_handle).await;
+        events::on_all_threads_changed(&app_handle);
+    }
+    Ok(report)
+}
+
+/// Runs `upgrade_thread_schema` unless the active storage was already upgraded to the
+/// current schema. Returns `None` when nothing had to be scanned.
+pub async fn upgrade_thread_schema_if_needed<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+) -> Result<Option<SchemaUpgradeReport>, String> {
+    let is_current = if should_use_sqlite() {
+        sqlite_schema_is_current(&db::ensure_database(&app_handle).await?).await?
+    } else {
+        files_schema_is_current(&get_data_dir(app_handle.clone()))
+    };
+    if is_current {
+        return Ok(None);
+    }
+    upgrade_thread_schema(app_handle).await.map(Some)
+}
+
+/// Full-text search over the messages of all threads, best matches first.
+/// The search index is built on the first call and kept up to date afterwards.
+#[tauri::command]
+pub async fn search_messages<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    query: String,
+    filters: Option<SearchFilters>,
+) -> Result<Vec<SearchHit>, String> {
+    let pool = search::ensure_search_index(&app_handle).await?;
+    search::search(&pool, &query, &filters.unwrap_or_default()).await
+}
+
+/// Rebuilds the search index from the active thread storage.
+/// Returns the number of indexed messages.
+#[tauri::command]
+pub async fn rebuild_search_index<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+) -> Result<usize, String> {
+    search::rebuild_search_index(&app_handle).await
+}
+
+/// Exports threads (all of them when `thread_ids` is empty) to the file at `path`,
+/// as a re-importable archive, a Markdown transcript or OpenAI fine-tuning JSONL.
+#[tauri::command]
+pub async fn export_threads<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    thread_ids: Vec<String>,
+    format: ExportFormat,
+    path: String,
+) -> Result<ExportSummary, String> {
+    export_threads_to_path(
+        &app_handle,
+        &thread_ids,
+        format,
+        std::path::Path::new(&path),
+    )
+    .await
+}
+
+/// Imports the threads of an archive created by `export_threads` under new ids.
+#[tauri::command]
+pub async fn import_threads<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    path: String,
+) -> Result<ImportSummary, String> {
+    import_archive(&app_handle, std::path::Path::new(&path)).await
+}
+
+/// Imports conversations exported from ChatGPT, Open WebUI or LibreChat. The format is
+/// detected when `source` is not given; with `dry_run` only the report is produced.
+#[tauri::command]
+pub async fn import_chat_history<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    path: String,
+    source: Option<ImportSource>,
+    dry_run: bool,
+) -> Result<HistoryImportReport, String> {
+    import_history(&app_handle, std::path::Path::new(&path), source, dry_run).await
+}
+
+/// Lists deleted threads and messages, most recently deleted first.
+/// Entries past the retention period are purged first.
+#[tauri::command]
+pub async fn list_trash<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+) -> Result<Vec<TrashEntry>, String> {
+    trash::purge_expired_trash(&app_handle).await?;
+    trash::list_entries(&app_handle)
+}
+
+/// Puts a deleted thread or message back. A message goes back to its old place in its
+/// thread, which must not be in the trash itself.
+#[tauri::command]
+pub async fn restore_from_trash<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    entry_id: String,
+) -> Result<TrashEntry, String> {
+    let entry = trash::load_entry(&app_handle, &entry_id)?;
+    match entry.kind {
+        TrashKind::Thread => restore_thread(&app_handle, &entry).await?,
+        TrashKind::Message => restore_message(&app_handle, &entry).await?,
+    }
+    trash::remove_entry(&app_handle, &entry_id)?;
+    match &entry.message_id {
+        Some(message_id) => events::on_message_changed(
+            &app_handle,
+            ChangeKind::Created,
+            &entry.thread_id,
+            message_id,
+        ),
+        None => events::on_thread_changed(&app_handle, ChangeKind::Created, &entry.thread_id),
+    }
+    Ok(entry)
+}
+
+async fn restore_thread<R: Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    entry: &TrashEntry,
+) -> Result<(), String> {
+    let thread_id = entry.thread_id.clone();
+    if get_thread(app_handle.clone(), thread_id.clone())
+        .await
+        .is_ok()
+    {
+        return Err("A thread with this id already exists".to_string());
+    }
+    let source = trash::trashed_thread_dir(app_handle, entry);
+
+    if should_use_sqlite() {
+        let thread: serde_json::Value = read_json_file(&source.join(THREADS_FILE))?;
+        let messages_path = source.join(MESSAGES_FILE);
+        let messages = if messages_path.exists() {
+            read_messages_from_path(&messages_path)?
+        } else {
+            Vec::new()
+        };
+        db::db_create_thread(app_handle.clone(), thread).await?;
+        for message in &messages {
+            if let Err(e) = db::db_create_message(app_handle.clone(), message.clone()).await {
+                let _ = db::db_delete_thread(app_handle.clone(), &thread_id).await;
+                return Err(e);
+            }
+        }
+    } else {
+        // Use file-based storage on desktop
+        ensure_data_dirs(app_handle.clone())?;
+        {
+            let lock = get_lock_for_thread(&thread_id).await;
+            let _guard = lock.lock().await;
+            fs::rename(&source, get_thread_dir(app_handle.clone(), &thread_id))
+                .map_err(|e| format!("Failed to restore thread: {}", e))?;
+        }
+        thread_index::refresh_thread(app_handle, &thread_id).await;
+    }
+
+    for message in list_messages(app_handle.clone(), thread_id).await? {
+        search::on_message_saved(app_handle, &message).await;
+    }
+    Ok(())
+}
+
+async fn restore_message<R: Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    entry: &TrashEntry,
+) -> Result<(), String> {
+    let thread_id = entry.thread_id.clone();
+    if get_thread(app_handle.clone(), thread_id.clone())
+        .await
+        .is_err()
+    {
+        return Err("The thread of this message was deleted; restore it first".to_string());
+    }
+    let message = trash::read_trashed_message(app_handle, entry)?;
+
+    if should_use_sqlite() {
+        db::db_restore_message(app_handle.clone(), message.clone(), entry.db_created_at).await?;
+    } else {
+        // Use file-based storage on desktop
+        let lock = get_lock_for_thread(&thread_id).await;
+        let _guard = lock.lock().await;
+
+        let mut messages = read_messages_from_file(app_handle.clone(), &thread_id)?;
+        let position = |id: &str| {
+            messages
+                .iter()
+                .position(|m| m.get("id").and_then(|v| v.as_str()) == Some(id))
+        };
+        if entry.message_id.as_deref().and_then(position).is_some() {
+            return Err("The message is already in its thread".to_string());
+        }
+        // Back after the message it followed, or last if that one is gone too
+        let index = match &entry.after_id {
+            Some(after_id) => position(after_id).map_or(messages.len(), |i| i + 1),
+            None => 0,
+        };
+        messages.insert(index, message.clone());
+        let path = get_messages_path(app_handle.clone(), &thread_id);
+        write_messages_to_file(&messages, &path)?;
+        thread_index::on_message_count_changed(app_handle, &thread_id, 1).await;
+    }
+
+    search::on_message_saved(app_handle, &message).await;
+    Ok(())
+}
+
+/// Permanently deletes the given trash entries, or all of them when `entry_ids` is not
+/// given. Returns the number of deleted entries.
+#[tauri::command]
+pub async fn empty_trash<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    entry_ids: Option<Vec<String>>,
+) -> Result<usize, String> {
+    let Some(entry_ids) = entry_ids else {
+        return trash::empty(&app_handle).await;
+    };
+    for entry_id in &entry_ids {
+        trash::delete_entry(&app_handle, entry_id).await?;
+    }
+    Ok(entry_ids.len())
+}
+
+/// Rewrites every thread of both storages and the trash with the current encryption key.
+async fn rewrite_thread_data<R: Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+) -> Result<RewriteReport, String> {
+    let mut report = encryption::rewrite_files(
+        &get_data_dir(app_handle.clone()),
+        &trash::get_trash_dir(app_handle),
+    )
+    .await?;
+    if should_use_sqlite() {
+        let pool = db::ensure_database(app_handle).await?;
+        let data_dir = get_jan_data_folder_path(app_handle.clone());
+        let sqlite = encryption::rewrite_sqlite(&pool, &data_dir).await?;
+        report.rewritten_threads += sqlite.rewritten_threads;
+        report.rewritten_messages += sqlite.rewritten_messages;
+        report.failed.extend(sqlite.failed);
+    }
+    // Indexed rows are encrypted with the old keys
+    thread_index::invalidate(app_handle).await;
+    events::on_all_threads_changed(app_handle);
+    Ok(report)
+}
+
+/// Returns whether thread data is encrypted and whether it is unlocked.
+#[tauri::command]
+pub async fn get_thread_encryption_status<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+) -> Result<EncryptionStatus, String> {
+    encryption::status(&get_jan_data_folder_path(app_handle))
+}
+
+/// Encrypts thread data with a new key protected by a passphrase or the OS keyring, and
+/// deletes the message search index. Threads that fail are listed in the report; running
+/// `reencrypt_thread_data` retries them.
+#[tauri::command]
+pub async fn enable_thread_encryption<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    key_source: KeySource,
+    passphrase: Option<String>,
+) -> Result<RewriteReport, String> {
+    let data_dir = get_jan_data_folder_path(app_handle.clone());
+    encryption::begin_enable(&data_dir, key_source, passphrase.as_deref())?;
+    search::remove_search_index(&app_handle).await?;
+    rewrite_thread_data(&app_handle).await
+}
+
+/// Unlocks passphrase-protected thread data until the app is closed.
+#[tauri::command]
+pub async fn unlock_thread_storage<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    passphrase: String,
+) -> Result<(), String> {
+    encryption::unlock(&get_jan_data_folder_path(app_handle.clone()), &passphrase)?;
+    events::on_all_threads_changed(&app_handle);
+    Ok(())
+}
+
+/// Decrypts all thread data and removes the keys. If a thread fails, the keys are kept so
+/// that calling this again finishes the job.
+#[tauri::command]
+pub async fn disable_thread_encryption<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+) -> Result<RewriteReport, String> {
+    let data_dir = get_jan_data_folder_path(app_handle.clone());
+    encryption::begin_disable(&data_dir)?;
+    let report = rewrite_thread_data(&app_handle).await?;
+    if report.failed.is_empty() {
+        encryption::finish_disable(&data_dir)?;
+    }
+    Ok(report)
+}
+
+/// Re-encrypts all thread data with a new key, optionally protected by a new passphrase or
+/// key source. The previous keys are dropped once every thread was rewritten.
+#[tauri::command]
+pub async fn reencrypt_thread_data<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    key_source: Option<KeySource>,
+    passphrase: Option<String>,
+) -> Result<RewriteReport, String> {
+    let data_dir = get_jan_data_folder_path(app_handle.clone());
+    encryption::begin_rotate(&data_dir, key_source, passphrase.as_deref())?;
+    let report = rewrite_thread_data(&app_handle).await?;
+    if report.failed.is_empty() {
+        encryption::finish_rotate(&data_dir)?;
+    }
+    Ok(report)
+}
+
+/// Copies a file into the attachment store and returns it, with the `attachment://` URL
+/// messages use to refer to it. Files with the same content are stored once.
+#[tauri::command]
+pub async fn put_attachment<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    path: String,
+    name: Option<String>,
+) -> Result<Attachment, String> {
+    let data_dir = get_jan_data_folder_path(app_handle);
+    attachments::put_file(&data_dir, std::path::Path::new(&path), name).await
+}
+
+/// Retrieves an attachment by the SHA-256 of its content.
+#[tauri::command]
+pub async fn get_attachment<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    id: String,
+) -> Result<Attachment, String> {
+    attachments::get(&get_jan_data_folder_path(app_handle), &id)?
+        .ok_or_else(|| "Attachment not found".to_string())
+}
+
+/// Deletes attachments no message refers to. Files put less than `min_age_secs` ago
+/// (one day by default) are kept, since their message may not be saved yet.
+#[tauri::command]
+pub async fn gc_attachments<R: Runtime>(
+    app_handle: tauri::AppHandle<R>,
+    min_age_secs: Option<u64>,
+) -> Result<GcReport, String> {
+    attachments::collect_garbage(&app_handle, min_age_secs.unwrap_or(DEFAULT_GC_MIN_AGE_SECS)).await
+}
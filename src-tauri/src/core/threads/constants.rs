@@ -2,3 +2,24 @@
 pub const THREADS_DIR: &str = "threads";
 pub const THREADS_FILE: &str = "thread.json";
 pub const MESSAGES_FILE: &str = "messages.jsonl";
+// Lines of messages.jsonl that could not be parsed
+pub const MESSAGES_CORRUPT_FILE: &str = "messages.corrupt.jsonl";
+// Byte offsets of the lines of messages.jsonl
+pub const MESSAGES_INDEX_FILE: &str = "messages.idx.json";
+// Schema version the thread files were last upgraded to, in the threads directory
+pub const SCHEMA_VERSION_FILE: &str = ".schema_version";
+// Deleted threads and messages, one directory per entry
+pub const TRASH_DIR: &str = "trash";
+pub const TRASH_ENTRY_FILE: &str = "entry.json";
+pub const TRASHED_THREAD_DIR: &str = "thread";
+pub const TRASHED_MESSAGE_FILE: &str = "message.json";
+// Keys of the at-rest encryption, in the Jan data folder
+pub const ENCRYPTION_FILE: &str = "threads_encryption.json";
+// Content-addressed attachments, in the Jan data folder
+pub const ATTACHMENTS_DIR: &str = "attachments";
+pub const ATTACHMENTS_INDEX_FILE: &str = "index.json";
+pub const ATTACHMENTS_TMP_DIR: &str = "tmp";
+// State of the sync engine, in the Jan data folder
+pub const SYNC_STATE_FILE: &str = "threads_sync.json";
+// Change logs of all devices, one `<device id>.jsonl` each, in the shared sync folder
+pub const SYNC_LOGS_DIR: &str = "jan-threads-sync";
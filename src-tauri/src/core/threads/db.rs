@@ -1,42 +1,81 @@
 /*!
-   SQLite Database Module for Mobile Thread Storage
+   SQLite Database Module for Thread Storage
 
-   This module provides SQLite-based storage for threads and messages on mobile platforms.
-   It ensures data persistence and retrieval work correctly on Android and iOS devices.
-
-   Note: This module is only compiled and used on mobile platforms (Android/iOS).
-   On desktop, the file-based storage in helpers.rs is used instead.
+   This module provides SQLite-based storage for threads and messages. It is always used on
+   mobile platforms (Android/iOS). On desktop it is opt-in through the `threads_storage`
+   setting; existing file-based threads are moved over by the migration module first.
 */
 
-#![allow(dead_code)] // Functions only used on mobile platforms
-
 use serde_json::Value;
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
 use sqlx::Row;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::OnceLock;
-use tauri::{AppHandle, Manager, Runtime};
+use tauri::{AppHandle, Runtime};
 use tokio::sync::Mutex;
 
-const DB_NAME: &str = "jan.db";
+use super::encryption::{from_record, to_record};
+use super::pagination::{MessageOrder, MessagesPage, PageQuery};
+use crate::core::app::commands::get_jan_data_folder_path;
+
+pub const DB_NAME: &str = "jan.db";
 
-/// Global database pool for mobile platforms
+/// Global database pool
 static DB_POOL: OnceLock<Mutex<Option<SqlitePool>>> = OnceLock::new();
 
+/// Directory for SQLite databases. Mobile keeps them in the app data directory; desktop
+/// keeps them in the Jan data folder so they move along with the file-based threads.
+pub fn get_database_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    {
+        use tauri::Manager;
+        app.path()
+            .app_data_dir()
+            .map_err(|e| format!("Failed to get app data dir: {}", e))
+    }
+
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    {
+        use crate::core::app::commands::get_jan_data_folder_path;
+        Ok(get_jan_data_folder_path(app.clone()))
+    }
+}
+
 /// Initialize database with connection pool and run migrations
 pub async fn init_database<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
-    // Get app data directory
-    let app_data_dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
+    let db_path = get_database_dir(app)?.join(DB_NAME);
+    let pool = open_database(&db_path).await?;
 
+    // Store pool globally
+    DB_POOL
+        .get_or_init(|| Mutex::new(None))
+        .lock()
+        .await
+        .replace(pool);
+
+    log::info!("SQLite database initialized successfully");
+    Ok(())
+}
+
+/// Return the global pool, initializing the database on first use
+pub async fn ensure_database<R: Runtime>(app: &AppHandle<R>) -> Result<SqlitePool, String> {
+    if let Ok(pool) = get_pool().await {
+        return Ok(pool);
+    }
+    init_database(app).await?;
+    get_pool().await
+}
+
+/// Open (creating if missing) the database at `db_path` and create the schema
+pub async fn open_database(db_path: &Path) -> Result<SqlitePool, String> {
     // Ensure directory exists
-    std::fs::create_dir_all(&app_data_dir)
-        .map_err(|e| format!("Failed to create app data dir: {}", e))?;
+    if let Some(parent) = db_path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create database dir: {}", e))?;
+    }
 
-    // Create database path
-    let db_path = app_data_dir.join(DB_NAME);
     let db_url = format!("sqlite:{}", db_path.display());
 
     log::info!("Initializing SQLite database at: {}", db_url);
@@ -44,7 +83,8 @@ pub async fn init_database<R: Runtime>(app: &AppHandle<R>) -> Result<(), String>
     // Create connection options
     let connect_options = SqliteConnectOptions::from_str(&db_url)
         .map_err(|e| format!("Failed to parse connection options: {}", e))?
-        .create_if_missing(true);
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal);
 
     // Create connection pool
     let pool = SqlitePoolOptions::new()
@@ -98,15 +138,15 @@ pub async fn init_database<R: Runtime>(app: &AppHandle<R>) -> Result<(), String>
     .await
     .map_err(|e| format!("Failed to create created_at index: {}", e))?;
 
-    // Store pool globally
-    DB_POOL
-        .get_or_init(|| Mutex::new(None))
-        .lock()
-        .await
-        .replace(pool);
+    // Serves the ordered, per-thread range scans of paginated listing
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_messages_thread_created_at ON messages(thread_id, created_at);",
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| format!("Failed to create thread_id, created_at index: {}", e))?;
 
-    log::info!("SQLite database initialized successfully for mobile platform");
-    Ok(())
+    Ok(pool)
 }
 
 /// Get database pool
@@ -121,6 +161,11 @@ async fn get_pool() -> Result<SqlitePool, String> {
         .ok_or("Database pool not available".to_string())
 }
 
+/// Serialize a record for a `data` column, encrypted when enabled for the data folder
+fn to_data<R: Runtime>(app: &AppHandle<R>, record: &Value) -> Result<String, String> {
+    to_record(&get_jan_data_folder_path(app.clone()), record)
+}
+
 /// List all threads from database
 pub async fn db_list_threads<R: Runtime>(
     _app_handle: AppHandle<R>,
@@ -136,7 +181,7 @@ pub async fn db_list_threads<R: Runtime>(
         .iter()
         .map(|row| {
             let data: String = row.get("data");
-            serde_json::from_str(&data).map_err(|e| e.to_string())
+            from_record(data.as_bytes())
         })
         .collect();
 
@@ -145,7 +190,7 @@ pub async fn db_list_threads<R: Runtime>(
 
 /// Create a new thread in database
 pub async fn db_create_thread<R: Runtime>(
-    _app_handle: AppHandle<R>,
+    app_handle: AppHandle<R>,
     thread: Value,
 ) -> Result<Value, String> {
     let pool = get_pool().await?;
@@ -155,7 +200,7 @@ pub async fn db_create_thread<R: Runtime>(
         .and_then(|v| v.as_str())
         .ok_or("Missing thread id")?;
 
-    let data = serde_json::to_string(&thread).map_err(|e| e.to_string())?;
+    let data = to_data(&app_handle, &thread)?;
 
     sqlx::query("INSERT INTO threads (id, data) VALUES (?1, ?2)")
         .bind(thread_id)
@@ -169,7 +214,7 @@ pub async fn db_create_thread<R: Runtime>(
 
 /// Modify an existing thread in database
 pub async fn db_modify_thread<R: Runtime>(
-    _app_handle: AppHandle<R>,
+    app_handle: AppHandle<R>,
     thread: Value,
 ) -> Result<(), String> {
     let pool = get_pool().await?;
@@ -179,7 +224,7 @@ pub async fn db_modify_thread<R: Runtime>(
         .and_then(|v| v.as_str())
         .ok_or("Missing thread id")?;
 
-    let data = serde_json::to_string(&thread).map_err(|e| e.to_string())?;
+    let data = to_data(&app_handle, &thread)?;
 
     sqlx::query("UPDATE threads SET data = ?1, updated_at = strftime('%s', 'now') WHERE id = ?2")
         .bind(&data)
@@ -216,7 +261,8 @@ pub async fn db_list_messages<R: Runtime>(
     let pool = get_pool().await?;
 
     let rows = sqlx::query(
-        "SELECT data FROM messages WHERE thread_id = ?1 ORDER BY created_at ASC",
+        // rowid keeps insertion order for messages created within the same second
+        "SELECT data FROM messages WHERE thread_id = ?1 ORDER BY created_at ASC, rowid ASC",
     )
     .bind(thread_id)
     .fetch_all(&pool)
@@ -227,16 +273,137 @@ pub async fn db_list_messages<R: Runtime>(
         .iter()
         .map(|row| {
             let data: String = row.get("data");
-            serde_json::from_str(&data).map_err(|e| e.to_string())
+            from_record(data.as_bytes())
         })
         .collect();
 
     messages
 }
 
+/// Position of a message in the `created_at, rowid` order used for listing
+async fn message_position(
+    pool: &SqlitePool,
+    thread_id: &str,
+    message_id: &str,
+) -> Result<(Option<i64>, i64), String> {
+    let row =
+        sqlx::query("SELECT created_at, rowid FROM messages WHERE id = ?1 AND thread_id = ?2")
+            .bind(message_id)
+            .bind(thread_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Message not found: {}", message_id))?;
+    Ok((row.get("created_at"), row.get("rowid")))
+}
+
+/// List one page of a thread's messages from database
+pub async fn db_list_messages_page<R: Runtime>(
+    _app_handle: AppHandle<R>,
+    thread_id: &str,
+    query: &PageQuery,
+) -> Result<MessagesPage, String> {
+    let pool = get_pool().await?;
+    select_messages_page(&pool, thread_id, query).await
+}
+
+/// Read one page of a thread's messages from `pool`
+pub async fn select_messages_page(
+    pool: &SqlitePool,
+    thread_id: &str,
+    query: &PageQuery,
+) -> Result<MessagesPage, String> {
+    let after = match &query.after_id {
+        Some(id) => Some(message_position(pool, thread_id, id).await?),
+        None => None,
+    };
+    let before = match &query.before_id {
+        Some(id) => Some(message_position(pool, thread_id, id).await?),
+        None => None,
+    };
+    let direction = match query.order {
+        MessageOrder::Asc => "ASC",
+        MessageOrder::Desc => "DESC",
+    };
+
+    // One extra row tells whether there is another page
+    let rows = sqlx::query(&format!(
+        r#"
+        SELECT data FROM messages
+        WHERE thread_id = ?1
+            AND (?2 IS NULL OR (created_at, rowid) > (?3, ?2))
+            AND (?4 IS NULL OR (created_at, rowid) < (?5, ?4))
+        ORDER BY created_at {direction}, rowid {direction}
+        LIMIT ?6
+        "#
+    ))
+    .bind(thread_id)
+    .bind(after.map(|(_, rowid)| rowid))
+    .bind(after.and_then(|(created_at, _)| created_at))
+    .bind(before.map(|(_, rowid)| rowid))
+    .bind(before.and_then(|(created_at, _)| created_at))
+    .bind(query.limit as i64 + 1)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to list messages: {}", e))?;
+
+    let has_more = rows.len() > query.limit;
+    let messages = rows
+        .iter()
+        .take(query.limit)
+        .map(|row| {
+            let data: String = row.get("data");
+            from_record(data.as_bytes())
+        })
+        .collect::<Result<Vec<Value>, _>>()?;
+
+    Ok(MessagesPage {
+        messages,
+        has_more,
+        quarantined: 0,
+    })
+}
+
+/// Count a thread's messages in database
+pub async fn db_count_messages<R: Runtime>(
+    _app_handle: AppHandle<R>,
+    thread_id: &str,
+) -> Result<usize, String> {
+    let pool = get_pool().await?;
+
+    let count: i64 = sqlx::query("SELECT COUNT(*) AS count FROM messages WHERE thread_id = ?1")
+        .bind(thread_id)
+        .fetch_one(&pool)
+        .await
+        .map_err(|e| format!("Failed to count messages: {}", e))?
+        .get("count");
+
+    Ok(count as usize)
+}
+
+/// Number of messages of each thread that has any
+pub async fn db_count_messages_by_thread<R: Runtime>(
+    _app_handle: AppHandle<R>,
+) -> Result<HashMap<String, usize>, String> {
+    let pool = get_pool().await?;
+
+    let rows = sqlx::query("SELECT thread_id, COUNT(*) AS count FROM messages GROUP BY thread_id")
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| format!("Failed to count messages: {}", e))?;
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let count: i64 = row.get("count");
+            (row.get("thread_id"), count as usize)
+        })
+        .collect())
+}
+
 /// Create a new message in database
 pub async fn db_create_message<R: Runtime>(
-    _app_handle: AppHandle<R>,
+    app_handle: AppHandle<R>,
     message: Value,
 ) -> Result<Value, String> {
     let pool = get_pool().await?;
@@ -251,7 +418,7 @@ pub async fn db_create_message<R: Runtime>(
         .and_then(|v| v.as_str())
         .ok_or("Missing thread_id")?;
 
-    let data = serde_json::to_string(&message).map_err(|e| e.to_string())?;
+    let data = to_data(&app_handle, &message)?;
 
     sqlx::query("INSERT INTO messages (id, thread_id, data) VALUES (?1, ?2, ?3)")
         .bind(message_id)
@@ -264,9 +431,55 @@ pub async fn db_create_message<R: Runtime>(
     Ok(message)
 }
 
+/// Re-insert a message restored from the trash. `created_at` puts it back in its
+/// place within the thread; without it the message goes last.
+pub async fn db_restore_message<R: Runtime>(
+    app_handle: AppHandle<R>,
+    message: Value,
+    created_at: Option<i64>,
+) -> Result<Value, String> {
+    let pool = get_pool().await?;
+
+    let message_id = message
+        .get("id")
+        .and_then(|v| v.as_str())
+        .ok_or("Missing message id")?;
+
+    let thread_id = message
+        .get("thread_id")
+        .and_then(|v| v.as_str())
+        .ok_or("Missing thread_id")?;
+
+    let data = to_data(&app_handle, &message)?;
+
+    sqlx::query(
+        "INSERT INTO messages (id, thread_id, data, created_at) VALUES (?1, ?2, ?3, COALESCE(?4, strftime('%s', 'now')))",
+    )
+    .bind(message_id)
+    .bind(thread_id)
+    .bind(&data)
+    .bind(created_at)
+    .execute(&pool)
+    .await
+    .map_err(|e| format!("Failed to restore message: {}", e))?;
+
+    Ok(message)
+}
+
+/// The `created_at` column of a message, which orders it within its thread
+pub async fn db_message_created_at<R: Runtime>(
+    _app_handle: AppHandle<R>,
+    thread_id: &str,
+    message_id: &str,
+) -> Result<Option<i64>, String> {
+    let pool = get_pool().await?;
+    let (created_at, _) = message_position(&pool, thread_id, message_id).await?;
+    Ok(created_at)
+}
+
 /// Modify an existing message in database
 pub async fn db_modify_message<R: Runtime>(
-    _app_handle: AppHandle<R>,
+    app_handle: AppHandle<R>,
     message: Value,
 ) -> Result<Value, String> {
     let pool = get_pool().await?;
@@ -276,14 +489,17 @@ pub async fn db_modify_message<R: Runtime>(
         .and_then(|v| v.as_str())
         .ok_or("Missing message id")?;
 
-    let data = serde_json::to_string(&message).map_err(|e| e.to_string())?;
+    let data = to_data(&app_handle, &message)?;
 
-    sqlx::query("UPDATE messages SET data = ?1 WHERE id = ?2")
+    let result = sqlx::query("UPDATE messages SET data = ?1 WHERE id = ?2")
         .bind(&data)
         .bind(message_id)
         .execute(&pool)
         .await
         .map_err(|e| format!("Failed to modify message: {}", e))?;
+    if result.rows_affected() == 0 {
+        return Err(format!("Message not found: {}", message_id));
+    }
 
     Ok(message)
 }
@@ -305,6 +521,24 @@ pub async fn db_delete_message<R: Runtime>(
     Ok(())
 }
 
+/// Get a thread from database
+pub async fn db_get_thread<R: Runtime>(
+    _app_handle: AppHandle<R>,
+    thread_id: &str,
+) -> Result<Value, String> {
+    let pool = get_pool().await?;
+
+    let row = sqlx::query("SELECT data FROM threads WHERE id = ?1")
+        .bind(thread_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|e| format!("Failed to get thread: {}", e))?
+        .ok_or("Thread not found")?;
+
+    let data: String = row.get("data");
+    from_record(data.as_bytes())
+}
+
 /// Get thread assistant information from thread metadata
 pub async fn db_get_thread_assistant<R: Runtime>(
     _app_handle: AppHandle<R>,
@@ -320,7 +554,7 @@ pub async fn db_get_thread_assistant<R: Runtime>(
         .ok_or("Thread not found")?;
 
     let data: String = row.get("data");
-    let thread: Value = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    let thread: Value = from_record(data.as_bytes())?;
 
     if let Some(assistants) = thread.get("assistants").and_then(|a| a.as_array()) {
         assistants
@@ -348,7 +582,7 @@ pub async fn db_create_thread_assistant<R: Runtime>(
         .ok_or("Thread not found")?;
 
     let data: String = row.get("data");
-    let mut thread: Value = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    let mut thread: Value = from_record(data.as_bytes())?;
 
     if let Some(assistants) = thread.get_mut("assistants").and_then(|a| a.as_array_mut()) {
         assistants.push(assistant.clone());
@@ -376,7 +610,7 @@ pub async fn db_modify_thread_assistant<R: Runtime>(
         .ok_or("Thread not found")?;
 
     let data: String = row.get("data");
-    let mut thread: Value = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    let mut thread: Value = from_record(data.as_bytes())?;
 
     let assistant_id = assistant
         .get("id")
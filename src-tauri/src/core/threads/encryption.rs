@@ -0,0 +1,674 @@
+/*!
+   At-Rest Encryption of Thread Data
+
+   When enabled, thread.json, every line of messages.jsonl, the files of the trash and the
+   `data` column of the SQLite tables are stored as `jan-enc1:<key id>:<base64>` records,
+   encrypted with AES-256-GCM. Records are encrypted one by one, so appending a message and
+   reading a page of messages by byte offset keep working. Plaintext records stay readable,
+   which lets data written before encryption was enabled be read while it is re-encrypted.
+
+   Records are encrypted with random data keys. `threads_encryption.json` in the Jan data folder
+   holds them wrapped with a key derived from the user's passphrase (Argon2id) or with a random
+   key kept in the OS keyring. With a passphrase, thread data is locked after every start until
+   `unlock_thread_storage` is called; reading or writing a record while locked fails instead of
+   treating the record as corrupt or writing it in plaintext.
+
+   Keys are held per data folder and only records stored inside that folder are encrypted.
+   Attachments are not encrypted, and the message search index, which holds message text, is
+   deleted when encryption is enabled and cannot be built while it is on.
+*/
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use jan_utils::crypto::{self, KEY_LEN};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{PoisonError, RwLock};
+use uuid::Uuid;
+
+use super::constants::{
+    ENCRYPTION_FILE, MESSAGES_FILE, THREADS_FILE, TRASHED_MESSAGE_FILE, TRASHED_THREAD_DIR,
+    TRASH_ENTRY_FILE,
+};
+use super::helpers::{
+    get_lock_for_thread, read_messages_with_recovery, write_file_atomic, write_messages_to_file,
+};
+use super::migration::ThreadError;
+
+const RECORD_PREFIX: &str = "jan-enc1:";
+const RECORD_AAD: &[u8] = b"jan-threads-record";
+const KEYRING_SERVICE: &str = "jan-threads-encryption";
+pub const LOCKED_ERROR: &str = "Thread storage is encrypted and locked";
+
+type Key = [u8; KEY_LEN];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    Passphrase,
+    Keyring,
+}
+
+/// A data key, encrypted with the key encryption key
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct WrappedKey {
+    id: String,
+    key: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct EncryptionConfig {
+    key_source: KeySource,
+    /// Salt of the passphrase key derivation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    salt: Option<String>,
+    /// Keyring account holding the key encryption key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    keyring_account: Option<String>,
+    /// Data keys, the one new records are encrypted with first
+    keys: Vec<WrappedKey>,
+    /// Set while the data is being decrypted to disable encryption
+    #[serde(default)]
+    disabling: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct EncryptionStatus {
+    pub enabled: bool,
+    pub key_source: Option<KeySource>,
+    /// Whether the keys are loaded, so thread data can be read
+    pub unlocked: bool,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct RewriteReport {
+    pub rewritten_threads: usize,
+    pub rewritten_messages: usize,
+    pub rewritten_trash_entries: usize,
+    pub failed: Vec<ThreadError>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordError {
+    /// The record is encrypted with a key that is not loaded
+    Locked,
+    Invalid(String),
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordError::Locked => f.write_str(LOCKED_ERROR),
+            RecordError::Invalid(e) => write!(f, "Invalid encrypted record: {}", e),
+        }
+    }
+}
+
+/// Keys of a data folder with encryption enabled
+struct Store {
+    root: PathBuf,
+    /// Key encryption key, `None` while locked
+    kek: Option<Key>,
+    /// Data keys by id, the one new records are encrypted with first
+    keys: Vec<(String, Key)>,
+    /// Write new records in plaintext, set while encryption is being disabled
+    plaintext: bool,
+}
+
+static STORES: RwLock<Vec<Store>> = RwLock::new(Vec::new());
+
+fn with_stores<T>(f: impl FnOnce(&mut Vec<Store>) -> T) -> T {
+    f(&mut STORES.write().unwrap_or_else(PoisonError::into_inner))
+}
+
+/// Encrypt a record stored at `path`, or in the database of the data folder `path`, when
+/// encryption is enabled for the data folder containing it
+pub fn seal(path: &Path, plain: String) -> Result<String, String> {
+    let stores = STORES.read().unwrap_or_else(PoisonError::into_inner);
+    let Some(store) = stores.iter().find(|store| path.starts_with(&store.root)) else {
+        return Ok(plain);
+    };
+    if store.plaintext {
+        return Ok(plain);
+    }
+    let (id, key) = store.keys.first().ok_or(LOCKED_ERROR)?;
+    let data = crypto::encrypt(key, plain.as_bytes(), RECORD_AAD)?;
+    Ok(format!("{}{}:{}", RECORD_PREFIX, id, STANDARD.encode(data)))
+}
+
+/// Decrypt a record read from storage; plaintext records are returned as they are
+pub fn open(data: &[u8]) -> Result<Cow<'_, [u8]>, RecordError> {
+    let Some(record) = data.strip_prefix(RECORD_PREFIX.as_bytes()) else {
+        return Ok(Cow::Borrowed(data));
+    };
+    let end = record
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(0, |i| i + 1);
+    let record = &record[..end];
+    let separator = record
+        .iter()
+        .position(|b| *b == b':')
+        .ok_or_else(|| RecordError::Invalid("missing key id".to_string()))?;
+    let id = std::str::from_utf8(&record[..separator])
+        .map_err(|e| RecordError::Invalid(e.to_string()))?;
+
+    let key = STORES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .flat_map(|store| store.keys.iter())
+        .find(|(key_id, _)| key_id == id)
+        .map(|(_, key)| *key)
+        .ok_or(RecordError::Locked)?;
+    let data = STANDARD
+        .decode(&record[separator + 1..])
+        .map_err(|e| RecordError::Invalid(e.to_string()))?;
+    crypto::decrypt(&key, &data, RECORD_AAD)
+        .map(Cow::Owned)
+        .map_err(RecordError::Invalid)
+}
+
+/// Serialize a value into a record stored at `path`
+pub fn to_record<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<String, String> {
+    seal(
+        path,
+        serde_json::to_string(value).map_err(|e| e.to_string())?,
+    )
+}
+
+pub fn from_record<T: DeserializeOwned>(data: &[u8]) -> Result<T, String> {
+    let data = open(data).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&data).map_err(|e| e.to_string())
+}
+
+/// Read a JSON file written by `write_json_file`
+pub fn read_json_file<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    from_record(&data)
+}
+
+/// Write a value as pretty-printed JSON, encrypted when enabled for the data folder
+pub fn write_json_file<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    write_file_atomic(path, seal(path, data)?.as_bytes())
+}
+
+fn config_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(ENCRYPTION_FILE)
+}
+
+fn load_config(data_dir: &Path) -> Result<Option<EncryptionConfig>, String> {
+    let path = config_path(data_dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let data = fs::read(&path).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&data)
+        .map(Some)
+        .map_err(|e| format!("Invalid encryption settings: {}", e))
+}
+
+fn save_config(data_dir: &Path, config: &EncryptionConfig) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    write_file_atomic(&config_path(data_dir), data.as_bytes())
+}
+
+/// Whether thread data in `data_dir` is, or is being, encrypted
+pub fn is_enabled(data_dir: &Path) -> bool {
+    config_path(data_dir).exists()
+}
+
+pub fn status(data_dir: &Path) -> Result<EncryptionStatus, String> {
+    let config = load_config(data_dir)?;
+    let unlocked = STORES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .any(|store| store.root == data_dir && store.kek.is_some());
+    Ok(EncryptionStatus {
+        enabled: config.as_ref().is_some_and(|config| !config.disabling),
+        key_source: config.map(|config| config.key_source),
+        unlocked: unlocked || !is_enabled(data_dir),
+    })
+}
+
+fn decode_key(encoded: &str) -> Result<Key, String> {
+    STANDARD
+        .decode(encoded)
+        .ok()
+        .and_then(|key| Key::try_from(key).ok())
+        .ok_or_else(|| "Invalid key".to_string())
+}
+
+fn wrap_key(kek: &Key, id: &str, key: &Key) -> Result<WrappedKey, String> {
+    Ok(WrappedKey {
+        id: id.to_string(),
+        key: STANDARD.encode(crypto::encrypt(kek, key, id.as_bytes())?),
+    })
+}
+
+fn unwrap_keys(kek: &Key, config: &EncryptionConfig) -> Result<Vec<(String, Key)>, String> {
+    config
+        .keys
+        .iter()
+        .map(|wrapped| {
+            let data = STANDARD.decode(&wrapped.key).map_err(|e| e.to_string())?;
+            let key = crypto::decrypt(kek, &data, wrapped.id.as_bytes())?;
+            let key = Key::try_from(key).map_err(|_| "Invalid key".to_string())?;
+            Ok((wrapped.id.clone(), key))
+        })
+        .collect()
+}
+
+fn passphrase_kek(passphrase: &str, config: &EncryptionConfig) -> Result<Key, String> {
+    let salt = config
+        .salt
+        .as_deref()
+        .ok_or("Missing passphrase salt")
+        .and_then(|salt| STANDARD.decode(salt).map_err(|_| "Invalid passphrase salt"))?;
+    crypto::derive_key(passphrase.as_bytes(), &salt)
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod os_keyring {
+    use super::{decode_key, Key, KEYRING_SERVICE};
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    fn entry(account: &str) -> Result<keyring::Entry, String> {
+        keyring::Entry::new(KEYRING_SERVICE, account).map_err(|e| e.to_string())
+    }
+
+    pub fn load(account: &str) -> Result<Key, String> {
+        let secret = entry(account)?
+            .get_password()
+            .map_err(|e| format!("Failed to read the key from the OS keyring: {}", e))?;
+        decode_key(&secret)
+    }
+
+    pub fn store(account: &str, key: &Key) -> Result<(), String> {
+        entry(account)?
+            .set_password(&STANDARD.encode(key))
+            .map_err(|e| format!("Failed to store the key in the OS keyring: {}", e))
+    }
+
+    pub fn delete(account: &str) -> Result<(), String> {
+        match entry(account)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+mod os_keyring {
+    use super::Key;
+
+    const UNAVAILABLE: &str = "The OS keyring is not available on this platform";
+
+    pub fn load(_account: &str) -> Result<Key, String> {
+        Err(UNAVAILABLE.to_string())
+    }
+
+    pub fn store(_account: &str, _key: &Key) -> Result<(), String> {
+        Err(UNAVAILABLE.to_string())
+    }
+
+    pub fn delete(_account: &str) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+fn keyring_kek(config: &EncryptionConfig) -> Result<Key, String> {
+    let account = config
+        .keyring_account
+        .as_deref()
+        .ok_or("Missing keyring account")?;
+    os_keyring::load(account)
+}
+
+/// Register the keys of a data folder, replacing those registered before
+fn install(data_dir: &Path, config: &EncryptionConfig, kek: Option<Key>) -> Result<(), String> {
+    let keys = match &kek {
+        Some(kek) => unwrap_keys(kek, config)?,
+        None => Vec::new(),
+    };
+    with_stores(|stores| {
+        stores.retain(|store| store.root != data_dir);
+        stores.push(Store {
+            root: data_dir.to_path_buf(),
+            kek,
+            keys,
+            plaintext: config.disabling,
+        });
+    });
+    Ok(())
+}
+
+fn current_kek(data_dir: &Path) -> Result<Key, String> {
+    with_stores(|stores| {
+        stores
+            .iter()
+            .find(|store| store.root == data_dir)
+            .and_then(|store| store.kek)
+            .ok_or_else(|| LOCKED_ERROR.to_string())
+    })
+}
+
+/// Load the keys of a data folder on startup or after switching to it. Keys kept in the
+/// keyring are loaded right away, passphrase-protected ones stay locked until `unlock`.
+pub fn load_keys(data_dir: &Path) -> Result<(), String> {
+    let Some(config) = load_config(data_dir)? else {
+        with_stores(|stores| stores.retain(|store| store.root != data_dir));
+        return Ok(());
+    };
+    // A copied data folder uses the keys already unlocked for the original
+    let kek = with_stores(|stores| {
+        stores
+            .iter()
+            .filter(|store| store.root != data_dir)
+            .filter_map(|store| store.kek)
+            .find(|kek| unwrap_keys(kek, &config).is_ok())
+    });
+    let kek = match (kek, config.key_source) {
+        (Some(kek), _) => Some(kek),
+        (None, KeySource::Keyring) => Some(keyring_kek(&config)?),
+        (None, KeySource::Passphrase) => None,
+    };
+    install(data_dir, &config, kek)
+}
+
+/// Unlock passphrase-protected thread data
+pub fn unlock(data_dir: &Path, passphrase: &str) -> Result<(), String> {
+    let config = load_config(data_dir)?.ok_or("Thread encryption is not enabled")?;
+    if config.key_source != KeySource::Passphrase {
+        return Err("Thread data is not protected by a passphrase".to_string());
+    }
+    let kek = passphrase_kek(passphrase, &config)?;
+    if unwrap_keys(&kek, &config).is_err() {
+        return Err("Wrong passphrase".to_string());
+    }
+    install(data_dir, &config, Some(kek))
+}
+
+/// Create a key encryption key for `source`, filling in the settings it needs
+fn new_kek(
+    config: &mut EncryptionConfig,
+    source: KeySource,
+    passphrase: Option<&str>,
+) -> Result<Key, String> {
+    config.key_source = source;
+    match source {
+        KeySource::Passphrase => {
+            let passphrase = passphrase
+                .filter(|passphrase| !passphrase.is_empty())
+                .ok_or("A passphrase is required")?;
+            config.salt = Some(STANDARD.encode(crypto::generate_salt()));
+            config.keyring_account = None;
+            passphrase_kek(passphrase, config)
+        }
+        KeySource::Keyring => {
+            let kek = crypto::generate_key();
+            let account = Uuid::new_v4().to_string();
+            os_keyring::store(&account, &kek)?;
+            config.salt = None;
+            config.keyring_account = Some(account);
+            Ok(kek)
+        }
+    }
+}
+
+/// Start encrypting new records with a new data key. The existing data must then be
+/// rewritten with `rewrite_files` / `rewrite_sqlite`.
+pub fn begin_enable(
+    data_dir: &Path,
+    source: KeySource,
+    passphrase: Option<&str>,
+) -> Result<(), String> {
+    if let Some(config) = load_config(data_dir)? {
+        return Err(if config.disabling {
+            "Thread encryption is being disabled, disable it again to finish first".to_string()
+        } else {
+            "Thread encryption is already enabled".to_string()
+        });
+    }
+    let mut config = EncryptionConfig {
+        key_source: source,
+        salt: None,
+        keyring_account: None,
+        keys: Vec::new(),
+        disabling: false,
+    };
+    let kek = new_kek(&mut config, source, passphrase)?;
+    let id = Uuid::new_v4().to_string();
+    config
+        .keys
+        .push(wrap_key(&kek, &id, &crypto::generate_key())?);
+    save_config(data_dir, &config)?;
+    install(data_dir, &config, Some(kek))
+}
+
+/// Add a new data key that new records are encrypted with, optionally protecting the keys
+/// with a new passphrase or key source. The old keys are kept until `finish_rotate`.
+pub fn begin_rotate(
+    data_dir: &Path,
+    source: Option<KeySource>,
+    passphrase: Option<&str>,
+) -> Result<(), String> {
+    let mut config = load_config(data_dir)?.ok_or("Thread encryption is not enabled")?;
+    if config.disabling {
+        return Err("Thread encryption is being disabled".to_string());
+    }
+    let old_kek = current_kek(data_dir)?;
+    let old_account = config.keyring_account.clone();
+    let keys = unwrap_keys(&old_kek, &config)?;
+
+    let kek = match (source, passphrase) {
+        (None, None) => old_kek,
+        (source, passphrase) => {
+            let source = source.unwrap_or(config.key_source);
+            new_kek(&mut config, source, passphrase)?
+        }
+    };
+    let id = Uuid::new_v4().to_string();
+    let mut wrapped = vec![wrap_key(&kek, &id, &crypto::generate_key())?];
+    for (id, key) in &keys {
+        wrapped.push(wrap_key(&kek, id, key)?);
+    }
+    config.keys = wrapped;
+    save_config(data_dir, &config)?;
+    if old_account.is_some() && old_account != config.keyring_account {
+        if let Err(e) = os_keyring::delete(old_account.as_deref().unwrap_or_default()) {
+            log::warn!("Failed to remove the old key from the OS keyring: {}", e);
+        }
+    }
+    install(data_dir, &config, Some(kek))
+}
+
+/// Drop every data key but the newest, once all records were rewritten with it
+pub fn finish_rotate(data_dir: &Path) -> Result<(), String> {
+    let mut config = load_config(data_dir)?.ok_or("Thread encryption is not enabled")?;
+    let kek = current_kek(data_dir)?;
+    config.keys.truncate(1);
+    save_config(data_dir, &config)?;
+    install(data_dir, &config, Some(kek))
+}
+
+/// Start writing new records in plaintext. The existing data must then be rewritten, after
+/// which `finish_disable` removes the keys.
+pub fn begin_disable(data_dir: &Path) -> Result<(), String> {
+    let mut config = load_config(data_dir)?.ok_or("Thread encryption is not enabled")?;
+    let kek = current_kek(data_dir)?;
+    config.disabling = true;
+    save_config(data_dir, &config)?;
+    install(data_dir, &config, Some(kek))
+}
+
+pub fn finish_disable(data_dir: &Path) -> Result<(), String> {
+    let config = load_config(data_dir)?.ok_or("Thread encryption is not enabled")?;
+    fs::remove_file(config_path(data_dir)).map_err(|e| e.to_string())?;
+    if let Some(account) = &config.keyring_account {
+        if let Err(e) = os_keyring::delete(account) {
+            log::warn!("Failed to remove the key from the OS keyring: {}", e);
+        }
+    }
+    with_stores(|stores| stores.retain(|store| store.root != data_dir));
+    Ok(())
+}
+
+/// Rewrite a thread directory with the current key. Returns the number of messages.
+fn rewrite_thread_dir(thread_dir: &Path) -> Result<usize, String> {
+    let thread_path = thread_dir.join(THREADS_FILE);
+    if thread_path.exists() {
+        let thread: Value = read_json_file(&thread_path)?;
+        write_json_file(&thread_path, &thread)?;
+    }
+    let messages_path = thread_dir.join(MESSAGES_FILE);
+    if !messages_path.exists() {
+        return Ok(0);
+    }
+    let messages = read_messages_with_recovery(&messages_path)?.messages;
+    write_messages_to_file(&messages, &messages_path)?;
+    Ok(messages.len())
+}
+
+fn rewrite_trash_entry(entry_dir: &Path) -> Result<(), String> {
+    let entry_path = entry_dir.join(TRASH_ENTRY_FILE);
+    if !entry_path.exists() {
+        return Ok(());
+    }
+    let entry: Value = read_json_file(&entry_path)?;
+    let message_path = entry_dir.join(TRASHED_MESSAGE_FILE);
+    if message_path.exists() {
+        let message: Value = read_json_file(&message_path)?;
+        write_json_file(&message_path, &message)?;
+    }
+    let thread_dir = entry_dir.join(TRASHED_THREAD_DIR);
+    if thread_dir.exists() {
+        rewrite_thread_dir(&thread_dir)?;
+    }
+    write_json_file(&entry_path, &entry)
+}
+
+fn subdirectories(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(fs::read_dir(dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect())
+}
+
+/// Rewrite every thread of the file storage and every trash entry with the current key
+pub async fn rewrite_files(threads_dir: &Path, trash_dir: &Path) -> Result<RewriteReport, String> {
+    let mut report = RewriteReport::default();
+    for thread_dir in subdirectories(threads_dir)? {
+        let thread_id = thread_dir
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let lock = get_lock_for_thread(&thread_id).await;
+        let _guard = lock.lock().await;
+        match rewrite_thread_dir(&thread_dir) {
+            Ok(messages) => {
+                report.rewritten_threads += 1;
+                report.rewritten_messages += messages;
+            }
+            Err(error) => report.failed.push(ThreadError { thread_id, error }),
+        }
+    }
+    for entry_dir in subdirectories(trash_dir)? {
+        match rewrite_trash_entry(&entry_dir) {
+            Ok(()) => report.rewritten_trash_entries += 1,
+            Err(error) => {
+                let entry_id = entry_dir.file_name().unwrap_or_default().to_string_lossy();
+                report.failed.push(ThreadError {
+                    thread_id: format!("trash/{}", entry_id),
+                    error,
+                });
+            }
+        }
+    }
+    Ok(report)
+}
+
+async fn rewrite_sqlite_thread(
+    pool: &SqlitePool,
+    scope: &Path,
+    thread_id: &str,
+) -> Result<usize, String> {
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    let reseal = |data: &str| -> Result<String, String> {
+        let plain = open(data.as_bytes()).map_err(|e| e.to_string())?;
+        seal(scope, String::from_utf8_lossy(&plain).into_owned())
+    };
+
+    let data: String = sqlx::query("SELECT data FROM threads WHERE id = ?1")
+        .bind(thread_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .get("data");
+    sqlx::query("UPDATE threads SET data = ?1 WHERE id = ?2")
+        .bind(reseal(&data)?)
+        .bind(thread_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let rows = sqlx::query("SELECT id, data FROM messages WHERE thread_id = ?1")
+        .bind(thread_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    for row in &rows {
+        sqlx::query("UPDATE messages SET data = ?1 WHERE id = ?2")
+            .bind(reseal(row.get("data"))?)
+            .bind(row.get::<String, _>("id"))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(rows.len())
+}
+
+/// Rewrite every thread stored in SQLite with the current key of the data folder `scope`.
+/// The database is vacuumed afterwards so freed pages keep no copies of the old records.
+pub async fn rewrite_sqlite(pool: &SqlitePool, scope: &Path) -> Result<RewriteReport, String> {
+    let thread_ids: Vec<String> = sqlx::query("SELECT id FROM threads")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to list threads: {}", e))?
+        .iter()
+        .map(|row| row.get("id"))
+        .collect();
+
+    let mut report = RewriteReport::default();
+    for thread_id in thread_ids {
+        let lock = get_lock_for_thread(&thread_id).await;
+        let _guard = lock.lock().await;
+        match rewrite_sqlite_thread(pool, scope, &thread_id).await {
+            Ok(messages) => {
+                report.rewritten_threads += 1;
+                report.rewritten_messages += messages;
+            }
+            Err(error) => report.failed.push(ThreadError { thread_id, error }),
+        }
+    }
+    sqlx::query("VACUUM")
+        .execute(pool)
+        .await
+        .map_err(|e| format!("Failed to vacuum database: {}", e))?;
+    Ok(report)
+}
@@ -0,0 +1,155 @@
+/*!
+   Thread Change Events
+
+   Every command that changes threads or messages emits a `threads://changed` event to all
+   windows, so each of them can refresh what it shows. The threads folder watcher emits the same
+   event, with `external` set, for changes made outside the app.
+
+   The watcher also sees the app's own writes. Changes emitted here are remembered for a short
+   while, and the watcher skips threads changed by the app within that time.
+
+   Reading a thread's messages moves lines that cannot be parsed to messages.corrupt.jsonl. When
+   that happens a `threads://messages-quarantined` event tells the windows how many were moved.
+*/
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Runtime};
+
+pub const THREADS_CHANGED_EVENT: &str = "threads://changed";
+pub const MESSAGES_QUARANTINED_EVENT: &str = "threads://messages-quarantined";
+
+/// Key of changes that may affect any thread
+const ALL_THREADS: &str = "";
+
+/// When each thread was last changed through the commands
+static LOCAL_CHANGES: OnceLock<Mutex<HashMap<String, Instant>>> = OnceLock::new();
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ThreadsChanged {
+    pub kind: ChangeKind,
+    /// Not set when any thread may have changed, e.g. after switching the storage
+    pub thread_id: Option<String>,
+    /// Set when a single message changed
+    pub message_id: Option<String>,
+    /// The change was made outside the app
+    pub external: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MessagesQuarantined {
+    pub thread_id: String,
+    /// Lines moved to messages.corrupt.jsonl
+    pub count: usize,
+}
+
+fn record_local_change(thread_id: Option<&str>) {
+    let now = Instant::now();
+    let mut changes = LOCAL_CHANGES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    changes.retain(|_, at| now.duration_since(*at) < Duration::from_secs(60));
+    changes.insert(thread_id.unwrap_or(ALL_THREADS).to_string(), now);
+}
+
+/// Whether the app itself changed a thread, or possibly all of them, within `window`
+pub fn changed_locally_within(thread_id: &str, window: Duration) -> bool {
+    let Some(changes) = LOCAL_CHANGES.get() else {
+        return false;
+    };
+    let changes = changes.lock().unwrap_or_else(PoisonError::into_inner);
+    [thread_id, ALL_THREADS]
+        .iter()
+        .any(|key| changes.get(*key).is_some_and(|at| at.elapsed() < window))
+}
+
+pub fn emit<R: Runtime>(app: &tauri::AppHandle<R>, change: ThreadsChanged) {
+    if !change.external {
+        record_local_change(change.thread_id.as_deref());
+    }
+    if let Err(e) = app.emit(THREADS_CHANGED_EVENT, change) {
+        log::warn!("Failed to emit {}: {}", THREADS_CHANGED_EVENT, e);
+    }
+}
+
+pub fn on_thread_changed<R: Runtime>(app: &tauri::AppHandle<R>, kind: ChangeKind, thread_id: &str) {
+    emit(
+        app,
+        ThreadsChanged {
+            kind,
+            thread_id: Some(thread_id.to_string()),
+            message_id: None,
+            external: false,
+        },
+    );
+}
+
+pub fn on_message_changed<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    kind: ChangeKind,
+    thread_id: &str,
+    message_id: &str,
+) {
+    emit(
+        app,
+        ThreadsChanged {
+            kind,
+            thread_id: Some(thread_id.to_string()),
+            message_id: Some(message_id.to_string()),
+            external: false,
+        },
+    );
+}
+
+pub fn on_message_saved<R: Runtime>(app: &tauri::AppHandle<R>, kind: ChangeKind, message: &Value) {
+    let id = |key: &str| {
+        message
+            .get(key)
+            .and_then(|id| id.as_str())
+            .unwrap_or_default()
+    };
+    on_message_changed(app, kind, id("thread_id"), id("id"));
+}
+
+/// Any thread may have changed, e.g. after rewriting all of them
+pub fn on_all_threads_changed<R: Runtime>(app: &tauri::AppHandle<R>) {
+    emit(
+        app,
+        ThreadsChanged {
+            kind: ChangeKind::Modified,
+            thread_id: None,
+            message_id: None,
+            external: false,
+        },
+    );
+}
+
+/// Lines of a thread's messages were quarantined while reading them; does nothing for none
+pub fn on_messages_quarantined<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    thread_id: &str,
+    count: usize,
+) {
+    if count == 0 {
+        return;
+    }
+    let payload = MessagesQuarantined {
+        thread_id: thread_id.to_string(),
+        count,
+    };
+    if let Err(e) = app.emit(MESSAGES_QUARANTINED_EVENT, payload) {
+        log::warn!("Failed to emit {}: {}", MESSAGES_QUARANTINED_EVENT, e);
+    }
+}
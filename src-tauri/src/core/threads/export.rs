@@ -0,0 +1,620 @@
+/*!
+   Thread Export and Import
+
+   Threads can be exported as a Jan archive that round-trips everything, as a Markdown
+   transcript for reading, or as OpenAI fine-tuning chat JSONL. Exports read through the
+   thread commands, so they behave the same for the file and SQLite backends.
+
+   Archive layout:
+     manifest.json                  format name, version and the exported threads
+     threads/<id>/thread.json
+     threads/<id>/messages.jsonl
+     threads/<id>/<other files>     attachments kept in the thread directory (file backend)
+     attachments/<sha256>           files of the attachment store the messages refer to
+
+   Imports always assign new thread and message ids, so importing an archive twice, or into
+   the installation it came from, creates copies instead of overwriting threads. Files of the
+   attachment store are put back into it before the messages, which record their references.
+*/
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use tauri::{AppHandle, Runtime};
+use uuid::Uuid;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use super::attachments::{self, message_attachment_ids, Attachment};
+use super::branches::ACTIVE_MESSAGE_KEY;
+use super::commands::{
+    create_message, create_thread, delete_thread_permanently, list_messages, list_threads,
+};
+use super::constants::{
+    ATTACHMENTS_DIR, MESSAGES_CORRUPT_FILE, MESSAGES_FILE, MESSAGES_INDEX_FILE, THREADS_DIR,
+    THREADS_FILE,
+};
+use super::helpers::{should_use_sqlite, write_file_atomic};
+use super::migration::ThreadError;
+use super::search::message_text;
+use super::utils::get_thread_dir;
+use crate::core::app::commands::get_jan_data_folder_path;
+
+pub const ARCHIVE_FORMAT: &str = "jan-threads";
+pub const ARCHIVE_VERSION: u32 = 1;
+const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// Zip archive that can be imported again
+    Archive,
+    Markdown,
+    /// One `{"messages": [...]}` chat example per thread
+    OpenaiJsonl,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ArchiveManifest {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: i64,
+    pub threads: Vec<ManifestThread>,
+    /// Files of the attachment store, under `attachments/<id>`
+    #[serde(default)]
+    pub attachments: Vec<ManifestAttachment>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ManifestThread {
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    pub messages: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ManifestAttachment {
+    /// SHA-256 of the content, as in `attachment://<id>` URLs
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct ExportSummary {
+    pub exported_threads: usize,
+    pub exported_messages: usize,
+    /// Threads left out because the format cannot represent them,
+    /// e.g. fine-tuning examples without an assistant reply
+    pub skipped_threads: usize,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct ImportSummary {
+    pub imported_threads: usize,
+    pub imported_messages: usize,
+    pub imported_attachments: usize,
+    /// Attachments that could not be stored because threads are kept in SQLite
+    pub skipped_attachments: usize,
+    /// Files put back into the attachment store
+    pub stored_attachments: usize,
+    /// New id of every imported thread, by its id in the archive
+    pub thread_ids: HashMap<String, String>,
+    pub failed: Vec<ThreadError>,
+}
+
+struct ExportedThread {
+    thread: Value,
+    messages: Vec<Value>,
+    /// Attachment paths relative to the thread directory
+    attachments: Vec<PathBuf>,
+    dir: PathBuf,
+}
+
+fn thread_id_of(thread: &Value) -> &str {
+    thread
+        .get("id")
+        .and_then(|id| id.as_str())
+        .unwrap_or_default()
+}
+
+fn thread_title(thread: &Value) -> &str {
+    thread
+        .get("title")
+        .and_then(|title| title.as_str())
+        .unwrap_or_default()
+}
+
+/// Files in a thread directory besides the ones the thread store maintains
+fn list_attachments(thread_dir: &Path) -> Vec<PathBuf> {
+    fn walk(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            // Temp files of interrupted writes start with a dot
+            if name.starts_with('.') {
+                continue;
+            }
+            if path.is_dir() {
+                walk(root, &path, files);
+            } else if dir != root
+                || ![
+                    THREADS_FILE,
+                    MESSAGES_FILE,
+                    MESSAGES_INDEX_FILE,
+                    MESSAGES_CORRUPT_FILE,
+                ]
+                .contains(&name.as_ref())
+            {
+                if let Ok(relative) = path.strip_prefix(root) {
+                    files.push(relative.to_path_buf());
+                }
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    walk(thread_dir, thread_dir, &mut files);
+    files.sort();
+    files
+}
+
+/// Threads to export with their messages; all threads when `thread_ids` is empty
+async fn collect_threads<R: Runtime>(
+    app: &AppHandle<R>,
+    thread_ids: &[String],
+) -> Result<Vec<ExportedThread>, String> {
+    let threads = list_threads(app.clone(), None).await?;
+    let selected: Vec<Value> = if thread_ids.is_empty() {
+        threads
+    } else {
+        thread_ids
+            .iter()
+            .map(|id| {
+                threads
+                    .iter()
+                    .find(|thread| thread_id_of(thread) == id)
+                    .cloned()
+                    .ok_or_else(|| format!("Thread not found: {}", id))
+            })
+            .collect::<Result<_, _>>()?
+    };
+
+    let mut exported = Vec::with_capacity(selected.len());
+    for thread in selected {
+        let thread_id = thread_id_of(&thread).to_string();
+        let messages = list_messages(app.clone(), thread_id.clone()).await?;
+        let dir = get_thread_dir(app.clone(), &thread_id);
+        let attachments = if should_use_sqlite() {
+            vec![]
+        } else {
+            list_attachments(&dir)
+        };
+        exported.push(ExportedThread {
+            thread,
+            messages,
+            attachments,
+            dir,
+        });
+    }
+    Ok(exported)
+}
+
+/// Files of the attachment store that the exported messages refer to
+fn collect_store_files<R: Runtime>(
+    app: &AppHandle<R>,
+    threads: &[ExportedThread],
+) -> Result<Vec<Attachment>, String> {
+    let data_dir = get_jan_data_folder_path(app.clone());
+    let ids: BTreeSet<String> = threads
+        .iter()
+        .flat_map(|exported| &exported.messages)
+        .flat_map(message_attachment_ids)
+        .collect();
+    let mut files = Vec::new();
+    for id in ids {
+        match attachments::get(&data_dir, &id)? {
+            Some(attachment) => files.push(attachment),
+            None => log::warn!(
+                "Attachment {} is missing from the store, not exporting it",
+                id
+            ),
+        }
+    }
+    Ok(files)
+}
+
+/// Name of an archive entry: always `/`-separated
+fn entry_name(thread_id: &str, relative: &Path) -> String {
+    let parts: Vec<_> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect();
+    format!("{}/{}/{}", THREADS_DIR, thread_id, parts.join("/"))
+}
+
+fn store_entry_name(id: &str) -> String {
+    format!("{}/{}", ATTACHMENTS_DIR, id)
+}
+
+fn write_archive(
+    path: &Path,
+    threads: &[ExportedThread],
+    store_files: &[Attachment],
+) -> Result<(), String> {
+    let manifest = ArchiveManifest {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        exported_at: chrono::Utc::now().timestamp(),
+        threads: threads
+            .iter()
+            .map(|exported| ManifestThread {
+                id: thread_id_of(&exported.thread).to_string(),
+                title: thread_title(&exported.thread).to_string(),
+                messages: exported.messages.len(),
+            })
+            .collect(),
+        attachments: store_files
+            .iter()
+            .map(|attachment| ManifestAttachment {
+                id: attachment.id.clone(),
+                name: attachment.name.clone(),
+            })
+            .collect(),
+    };
+
+    // Built next to the target and renamed at the end, so a failed export leaves no partial zip
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or("Invalid export path")?;
+    let tmp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, Uuid::new_v4()));
+    let result = (|| -> Result<(), String> {
+        let file = File::create(&tmp_path).map_err(|e| e.to_string())?;
+        let mut zip = ZipWriter::new(file);
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        let mut add = |name: String, data: &[u8]| -> Result<(), String> {
+            zip.start_file(name, options).map_err(|e| e.to_string())?;
+            zip.write_all(data).map_err(|e| e.to_string())
+        };
+
+        let data = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+        add(MANIFEST_FILE.to_string(), &data)?;
+        for exported in threads {
+            let thread_id = thread_id_of(&exported.thread);
+            let data = serde_json::to_vec_pretty(&exported.thread).map_err(|e| e.to_string())?;
+            add(entry_name(thread_id, Path::new(THREADS_FILE)), &data)?;
+
+            let mut lines = String::new();
+            for message in &exported.messages {
+                lines.push_str(&serde_json::to_string(message).map_err(|e| e.to_string())?);
+                lines.push('\n');
+            }
+            add(
+                entry_name(thread_id, Path::new(MESSAGES_FILE)),
+                lines.as_bytes(),
+            )?;
+
+            for relative in &exported.attachments {
+                let data = fs::read(exported.dir.join(relative)).map_err(|e| e.to_string())?;
+                add(entry_name(thread_id, relative), &data)?;
+            }
+        }
+        for attachment in store_files {
+            let data = fs::read(&attachment.path).map_err(|e| e.to_string())?;
+            add(store_entry_name(&attachment.id), &data)?;
+        }
+
+        let file = zip.finish().map_err(|e| e.to_string())?;
+        file.sync_all().map_err(|e| e.to_string())?;
+        fs::rename(&tmp_path, path).map_err(|e| e.to_string())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+fn role_label<'a>(role: &'a str, thread: &'a Value) -> &'a str {
+    match role {
+        "user" => "User",
+        "system" => "System",
+        "assistant" => thread
+            .get("assistants")
+            .and_then(|assistants| assistants.get(0))
+            .and_then(|assistant| assistant.get("name"))
+            .and_then(|name| name.as_str())
+            .filter(|name| !name.is_empty())
+            .unwrap_or("Assistant"),
+        role => role,
+    }
+}
+
+/// Markdown transcript of a thread; messages without text are left out
+pub fn thread_markdown(thread: &Value, messages: &[Value]) -> String {
+    let title = Some(thread_title(thread))
+        .filter(|title| !title.is_empty())
+        .unwrap_or("Untitled thread");
+    let mut markdown = format!("# {}\n", title);
+    for message in messages {
+        let text = message_text(message);
+        if text.trim().is_empty() {
+            continue;
+        }
+        let role = message
+            .get("role")
+            .and_then(|role| role.as_str())
+            .unwrap_or_default();
+        markdown.push_str(&format!(
+            "\n## {}\n\n{}\n",
+            role_label(role, thread),
+            text.trim_end()
+        ));
+    }
+    markdown
+}
+
+/// Fine-tuning chat example for a thread, or `None` if it has no assistant reply.
+/// The assistant's instructions become the system message.
+pub fn thread_chat_example(thread: &Value, messages: &[Value]) -> Option<Value> {
+    let mut chat = Vec::new();
+    let instructions = thread
+        .get("assistants")
+        .and_then(|assistants| assistants.get(0))
+        .and_then(|assistant| assistant.get("instructions"))
+        .and_then(|instructions| instructions.as_str())
+        .filter(|instructions| !instructions.trim().is_empty());
+    if let Some(instructions) = instructions {
+        chat.push(json!({"role": "system", "content": instructions}));
+    }
+    for message in messages {
+        let role = message
+            .get("role")
+            .and_then(|role| role.as_str())
+            .unwrap_or_default();
+        let text = message_text(message);
+        if ["system", "user", "assistant"].contains(&role) && !text.trim().is_empty() {
+            chat.push(json!({"role": role, "content": text}));
+        }
+    }
+
+    if chat.iter().any(|message| message["role"] == "assistant") {
+        Some(json!({ "messages": chat }))
+    } else {
+        None
+    }
+}
+
+/// Export the given threads (all when `thread_ids` is empty) to `path`
+pub async fn export_threads_to_path<R: Runtime>(
+    app: &AppHandle<R>,
+    thread_ids: &[String],
+    format: ExportFormat,
+    path: &Path,
+) -> Result<ExportSummary, String> {
+    let threads = collect_threads(app, thread_ids).await?;
+    let mut summary = ExportSummary::default();
+
+    match format {
+        ExportFormat::Archive => {
+            let store_files = collect_store_files(app, &threads)?;
+            write_archive(path, &threads, &store_files)?;
+            summary.exported_threads = threads.len();
+            summary.exported_messages = threads.iter().map(|t| t.messages.len()).sum();
+        }
+        ExportFormat::Markdown => {
+            let transcripts: Vec<String> = threads
+                .iter()
+                .map(|t| thread_markdown(&t.thread, &t.messages))
+                .collect();
+            write_file_atomic(path, transcripts.join("\n---\n\n").as_bytes())?;
+            summary.exported_threads = threads.len();
+            summary.exported_messages = threads.iter().map(|t| t.messages.len()).sum();
+        }
+        ExportFormat::OpenaiJsonl => {
+            let mut lines = String::new();
+            for exported in &threads {
+                match thread_chat_example(&exported.thread, &exported.messages) {
+                    Some(example) => {
+                        summary.exported_threads += 1;
+                        summary.exported_messages += example["messages"]
+                            .as_array()
+                            .map(|chat| chat.len())
+                            .unwrap_or_default();
+                        lines.push_str(&example.to_string());
+                        lines.push('\n');
+                    }
+                    None => summary.skipped_threads += 1,
+                }
+            }
+            write_file_atomic(path, lines.as_bytes())?;
+        }
+    }
+
+    log::info!(
+        "Exported {} threads to {}",
+        summary.exported_threads,
+        path.display()
+    );
+    Ok(summary)
+}
+
+fn read_entry(zip: &mut ZipArchive<File>, name: &str) -> Result<Vec<u8>, String> {
+    let mut entry = zip
+        .by_name(name)
+        .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data).map_err(|e| e.to_string())?;
+    Ok(data)
+}
+
+/// Relative path of an attachment entry, rejecting anything that could escape the thread dir
+fn attachment_path(name: &str, prefix: &str) -> Option<PathBuf> {
+    let relative = name.strip_prefix(prefix)?;
+    if relative.is_empty()
+        || relative.ends_with('/')
+        || [THREADS_FILE, MESSAGES_FILE].contains(&relative)
+    {
+        return None;
+    }
+    let path = Path::new(relative);
+    path.components()
+        .all(|part| matches!(part, Component::Normal(_)))
+        .then(|| path.to_path_buf())
+}
+
+/// Import every thread of a Jan archive under new ids
+pub async fn import_archive<R: Runtime>(
+    app: &AppHandle<R>,
+    path: &Path,
+) -> Result<ImportSummary, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut zip = ZipArchive::new(file).map_err(|e| format!("Invalid archive: {}", e))?;
+    let manifest: ArchiveManifest = serde_json::from_slice(
+        &read_entry(&mut zip, MANIFEST_FILE).map_err(|_| "Not a Jan thread archive")?,
+    )
+    .map_err(|e| format!("Invalid archive manifest: {}", e))?;
+    if manifest.format != ARCHIVE_FORMAT {
+        return Err(format!("Unsupported archive format: {}", manifest.format));
+    }
+    if manifest.version > ARCHIVE_VERSION {
+        return Err(format!(
+            "Archive version {} is newer than the supported version {}",
+            manifest.version, ARCHIVE_VERSION
+        ));
+    }
+
+    let mut summary = ImportSummary::default();
+    restore_store_files(app, &mut zip, &manifest, &mut summary).await;
+    for entry in &manifest.threads {
+        match import_thread(app, &mut zip, &entry.id, &mut summary).await {
+            Ok(new_id) => {
+                summary.imported_threads += 1;
+                summary.thread_ids.insert(entry.id.clone(), new_id);
+            }
+            Err(error) => {
+                log::error!("Failed to import thread {}: {}", entry.id, error);
+                summary.failed.push(ThreadError {
+                    thread_id: entry.id.clone(),
+                    error,
+                });
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Put the files of the attachment store in an archive back into it. Done before the messages
+/// are created, so that their references to the files are recorded.
+async fn restore_store_files<R: Runtime>(
+    app: &AppHandle<R>,
+    zip: &mut ZipArchive<File>,
+    manifest: &ArchiveManifest,
+    summary: &mut ImportSummary,
+) {
+    let data_dir = get_jan_data_folder_path(app.clone());
+    for attachment in &manifest.attachments {
+        let stored = match read_entry(zip, &store_entry_name(&attachment.id)) {
+            Ok(data) => attachments::put_data(&data_dir, &data, attachment.name.clone()).await,
+            Err(e) => Err(e),
+        };
+        match stored {
+            Ok(stored) if stored.id == attachment.id => summary.stored_attachments += 1,
+            Ok(_) => log::warn!("Attachment {} does not match its content", attachment.id),
+            Err(e) => log::warn!("Failed to restore attachment {}: {}", attachment.id, e),
+        }
+    }
+}
+
+async fn import_thread<R: Runtime>(
+    app: &AppHandle<R>,
+    zip: &mut ZipArchive<File>,
+    thread_id: &str,
+    summary: &mut ImportSummary,
+) -> Result<String, String> {
+    let prefix = format!("{}/{}/", THREADS_DIR, thread_id);
+    let mut thread: Value =
+        serde_json::from_slice(&read_entry(zip, &format!("{}{}", prefix, THREADS_FILE))?)
+            .map_err(|e| e.to_string())?;
+    let messages = read_entry(zip, &format!("{}{}", prefix, MESSAGES_FILE))?;
+    let messages = String::from_utf8_lossy(&messages)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str::<Value>(line).map_err(|e| e.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let attachments: Vec<(String, PathBuf)> = zip
+        .file_names()
+        .filter_map(|name| Some((name.to_string(), attachment_path(name, &prefix)?)))
+        .collect();
+
+    // New message ids, by old id, so that branches and the active branch keep pointing at
+    // the imported messages
+    let new_ids: HashMap<String, String> = messages
+        .iter()
+        .filter_map(|message| message.get("id").and_then(|id| id.as_str()))
+        .map(|id| (id.to_string(), Uuid::new_v4().to_string()))
+        .collect();
+    let new_id_of = |id: &Value| id.as_str().and_then(|id| new_ids.get(id)).cloned();
+
+    if let Some(thread) = thread.as_object_mut() {
+        thread.remove("id");
+        if let Some(metadata) = thread.get_mut("metadata").and_then(|m| m.as_object_mut()) {
+            if let Some(active_id) = metadata.get(ACTIVE_MESSAGE_KEY).and_then(new_id_of) {
+                metadata.insert(ACTIVE_MESSAGE_KEY.to_string(), Value::String(active_id));
+            }
+        }
+    }
+    let created = create_thread(app.clone(), thread).await?;
+    let new_id = thread_id_of(&created).to_string();
+
+    // A thread that fails halfway is removed again rather than left incomplete
+    let result = async {
+        let mut imported_messages = 0;
+        for mut message in messages {
+            let id = message.get("id").and_then(new_id_of);
+            message["id"] = Value::String(id.unwrap_or_else(|| Uuid::new_v4().to_string()));
+            if let Some(parent_id) = message.get("parent_id").and_then(new_id_of) {
+                message["parent_id"] = Value::String(parent_id);
+            }
+            message["thread_id"] = Value::String(new_id.clone());
+            create_message(app.clone(), message).await?;
+            imported_messages += 1;
+        }
+
+        let mut copied = 0;
+        if !attachments.is_empty() && !should_use_sqlite() {
+            let thread_dir = get_thread_dir(app.clone(), &new_id);
+            for (name, relative) in &attachments {
+                let target = thread_dir.join(relative);
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+                }
+                fs::write(&target, read_entry(zip, name)?).map_err(|e| e.to_string())?;
+                copied += 1;
+            }
+        }
+        Ok::<_, String>((imported_messages, copied))
+    }
+    .await;
+
+    match result {
+        Ok((imported_messages, copied)) => {
+            summary.imported_messages += imported_messages;
+            summary.imported_attachments += copied;
+            summary.skipped_attachments += attachments.len() - copied;
+            Ok(new_id)
+        }
+        Err(e) => {
+            let _ = delete_thread_permanently(app.clone(), new_id).await;
+            Err(e)
+        }
+    }
+}
@@ -1,21 +1,39 @@
-use std::fs::{self, File};
-use std::io::{BufRead, BufReader, Write};
+use serde_json::Value;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
 use tauri::Runtime;
+use uuid::Uuid;
 
 // For async file write serialization
 use std::sync::OnceLock;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+use super::constants::MESSAGES_CORRUPT_FILE;
+use super::encryption::{open, to_record, write_json_file, RecordError};
+use super::events::on_messages_quarantined;
+use super::pagination::{record_append, save_index, IndexEntry};
 use super::utils::{get_messages_path, get_thread_metadata_path};
 
 // Global per-thread locks for message file writes
 pub static MESSAGE_LOCKS: OnceLock<Mutex<HashMap<String, Arc<Mutex<()>>>>> = OnceLock::new();
 
-/// Check if the platform should use SQLite (mobile platforms)
+// Set once the desktop SQLite backend is opened and migrated
+static DESKTOP_SQLITE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Check if threads should be stored in SQLite: always on mobile platforms,
+/// on desktop only when enabled through the `threads_storage` setting
 pub fn should_use_sqlite() -> bool {
     cfg!(any(target_os = "android", target_os = "ios"))
+        || DESKTOP_SQLITE_ENABLED.load(Ordering::Acquire)
+}
+
+/// Switch the desktop backend; the caller must have migrated the data first
+pub fn set_desktop_sqlite_enabled(enabled: bool) {
+    DESKTOP_SQLITE_ENABLED.store(enabled, Ordering::Release);
 }
 
 /// Get a lock for a specific thread to ensure thread-safe message file operations
@@ -30,63 +48,219 @@ pub async fn get_lock_for_thread(thread_id: &str) -> Arc<Mutex<()>> {
     lock
 }
 
-/// Write messages to a thread's messages.jsonl file
-pub fn write_messages_to_file(
-    messages: &[serde_json::Value],
-    path: &std::path::Path,
-) -> Result<(), String> {
-    let mut file = File::create(path).map_err(|e| e.to_string())?;
+/// Replace `path` with `data` so that a crash leaves either the old or the new content:
+/// the data is written and fsynced to a temp file in the same directory, then renamed over
+/// the target.
+pub fn write_file_atomic(path: &Path, data: &[u8]) -> Result<(), String> {
+    let dir = path.parent().ok_or("Invalid file path")?;
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or("Invalid file path")?;
+    let tmp_path = dir.join(format!(".{}.{}.tmp", file_name, Uuid::new_v4()));
+
+    let result = (|| {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    })();
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(format!("Failed to write {}: {}", path.display(), e));
+    }
+
+    // Persist the rename itself; directories cannot be opened for syncing on Windows
+    #[cfg(unix)]
+    {
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// Write messages to a thread's messages.jsonl file, along with its offset index
+pub fn write_messages_to_file(messages: &[Value], path: &Path) -> Result<(), String> {
+    let mut data = String::new();
+    let mut entries = Vec::with_capacity(messages.len());
     for msg in messages {
-        let data = serde_json::to_string(msg).map_err(|e| e.to_string())?;
-        writeln!(file, "{data}").map_err(|e| e.to_string())?;
+        let line = to_record(path, msg)?;
+        entries.push(IndexEntry {
+            id: message_id(msg),
+            offset: data.len() as u64,
+            len: line.len() as u64,
+        });
+        data.push_str(&line);
+        data.push('\n');
+    }
+    write_file_atomic(path, data.as_bytes())?;
+
+    // A failed index write only costs a rescan on the next page read
+    if let Err(e) = save_index(path, entries) {
+        log::warn!(
+            "Failed to write message index for {}: {}",
+            path.display(),
+            e
+        );
+    }
+    Ok(())
+}
+
+fn message_id(message: &Value) -> String {
+    message
+        .get("id")
+        .and_then(|id| id.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Append a message to a messages.jsonl file and fsync it.
+/// A line left unterminated by an interrupted append is closed first, so it is
+/// quarantined on its own instead of swallowing the new message.
+pub fn append_message_to_file(message: &Value, path: &Path) -> Result<(), String> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| e.to_string())?;
+
+    let line = to_record(path, message)?;
+    let mut data = format!("{line}\n");
+    let mut offset = file.metadata().map_err(|e| e.to_string())?.len();
+    if offset > 0 {
+        let mut last = [0u8; 1];
+        file.seek(SeekFrom::End(-1)).map_err(|e| e.to_string())?;
+        file.read_exact(&mut last).map_err(|e| e.to_string())?;
+        if last[0] != b'\n' {
+            data.insert(0, '\n');
+            offset += 1;
+        }
+    }
+
+    file.write_all(data.as_bytes()).map_err(|e| e.to_string())?;
+    file.sync_data().map_err(|e| e.to_string())?;
+    drop(file);
+
+    let entry = IndexEntry {
+        id: message_id(message),
+        offset,
+        len: line.len() as u64,
+    };
+    if let Err(e) = record_append(path, entry) {
+        log::warn!(
+            "Failed to update message index for {}: {}",
+            path.display(),
+            e
+        );
     }
     Ok(())
 }
 
-/// Read messages from a thread's messages.jsonl file
+/// Messages read from a messages.jsonl file
+#[derive(Debug, Default)]
+pub struct MessagesFile {
+    pub messages: Vec<Value>,
+    /// Lines that could not be parsed and were moved to messages.corrupt.jsonl
+    pub quarantined: usize,
+}
+
+/// Read messages from a thread's messages.jsonl file, emitting
+/// `threads://messages-quarantined` if corrupt lines had to be moved out of it
 pub fn read_messages_from_file<R: Runtime>(
     app_handle: tauri::AppHandle<R>,
     thread_id: &str,
-) -> Result<Vec<serde_json::Value>, String> {
-    let path = get_messages_path(app_handle, thread_id);
+) -> Result<Vec<Value>, String> {
+    let read = read_messages_with_recovery(&get_messages_path(app_handle.clone(), thread_id))?;
+    on_messages_quarantined(&app_handle, thread_id, read.quarantined);
+    Ok(read.messages)
+}
+
+/// Read messages from a messages.jsonl file at an explicit path
+pub fn read_messages_from_path(path: &Path) -> Result<Vec<Value>, String> {
+    Ok(read_messages_with_recovery(path)?.messages)
+}
+
+/// Read messages, moving lines that are not valid JSON (e.g. left by a crash during a
+/// write) to messages.corrupt.jsonl next to the file instead of failing the whole thread.
+/// Callers must hold the thread's lock, since the file is rewritten without those lines.
+pub fn read_messages_with_recovery(path: &Path) -> Result<MessagesFile, String> {
     if !path.exists() {
-        return Ok(vec![]);
+        return Ok(MessagesFile::default());
     }
 
-    let file = File::open(&path).map_err(|e| {
-        eprintln!("Error opening file {}: {}", path.display(), e);
+    let bytes = fs::read(path).map_err(|e| {
+        log::error!("Error reading file {}: {}", path.display(), e);
         e.to_string()
     })?;
-    let reader = BufReader::new(file);
-
-    let mut messages = Vec::new();
-    for line in reader.lines() {
-        let line = line.map_err(|e| {
-            eprintln!("Error reading line from file {}: {}", path.display(), e);
-            e.to_string()
-        })?;
-        let message: serde_json::Value = serde_json::from_str(&line).map_err(|e| {
-            eprintln!(
-                "Error parsing JSON from line in file {}: {}",
-                path.display(),
-                e
-            );
-            e.to_string()
-        })?;
-        messages.push(message);
+
+    let mut result = MessagesFile::default();
+    let mut valid_lines = Vec::new();
+    let mut corrupt_records = String::new();
+    for (index, line) in bytes.split(|b| *b == b'\n').enumerate() {
+        if line.iter().all(|b| b.is_ascii_whitespace()) {
+            continue;
+        }
+        let parsed = match open(line) {
+            Ok(data) => serde_json::from_slice::<Value>(&data).map_err(|e| e.to_string()),
+            // Without the key nothing can be told about the line, so it is left alone
+            Err(RecordError::Locked) => return Err(RecordError::Locked.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match parsed {
+            Ok(message) => {
+                result.messages.push(message);
+                valid_lines.push(line);
+            }
+            Err(e) => {
+                let record = serde_json::json!({
+                    "line": index + 1,
+                    "error": e,
+                    "content": String::from_utf8_lossy(line),
+                    "quarantined_at": chrono::Utc::now().timestamp(),
+                });
+                corrupt_records.push_str(&record.to_string());
+                corrupt_records.push('\n');
+                result.quarantined += 1;
+            }
+        }
     }
 
-    Ok(messages)
+    if result.quarantined > 0 {
+        log::warn!(
+            "Quarantined {} corrupt line(s) from {}",
+            result.quarantined,
+            path.display()
+        );
+        let corrupt_path = path.with_file_name(MESSAGES_CORRUPT_FILE);
+        let mut corrupt_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&corrupt_path)
+            .map_err(|e| e.to_string())?;
+        corrupt_file
+            .write_all(corrupt_records.as_bytes())
+            .and_then(|_| corrupt_file.sync_data())
+            .map_err(|e| e.to_string())?;
+
+        // Only drop the lines from the thread once they are safe in the sidecar
+        let mut data = valid_lines.join(&b'\n');
+        if !data.is_empty() {
+            data.push(b'\n');
+        }
+        write_file_atomic(path, &data)?;
+    }
+
+    Ok(result)
 }
 
 /// Update thread metadata by writing to thread.json
 pub fn update_thread_metadata<R: Runtime>(
     app_handle: tauri::AppHandle<R>,
     thread_id: &str,
-    thread: &serde_json::Value,
+    thread: &Value,
 ) -> Result<(), String> {
     let path = get_thread_metadata_path(app_handle, thread_id);
-    let data = serde_json::to_string_pretty(thread).map_err(|e| e.to_string())?;
-    fs::write(path, data).map_err(|e| e.to_string())?;
-    Ok(())
+    write_json_file(&path, thread)
 }
@@ -0,0 +1,599 @@
+/*!
+   Importers for Chat History of Other Apps
+
+   Converts exports of ChatGPT (`conversations.json`), Open WebUI and LibreChat into Jan thread
+   and message JSON and stores them through the regular thread commands. All three apps keep
+   conversations as trees where regenerated or edited messages start a new branch; only the
+   branch that was active in the source app is imported and the others are counted as skipped.
+
+   Imported threads remember their origin in `metadata.imported_from` / `metadata.source_id`,
+   so running an import again skips conversations that are already in Jan.
+*/
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tauri::{AppHandle, Runtime};
+
+use super::commands::{create_message, create_thread, delete_thread_permanently, list_threads};
+use super::migration::ThreadError;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportSource {
+    Chatgpt,
+    Openwebui,
+    Librechat,
+}
+
+impl ImportSource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ImportSource::Chatgpt => "chatgpt",
+            ImportSource::Openwebui => "openwebui",
+            ImportSource::Librechat => "librechat",
+        }
+    }
+}
+
+/// A conversation converted to Jan thread and message JSON, without ids
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvertedThread {
+    pub source_id: String,
+    pub thread: Value,
+    pub messages: Vec<Value>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SkippedConversation {
+    pub source_id: String,
+    pub title: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Conversion {
+    pub threads: Vec<ConvertedThread>,
+    pub skipped_conversations: Vec<SkippedConversation>,
+    /// Messages on branches that were not active in the source app
+    pub skipped_branch_messages: usize,
+    /// Messages without importable text: tool calls, images, hidden system prompts
+    pub skipped_messages: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HistoryImportReport {
+    pub source: ImportSource,
+    pub dry_run: bool,
+    /// Threads imported, or that would be imported on a dry run
+    pub threads: usize,
+    pub messages: usize,
+    pub skipped_branch_messages: usize,
+    pub skipped_messages: usize,
+    pub skipped_conversations: Vec<SkippedConversation>,
+    pub failed: Vec<ThreadError>,
+}
+
+/// Guess the app an export comes from by the shape of its first conversation
+pub fn detect_source(data: &Value) -> Option<ImportSource> {
+    let first = match data {
+        Value::Array(items) => items.first()?,
+        other => other,
+    };
+    if first.get("mapping").is_some() {
+        Some(ImportSource::Chatgpt)
+    } else if first.get("chat").is_some() {
+        Some(ImportSource::Openwebui)
+    } else if first.get("conversationId").is_some() && first.get("messages").is_some() {
+        Some(ImportSource::Librechat)
+    } else {
+        None
+    }
+}
+
+fn str_field<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key).and_then(|v| v.as_str())
+}
+
+/// Seconds as a float or integer, as used by ChatGPT and Open WebUI
+fn seconds_field(value: &Value, key: &str) -> Option<f64> {
+    value.get(key).and_then(|v| v.as_f64())
+}
+
+/// RFC 3339 timestamp as used by LibreChat, in seconds
+fn iso_field(value: &Value, key: &str) -> Option<f64> {
+    let time = chrono::DateTime::parse_from_rfc3339(str_field(value, key)?).ok()?;
+    Some(time.timestamp_millis() as f64 / 1000.0)
+}
+
+fn jan_thread(
+    source: ImportSource,
+    source_id: &str,
+    title: &str,
+    created: Option<f64>,
+    updated: Option<f64>,
+) -> Value {
+    let created = created.unwrap_or_else(|| chrono::Utc::now().timestamp() as f64);
+    let title = if title.trim().is_empty() {
+        "Imported conversation"
+    } else {
+        title.trim()
+    };
+    json!({
+        "object": "thread",
+        "title": title,
+        "assistants": [{"id": "jan", "name": "Jan"}],
+        "created": created,
+        "updated": updated.unwrap_or(created),
+        "metadata": {
+            "imported_from": source.as_str(),
+            "source_id": source_id,
+        },
+    })
+}
+
+/// Message timestamps are in milliseconds, unlike thread timestamps
+fn jan_message(role: &str, text: &str, created: Option<f64>, model: Option<&str>) -> Value {
+    let created_at = created
+        .map(|seconds| (seconds * 1000.0) as i64)
+        .unwrap_or(0);
+    let mut metadata = Map::new();
+    if let Some(model) = model {
+        metadata.insert("model".to_string(), Value::String(model.to_string()));
+    }
+    json!({
+        "object": "thread.message",
+        "role": role,
+        "content": [{"type": "text", "text": {"value": text, "annotations": []}}],
+        "status": "ready",
+        "created_at": created_at,
+        "completed_at": created_at,
+        "metadata": metadata,
+    })
+}
+
+/// Ids from the root of a tree down to `leaf`, following `parent_of`
+fn branch_to<'a>(leaf: &'a str, parent_of: impl Fn(&str) -> Option<&'a str>) -> Vec<&'a str> {
+    let mut branch = vec![leaf];
+    let mut seen = HashSet::from([leaf]);
+    while let Some(parent) = parent_of(branch[branch.len() - 1]) {
+        // Exports are not always well-formed; never loop forever on a cycle
+        if !seen.insert(parent) {
+            break;
+        }
+        branch.push(parent);
+    }
+    branch.reverse();
+    branch
+}
+
+/// Messages converted from one branch of a conversation
+struct Branch {
+    messages: Vec<Value>,
+    /// Source messages on the branch, converted or not
+    source_messages: usize,
+    skipped: usize,
+}
+
+impl Branch {
+    fn new() -> Self {
+        Self {
+            messages: Vec::new(),
+            source_messages: 0,
+            skipped: 0,
+        }
+    }
+
+    fn push(&mut self, role: &str, text: &str, created: Option<f64>, model: Option<&str>) {
+        self.source_messages += 1;
+        if ["user", "assistant", "system"].contains(&role) && !text.trim().is_empty() {
+            self.messages.push(jan_message(role, text, created, model));
+        } else {
+            self.skipped += 1;
+        }
+    }
+}
+
+fn finish_conversation(
+    conversion: &mut Conversion,
+    source_id: String,
+    thread: Value,
+    branch: Branch,
+    total_messages: usize,
+) {
+    conversion.skipped_messages += branch.skipped;
+    conversion.skipped_branch_messages += total_messages.saturating_sub(branch.source_messages);
+    if branch.messages.is_empty() {
+        conversion.skipped_conversations.push(SkippedConversation {
+            source_id,
+            title: str_field(&thread, "title").unwrap_or_default().to_string(),
+            reason: "No messages with text".to_string(),
+        });
+    } else {
+        conversion.threads.push(ConvertedThread {
+            source_id,
+            thread,
+            messages: branch.messages,
+        });
+    }
+}
+
+fn convert_chatgpt(conversation: &Value, conversion: &mut Conversion) -> Result<(), String> {
+    let mapping = conversation
+        .get("mapping")
+        .and_then(|m| m.as_object())
+        .ok_or("Missing mapping")?;
+    let source_id = str_field(conversation, "conversation_id")
+        .or_else(|| str_field(conversation, "id"))
+        .unwrap_or_default()
+        .to_string();
+
+    // current_node is the leaf of the branch that was on screen; without it use the newest leaf
+    let leaf = str_field(conversation, "current_node")
+        .filter(|id| mapping.contains_key(*id))
+        .or_else(|| {
+            mapping
+                .iter()
+                .filter(|(_, node)| {
+                    node.get("children")
+                        .and_then(|c| c.as_array())
+                        .map_or(true, |c| c.is_empty())
+                })
+                .max_by(|(_, a), (_, b)| {
+                    let time = |node: &Value| {
+                        node.get("message")
+                            .and_then(|m| seconds_field(m, "create_time"))
+                            .unwrap_or_default()
+                    };
+                    time(a).total_cmp(&time(b))
+                })
+                .map(|(id, _)| id.as_str())
+        })
+        .ok_or("Conversation has no messages")?;
+
+    let mut branch = Branch::new();
+    for id in branch_to(leaf, |id| {
+        mapping.get(id).and_then(|n| str_field(n, "parent"))
+    }) {
+        let Some(message) = mapping.get(id).and_then(|n| n.get("message")) else {
+            continue;
+        };
+        if message.is_null() {
+            continue;
+        }
+        let role = message
+            .get("author")
+            .and_then(|a| str_field(a, "role"))
+            .unwrap_or_default();
+        let hidden = message
+            .get("metadata")
+            .and_then(|m| m.get("is_visually_hidden_from_conversation"))
+            .and_then(|h| h.as_bool())
+            .unwrap_or(false);
+        let content = message.get("content").cloned().unwrap_or_default();
+        let text = match str_field(&content, "content_type") {
+            // Image and file parts of multimodal messages are objects and are left out
+            Some("text") | Some("multimodal_text") if !hidden => content
+                .get("parts")
+                .and_then(|p| p.as_array())
+                .map(|parts| {
+                    parts
+                        .iter()
+                        .filter_map(|part| part.as_str())
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                })
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
+        let model = message
+            .get("metadata")
+            .and_then(|m| str_field(m, "model_slug"));
+        branch.push(role, &text, seconds_field(message, "create_time"), model);
+    }
+
+    let total_messages = mapping
+        .values()
+        .filter(|node| node.get("message").is_some_and(|m| !m.is_null()))
+        .count();
+    let thread = jan_thread(
+        ImportSource::Chatgpt,
+        &source_id,
+        str_field(conversation, "title").unwrap_or_default(),
+        seconds_field(conversation, "create_time"),
+        seconds_field(conversation, "update_time"),
+    );
+    finish_conversation(conversion, source_id, thread, branch, total_messages);
+    Ok(())
+}
+
+fn convert_openwebui(item: &Value, conversion: &mut Conversion) -> Result<(), String> {
+    let chat = item.get("chat").ok_or("Missing chat")?;
+    let source_id = str_field(item, "id")
+        .or_else(|| str_field(chat, "id"))
+        .unwrap_or_default()
+        .to_string();
+
+    let mut branch = Branch::new();
+    let mut push = |message: &Value| {
+        branch.push(
+            str_field(message, "role").unwrap_or_default(),
+            str_field(message, "content").unwrap_or_default(),
+            seconds_field(message, "timestamp"),
+            str_field(message, "model"),
+        )
+    };
+    let history = chat.get("history").and_then(|h| h.get("messages"));
+    let total_messages = match (history.and_then(|m| m.as_object()), chat.get("history")) {
+        (Some(messages), Some(history)) => {
+            if let Some(leaf) =
+                str_field(history, "currentId").filter(|id| messages.contains_key(*id))
+            {
+                let branch_ids = branch_to(leaf, |id| {
+                    messages.get(id).and_then(|m| str_field(m, "parentId"))
+                });
+                for id in branch_ids {
+                    if let Some(message) = messages.get(id) {
+                        push(message);
+                    }
+                }
+            }
+            messages.len()
+        }
+        // Older exports only have the visible messages as a list
+        _ => {
+            let messages = chat
+                .get("messages")
+                .and_then(|m| m.as_array())
+                .cloned()
+                .unwrap_or_default();
+            messages.iter().for_each(&mut push);
+            messages.len()
+        }
+    };
+
+    let title = str_field(item, "title")
+        .or_else(|| str_field(chat, "title"))
+        .unwrap_or_default();
+    let thread = jan_thread(
+        ImportSource::Openwebui,
+        &source_id,
+        title,
+        seconds_field(item, "created_at"),
+        seconds_field(item, "updated_at"),
+    );
+    finish_conversation(conversion, source_id, thread, branch, total_messages);
+    Ok(())
+}
+
+/// LibreChat exports messages either as a list linked by `parentMessageId`
+/// or, for "recursive" exports, as nested `children`
+fn flatten_librechat<'a>(
+    messages: &'a [Value],
+    parent: Option<&'a str>,
+    nodes: &mut Vec<(&'a Value, Option<&'a str>)>,
+) {
+    for message in messages {
+        let parent = parent.or_else(|| str_field(message, "parentMessageId"));
+        nodes.push((message, parent));
+        if let Some(children) = message.get("children").and_then(|c| c.as_array()) {
+            flatten_librechat(children, str_field(message, "messageId"), nodes);
+        }
+    }
+}
+
+fn librechat_text(message: &Value) -> String {
+    if let Some(text) = str_field(message, "text").filter(|text| !text.is_empty()) {
+        return text.to_string();
+    }
+    message
+        .get("content")
+        .and_then(|c| c.as_array())
+        .map(|parts| {
+            parts
+                .iter()
+                .filter(|part| str_field(part, "type") == Some("text"))
+                .filter_map(|part| {
+                    let text = part.get("text")?;
+                    text.as_str().or_else(|| str_field(text, "value"))
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_default()
+}
+
+fn convert_librechat(conversation: &Value, conversion: &mut Conversion) -> Result<(), String> {
+    let messages = conversation
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .ok_or("Missing messages")?;
+    let source_id = str_field(conversation, "conversationId")
+        .unwrap_or_default()
+        .to_string();
+
+    let mut nodes = Vec::new();
+    flatten_librechat(messages, None, &mut nodes);
+    let by_id: HashMap<&str, (&Value, Option<&str>)> = nodes
+        .iter()
+        .filter_map(|(message, parent)| {
+            Some((str_field(message, "messageId")?, (*message, *parent)))
+        })
+        .collect();
+    let parents: HashSet<&str> = nodes.iter().filter_map(|(_, parent)| *parent).collect();
+
+    // LibreChat does not store the selected branch; take the one with the newest message
+    let leaf = nodes
+        .iter()
+        .filter_map(|(message, _)| str_field(message, "messageId"))
+        .filter(|id| !parents.contains(id))
+        .max_by(|a, b| {
+            let time = |id: &str| iso_field(by_id[id].0, "createdAt").unwrap_or_default();
+            time(a).total_cmp(&time(b))
+        });
+
+    let mut branch = Branch::new();
+    if let Some(leaf) = leaf {
+        for id in branch_to(leaf, |id| by_id.get(id).and_then(|(_, parent)| *parent)) {
+            let Some((message, _)) = by_id.get(id) else {
+                continue;
+            };
+            let is_user = message
+                .get("isCreatedByUser")
+                .and_then(|u| u.as_bool())
+                .unwrap_or(false);
+            branch.push(
+                if is_user { "user" } else { "assistant" },
+                &librechat_text(message),
+                iso_field(message, "createdAt"),
+                str_field(message, "model"),
+            );
+        }
+    }
+
+    let thread = jan_thread(
+        ImportSource::Librechat,
+        &source_id,
+        str_field(conversation, "title").unwrap_or_default(),
+        iso_field(conversation, "createdAt"),
+        iso_field(conversation, "updatedAt"),
+    );
+    finish_conversation(conversion, source_id, thread, branch, nodes.len());
+    Ok(())
+}
+
+/// Convert an export to Jan threads. `source` is detected when not given.
+pub fn convert_history(
+    data: &Value,
+    source: Option<ImportSource>,
+) -> Result<(ImportSource, Conversion), String> {
+    let source = source
+        .or_else(|| detect_source(data))
+        .ok_or("Unrecognized chat history format")?;
+    let conversations = match data {
+        Value::Array(items) => items.iter().collect::<Vec<_>>(),
+        other => vec![other],
+    };
+
+    let mut conversion = Conversion::default();
+    for (index, conversation) in conversations.into_iter().enumerate() {
+        let result = match source {
+            ImportSource::Chatgpt => convert_chatgpt(conversation, &mut conversion),
+            ImportSource::Openwebui => convert_openwebui(conversation, &mut conversion),
+            ImportSource::Librechat => convert_librechat(conversation, &mut conversion),
+        };
+        if let Err(reason) = result {
+            conversion.skipped_conversations.push(SkippedConversation {
+                source_id: format!("#{}", index),
+                title: str_field(conversation, "title")
+                    .unwrap_or_default()
+                    .to_string(),
+                reason,
+            });
+        }
+    }
+    Ok((source, conversion))
+}
+
+/// Import the chat history export at `path`. With `dry_run` nothing is written
+/// and the report tells what an import would do.
+pub async fn import_history<R: Runtime>(
+    app: &AppHandle<R>,
+    path: &Path,
+    source: Option<ImportSource>,
+    dry_run: bool,
+) -> Result<HistoryImportReport, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    let data: Value =
+        serde_json::from_slice(&data).map_err(|e| format!("Invalid export file: {}", e))?;
+    let (source, conversion) = convert_history(&data, source)?;
+
+    let imported: HashSet<String> = list_threads(app.clone(), None)
+        .await?
+        .iter()
+        .filter_map(|thread| {
+            let metadata = thread.get("metadata")?;
+            (str_field(metadata, "imported_from")? == source.as_str())
+                .then(|| str_field(metadata, "source_id").map(|id| id.to_string()))?
+        })
+        .collect();
+
+    let mut report = HistoryImportReport {
+        source,
+        dry_run,
+        threads: 0,
+        messages: 0,
+        skipped_branch_messages: conversion.skipped_branch_messages,
+        skipped_messages: conversion.skipped_messages,
+        skipped_conversations: conversion.skipped_conversations,
+        failed: vec![],
+    };
+    for converted in conversion.threads {
+        if !converted.source_id.is_empty() && imported.contains(&converted.source_id) {
+            report.skipped_conversations.push(SkippedConversation {
+                title: str_field(&converted.thread, "title")
+                    .unwrap_or_default()
+                    .to_string(),
+                source_id: converted.source_id,
+                reason: "Already imported".to_string(),
+            });
+            continue;
+        }
+        if dry_run {
+            report.threads += 1;
+            report.messages += converted.messages.len();
+            continue;
+        }
+
+        let count = converted.messages.len();
+        match import_thread(app, converted.thread, converted.messages).await {
+            Ok(()) => {
+                report.threads += 1;
+                report.messages += count;
+            }
+            Err(error) => {
+                log::error!(
+                    "Failed to import conversation {}: {}",
+                    converted.source_id,
+                    error
+                );
+                report.failed.push(ThreadError {
+                    thread_id: converted.source_id,
+                    error,
+                });
+            }
+        }
+    }
+
+    if !dry_run {
+        log::info!(
+            "Imported {} threads with {} messages from {}",
+            report.threads,
+            report.messages,
+            source.as_str()
+        );
+    }
+    Ok(report)
+}
+
+async fn import_thread<R: Runtime>(
+    app: &AppHandle<R>,
+    thread: Value,
+    messages: Vec<Value>,
+) -> Result<(), String> {
+    let created = create_thread(app.clone(), thread).await?;
+    let thread_id = str_field(&created, "id")
+        .ok_or("Created thread has no id")?
+        .to_string();
+
+    for mut message in messages {
+        message["thread_id"] = Value::String(thread_id.clone());
+        if let Err(e) = create_message(app.clone(), message).await {
+            // Don't leave a half-imported thread behind
+            let _ = delete_thread_permanently(app.clone(), thread_id).await;
+            return Err(e);
+        }
+    }
+    Ok(())
+}
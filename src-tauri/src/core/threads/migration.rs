@@ -0,0 +1,585 @@
+/*!
+   Migration Between File-Based and SQLite Thread Storage
+
+   Desktop threads live in `threads/<id>/thread.json` + `messages.jsonl`. When the SQLite backend
+   is enabled, every thread directory is copied into the database in its own transaction and
+   recorded in `threads_migration` together with the size and modification time of its files, so
+   an interrupted migration resumes where it stopped instead of starting over, and a thread whose
+   files changed after it was copied is copied again. The files are left untouched until the user
+   switches back, at which point the database is exported to the same layout.
+
+   `upgrade_files_schema` / `upgrade_sqlite_schema` bring records written by older versions of
+   Jan up to the current thread and message schema, see `models`. Once a storage is upgraded, its
+   schema version is recorded (`threads/.schema_version`, or the `threads_meta` table), and the
+   startup check skips the scan until the schema changes again.
+
+   All functions take the pool and the threads directory explicitly so they can run against
+   temporary data in tests.
+*/
+
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{Row, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+use super::constants::{MESSAGES_FILE, SCHEMA_VERSION_FILE, THREADS_FILE};
+use super::encryption::{from_record, read_json_file, to_record, write_json_file, LOCKED_ERROR};
+use super::helpers::{
+    get_lock_for_thread, read_messages_from_path, write_file_atomic, write_messages_to_file,
+};
+use super::models::{normalize_message, normalize_thread, schema_version, SCHEMA_VERSION};
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct MigrationReport {
+    /// Threads copied by this run
+    pub migrated_threads: usize,
+    /// Threads copied by a previous, possibly interrupted, run and unchanged since
+    pub skipped_threads: usize,
+    pub migrated_messages: usize,
+    pub failed: Vec<ThreadError>,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct SchemaUpgradeReport {
+    pub upgraded_threads: usize,
+    pub upgraded_messages: usize,
+    /// Threads left as they were because a record could not be upgraded
+    pub failed: Vec<ThreadError>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ThreadError {
+    pub thread_id: String,
+    pub error: String,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct VerificationReport {
+    pub checked_threads: usize,
+    pub mismatches: Vec<CountMismatch>,
+}
+
+impl VerificationReport {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// A thread whose message count differs between the files and the database.
+/// `db_messages` is `None` when the thread is missing from the database.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CountMismatch {
+    pub thread_id: String,
+    pub file_messages: usize,
+    pub db_messages: Option<usize>,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct ExportReport {
+    pub exported_threads: usize,
+    pub exported_messages: usize,
+    /// Thread directories removed because the thread no longer exists in the database
+    pub removed_threads: usize,
+}
+
+async fn ensure_migration_table(pool: &SqlitePool) -> Result<(), String> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS threads_migration (
+            thread_id TEXT PRIMARY KEY,
+            message_count INTEGER NOT NULL,
+            files_stamp TEXT NOT NULL DEFAULT '',
+            migrated_at INTEGER DEFAULT (strftime('%s', 'now'))
+        );
+        "#,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to create migration table: {}", e))?;
+    Ok(())
+}
+
+/// Thread directories (those containing a thread.json), sorted by id
+fn list_thread_ids(threads_dir: &Path) -> Result<Vec<String>, String> {
+    if !threads_dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut ids: Vec<String> = fs::read_dir(threads_dir)
+        .map_err(|e| e.to_string())?
+        .flatten()
+        .filter(|entry| entry.path().join(THREADS_FILE).is_file())
+        .filter_map(|entry| entry.file_name().to_str().map(|name| name.to_string()))
+        .collect();
+    ids.sort();
+    Ok(ids)
+}
+
+/// Read a thread directory, filling in ids the same way the file backend would have
+fn read_thread_files(threads_dir: &Path, thread_id: &str) -> Result<(Value, Vec<Value>), String> {
+    let thread_dir = threads_dir.join(thread_id);
+    let mut thread: Value = read_json_file(&thread_dir.join(THREADS_FILE))?;
+    // The directory name is what the file backend looks threads up by
+    thread["id"] = Value::String(thread_id.to_string());
+
+    let mut messages = read_messages_from_path(&thread_dir.join(MESSAGES_FILE))?;
+    for message in messages.iter_mut() {
+        if message.get("id").and_then(|id| id.as_str()).is_none() {
+            message["id"] = Value::String(Uuid::new_v4().to_string());
+        }
+        if message
+            .get("thread_id")
+            .and_then(|id| id.as_str())
+            .is_none()
+        {
+            message["thread_id"] = Value::String(thread_id.to_string());
+        }
+    }
+    Ok((thread, messages))
+}
+
+/// Size and modification time of the files of a thread, to tell whether they changed since
+/// the thread was migrated
+fn files_stamp(threads_dir: &Path, thread_id: &str) -> String {
+    let thread_dir = threads_dir.join(thread_id);
+    [THREADS_FILE, MESSAGES_FILE]
+        .iter()
+        .map(|file| match fs::metadata(thread_dir.join(file)) {
+            Ok(metadata) => {
+                let modified = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|since| since.as_nanos())
+                    .unwrap_or_default();
+                format!("{}:{}", metadata.len(), modified)
+            }
+            Err(_) => "-".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Message ids repeat when a line was duplicated; the database keeps one row per id
+fn distinct_message_count(messages: &[Value]) -> usize {
+    messages
+        .iter()
+        .filter_map(|message| message.get("id").and_then(|id| id.as_str()))
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// Copy every thread directory under `threads_dir` that has not been migrated yet, or whose
+/// files changed since it was
+pub async fn migrate_files_to_sqlite(
+    pool: &SqlitePool,
+    threads_dir: &Path,
+) -> Result<MigrationReport, String> {
+    ensure_migration_table(pool).await?;
+
+    let migrated: HashMap<String, String> =
+        sqlx::query("SELECT thread_id, files_stamp FROM threads_migration")
+            .fetch_all(pool)
+            .await
+            .map_err(|e| format!("Failed to read migration state: {}", e))?
+            .iter()
+            .map(|row| (row.get("thread_id"), row.get("files_stamp")))
+            .collect();
+
+    let mut report = MigrationReport::default();
+    for thread_id in list_thread_ids(threads_dir)? {
+        // Keep writers of this thread out while it is being checked and copied
+        let lock = get_lock_for_thread(&thread_id).await;
+        let _guard = lock.lock().await;
+
+        let stamp = files_stamp(threads_dir, &thread_id);
+        if migrated.get(&thread_id) == Some(&stamp) {
+            report.skipped_threads += 1;
+            continue;
+        }
+
+        match migrate_thread(pool, threads_dir, &thread_id, &stamp).await {
+            Ok(count) => {
+                report.migrated_threads += 1;
+                report.migrated_messages += count;
+            }
+            Err(error) => {
+                log::error!("Failed to migrate thread {}: {}", thread_id, error);
+                report.failed.push(ThreadError { thread_id, error });
+            }
+        }
+    }
+
+    log::info!(
+        "Thread migration: {} migrated, {} already migrated, {} failed",
+        report.migrated_threads,
+        report.skipped_threads,
+        report.failed.len()
+    );
+    Ok(report)
+}
+
+async fn migrate_thread(
+    pool: &SqlitePool,
+    threads_dir: &Path,
+    thread_id: &str,
+    stamp: &str,
+) -> Result<usize, String> {
+    let (thread, messages) = read_thread_files(threads_dir, thread_id)?;
+    let data = to_record(threads_dir, &thread)?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    sqlx::query(
+        "INSERT INTO threads (id, data) VALUES (?1, ?2) ON CONFLICT(id) DO UPDATE SET data = excluded.data",
+    )
+    .bind(thread_id)
+    .bind(&data)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to insert thread: {}", e))?;
+
+    // Rows may be left over from an earlier migration of this thread
+    sqlx::query("DELETE FROM messages WHERE thread_id = ?1")
+        .bind(thread_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to clear messages: {}", e))?;
+
+    for message in &messages {
+        let message_id = message
+            .get("id")
+            .and_then(|id| id.as_str())
+            .unwrap_or_default();
+        let data = to_record(threads_dir, message)?;
+        sqlx::query(
+            "INSERT INTO messages (id, thread_id, data) VALUES (?1, ?2, ?3) ON CONFLICT(id) DO UPDATE SET thread_id = excluded.thread_id, data = excluded.data",
+        )
+        .bind(message_id)
+        .bind(thread_id)
+        .bind(&data)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to insert message: {}", e))?;
+    }
+
+    let count = distinct_message_count(&messages);
+    sqlx::query(
+        "INSERT INTO threads_migration (thread_id, message_count, files_stamp) VALUES (?1, ?2, ?3) ON CONFLICT(thread_id) DO UPDATE SET message_count = excluded.message_count, files_stamp = excluded.files_stamp",
+    )
+    .bind(thread_id)
+    .bind(count as i64)
+    .bind(stamp)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to record migration: {}", e))?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+/// Compare the message count of every thread directory with the database
+pub async fn verify_files_against_sqlite(
+    pool: &SqlitePool,
+    threads_dir: &Path,
+) -> Result<VerificationReport, String> {
+    let mut report = VerificationReport::default();
+    for thread_id in list_thread_ids(threads_dir)? {
+        report.checked_threads += 1;
+
+        let file_messages = match read_thread_files(threads_dir, &thread_id) {
+            Ok((_, messages)) => distinct_message_count(&messages),
+            Err(e) => {
+                log::warn!(
+                    "Failed to read thread {} for verification: {}",
+                    thread_id,
+                    e
+                );
+                0
+            }
+        };
+
+        let exists = sqlx::query("SELECT 1 FROM threads WHERE id = ?1")
+            .bind(&thread_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| e.to_string())?
+            .is_some();
+        let db_messages = if exists {
+            let count: i64 =
+                sqlx::query("SELECT COUNT(*) AS count FROM messages WHERE thread_id = ?1")
+                    .bind(&thread_id)
+                    .fetch_one(pool)
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .get("count");
+            Some(count as usize)
+        } else {
+            None
+        };
+
+        if db_messages != Some(file_messages) {
+            report.mismatches.push(CountMismatch {
+                thread_id,
+                file_messages,
+                db_messages,
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// Write every thread in the database back to `threads_dir` in the file layout.
+/// With `prune` the files become the only copy: thread directories that no longer exist
+/// in the database are removed and the database is emptied, so a later switch back to
+/// SQLite migrates from scratch instead of resurrecting stale rows.
+pub async fn export_sqlite_to_files(
+    pool: &SqlitePool,
+    threads_dir: &Path,
+    prune: bool,
+) -> Result<ExportReport, String> {
+    fs::create_dir_all(threads_dir).map_err(|e| e.to_string())?;
+
+    let rows = sqlx::query("SELECT id, data FROM threads")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to list threads: {}", e))?;
+
+    let mut report = ExportReport::default();
+    let mut exported = HashSet::new();
+    for row in &rows {
+        let thread_id: String = row.get("id");
+        let thread: Value = from_record(row.get::<&str, _>("data").as_bytes())?;
+
+        let messages: Vec<Value> = sqlx::query(
+            "SELECT data FROM messages WHERE thread_id = ?1 ORDER BY created_at ASC, rowid ASC",
+        )
+        .bind(&thread_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to list messages: {}", e))?
+        .iter()
+        .map(|row| from_record(row.get::<&str, _>("data").as_bytes()))
+        .collect::<Result<_, String>>()?;
+
+        let lock = get_lock_for_thread(&thread_id).await;
+        let _guard = lock.lock().await;
+
+        let thread_dir = threads_dir.join(&thread_id);
+        fs::create_dir_all(&thread_dir).map_err(|e| e.to_string())?;
+        write_json_file(&thread_dir.join(THREADS_FILE), &thread)?;
+        write_messages_to_file(&messages, &thread_dir.join(MESSAGES_FILE))?;
+
+        report.exported_threads += 1;
+        report.exported_messages += messages.len();
+        exported.insert(thread_id);
+    }
+
+    if prune {
+        for thread_id in list_thread_ids(threads_dir)? {
+            if !exported.contains(&thread_id) {
+                fs::remove_dir_all(threads_dir.join(&thread_id)).map_err(|e| e.to_string())?;
+                report.removed_threads += 1;
+            }
+        }
+
+        ensure_migration_table(pool).await?;
+        let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+        for table in ["messages", "threads", "threads_migration"] {
+            sqlx::query(&format!("DELETE FROM {}", table))
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to clear {}: {}", table, e))?;
+        }
+        tx.commit().await.map_err(|e| e.to_string())?;
+    }
+
+    Ok(report)
+}
+
+fn is_outdated(record: &Value) -> bool {
+    schema_version(record) < SCHEMA_VERSION
+}
+
+impl SchemaUpgradeReport {
+    /// Whether the run covered every record, so the storage can be marked as upgraded.
+    /// Records that fail validation do not change on a later run; locked ones may.
+    fn is_complete(&self) -> bool {
+        self.failed
+            .iter()
+            .all(|failure| failure.error != LOCKED_ERROR)
+    }
+}
+
+/// Whether the file storage was upgraded to the current schema
+pub fn files_schema_is_current(threads_dir: &Path) -> bool {
+    fs::read_to_string(threads_dir.join(SCHEMA_VERSION_FILE))
+        .ok()
+        .and_then(|version| version.trim().parse::<u64>().ok())
+        .is_some_and(|version| version >= SCHEMA_VERSION)
+}
+
+async fn ensure_meta_table(pool: &SqlitePool) -> Result<(), String> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS threads_meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);",
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to create meta table: {}", e))?;
+    Ok(())
+}
+
+/// Whether the SQLite storage was upgraded to the current schema
+pub async fn sqlite_schema_is_current(pool: &SqlitePool) -> Result<bool, String> {
+    ensure_meta_table(pool).await?;
+    let version: Option<String> =
+        sqlx::query_scalar("SELECT value FROM threads_meta WHERE key = 'schema_version'")
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    Ok(version
+        .and_then(|version| version.parse::<u64>().ok())
+        .is_some_and(|version| version >= SCHEMA_VERSION))
+}
+
+/// Upgrade one thread directory; nothing is written unless every record upgrades.
+/// Returns the number of upgraded threads (0 or 1) and messages.
+fn upgrade_thread_dir(thread_dir: &Path) -> Result<(usize, usize), String> {
+    let thread_path = thread_dir.join(THREADS_FILE);
+    let thread: Value = read_json_file(&thread_path)?;
+    let thread = is_outdated(&thread)
+        .then(|| normalize_thread(thread))
+        .transpose()?;
+
+    let messages_path = thread_dir.join(MESSAGES_FILE);
+    let mut messages = if messages_path.exists() {
+        read_messages_from_path(&messages_path)?
+    } else {
+        Vec::new()
+    };
+    let mut upgraded_messages = 0;
+    for message in messages.iter_mut() {
+        if is_outdated(message) {
+            *message = normalize_message(message.take())?;
+            upgraded_messages += 1;
+        }
+    }
+
+    if let Some(thread) = &thread {
+        write_json_file(&thread_path, thread)?;
+    }
+    if upgraded_messages > 0 {
+        write_messages_to_file(&messages, &messages_path)?;
+    }
+    Ok((usize::from(thread.is_some()), upgraded_messages))
+}
+
+/// Upgrade the thread and message records of the file storage to the current schema
+pub async fn upgrade_files_schema(threads_dir: &Path) -> Result<SchemaUpgradeReport, String> {
+    let mut report = SchemaUpgradeReport::default();
+    for thread_id in list_thread_ids(threads_dir)? {
+        let lock = get_lock_for_thread(&thread_id).await;
+        let _guard = lock.lock().await;
+        match upgrade_thread_dir(&threads_dir.join(&thread_id)) {
+            Ok((threads, messages)) => {
+                report.upgraded_threads += threads;
+                report.upgraded_messages += messages;
+            }
+            Err(error) => report.failed.push(ThreadError { thread_id, error }),
+        }
+    }
+    if report.is_complete() && threads_dir.exists() {
+        write_file_atomic(
+            &threads_dir.join(SCHEMA_VERSION_FILE),
+            SCHEMA_VERSION.to_string().as_bytes(),
+        )?;
+    }
+    Ok(report)
+}
+
+/// Upgrade the thread and message records stored in SQLite to the current schema.
+/// A thread is upgraded in one transaction together with its messages. `data_dir` is the
+/// data folder the database belongs to, whose keys encrypt the upgraded records.
+pub async fn upgrade_sqlite_schema(
+    pool: &SqlitePool,
+    data_dir: &Path,
+) -> Result<SchemaUpgradeReport, String> {
+    let rows = sqlx::query("SELECT id, data FROM threads")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to list threads: {}", e))?;
+
+    let mut report = SchemaUpgradeReport::default();
+    for row in &rows {
+        let thread_id: String = row.get("id");
+        let data: String = row.get("data");
+        match upgrade_sqlite_thread(pool, data_dir, &thread_id, &data).await {
+            Ok((threads, messages)) => {
+                report.upgraded_threads += threads;
+                report.upgraded_messages += messages;
+            }
+            Err(error) => report.failed.push(ThreadError { thread_id, error }),
+        }
+    }
+    if report.is_complete() {
+        ensure_meta_table(pool).await?;
+        sqlx::query(
+            "INSERT OR REPLACE INTO threads_meta (key, value) VALUES ('schema_version', ?1)",
+        )
+        .bind(SCHEMA_VERSION.to_string())
+        .execute(pool)
+        .await
+        .map_err(|e| format!("Failed to record schema version: {}", e))?;
+    }
+    Ok(report)
+}
+
+async fn upgrade_sqlite_thread(
+    pool: &SqlitePool,
+    data_dir: &Path,
+    thread_id: &str,
+    data: &str,
+) -> Result<(usize, usize), String> {
+    let thread: Value = from_record(data.as_bytes())?;
+    let thread = is_outdated(&thread)
+        .then(|| normalize_thread(thread))
+        .transpose()?;
+
+    let mut messages = Vec::new();
+    for row in sqlx::query("SELECT id, data FROM messages WHERE thread_id = ?1")
+        .bind(thread_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to list messages: {}", e))?
+    {
+        let message: Value = from_record(row.get::<&str, _>("data").as_bytes())?;
+        if is_outdated(&message) {
+            messages.push((row.get::<String, _>("id"), normalize_message(message)?));
+        }
+    }
+    if thread.is_none() && messages.is_empty() {
+        return Ok((0, 0));
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    if let Some(thread) = &thread {
+        sqlx::query("UPDATE threads SET data = ?1 WHERE id = ?2")
+            .bind(to_record(data_dir, thread)?)
+            .bind(thread_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to upgrade thread: {}", e))?;
+    }
+    for (message_id, message) in &messages {
+        sqlx::query("UPDATE messages SET data = ?1 WHERE id = ?2")
+            .bind(to_record(data_dir, message)?)
+            .bind(message_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to upgrade message: {}", e))?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok((usize::from(thread.is_some()), messages.len()))
+}
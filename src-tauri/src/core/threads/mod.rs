@@ -2,7 +2,8 @@
    Thread and Message Persistence Module
 
    This module provides all logic for managing threads and their messages, including creation, modification, deletion, and listing.
-   Messages for each thread are persisted in a JSONL file (messages.jsonl) per thread directory.
+   Messages for each thread are persisted in a JSONL file (messages.jsonl) per thread directory, or in SQLite on
+   mobile and on desktop when the `threads_storage` setting is `sqlite`.
 
    **Concurrency and Consistency Guarantee:**
    - All operations that write or modify messages for a thread are protected by a global, per-thread asynchronous lock.
@@ -10,12 +11,25 @@
    - As a result, the messages.jsonl file for each thread is always consistent and never corrupted, even under concurrent access.
 */
 
+pub mod attachments;
+pub mod branches;
 pub mod commands;
 mod constants;
-#[cfg(any(target_os = "android", target_os = "ios"))]
 pub mod db;
+pub mod encryption;
+pub mod events;
+pub mod export;
 pub mod helpers;
+pub mod importers;
+pub mod migration;
+pub mod models;
+pub mod pagination;
+pub mod search;
+pub mod sync;
+pub mod thread_index;
+pub mod trash;
 pub mod utils;
+pub mod watcher;
 
 #[cfg(test)]
 mod tests;
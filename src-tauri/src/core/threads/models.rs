@@ -0,0 +1,385 @@
+/*!
+   Typed Thread and Message Records
+
+   Threads and messages are stored as the JSON the frontend sends, so these types accept every
+   field the frontend knows about and keep the rest in a flattened `extra` map; a record passes
+   through them without losing fields added by newer frontends. Writes go through
+   `normalize_thread` / `normalize_message`, which upgrade the record to the current schema,
+   validate it and stamp it with `schema_version`.
+
+   Records without `schema_version` are version 0. Version 1 makes `assistants` a list, gives
+   assistants that only have the legacy `assistant_id` an `id`, and turns plain-string message
+   `content` into a list of content parts. Legacy `null` values read as the field's default,
+   except for `metadata`, which keeps its `null`. Records on disk are upgraded by
+   `migration::upgrade_files_schema` / `upgrade_sqlite_schema`.
+*/
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Map, Number, Value};
+
+pub const SCHEMA_VERSION: u64 = 1;
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Thread {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub title: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub assistants: Vec<Assistant>,
+    /// Seconds since the epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<Number>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated: Option<Number>,
+    /// `Some(None)` for a `null` metadata
+    #[serde(
+        default,
+        deserialize_with = "null_as_some",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub metadata: Option<Option<Map<String, Value>>>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub schema_version: u64,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Assistant {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Value>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+    /// Roles this version does not know are kept as they are
+    #[serde(untagged)]
+    Other(String),
+}
+
+/// A message. `parent_id` stays in `extra` because a missing and a `null` parent mean
+/// different things, see `branches`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Message {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub thread_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assistant_id: Option<String>,
+    pub role: Role,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub content: Vec<ContentPart>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// Milliseconds since the epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<Number>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<Number>,
+    /// `Some(None)` for a `null` metadata
+    #[serde(
+        default,
+        deserialize_with = "null_as_some",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub metadata: Option<Option<Map<String, Value>>>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub schema_version: u64,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A part of a message's content, tagged by `type`. Parts of types this version does not
+/// know are kept as they are.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentPart {
+    Text(TextPart),
+    ImageUrl(ImagePart),
+    ToolCall(ToolCallPart),
+    ToolResult(ToolResultPart),
+    Other(Map<String, Value>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TextPart {
+    pub text: TextValue,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Older messages store the text directly, newer ones as `{ value, annotations }`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum TextValue {
+    Plain(String),
+    Annotated {
+        value: String,
+        #[serde(default)]
+        annotations: Vec<Value>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ImagePart {
+    pub image_url: ImageUrl,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ImageUrl {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ToolCallPart {
+    pub tool_call: ToolCall,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    /// Arguments as sent by the model, usually a JSON string
+    #[serde(default)]
+    pub arguments: Value,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ToolResultPart {
+    pub tool_result: ToolResult,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ToolResult {
+    pub tool_call_id: String,
+    #[serde(default)]
+    pub content: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_error: Option<bool>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Tell a `null` field apart from a missing one
+fn null_as_some<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(Some(Option::deserialize(deserializer)?))
+}
+
+impl<'de> Deserialize<'de> for ContentPart {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut part = Map::deserialize(deserializer)?;
+        let kind = match part.get("type") {
+            Some(Value::String(kind)) => kind.clone(),
+            _ => return Err(D::Error::custom("content part without a type")),
+        };
+        let known = ["text", "image_url", "tool_call", "tool_result"];
+        if !known.contains(&kind.as_str()) {
+            return Ok(ContentPart::Other(part));
+        }
+        part.remove("type");
+        let part = Value::Object(part);
+        let invalid = |e: serde_json::Error| D::Error::custom(format!("{} part: {}", kind, e));
+        Ok(match kind.as_str() {
+            "text" => ContentPart::Text(serde_json::from_value(part).map_err(invalid)?),
+            "image_url" => ContentPart::ImageUrl(serde_json::from_value(part).map_err(invalid)?),
+            "tool_call" => ContentPart::ToolCall(serde_json::from_value(part).map_err(invalid)?),
+            _ => ContentPart::ToolResult(serde_json::from_value(part).map_err(invalid)?),
+        })
+    }
+}
+
+impl Serialize for ContentPart {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (kind, part) = match self {
+            ContentPart::Text(part) => ("text", serde_json::to_value(part)),
+            ContentPart::ImageUrl(part) => ("image_url", serde_json::to_value(part)),
+            ContentPart::ToolCall(part) => ("tool_call", serde_json::to_value(part)),
+            ContentPart::ToolResult(part) => ("tool_result", serde_json::to_value(part)),
+            ContentPart::Other(part) => return part.serialize(serializer),
+        };
+        let mut part = match part.map_err(serde::ser::Error::custom)? {
+            Value::Object(part) => part,
+            _ => return Err(serde::ser::Error::custom("content part is not an object")),
+        };
+        part.insert("type".to_string(), Value::String(kind.to_string()));
+        part.serialize(serializer)
+    }
+}
+
+fn require(value: &str, field: &str) -> Result<(), String> {
+    if value.trim().is_empty() {
+        return Err(format!("Missing {}", field));
+    }
+    Ok(())
+}
+
+impl Thread {
+    pub fn validate(&self) -> Result<(), String> {
+        require(&self.id, "thread id")?;
+        for assistant in &self.assistants {
+            require(&assistant.id, "assistant id")?;
+        }
+        Ok(())
+    }
+}
+
+impl Assistant {
+    pub fn validate(&self) -> Result<(), String> {
+        require(&self.id, "assistant id")
+    }
+}
+
+impl Message {
+    pub fn validate(&self) -> Result<(), String> {
+        require(&self.id, "message id")?;
+        require(&self.thread_id, "thread_id")?;
+        for part in &self.content {
+            match part {
+                ContentPart::ToolCall(part) => {
+                    require(&part.tool_call.id, "tool call id")?;
+                    require(&part.tool_call.name, "tool call name")?;
+                }
+                ContentPart::ToolResult(part) => {
+                    require(&part.tool_result.tool_call_id, "tool result tool_call_id")?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Schema version a record was written with
+pub fn schema_version(record: &Value) -> u64 {
+    record
+        .get(SCHEMA_VERSION_KEY)
+        .and_then(|version| version.as_u64())
+        .unwrap_or(0)
+}
+
+/// Upgrade a record of a previous schema version in place, step by step
+fn upgrade(record: &mut Value, step: fn(&mut Map<String, Value>)) -> Result<(), String> {
+    let version = schema_version(record);
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "Record has schema version {}, this version of Jan supports up to {}",
+            version, SCHEMA_VERSION
+        ));
+    }
+    let record = record.as_object_mut().ok_or("Record is not an object")?;
+    if version < 1 {
+        step(record);
+    }
+    record.insert(SCHEMA_VERSION_KEY.to_string(), json!(SCHEMA_VERSION));
+    Ok(())
+}
+
+fn thread_v0_to_v1(thread: &mut Map<String, Value>) {
+    match thread.get_mut("assistants") {
+        Some(Value::Array(assistants)) => {
+            for assistant in assistants.iter_mut().filter_map(Value::as_object_mut) {
+                let has_id = assistant.get("id").is_some_and(|id| !id.is_null());
+                if let (false, Some(id)) = (has_id, assistant.get("assistant_id").cloned()) {
+                    assistant.insert("id".to_string(), id);
+                }
+            }
+        }
+        _ => {
+            thread.insert("assistants".to_string(), json!([]));
+        }
+    }
+}
+
+fn message_v0_to_v1(message: &mut Map<String, Value>) {
+    match message.get("content") {
+        Some(Value::String(text)) => {
+            let part = json!({ "type": "text", "text": { "value": text, "annotations": [] } });
+            message.insert("content".to_string(), json!([part]));
+        }
+        Some(Value::Array(_)) => {}
+        _ => {
+            message.insert("content".to_string(), json!([]));
+        }
+    }
+}
+
+/// Upgrade, validate and re-serialize a thread before it is written
+pub fn normalize_thread(mut thread: Value) -> Result<Value, String> {
+    upgrade(&mut thread, thread_v0_to_v1)?;
+    let thread: Thread =
+        serde_json::from_value(thread).map_err(|e| format!("Invalid thread: {}", e))?;
+    thread
+        .validate()
+        .map_err(|e| format!("Invalid thread: {}", e))?;
+    serde_json::to_value(thread).map_err(|e| e.to_string())
+}
+
+/// Upgrade, validate and re-serialize a message before it is written
+pub fn normalize_message(mut message: Value) -> Result<Value, String> {
+    upgrade(&mut message, message_v0_to_v1)?;
+    let message: Message =
+        serde_json::from_value(message).map_err(|e| format!("Invalid message: {}", e))?;
+    message
+        .validate()
+        .map_err(|e| format!("Invalid message: {}", e))?;
+    serde_json::to_value(message).map_err(|e| e.to_string())
+}
+
+/// Validate and re-serialize an assistant before it is written to a thread
+pub fn normalize_assistant(assistant: Value) -> Result<Value, String> {
+    let assistant: Assistant =
+        serde_json::from_value(assistant).map_err(|e| format!("Invalid assistant: {}", e))?;
+    assistant
+        .validate()
+        .map_err(|e| format!("Invalid assistant: {}", e))?;
+    serde_json::to_value(assistant).map_err(|e| e.to_string())
+}
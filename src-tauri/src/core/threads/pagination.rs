@@ -0,0 +1,252 @@
+/*!
+   Paginated Message Listing
+
+   For file-based threads, `messages.idx.json` next to `messages.jsonl` records the id and byte
+   range of every line, so a page is read by seeking to its lines instead of parsing the whole
+   file. The index remembers the length and modification time of the file it describes and is
+   rebuilt when they no longer match, e.g. after the file was edited outside of Jan. Appends
+   and rewrites made through the thread commands update it in place.
+*/
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use super::constants::MESSAGES_INDEX_FILE;
+use super::encryption::{from_record, open, RecordError};
+use super::helpers::{read_messages_with_recovery, write_file_atomic};
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageOrder {
+    /// Oldest first
+    #[default]
+    Asc,
+    /// Newest first
+    Desc,
+}
+
+/// Selects the messages strictly between `after_id` and `before_id`, in chronological order.
+/// With `MessageOrder::Desc` the page is taken from the newest end of that range.
+#[derive(Debug, Clone)]
+pub struct PageQuery {
+    pub before_id: Option<String>,
+    pub after_id: Option<String>,
+    pub limit: usize,
+    pub order: MessageOrder,
+}
+
+impl Default for PageQuery {
+    fn default() -> Self {
+        Self {
+            before_id: None,
+            after_id: None,
+            limit: DEFAULT_PAGE_SIZE,
+            order: MessageOrder::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct MessagesPage {
+    /// Messages in the requested order
+    pub messages: Vec<Value>,
+    /// More messages follow in the requested order
+    pub has_more: bool,
+    /// Corrupt lines moved to messages.corrupt.jsonl while reading the page
+    pub quarantined: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct MessageIndex {
+    /// Length and modification time of messages.jsonl when the index was written
+    file_len: u64,
+    modified_ns: u64,
+    pub entries: Vec<IndexEntry>,
+    /// Corrupt lines moved out of messages.jsonl before it was indexed
+    #[serde(skip)]
+    pub quarantined: usize,
+}
+
+/// A line of messages.jsonl, without its trailing newline
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    pub id: String,
+    pub offset: u64,
+    pub len: u64,
+}
+
+#[derive(Deserialize)]
+struct MessageId {
+    id: Option<String>,
+}
+
+fn index_path(messages_path: &Path) -> PathBuf {
+    messages_path.with_file_name(MESSAGES_INDEX_FILE)
+}
+
+fn file_stamp(path: &Path) -> Result<(u64, u64), String> {
+    let metadata = fs::metadata(path).map_err(|e| e.to_string())?;
+    let modified_ns = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or_default();
+    Ok((metadata.len(), modified_ns))
+}
+
+fn read_index(messages_path: &Path) -> Option<MessageIndex> {
+    let data = fs::read(index_path(messages_path)).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+/// Save `entries` as the index of `messages_path` in its current state
+pub fn save_index(messages_path: &Path, entries: Vec<IndexEntry>) -> Result<MessageIndex, String> {
+    let (file_len, modified_ns) = file_stamp(messages_path)?;
+    let index = MessageIndex {
+        file_len,
+        modified_ns,
+        entries,
+        quarantined: 0,
+    };
+    let data = serde_json::to_vec(&index).map_err(|e| e.to_string())?;
+    write_file_atomic(&index_path(messages_path), &data)?;
+    Ok(index)
+}
+
+/// Extend the index after a line was appended at `entry.offset`. An index that was
+/// already out of date is left alone; it is rebuilt on the next read.
+pub fn record_append(messages_path: &Path, entry: IndexEntry) -> Result<(), String> {
+    let Some(mut index) = read_index(messages_path) else {
+        return Ok(());
+    };
+    if index.file_len != entry.offset {
+        return Ok(());
+    }
+    index.entries.push(entry);
+    save_index(messages_path, index.entries)?;
+    Ok(())
+}
+
+/// Index every line of the file, or `None` if a line is not a valid record
+fn scan_entries(messages_path: &Path) -> Result<Option<Vec<IndexEntry>>, String> {
+    let bytes = fs::read(messages_path).map_err(|e| e.to_string())?;
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    for line in bytes.split(|b| *b == b'\n') {
+        let len = line.len() as u64;
+        if !line.iter().all(|b| b.is_ascii_whitespace()) {
+            let parsed = match open(line) {
+                Ok(data) => serde_json::from_slice::<MessageId>(&data).ok(),
+                Err(RecordError::Locked) => return Err(RecordError::Locked.to_string()),
+                Err(_) => None,
+            };
+            match parsed {
+                Some(message) => entries.push(IndexEntry {
+                    id: message.id.unwrap_or_default(),
+                    offset,
+                    len,
+                }),
+                None => return Ok(None),
+            }
+        }
+        offset += len + 1;
+    }
+    Ok(Some(entries))
+}
+
+/// Load the index of `messages_path`, rebuilding it if the file changed since it was written.
+/// Callers must hold the thread's lock.
+pub fn load_index(messages_path: &Path) -> Result<MessageIndex, String> {
+    if !messages_path.exists() {
+        return Ok(MessageIndex::default());
+    }
+    if let Some(index) = read_index(messages_path) {
+        if (index.file_len, index.modified_ns) == file_stamp(messages_path)? {
+            return Ok(index);
+        }
+    }
+
+    let mut quarantined = 0;
+    let entries = match scan_entries(messages_path)? {
+        Some(entries) => entries,
+        None => {
+            // Move corrupt lines out of the way, then index what is left
+            quarantined = read_messages_with_recovery(messages_path)?.quarantined;
+            scan_entries(messages_path)?.ok_or("Failed to index messages")?
+        }
+    };
+    let index = save_index(messages_path, entries)?;
+    Ok(MessageIndex {
+        quarantined,
+        ..index
+    })
+}
+
+/// Pick the page out of `entries`, which are in chronological order
+fn select_page<'a>(
+    entries: &'a [IndexEntry],
+    query: &PageQuery,
+) -> Result<(Vec<&'a IndexEntry>, bool), String> {
+    let position = |id: &str| {
+        entries
+            .iter()
+            .position(|entry| entry.id == id)
+            .ok_or_else(|| format!("Message not found: {}", id))
+    };
+    let start = match &query.after_id {
+        Some(id) => position(id)? + 1,
+        None => 0,
+    };
+    let end = match &query.before_id {
+        Some(id) => position(id)?,
+        None => entries.len(),
+    };
+    let window = if start < end {
+        &entries[start..end]
+    } else {
+        &[]
+    };
+
+    let page = match query.order {
+        MessageOrder::Asc => window.iter().take(query.limit).collect(),
+        MessageOrder::Desc => window.iter().rev().take(query.limit).collect(),
+    };
+    Ok((page, window.len() > query.limit))
+}
+
+fn read_entries(messages_path: &Path, entries: &[&IndexEntry]) -> Result<Vec<Value>, String> {
+    let mut file = File::open(messages_path).map_err(|e| e.to_string())?;
+    entries
+        .iter()
+        .map(|entry| {
+            let mut line = vec![0; entry.len as usize];
+            file.seek(SeekFrom::Start(entry.offset))
+                .and_then(|_| file.read_exact(&mut line))
+                .map_err(|e| e.to_string())?;
+            from_record(&line)
+        })
+        .collect()
+}
+
+/// Read one page of messages from a messages.jsonl file. Callers must hold the thread's lock.
+pub fn read_messages_page(messages_path: &Path, query: &PageQuery) -> Result<MessagesPage, String> {
+    let index = load_index(messages_path)?;
+    let (entries, has_more) = select_page(&index.entries, query)?;
+    Ok(MessagesPage {
+        messages: read_entries(messages_path, &entries)?,
+        has_more,
+        quarantined: index.quarantined,
+    })
+}
+
+/// Number of messages in a messages.jsonl file. Callers must hold the thread's lock.
+pub fn count_messages_in_file(messages_path: &Path) -> Result<usize, String> {
+    Ok(load_index(messages_path)?.entries.len())
+}
@@ -0,0 +1,466 @@
+/*!
+   Full-Text Search Index for Thread Messages
+
+   Messages are indexed with SQLite FTS5 in a separate `search.db`, whichever backend stores the
+   threads themselves. The index is built from the active backend on the first search; after
+   that, the message commands keep it up to date. Until the index exists those commands skip
+   indexing entirely, so users who never search pay nothing.
+*/
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
+use sqlx::{Row, Sqlite, Transaction};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tauri::{AppHandle, Runtime};
+use tokio::sync::Mutex;
+
+use super::commands::{get_thread_assistant, list_messages, list_threads};
+use super::db::get_database_dir;
+use super::encryption;
+use crate::core::app::commands::get_jan_data_folder_path;
+
+pub const SEARCH_DB_NAME: &str = "search.db";
+const DEFAULT_LIMIT: u32 = 50;
+// Private-use characters never appear in normal text, so they can mark matches in snippets
+const HIGHLIGHT_START: char = '\u{E000}';
+const HIGHLIGHT_END: char = '\u{E001}';
+
+/// Open indexes keyed by database path; the path changes with the data folder
+static SEARCH_POOLS: OnceLock<Mutex<HashMap<PathBuf, SqlitePool>>> = OnceLock::new();
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct SearchFilters {
+    pub thread_id: Option<String>,
+    /// Messages of this assistant, or of threads using it when the message has none
+    pub assistant_id: Option<String>,
+    pub role: Option<String>,
+    /// Inclusive `created_at` range, in the unit messages are stored with
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub thread_id: String,
+    pub message_id: String,
+    pub role: String,
+    pub assistant_id: Option<String>,
+    /// Excerpt around the matched terms
+    pub snippet: String,
+    /// `[start, end)` of each matched term in `snippet`, in UTF-16 code units
+    pub highlights: Vec<[usize; 2]>,
+    pub created_at: Option<i64>,
+    pub completed_at: Option<i64>,
+}
+
+/// Open (creating if missing) the search index at `path`
+pub async fn open_search_index(path: &Path) -> Result<SqlitePool, String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create search index dir: {}", e))?;
+    }
+
+    let connect_options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(2)
+        .connect_with(connect_options)
+        .await
+        .map_err(|e| format!("Failed to open search index: {}", e))?;
+
+    for statement in [
+        r#"
+        CREATE TABLE IF NOT EXISTS search_messages (
+            id INTEGER PRIMARY KEY,
+            message_id TEXT NOT NULL UNIQUE,
+            thread_id TEXT NOT NULL,
+            role TEXT NOT NULL,
+            assistant_id TEXT,
+            created_at INTEGER,
+            completed_at INTEGER
+        );
+        "#,
+        "CREATE INDEX IF NOT EXISTS idx_search_messages_thread_id ON search_messages(thread_id);",
+        // rowid of search_fts is search_messages.id
+        "CREATE VIRTUAL TABLE IF NOT EXISTS search_fts USING fts5(text, tokenize = 'unicode61 remove_diacritics 2');",
+        "CREATE TABLE IF NOT EXISTS search_meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);",
+    ] {
+        sqlx::query(statement)
+            .execute(&pool)
+            .await
+            .map_err(|e| format!("Failed to create search index: {}", e))?;
+    }
+
+    Ok(pool)
+}
+
+/// The search index for the current data folder. Without `create`, returns `None`
+/// when no index has been built yet. There is no index while thread encryption is enabled.
+async fn get_search_index<R: Runtime>(
+    app: &AppHandle<R>,
+    create: bool,
+) -> Result<Option<SqlitePool>, String> {
+    if encryption::is_enabled(&get_jan_data_folder_path(app.clone())) {
+        if create {
+            return Err(
+                "Message search is not available while thread encryption is enabled".to_string(),
+            );
+        }
+        return Ok(None);
+    }
+    let path = get_database_dir(app)?.join(SEARCH_DB_NAME);
+    let mut pools = SEARCH_POOLS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .await;
+    if let Some(pool) = pools.get(&path) {
+        return Ok(Some(pool.clone()));
+    }
+    if !create && !path.exists() {
+        return Ok(None);
+    }
+    let pool = open_search_index(&path).await?;
+    pools.insert(path, pool.clone());
+    Ok(Some(pool))
+}
+
+/// Close and delete the search index of the current data folder
+pub async fn remove_search_index<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let path = get_database_dir(app)?.join(SEARCH_DB_NAME);
+    let pool = SEARCH_POOLS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .await
+        .remove(&path);
+    if let Some(pool) = pool {
+        pool.close().await;
+    }
+    for suffix in ["", "-wal", "-shm"] {
+        let file = path.with_file_name(format!("{}{}", SEARCH_DB_NAME, suffix));
+        if file.exists() {
+            std::fs::remove_file(&file).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Return the search index, building it from the active storage backend on first use
+pub async fn ensure_search_index<R: Runtime>(app: &AppHandle<R>) -> Result<SqlitePool, String> {
+    let pool = get_search_index(app, true)
+        .await?
+        .ok_or("Search index not available")?;
+    let built = sqlx::query("SELECT value FROM search_meta WHERE key = 'built'")
+        .fetch_optional(&pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .is_some();
+    if !built {
+        rebuild_search_index(app).await?;
+    }
+    Ok(pool)
+}
+
+/// Drop the index and index every message again. Returns the number of indexed messages.
+pub async fn rebuild_search_index<R: Runtime>(app: &AppHandle<R>) -> Result<usize, String> {
+    let pool = get_search_index(app, true)
+        .await?
+        .ok_or("Search index not available")?;
+
+    // Cleared first, so an interrupted rebuild is started over by the next search
+    for statement in [
+        "DELETE FROM search_meta WHERE key = 'built'",
+        "DELETE FROM search_fts",
+        "DELETE FROM search_messages",
+    ] {
+        sqlx::query(statement)
+            .execute(&pool)
+            .await
+            .map_err(|e| format!("Failed to clear search index: {}", e))?;
+    }
+
+    let mut count = 0;
+    for thread in list_threads(app.clone(), None).await? {
+        let Some(thread_id) = thread.get("id").and_then(|id| id.as_str()) else {
+            continue;
+        };
+        let assistant_id = thread
+            .get("assistants")
+            .and_then(|assistants| assistants.get(0))
+            .and_then(assistant_id_of);
+        let messages = list_messages(app.clone(), thread_id.to_string()).await?;
+        index_messages(&pool, &messages, assistant_id.as_deref()).await?;
+        count += messages.len();
+    }
+
+    sqlx::query("INSERT OR REPLACE INTO search_meta (key, value) VALUES ('built', '1')")
+        .execute(&pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    log::info!("Rebuilt message search index with {} messages", count);
+    Ok(count)
+}
+
+fn assistant_id_of(assistant: &Value) -> Option<String> {
+    assistant
+        .get("id")
+        .or_else(|| assistant.get("assistant_id"))
+        .and_then(|id| id.as_str())
+        .map(|id| id.to_string())
+}
+
+/// Searchable text of a message: all of its text content parts
+pub fn message_text(message: &Value) -> String {
+    match message.get("content") {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter(|part| part.get("type").and_then(|t| t.as_str()) == Some("text"))
+            .filter_map(|part| {
+                let text = part.get("text")?;
+                text.as_str()
+                    .or_else(|| text.get("value").and_then(|value| value.as_str()))
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// Add or replace messages in the index, in one transaction.
+/// `thread_assistant_id` is used for messages that carry no `assistant_id`.
+pub async fn index_messages(
+    pool: &SqlitePool,
+    messages: &[Value],
+    thread_assistant_id: Option<&str>,
+) -> Result<(), String> {
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    for message in messages {
+        index_message(&mut tx, message, thread_assistant_id).await?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())
+}
+
+async fn index_message(
+    tx: &mut Transaction<'_, Sqlite>,
+    message: &Value,
+    thread_assistant_id: Option<&str>,
+) -> Result<(), String> {
+    let field = |key: &str| message.get(key).and_then(|v| v.as_str());
+    let message_id = field("id").ok_or("Missing message id")?;
+    let thread_id = field("thread_id").ok_or("Missing thread_id")?;
+    let assistant_id = field("assistant_id").or(thread_assistant_id);
+
+    let row = sqlx::query(
+        r#"
+        INSERT INTO search_messages (message_id, thread_id, role, assistant_id, created_at, completed_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ON CONFLICT(message_id) DO UPDATE SET
+            thread_id = excluded.thread_id,
+            role = excluded.role,
+            assistant_id = excluded.assistant_id,
+            created_at = excluded.created_at,
+            completed_at = excluded.completed_at
+        RETURNING id
+        "#,
+    )
+    .bind(message_id)
+    .bind(thread_id)
+    .bind(field("role").unwrap_or_default())
+    .bind(assistant_id)
+    .bind(message.get("created_at").and_then(|v| v.as_i64()))
+    .bind(message.get("completed_at").and_then(|v| v.as_i64()))
+    .fetch_one(&mut **tx)
+    .await
+    .map_err(|e| format!("Failed to index message: {}", e))?;
+    let rowid: i64 = row.get("id");
+
+    sqlx::query("DELETE FROM search_fts WHERE rowid = ?1")
+        .bind(rowid)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query("INSERT INTO search_fts (rowid, text) VALUES (?1, ?2)")
+        .bind(rowid)
+        .bind(message_text(message))
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| format!("Failed to index message: {}", e))?;
+    Ok(())
+}
+
+/// Remove the messages whose `search_messages` `column` equals `value`
+async fn remove_where(pool: &SqlitePool, column: &str, value: &str) -> Result<(), String> {
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    sqlx::query(&format!(
+        "DELETE FROM search_fts WHERE rowid IN (SELECT id FROM search_messages WHERE {} = ?1)",
+        column
+    ))
+    .bind(value)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    sqlx::query(&format!(
+        "DELETE FROM search_messages WHERE {} = ?1",
+        column
+    ))
+    .bind(value)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    tx.commit().await.map_err(|e| e.to_string())
+}
+
+/// Keep an existing index in sync after a message was created or modified.
+/// Failures are only logged: searching must never get in the way of saving.
+pub async fn on_message_saved<R: Runtime>(app: &AppHandle<R>, message: &Value) {
+    let pool = match get_search_index(app, false).await {
+        Ok(Some(pool)) => pool,
+        Ok(None) => return,
+        Err(e) => {
+            log::warn!("Failed to open search index: {}", e);
+            return;
+        }
+    };
+
+    let thread_assistant_id = match message.get("assistant_id") {
+        Some(_) => None,
+        None => match message.get("thread_id").and_then(|id| id.as_str()) {
+            Some(thread_id) => get_thread_assistant(app.clone(), thread_id.to_string())
+                .await
+                .ok()
+                .and_then(|assistant| assistant_id_of(&assistant)),
+            None => None,
+        },
+    };
+    if let Err(e) = index_messages(
+        &pool,
+        std::slice::from_ref(message),
+        thread_assistant_id.as_deref(),
+    )
+    .await
+    {
+        log::warn!("Failed to update search index: {}", e);
+    }
+}
+
+pub async fn on_message_deleted<R: Runtime>(app: &AppHandle<R>, message_id: &str) {
+    if let Ok(Some(pool)) = get_search_index(app, false).await {
+        if let Err(e) = remove_where(&pool, "message_id", message_id).await {
+            log::warn!("Failed to update search index: {}", e);
+        }
+    }
+}
+
+pub async fn on_thread_deleted<R: Runtime>(app: &AppHandle<R>, thread_id: &str) {
+    if let Ok(Some(pool)) = get_search_index(app, false).await {
+        if let Err(e) = remove_where(&pool, "thread_id", thread_id).await {
+            log::warn!("Failed to update search index: {}", e);
+        }
+    }
+}
+
+/// Turn user input into an FTS5 query: every word must match, the last one as a
+/// prefix so results show up while typing. Quoting keeps FTS5 operators and
+/// punctuation from being interpreted as syntax.
+pub fn build_fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+    let last = terms.last()?;
+    let mut fts_query = terms[..terms.len() - 1].join(" ");
+    if !fts_query.is_empty() {
+        fts_query.push(' ');
+    }
+    fts_query.push_str(last);
+    fts_query.push('*');
+    Some(fts_query)
+}
+
+/// Strip the highlight markers from an FTS5 snippet and return their positions
+fn split_highlights(marked: &str) -> (String, Vec<[usize; 2]>) {
+    let mut snippet = String::with_capacity(marked.len());
+    let mut highlights = Vec::new();
+    let mut position = 0;
+    let mut start = None;
+    for c in marked.chars() {
+        match c {
+            HIGHLIGHT_START => start = Some(position),
+            HIGHLIGHT_END => {
+                if let Some(start) = start.take() {
+                    highlights.push([start, position]);
+                }
+            }
+            c => {
+                snippet.push(c);
+                position += c.len_utf16();
+            }
+        }
+    }
+    (snippet, highlights)
+}
+
+/// Search the index, best matches first
+pub async fn search(
+    pool: &SqlitePool,
+    query: &str,
+    filters: &SearchFilters,
+) -> Result<Vec<SearchHit>, String> {
+    let Some(fts_query) = build_fts_query(query) else {
+        return Ok(vec![]);
+    };
+
+    let rows = sqlx::query(
+        r#"
+        SELECT m.message_id, m.thread_id, m.role, m.assistant_id, m.created_at, m.completed_at,
+            snippet(search_fts, 0, ?2, ?3, '…', 16) AS snippet
+        FROM search_fts
+        JOIN search_messages m ON m.id = search_fts.rowid
+        WHERE search_fts MATCH ?1
+            AND (?4 IS NULL OR m.thread_id = ?4)
+            AND (?5 IS NULL OR m.assistant_id = ?5)
+            AND (?6 IS NULL OR m.role = ?6)
+            AND (?7 IS NULL OR m.created_at >= ?7)
+            AND (?8 IS NULL OR m.created_at <= ?8)
+        ORDER BY rank, m.created_at DESC
+        LIMIT ?9 OFFSET ?10
+        "#,
+    )
+    .bind(fts_query)
+    .bind(HIGHLIGHT_START.to_string())
+    .bind(HIGHLIGHT_END.to_string())
+    .bind(filters.thread_id.as_deref())
+    .bind(filters.assistant_id.as_deref())
+    .bind(filters.role.as_deref())
+    .bind(filters.from)
+    .bind(filters.to)
+    .bind(filters.limit.unwrap_or(DEFAULT_LIMIT) as i64)
+    .bind(filters.offset.unwrap_or(0) as i64)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to search messages: {}", e))?;
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let (snippet, highlights) = split_highlights(row.get("snippet"));
+            SearchHit {
+                thread_id: row.get("thread_id"),
+                message_id: row.get("message_id"),
+                role: row.get("role"),
+                assistant_id: row.get("assistant_id"),
+                snippet,
+                highlights,
+                created_at: row.get("created_at"),
+                completed_at: row.get("completed_at"),
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,561 @@
+/*!
+   Sync Through a Shared Folder
+
+   Replicates threads and messages between devices through a folder they all see, e.g. one kept
+   in sync by Syncthing or on a network share. Each device only appends to its own change log,
+   `jan-threads-sync/<device id>.jsonl`, so no file in the folder has two writers. A sync run
+
+   1. compares the local threads and messages with what was synced last and appends a change
+      for each record created, modified or deleted since, then
+   2. applies the changes other devices appended since the previous run.
+
+   Every version of a record carries a vector clock. A change replaces the local version when
+   its clock is ahead; concurrent versions are resolved the same way on every device: a deletion
+   loses against an edit, otherwise the later change wins and the device id breaks ties.
+   Messages are never lost to a concurrent deletion. A thread is only deleted once the deleting
+   device knew all of its messages, and a message arriving for a deleted thread brings the thread
+   back. Changes are applied through the thread commands, so deleted records end up in the trash.
+
+   Change logs hold plain JSON, so syncing is refused while at-rest encryption is enabled.
+*/
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use tauri::Runtime;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use super::commands::{
+    create_message, delete_message, delete_thread, get_thread, insert_thread, list_messages,
+    list_threads, modify_message, modify_thread,
+};
+use super::constants::{SYNC_LOGS_DIR, SYNC_STATE_FILE};
+use super::encryption;
+use super::helpers::write_file_atomic;
+use crate::core::app::commands::get_jan_data_folder_path;
+
+/// One sync run at a time
+static SYNC_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Latest known change of each device
+pub type Clock = BTreeMap<String, u64>;
+
+/// A line of a device's change log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    pub device: String,
+    /// Position in the device's log, counting from 1
+    pub seq: u64,
+    /// Milliseconds since the epoch, to order concurrent changes
+    pub at: i64,
+    /// Version of the record
+    pub clock: Clock,
+    pub thread_id: String,
+    /// Set for messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    /// The record, or `None` when it was deleted
+    pub data: Option<Value>,
+    /// Changes the device knew when it deleted a thread
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seen: Option<Clock>,
+}
+
+/// Last synced version of a record
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct RecordState {
+    device: String,
+    seq: u64,
+    at: i64,
+    clock: Clock,
+    /// Hash of the record as stored locally; `None` once it was deleted
+    hash: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SyncState {
+    device_id: String,
+    /// Last change appended to this device's log
+    seq: u64,
+    /// Last change applied from each other device
+    applied: Clock,
+    /// Keyed by thread id, or `<thread id>/<message id>`
+    records: BTreeMap<String, RecordState>,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct SyncReport {
+    /// Changes appended to this device's log
+    pub sent: usize,
+    /// Changes of other devices applied locally
+    pub received: usize,
+    /// Concurrent changes that were resolved
+    pub conflicts: usize,
+}
+
+/// How clock `a` relates to clock `b`, or `None` when they are concurrent
+pub fn compare_clocks(a: &Clock, b: &Clock) -> Option<Ordering> {
+    let mut ordering = Ordering::Equal;
+    for device in a.keys().chain(b.keys()) {
+        let seq = |clock: &Clock| clock.get(device).copied().unwrap_or(0);
+        match seq(a).cmp(&seq(b)) {
+            Ordering::Equal => {}
+            device_ordering if ordering == Ordering::Equal => ordering = device_ordering,
+            device_ordering if device_ordering != ordering => return None,
+            _ => {}
+        }
+    }
+    Some(ordering)
+}
+
+fn merge_clocks(clock: &mut Clock, other: &Clock) {
+    for (device, seq) in other {
+        let entry = clock.entry(device.clone()).or_insert(0);
+        *entry = (*entry).max(*seq);
+    }
+}
+
+fn record_key(thread_id: &str, message_id: Option<&str>) -> String {
+    match message_id {
+        Some(message_id) => format!("{}/{}", thread_id, message_id),
+        None => thread_id.to_string(),
+    }
+}
+
+fn hash_record(record: &Value) -> String {
+    format!("{:x}", Sha256::digest(record.to_string().as_bytes()))
+}
+
+fn load_state(data_dir: &Path) -> Result<SyncState, String> {
+    let path = data_dir.join(SYNC_STATE_FILE);
+    if !path.exists() {
+        return Ok(SyncState {
+            device_id: Uuid::new_v4().to_string(),
+            seq: 0,
+            applied: Clock::new(),
+            records: BTreeMap::new(),
+        });
+    }
+    let data = fs::read(&path).map_err(|e| format!("Failed to read sync state: {}", e))?;
+    serde_json::from_slice(&data).map_err(|e| format!("Failed to parse sync state: {}", e))
+}
+
+fn save_state(data_dir: &Path, state: &SyncState) -> Result<(), String> {
+    let data = serde_json::to_vec(state).map_err(|e| e.to_string())?;
+    write_file_atomic(&data_dir.join(SYNC_STATE_FILE), &data)
+}
+
+/// Read the change log of every device. A log is read up to its first line that cannot be
+/// parsed or is out of sequence, e.g. one the shared folder has only partly synced yet.
+fn read_logs(logs_dir: &Path) -> Result<BTreeMap<String, Vec<Change>>, String> {
+    let mut logs = BTreeMap::new();
+    for entry in fs::read_dir(logs_dir).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("jsonl") {
+            continue;
+        }
+        let Some(device) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let file = File::open(&path).map_err(|e| format!("Failed to open change log: {}", e))?;
+        let mut changes: Vec<Change> = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| format!("Failed to read change log: {}", e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Change>(&line) {
+                Ok(change) if change.device == device && change.seq == changes.len() as u64 + 1 => {
+                    changes.push(change)
+                }
+                _ => {
+                    log::warn!(
+                        "Ignoring the change log of {} after change {}",
+                        device,
+                        changes.len()
+                    );
+                    break;
+                }
+            }
+        }
+        logs.insert(device.to_string(), changes);
+    }
+    Ok(logs)
+}
+
+fn append_changes(path: &Path, changes: &[Change]) -> Result<(), String> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let mut lines = String::new();
+    for change in changes {
+        lines.push_str(&serde_json::to_string(change).map_err(|e| e.to_string())?);
+        lines.push('\n');
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open change log: {}", e))?;
+    file.write_all(lines.as_bytes())
+        .and_then(|_| file.sync_all())
+        .map_err(|e| format!("Failed to write change log: {}", e))
+}
+
+/// Every local thread and message, each thread before its messages
+async fn read_local_records<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+) -> Result<Vec<(String, Option<String>, Value)>, String> {
+    let mut records = Vec::new();
+    for thread in list_threads(app.clone(), None).await? {
+        let Some(thread_id) = thread.get("id").and_then(|id| id.as_str()) else {
+            continue;
+        };
+        let thread_id = thread_id.to_string();
+        let messages = list_messages(app.clone(), thread_id.clone()).await?;
+        records.push((thread_id.clone(), None, thread));
+        for message in messages {
+            if let Some(message_id) = message.get("id").and_then(|id| id.as_str()) {
+                let message_id = message_id.to_string();
+                records.push((thread_id.clone(), Some(message_id), message));
+            }
+        }
+    }
+    Ok(records)
+}
+
+struct SyncRun {
+    state: SyncState,
+    /// Changes to append to this device's log
+    outgoing: Vec<Change>,
+    /// Records changed by applied changes, by thread, whose local hash is taken at the end
+    touched: BTreeMap<String, BTreeSet<Option<String>>>,
+    report: SyncReport,
+}
+
+impl SyncRun {
+    fn is_stored(&self, key: &str) -> bool {
+        self.state
+            .records
+            .get(key)
+            .is_some_and(|record| record.hash.is_some())
+    }
+
+    /// Changes this device knows of, its own included
+    fn knowledge(&self) -> Clock {
+        let mut clock = self.state.applied.clone();
+        clock.insert(self.state.device_id.clone(), self.state.seq);
+        clock
+    }
+
+    /// Append a change made on this device, its version being `clock` advanced by the change
+    fn record_local_change(
+        &mut self,
+        thread_id: &str,
+        message_id: Option<&str>,
+        data: Option<Value>,
+        mut clock: Clock,
+    ) {
+        self.state.seq += 1;
+        let device = self.state.device_id.clone();
+        clock.insert(device.clone(), self.state.seq);
+        let seen = (message_id.is_none() && data.is_none()).then(|| self.knowledge());
+        let change = Change {
+            device: device.clone(),
+            seq: self.state.seq,
+            at: chrono::Utc::now().timestamp_millis(),
+            clock: clock.clone(),
+            thread_id: thread_id.to_string(),
+            message_id: message_id.map(str::to_string),
+            data,
+            seen,
+        };
+        self.state.records.insert(
+            record_key(thread_id, message_id),
+            RecordState {
+                device,
+                seq: change.seq,
+                at: change.at,
+                clock,
+                hash: change.data.as_ref().map(hash_record),
+            },
+        );
+        self.outgoing.push(change);
+        self.report.sent += 1;
+    }
+
+    /// Record every local change since the last run
+    async fn send_local_changes<R: Runtime>(
+        &mut self,
+        app: &tauri::AppHandle<R>,
+    ) -> Result<(), String> {
+        let mut local_keys = BTreeSet::new();
+        for (thread_id, message_id, data) in read_local_records(app).await? {
+            let key = record_key(&thread_id, message_id.as_deref());
+            let record = self.state.records.get(&key);
+            if record.and_then(|record| record.hash.as_deref()) != Some(hash_record(&data).as_str())
+            {
+                let clock = record
+                    .map(|record| record.clock.clone())
+                    .unwrap_or_default();
+                self.record_local_change(&thread_id, message_id.as_deref(), Some(data), clock);
+            }
+            local_keys.insert(key);
+        }
+
+        // Messages are deleted before their threads
+        let mut deleted: Vec<(String, Clock)> = self
+            .state
+            .records
+            .iter()
+            .filter(|(key, record)| record.hash.is_some() && !local_keys.contains(*key))
+            .map(|(key, record)| (key.clone(), record.clock.clone()))
+            .collect();
+        deleted.sort_by_key(|(key, _)| !key.contains('/'));
+        for (key, clock) in deleted {
+            let (thread_id, message_id) = match key.split_once('/') {
+                Some((thread_id, message_id)) => (thread_id, Some(message_id)),
+                None => (key.as_str(), None),
+            };
+            self.record_local_change(thread_id, message_id, None, clock);
+        }
+        Ok(())
+    }
+
+    /// Apply the changes of other devices not applied yet
+    async fn receive_changes<R: Runtime>(
+        &mut self,
+        app: &tauri::AppHandle<R>,
+        logs: &BTreeMap<String, Vec<Change>>,
+    ) -> Result<(), String> {
+        for (device, changes) in logs {
+            if *device == self.state.device_id {
+                continue;
+            }
+            let applied = self.state.applied.get(device).copied().unwrap_or(0);
+            for change in changes.iter().filter(|change| change.seq > applied) {
+                self.receive_change(app, change, logs).await?;
+                self.state.applied.insert(device.clone(), change.seq);
+            }
+        }
+        Ok(())
+    }
+
+    async fn receive_change<R: Runtime>(
+        &mut self,
+        app: &tauri::AppHandle<R>,
+        change: &Change,
+        logs: &BTreeMap<String, Vec<Change>>,
+    ) -> Result<(), String> {
+        let thread_id = change.thread_id.as_str();
+        let message_id = change.message_id.as_deref();
+        let key = record_key(thread_id, message_id);
+        if let Some(local) = self.state.records.get(&key) {
+            let wins = match compare_clocks(&change.clock, &local.clock) {
+                Some(ordering) => ordering == Ordering::Greater,
+                None => {
+                    self.report.conflicts += 1;
+                    match (change.data.is_some(), local.hash.is_some()) {
+                        (true, false) => true,
+                        (false, true) => false,
+                        _ => (change.at, &change.device) > (local.at, &local.device),
+                    }
+                }
+            };
+            if !wins {
+                return Ok(());
+            }
+        }
+
+        let stored = self.is_stored(&key);
+        match (message_id, &change.data) {
+            (None, Some(thread)) if stored => modify_thread(app.clone(), thread.clone()).await?,
+            (None, Some(thread)) => {
+                insert_thread(app.clone(), thread.clone()).await?;
+            }
+            (Some(_), Some(message)) => {
+                if !self.is_stored(thread_id) {
+                    self.restore_thread(app, thread_id, logs).await?;
+                }
+                if stored {
+                    modify_message(app.clone(), message.clone()).await?;
+                } else {
+                    create_message(app.clone(), message.clone()).await?;
+                }
+            }
+            (Some(message_id), None) if stored && self.is_stored(thread_id) => {
+                delete_message(app.clone(), thread_id.to_string(), message_id.to_string()).await?
+            }
+            (None, None) if stored => {
+                let seen = change.seen.clone().unwrap_or_default();
+                if self.has_unseen_messages(thread_id, &seen) {
+                    // Kept for the messages; the deleting device restores it when they arrive
+                    self.report.conflicts += 1;
+                    return Ok(());
+                }
+                delete_thread(app.clone(), thread_id.to_string()).await?;
+                let prefix = format!("{}/", thread_id);
+                for (_, record) in self
+                    .state
+                    .records
+                    .range_mut(prefix.clone()..)
+                    .take_while(|(key, _)| key.starts_with(&prefix))
+                {
+                    record.hash = None;
+                }
+            }
+            _ => {}
+        }
+
+        self.state.records.insert(
+            key,
+            RecordState {
+                device: change.device.clone(),
+                seq: change.seq,
+                at: change.at,
+                clock: change.clock.clone(),
+                hash: change.data.as_ref().map(hash_record),
+            },
+        );
+        if change.data.is_some() {
+            self.touch(thread_id, message_id);
+        }
+        self.report.received += 1;
+        Ok(())
+    }
+
+    /// Whether a local message of the thread is newer than the changes in `seen`
+    fn has_unseen_messages(&self, thread_id: &str, seen: &Clock) -> bool {
+        let prefix = format!("{}/", thread_id);
+        self.state
+            .records
+            .range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .any(|(_, record)| {
+                record.hash.is_some() && seen.get(&record.device).copied().unwrap_or(0) < record.seq
+            })
+    }
+
+    /// Bring back a deleted thread a message arrived for, from its latest version in any log.
+    /// The thread is recorded as changed on this device, so other devices restore it too.
+    async fn restore_thread<R: Runtime>(
+        &mut self,
+        app: &tauri::AppHandle<R>,
+        thread_id: &str,
+        logs: &BTreeMap<String, Vec<Change>>,
+    ) -> Result<(), String> {
+        let latest = logs
+            .values()
+            .flatten()
+            .chain(&self.outgoing)
+            .filter(|change| {
+                change.thread_id == thread_id
+                    && change.message_id.is_none()
+                    && change.data.is_some()
+            })
+            .max_by(|a, b| (a.at, &a.device).cmp(&(b.at, &b.device)))
+            .cloned()
+            .ok_or_else(|| format!("Thread {} of a synced message is unknown", thread_id))?;
+
+        insert_thread(app.clone(), latest.data.clone().unwrap_or_default()).await?;
+        let mut clock = self
+            .state
+            .records
+            .get(thread_id)
+            .map(|record| record.clock.clone())
+            .unwrap_or_default();
+        merge_clocks(&mut clock, &latest.clock);
+        self.record_local_change(thread_id, None, latest.data, clock);
+        self.touch(thread_id, None);
+        Ok(())
+    }
+
+    fn touch(&mut self, thread_id: &str, message_id: Option<&str>) {
+        self.touched
+            .entry(thread_id.to_string())
+            .or_default()
+            .insert(message_id.map(str::to_string));
+    }
+
+    /// Hash records changed by this run as the local storage returns them, which may differ
+    /// from the applied data, so they are not sent back as local changes
+    async fn rehash_touched<R: Runtime>(&mut self, app: &tauri::AppHandle<R>) {
+        for (thread_id, records) in std::mem::take(&mut self.touched) {
+            let mut stored = Vec::new();
+            if records.contains(&None) {
+                if let Ok(thread) = get_thread(app.clone(), thread_id.clone()).await {
+                    stored.push((thread_id.clone(), thread));
+                }
+            }
+            if let Ok(messages) = list_messages(app.clone(), thread_id.clone()).await {
+                for message in messages {
+                    let message_id = message.get("id").and_then(|id| id.as_str());
+                    if records.contains(&message_id.map(str::to_string)) {
+                        stored.push((record_key(&thread_id, message_id), message));
+                    }
+                }
+            }
+            for (key, data) in stored {
+                if let Some(record) = self.state.records.get_mut(&key) {
+                    if record.hash.is_some() {
+                        record.hash = Some(hash_record(&data));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Exchange changes with the other devices syncing through `folder`
+pub async fn sync_with_folder<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    folder: &Path,
+) -> Result<SyncReport, String> {
+    let _guard = SYNC_LOCK.lock().await;
+    let data_dir = get_jan_data_folder_path(app.clone());
+    if encryption::is_enabled(&data_dir) {
+        return Err("Threads cannot be synced while thread encryption is enabled".to_string());
+    }
+    let logs_dir = folder.join(SYNC_LOGS_DIR);
+    fs::create_dir_all(&logs_dir).map_err(|e| format!("Failed to create sync folder: {}", e))?;
+
+    let mut run = SyncRun {
+        state: load_state(&data_dir)?,
+        outgoing: Vec::new(),
+        touched: BTreeMap::new(),
+        report: SyncReport::default(),
+    };
+    let logs = read_logs(&logs_dir)?;
+    // The log may be ahead of a state restored from a backup
+    if let Some(last) = logs
+        .get(&run.state.device_id)
+        .and_then(|changes| changes.last())
+    {
+        run.state.seq = run.state.seq.max(last.seq);
+    }
+
+    let result = match run.send_local_changes(app).await {
+        Ok(()) => run.receive_changes(app, &logs).await,
+        Err(e) => Err(e),
+    };
+    // Keep what was done before a failure, so the next run continues from there
+    run.rehash_touched(app).await;
+    let log_path = logs_dir.join(format!("{}.jsonl", run.state.device_id));
+    append_changes(&log_path, &run.outgoing)?;
+    save_state(&data_dir, &run.state)?;
+    result?;
+
+    log::info!(
+        "Synced threads: {} sent, {} received, {} conflicts",
+        run.report.sent,
+        run.report.received,
+        run.report.conflicts
+    );
+    Ok(run.report)
+}
@@ -1,12 +1,39 @@
 
+use super::attachments::{message_attachment_ids, ATTACHMENT_URL_PREFIX};
 use super::commands::*;
-use super::helpers::should_use_sqlite;
+use super::constants::{MESSAGES_CORRUPT_FILE, MESSAGES_FILE};
+use super::branches::{active_branch, reparent_children};
+use super::db;
+use super::encryption::{self, KeySource, LOCKED_ERROR};
+use super::events::{MESSAGES_QUARANTINED_EVENT, THREADS_CHANGED_EVENT};
+use super::export::{thread_chat_example, thread_markdown, ExportFormat};
+use super::helpers::{
+    read_messages_with_recovery, should_use_sqlite, write_file_atomic, write_messages_to_file,
+};
+use super::importers::{convert_history, detect_source, ImportSource};
+use super::migration::{
+    export_sqlite_to_files, files_schema_is_current, migrate_files_to_sqlite,
+    sqlite_schema_is_current, upgrade_files_schema, upgrade_sqlite_schema,
+    verify_files_against_sqlite, CountMismatch,
+};
+use super::models::{normalize_message, normalize_thread, SCHEMA_VERSION};
+use super::pagination::{count_messages_in_file, read_messages_page, MessageOrder, PageQuery};
+use super::search::{build_fts_query, message_text, SearchFilters, SEARCH_DB_NAME};
+use super::sync::{compare_clocks, sync_with_folder, Clock, SyncReport};
+use super::thread_index::{
+    self, apply_query, IndexedThread, SortOrder, ThreadQuery, ThreadSortKey, THREAD_INDEX_DB_NAME,
+};
+use super::trash::{self, TrashKind};
+use super::watcher::{changed_threads, report_external_changes};
 use crate::core::app::commands::get_jan_data_folder_path;
 use futures_util::future;
 use serde_json::json;
+use std::cmp::Ordering;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
 use tauri::test::{mock_app, MockRuntime};
+use tauri::Listener;
 
 // Helper to create a mock app handle with a temp data dir
 fn mock_app_with_temp_data_dir() -> (tauri::App<MockRuntime>, PathBuf) {
@@ -61,7 +88,7 @@ async fn test_create_and_list_threads() {
     assert_eq!(created["title"], "Test Thread");
 
     // List threads
-    let threads = list_threads(app.handle().clone()).await.unwrap();
+    let threads = list_threads(app.handle().clone(), None).await.unwrap();
     assert!(!threads.is_empty());
 
     // Clean up
@@ -198,7 +225,7 @@ async fn test_desktop_storage_backend() {
         let thread_id = created["id"].as_str().unwrap().to_string();
 
         // Verify we can retrieve the thread (which proves file storage works)
-        let threads = list_threads(app.handle().clone()).await.unwrap();
+        let threads = list_threads(app.handle().clone(), None).await.unwrap();
         let found = threads.iter().any(|t| t["id"] == thread_id);
         assert!(found, "Thread should be retrievable from file-based storage");
 
@@ -257,7 +284,7 @@ async fn test_modify_and_delete_thread() {
         .unwrap();
 
     // Verify modification by listing threads
-    let threads = list_threads(app.handle().clone()).await.unwrap();
+    let threads = list_threads(app.handle().clone(), None).await.unwrap();
     let found_thread = threads.iter().find(|t| t["id"] == thread_id);
     assert!(found_thread.is_some(), "Modified thread should exist");
     assert_eq!(found_thread.unwrap()["title"], "Modified Title");
@@ -338,10 +365,61 @@ async fn test_modify_and_delete_message() {
         .unwrap();
     assert_eq!(messages.len(), 0, "Message should be deleted");
 
+    // Deleting it again leaves the file alone
+    let messages_path = data_dir
+        .join("threads")
+        .join(&thread_id)
+        .join(MESSAGES_FILE);
+    let written_at = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1);
+    fs::File::options()
+        .write(true)
+        .open(&messages_path)
+        .unwrap()
+        .set_modified(written_at)
+        .unwrap();
+    delete_message(app.handle().clone(), thread_id.clone(), message_id.clone())
+        .await
+        .unwrap();
+    let modified = fs::metadata(&messages_path).unwrap().modified().unwrap();
+    assert_eq!(modified, written_at);
+
     // Clean up
     let _ = fs::remove_dir_all(data_dir);
 }
 
+#[tokio::test]
+async fn test_modify_missing_message_fails() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let thread = create_thread(app.handle().clone(), create_test_thread("Missing"))
+        .await
+        .unwrap();
+    let thread_id = thread["id"].as_str().unwrap().to_string();
+    let message = create_message(
+        app.handle().clone(),
+        create_test_message(&thread_id, "Original"),
+    )
+    .await
+    .unwrap();
+    let (listener, changes) =
+        record_thread_changes(&app, Arc::new(Mutex::new(vec![thread_id.clone()])));
+
+    let mut missing = message.clone();
+    missing["id"] = json!("missing");
+    missing["content"] = json!([{"type": "text", "text": "Edited"}]);
+    let result = modify_message(app.handle().clone(), missing).await;
+    assert_eq!(result.unwrap_err(), "Message not found: missing");
+    app.handle().unlisten(listener);
+
+    // Nothing was saved or announced
+    let messages = list_messages(app.handle().clone(), thread_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(messages, vec![message]);
+    assert!(changes.lock().unwrap().is_empty());
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
 #[tokio::test]
 async fn test_modify_thread_assistant() {
     let (app, data_dir) = mock_app_with_temp_data_dir();
@@ -441,7 +519,7 @@ async fn test_concurrent_message_operations() {
 #[tokio::test]
 async fn test_empty_thread_list() {
     let (app, data_dir) = mock_app_with_temp_data_dir();
-    let threads = list_threads(app.handle().clone()).await.unwrap();
+    let threads = list_threads(app.handle().clone(), None).await.unwrap();
     assert_eq!(threads.len(), 0);
     let _ = fs::remove_dir_all(data_dir);
 }
@@ -461,3 +539,2404 @@ async fn test_empty_message_list() {
 
     let _ = fs::remove_dir_all(data_dir);
 }
+
+// Helper to create a thread with `count` messages through the file backend
+async fn create_file_thread(app: &tauri::App<MockRuntime>, title: &str, count: usize) -> String {
+    let created = create_thread(app.handle().clone(), create_test_thread(title))
+        .await
+        .unwrap();
+    let thread_id = created["id"].as_str().unwrap().to_string();
+    for i in 0..count {
+        create_message(
+            app.handle().clone(),
+            create_test_message(&thread_id, &format!("{title} {i}")),
+        )
+        .await
+        .unwrap();
+    }
+    thread_id
+}
+
+async fn db_message_texts(pool: &sqlx::SqlitePool, thread_id: &str) -> Vec<String> {
+    use sqlx::Row;
+    sqlx::query("SELECT data FROM messages WHERE thread_id = ?1 ORDER BY created_at, rowid")
+        .bind(thread_id)
+        .fetch_all(pool)
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| {
+            let message: serde_json::Value = serde_json::from_str(row.get("data")).unwrap();
+            message["content"][0]["text"].as_str().unwrap().to_string()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_migrate_files_to_sqlite_resumes() {
+    use super::utils::get_data_dir;
+
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let first = create_file_thread(&app, "First", 3).await;
+    let second = create_file_thread(&app, "Second", 2).await;
+    let threads_dir = get_data_dir(app.handle().clone());
+    let pool = db::open_database(&data_dir.join(db::DB_NAME))
+        .await
+        .unwrap();
+
+    let report = migrate_files_to_sqlite(&pool, &threads_dir).await.unwrap();
+    assert_eq!(report.migrated_threads, 2);
+    assert_eq!(report.migrated_messages, 5);
+    assert!(report.failed.is_empty());
+    assert_eq!(
+        db_message_texts(&pool, &first).await,
+        vec!["First 0", "First 1", "First 2"]
+    );
+
+    // Simulate a run that stopped before the second thread was committed
+    for query in [
+        "DELETE FROM messages WHERE thread_id = ?1",
+        "DELETE FROM threads WHERE id = ?1",
+        "DELETE FROM threads_migration WHERE thread_id = ?1",
+    ] {
+        sqlx::query(query)
+            .bind(&second)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+    let report = migrate_files_to_sqlite(&pool, &threads_dir).await.unwrap();
+    assert_eq!(report.migrated_threads, 1);
+    assert_eq!(report.skipped_threads, 1);
+    assert_eq!(report.migrated_messages, 2);
+
+    let verification = verify_files_against_sqlite(&pool, &threads_dir)
+        .await
+        .unwrap();
+    assert_eq!(verification.checked_threads, 2);
+    assert!(verification.is_ok());
+
+    // Messages written to the files after migration are reported
+    create_message(app.handle().clone(), create_test_message(&first, "late"))
+        .await
+        .unwrap();
+    let verification = verify_files_against_sqlite(&pool, &threads_dir)
+        .await
+        .unwrap();
+    assert_eq!(
+        verification.mismatches,
+        vec![CountMismatch {
+            thread_id: first.clone(),
+            file_messages: 4,
+            db_messages: Some(3),
+        }]
+    );
+
+    // ...and copied again by the next run
+    let report = migrate_files_to_sqlite(&pool, &threads_dir).await.unwrap();
+    assert_eq!(report.migrated_threads, 1);
+    assert_eq!(report.skipped_threads, 1);
+    assert_eq!(report.migrated_messages, 4);
+    assert_eq!(
+        db_message_texts(&pool, &first).await,
+        vec!["First 0", "First 1", "First 2", "late"]
+    );
+    let verification = verify_files_against_sqlite(&pool, &threads_dir)
+        .await
+        .unwrap();
+    assert!(verification.is_ok());
+
+    pool.close().await;
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn test_export_sqlite_to_files() {
+    use super::helpers::read_messages_from_path;
+    use super::utils::get_data_dir;
+
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let kept = create_file_thread(&app, "Kept", 2).await;
+    let deleted = create_file_thread(&app, "Deleted", 1).await;
+    let threads_dir = get_data_dir(app.handle().clone());
+    let pool = db::open_database(&data_dir.join(db::DB_NAME))
+        .await
+        .unwrap();
+    migrate_files_to_sqlite(&pool, &threads_dir).await.unwrap();
+
+    // Changes made while SQLite was the active backend
+    sqlx::query("DELETE FROM messages WHERE thread_id = ?1")
+        .bind(&deleted)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM threads WHERE id = ?1")
+        .bind(&deleted)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let message = create_test_message(&kept, "Kept 2");
+    sqlx::query("INSERT INTO messages (id, thread_id, data) VALUES ('new', ?1, ?2)")
+        .bind(&kept)
+        .bind(message.to_string())
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    // A plain export leaves the live threads alone
+    let export_dir = data_dir.join("export");
+    let report = export_sqlite_to_files(&pool, &export_dir, false)
+        .await
+        .unwrap();
+    assert_eq!(report.exported_threads, 1);
+    assert_eq!(report.exported_messages, 3);
+    assert!(export_dir.join(&kept).join("thread.json").exists());
+    assert!(threads_dir.join(&deleted).exists());
+
+    // Switching back hands the data over to the files
+    let report = export_sqlite_to_files(&pool, &threads_dir, true)
+        .await
+        .unwrap();
+    assert_eq!(report.removed_threads, 1);
+    assert!(!threads_dir.join(&deleted).exists());
+    let texts: Vec<String> =
+        read_messages_from_path(&threads_dir.join(&kept).join("messages.jsonl"))
+            .unwrap()
+            .iter()
+            .map(|m| m["content"][0]["text"].as_str().unwrap().to_string())
+            .collect();
+    assert_eq!(texts, vec!["Kept 0", "Kept 1", "Kept 2"]);
+    assert!(db_message_texts(&pool, &kept).await.is_empty());
+
+    pool.close().await;
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[test]
+fn test_build_fts_query() {
+    assert_eq!(build_fts_query("   "), None);
+    assert_eq!(build_fts_query("rust"), Some("\"rust\"*".to_string()));
+    // Operators and quotes are matched literally
+    assert_eq!(
+        build_fts_query("foo OR \"bar"),
+        Some("\"foo\" \"OR\" \"\"\"bar\"*".to_string())
+    );
+}
+
+#[test]
+fn test_message_text() {
+    let message = json!({
+        "content": [
+            {"type": "text", "text": "plain"},
+            {"type": "image_url", "image_url": {"url": "x"}},
+            {"type": "text", "text": {"value": "annotated", "annotations": []}}
+        ]
+    });
+    assert_eq!(message_text(&message), "plain\nannotated");
+    assert_eq!(message_text(&json!({"content": "string"})), "string");
+    assert_eq!(message_text(&json!({})), "");
+}
+
+#[tokio::test]
+async fn test_search_messages() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let mut thread = create_test_thread("Search");
+    thread["assistants"] = json!([{"id": "jan", "name": "Jan"}]);
+    let thread = create_thread(app.handle().clone(), thread).await.unwrap();
+    let thread_id = thread["id"].as_str().unwrap().to_string();
+
+    create_message(
+        app.handle().clone(),
+        create_test_message(&thread_id, "How do I compile Rust code?"),
+    )
+    .await
+    .unwrap();
+    let mut reply = create_test_message(&thread_id, "Run cargo build to compile it");
+    reply["role"] = json!("assistant");
+    reply["created_at"] = json!(456);
+    create_message(app.handle().clone(), reply).await.unwrap();
+
+    // Nothing is indexed until the first search
+    assert!(!data_dir.join(SEARCH_DB_NAME).exists());
+
+    let hits = search_messages(app.handle().clone(), "compil".to_string(), None)
+        .await
+        .unwrap();
+    assert_eq!(hits.len(), 2);
+    assert!(hits.iter().all(|hit| hit.thread_id == thread_id));
+    assert!(hits
+        .iter()
+        .all(|hit| hit.assistant_id.as_deref() == Some("jan")));
+    let hit = hits.iter().find(|hit| hit.role == "user").unwrap();
+    assert_eq!(hit.snippet, "How do I compile Rust code?");
+    assert_eq!(hit.highlights, vec![[9, 16]]);
+    assert_eq!(hit.created_at, Some(123));
+
+    let filters = |role: &str, from: Option<i64>| SearchFilters {
+        role: Some(role.to_string()),
+        from,
+        ..Default::default()
+    };
+    let hits = search_messages(
+        app.handle().clone(),
+        "compile".to_string(),
+        Some(filters("assistant", None)),
+    )
+    .await
+    .unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].created_at, Some(456));
+    let hits = search_messages(
+        app.handle().clone(),
+        "compile".to_string(),
+        Some(filters("user", Some(200))),
+    )
+    .await
+    .unwrap();
+    assert!(hits.is_empty());
+    let hits = search_messages(
+        app.handle().clone(),
+        "compile".to_string(),
+        Some(SearchFilters {
+            assistant_id: Some("other".to_string()),
+            ..Default::default()
+        }),
+    )
+    .await
+    .unwrap();
+    assert!(hits.is_empty());
+
+    // Later changes are picked up without a rebuild
+    let created = create_message(
+        app.handle().clone(),
+        create_test_message(&thread_id, "Café au lait"),
+    )
+    .await
+    .unwrap();
+    let hits = search_messages(app.handle().clone(), "cafe".to_string(), None)
+        .await
+        .unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message_id, created["id"].as_str().unwrap());
+
+    let mut modified = created.clone();
+    modified["content"] = json!([{"type": "text", "text": "Green tea"}]);
+    modify_message(app.handle().clone(), modified)
+        .await
+        .unwrap();
+    let search = |query: &str| search_messages(app.handle().clone(), query.to_string(), None);
+    assert!(search("cafe").await.unwrap().is_empty());
+    assert_eq!(search("tea").await.unwrap().len(), 1);
+
+    let message_id = created["id"].as_str().unwrap().to_string();
+    delete_message(app.handle().clone(), thread_id.clone(), message_id)
+        .await
+        .unwrap();
+    assert!(search("tea").await.unwrap().is_empty());
+
+    assert_eq!(rebuild_search_index(app.handle().clone()).await.unwrap(), 2);
+    delete_thread(app.handle().clone(), thread_id)
+        .await
+        .unwrap();
+    assert!(search("compile").await.unwrap().is_empty());
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+// Helper to create an empty directory for tests that work on files directly
+fn create_temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("jan-threads-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_write_file_atomic_replaces_without_leftovers() {
+    let dir = create_temp_dir();
+    let path = dir.join(MESSAGES_FILE);
+    write_file_atomic(&path, b"old\n").unwrap();
+    write_file_atomic(&path, b"new\n").unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_read_messages_quarantines_partial_write() {
+    let dir = create_temp_dir();
+    let path = dir.join(MESSAGES_FILE);
+    let messages = vec![
+        json!({"id": "1", "role": "user"}),
+        json!({"id": "2", "role": "assistant"}),
+    ];
+    write_messages_to_file(&messages, &path).unwrap();
+
+    // A crash in the middle of appending the third message, plus stray garbage
+    let mut data = fs::read(&path).unwrap();
+    data.extend_from_slice(b"\xff\xfe not json\n\n{\"id\": \"3\", \"ro");
+    fs::write(&path, data).unwrap();
+
+    let read = read_messages_with_recovery(&path).unwrap();
+    assert_eq!(read.messages, messages);
+    assert_eq!(read.quarantined, 2);
+
+    let corrupt: Vec<serde_json::Value> = fs::read_to_string(dir.join(MESSAGES_CORRUPT_FILE))
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(corrupt.len(), 2);
+    assert_eq!(corrupt[0]["line"], 3);
+    assert_eq!(corrupt[1]["line"], 5);
+    assert_eq!(corrupt[1]["content"], "{\"id\": \"3\", \"ro");
+
+    // The corrupt lines were removed, so they are reported only once
+    let read = read_messages_with_recovery(&path).unwrap();
+    assert_eq!(read.messages, messages);
+    assert_eq!(read.quarantined, 0);
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_listing_messages_reports_quarantined_lines() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let thread = create_thread(app.handle().clone(), create_test_thread("Corrupt"))
+        .await
+        .unwrap();
+    let thread_id = thread["id"].as_str().unwrap().to_string();
+    for text in ["question", "answer"] {
+        create_message(app.handle().clone(), create_test_message(&thread_id, text))
+            .await
+            .unwrap();
+    }
+    let path = data_dir
+        .join("threads")
+        .join(&thread_id)
+        .join(MESSAGES_FILE);
+    let corrupt = |garbage: &[u8]| {
+        let mut data = fs::read(&path).unwrap();
+        data.extend_from_slice(garbage);
+        fs::write(&path, data).unwrap();
+    };
+
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    let recorded = reported.clone();
+    let listener = app
+        .handle()
+        .listen_any(MESSAGES_QUARANTINED_EVENT, move |event| {
+            let payload: serde_json::Value = serde_json::from_str(event.payload()).unwrap();
+            recorded.lock().unwrap().push((
+                payload["thread_id"].as_str().unwrap().to_string(),
+                payload["count"].as_u64().unwrap(),
+            ));
+        });
+
+    corrupt(b"not json\n{\"id\": ");
+    let messages = list_messages(app.handle().clone(), thread_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(message_texts(&messages), vec!["question", "answer"]);
+    // Nothing is left to quarantine on the next read
+    list_messages(app.handle().clone(), thread_id.clone())
+        .await
+        .unwrap();
+
+    corrupt(b"{\"id\": ");
+    let page = list_messages_page(
+        app.handle().clone(),
+        thread_id.clone(),
+        None,
+        None,
+        None,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(message_texts(&page.messages), vec!["question", "answer"]);
+    assert_eq!(page.quarantined, 1);
+    let page = list_messages_page(
+        app.handle().clone(),
+        thread_id.clone(),
+        None,
+        None,
+        None,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(page.quarantined, 0);
+    app.handle().unlisten(listener);
+
+    assert_eq!(
+        *reported.lock().unwrap(),
+        vec![(thread_id.clone(), 2), (thread_id, 1)]
+    );
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn test_create_message_after_interrupted_append() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let thread = create_thread(app.handle().clone(), create_test_thread("Crash"))
+        .await
+        .unwrap();
+    let thread_id = thread["id"].as_str().unwrap().to_string();
+    create_message(
+        app.handle().clone(),
+        create_test_message(&thread_id, "first"),
+    )
+    .await
+    .unwrap();
+
+    // Simulate a crash that left half a line without a newline
+    let path = data_dir
+        .join("threads")
+        .join(&thread_id)
+        .join(MESSAGES_FILE);
+    let mut data = fs::read(&path).unwrap();
+    data.extend_from_slice(b"{\"object\": \"mess");
+    fs::write(&path, data).unwrap();
+
+    create_message(
+        app.handle().clone(),
+        create_test_message(&thread_id, "second"),
+    )
+    .await
+    .unwrap();
+
+    let messages = list_messages(app.handle().clone(), thread_id.clone())
+        .await
+        .unwrap();
+    let texts: Vec<_> = messages
+        .iter()
+        .map(|m| m["content"][0]["text"].as_str().unwrap())
+        .collect();
+    assert_eq!(texts, vec!["first", "second"]);
+    assert!(path.with_file_name(MESSAGES_CORRUPT_FILE).exists());
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+// Helper to list the ids of a page of messages
+fn page_ids(messages: &[serde_json::Value]) -> Vec<&str> {
+    messages.iter().map(|m| m["id"].as_str().unwrap()).collect()
+}
+
+fn page_query(
+    before_id: Option<&str>,
+    after_id: Option<&str>,
+    limit: usize,
+    order: MessageOrder,
+) -> PageQuery {
+    PageQuery {
+        before_id: before_id.map(|id| id.to_string()),
+        after_id: after_id.map(|id| id.to_string()),
+        limit,
+        order,
+    }
+}
+
+#[tokio::test]
+async fn test_list_messages_page_from_files() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let thread = create_thread(app.handle().clone(), create_test_thread("Pages"))
+        .await
+        .unwrap();
+    let thread_id = thread["id"].as_str().unwrap().to_string();
+    for i in 0..5 {
+        let mut message = create_test_message(&thread_id, &format!("message {i}"));
+        message["id"] = json!(format!("m{i}"));
+        create_message(app.handle().clone(), message).await.unwrap();
+    }
+
+    let page = |before: Option<&str>, after: Option<&str>, limit, order| {
+        list_messages_page(
+            app.handle().clone(),
+            thread_id.clone(),
+            before.map(|id| id.to_string()),
+            after.map(|id| id.to_string()),
+            Some(limit),
+            Some(order),
+        )
+    };
+
+    let first = page(None, None, 2, MessageOrder::Asc).await.unwrap();
+    assert_eq!(page_ids(&first.messages), vec!["m0", "m1"]);
+    assert!(first.has_more);
+    let next = page(None, Some("m1"), 2, MessageOrder::Asc).await.unwrap();
+    assert_eq!(page_ids(&next.messages), vec!["m2", "m3"]);
+    let latest = page(None, None, 2, MessageOrder::Desc).await.unwrap();
+    assert_eq!(page_ids(&latest.messages), vec!["m4", "m3"]);
+    let older = page(Some("m3"), None, 10, MessageOrder::Desc)
+        .await
+        .unwrap();
+    assert_eq!(page_ids(&older.messages), vec!["m2", "m1", "m0"]);
+    assert!(!older.has_more);
+    let between = page(Some("m4"), Some("m1"), 10, MessageOrder::Asc)
+        .await
+        .unwrap();
+    assert_eq!(page_ids(&between.messages), vec!["m2", "m3"]);
+    assert!(page(Some("missing"), None, 2, MessageOrder::Asc)
+        .await
+        .is_err());
+
+    // Rewrites keep the offset index in step
+    let mut modified = first.messages[1].clone();
+    modified["content"] = json!([{"type": "text", "text": "a much longer replacement text"}]);
+    modify_message(app.handle().clone(), modified.clone())
+        .await
+        .unwrap();
+    delete_message(app.handle().clone(), thread_id.clone(), "m0".to_string())
+        .await
+        .unwrap();
+    let all = page(None, None, 10, MessageOrder::Asc).await.unwrap();
+    assert_eq!(page_ids(&all.messages), vec!["m1", "m2", "m3", "m4"]);
+    assert_eq!(all.messages[0], modified);
+    assert_eq!(
+        count_messages(app.handle().clone(), thread_id.clone())
+            .await
+            .unwrap(),
+        4
+    );
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[test]
+fn test_message_index_rebuilds_after_external_edit() {
+    let dir = create_temp_dir();
+    let path = dir.join(MESSAGES_FILE);
+    let messages: Vec<_> = (0..3).map(|i| json!({"id": format!("m{i}")})).collect();
+    write_messages_to_file(&messages, &path).unwrap();
+    assert_eq!(count_messages_in_file(&path).unwrap(), 3);
+
+    // Edited by hand: one message more and a corrupt line
+    fs::write(
+        &path,
+        "{\"id\": \"m0\"}\n{\"id\": \"m1\"}\nnot json\n{\"id\": \"m2\"}\n{\"id\": \"m3\"}\n",
+    )
+    .unwrap();
+    assert_eq!(count_messages_in_file(&path).unwrap(), 4);
+    let page =
+        read_messages_page(&path, &page_query(None, Some("m1"), 10, MessageOrder::Asc)).unwrap();
+    assert_eq!(page_ids(&page.messages), vec!["m2", "m3"]);
+    assert!(dir.join(MESSAGES_CORRUPT_FILE).exists());
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_select_messages_page_from_sqlite() {
+    let dir = create_temp_dir();
+    let pool = db::open_database(&dir.join(db::DB_NAME)).await.unwrap();
+    sqlx::query("INSERT INTO threads (id, data) VALUES ('t', '{}')")
+        .execute(&pool)
+        .await
+        .unwrap();
+    // m1 and m2 share a timestamp, so insertion order breaks the tie
+    for (id, created_at) in [("m0", 10), ("m1", 20), ("m2", 20), ("m3", 30), ("m4", 40)] {
+        sqlx::query(
+            "INSERT INTO messages (id, thread_id, data, created_at) VALUES (?1, 't', ?2, ?3)",
+        )
+        .bind(id)
+        .bind(json!({"id": id}).to_string())
+        .bind(created_at)
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+
+    let page = |before, after, limit, order| {
+        let pool = pool.clone();
+        async move {
+            db::select_messages_page(&pool, "t", &page_query(before, after, limit, order))
+                .await
+                .unwrap()
+        }
+    };
+    let first = page(None, None, 2, MessageOrder::Asc).await;
+    assert_eq!(page_ids(&first.messages), vec!["m0", "m1"]);
+    assert!(first.has_more);
+    let next = page(None, Some("m1"), 2, MessageOrder::Asc).await;
+    assert_eq!(page_ids(&next.messages), vec!["m2", "m3"]);
+    let older = page(Some("m3"), None, 10, MessageOrder::Desc).await;
+    assert_eq!(page_ids(&older.messages), vec!["m2", "m1", "m0"]);
+    assert!(!older.has_more);
+    let between = page(Some("m4"), Some("m0"), 2, MessageOrder::Desc).await;
+    assert_eq!(page_ids(&between.messages), vec!["m3", "m2"]);
+    assert!(between.has_more);
+
+    pool.close().await;
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_export_and_import_archive() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let mut ids = vec![];
+    for title in ["First", "Second"] {
+        let thread = create_thread(app.handle().clone(), create_test_thread(title))
+            .await
+            .unwrap();
+        let thread_id = thread["id"].as_str().unwrap().to_string();
+        for text in ["question", "answer"] {
+            create_message(
+                app.handle().clone(),
+                create_test_message(&thread_id, &format!("{title} {text}")),
+            )
+            .await
+            .unwrap();
+        }
+        ids.push(thread_id);
+    }
+    let attachment = data_dir.join("threads").join(&ids[0]).join("files/a.txt");
+    fs::create_dir_all(attachment.parent().unwrap()).unwrap();
+    fs::write(&attachment, "attached").unwrap();
+
+    let archive = data_dir.join("export.zip");
+    let summary = export_threads(
+        app.handle().clone(),
+        ids.clone(),
+        ExportFormat::Archive,
+        archive.to_string_lossy().to_string(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(summary.exported_threads, 2);
+    assert_eq!(summary.exported_messages, 4);
+
+    // Importing into the same store creates copies under new ids
+    for _ in 0..2 {
+        let summary = import_threads(app.handle().clone(), archive.to_string_lossy().to_string())
+            .await
+            .unwrap();
+        assert_eq!(summary.imported_threads, 2);
+        assert_eq!(summary.imported_messages, 4);
+        assert_eq!(summary.imported_attachments, 1);
+        assert!(summary.failed.is_empty());
+
+        let new_id = &summary.thread_ids[&ids[0]];
+        assert!(!ids.contains(new_id));
+        let messages = list_messages(app.handle().clone(), new_id.clone())
+            .await
+            .unwrap();
+        let texts: Vec<_> = messages
+            .iter()
+            .map(|m| m["content"][0]["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(texts, vec!["First question", "First answer"]);
+        assert!(messages.iter().all(|m| m["thread_id"] == new_id.as_str()));
+        let copied = data_dir.join("threads").join(new_id).join("files/a.txt");
+        assert_eq!(fs::read_to_string(copied).unwrap(), "attached");
+    }
+    let threads = list_threads(app.handle().clone(), None).await.unwrap();
+    assert_eq!(threads.len(), 6);
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn test_import_rejects_newer_archive() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let archive = data_dir.join("future.zip");
+    let mut zip = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+    zip.start_file("manifest.json", zip::write::FileOptions::default())
+        .unwrap();
+    std::io::Write::write_all(
+        &mut zip,
+        json!({"format": "jan-threads", "version": 99, "exported_at": 0, "threads": []})
+            .to_string()
+            .as_bytes(),
+    )
+    .unwrap();
+    zip.finish().unwrap();
+
+    let result = import_threads(app.handle().clone(), archive.to_string_lossy().to_string()).await;
+    assert!(result.unwrap_err().contains("newer"));
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn test_archive_carries_attachment_store_files() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let files = create_temp_dir();
+    fs::write(files.join("report.txt"), "Quarterly report").unwrap();
+    let attachment = put_attachment(
+        app.handle().clone(),
+        files.join("report.txt").to_string_lossy().to_string(),
+        None,
+    )
+    .await
+    .unwrap();
+    let thread = create_thread(app.handle().clone(), create_test_thread("Report"))
+        .await
+        .unwrap();
+    let thread_id = thread["id"].as_str().unwrap().to_string();
+    create_message(
+        app.handle().clone(),
+        create_test_message(&thread_id, &format!("See {}", attachment.url)),
+    )
+    .await
+    .unwrap();
+
+    let archive = data_dir.join("report.zip");
+    export_threads(
+        app.handle().clone(),
+        vec![thread_id.clone()],
+        ExportFormat::Archive,
+        archive.to_string_lossy().to_string(),
+    )
+    .await
+    .unwrap();
+
+    // The archive restores the file after it was collected
+    delete_thread_permanently(app.handle().clone(), thread_id)
+        .await
+        .unwrap();
+    gc_attachments(app.handle().clone(), Some(0)).await.unwrap();
+    assert!(!std::path::Path::new(&attachment.path).exists());
+
+    let summary = import_threads(app.handle().clone(), archive.to_string_lossy().to_string())
+        .await
+        .unwrap();
+    assert_eq!(summary.stored_attachments, 1);
+    assert!(summary.failed.is_empty());
+    let restored = get_attachment(app.handle().clone(), attachment.id.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(&restored.path).unwrap(),
+        "Quarterly report"
+    );
+    assert_eq!(restored.name.as_deref(), Some("report.txt"));
+    assert_eq!(restored.ref_count, 1);
+
+    let _ = fs::remove_dir_all(files);
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn test_import_archive_keeps_branches() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let thread = create_thread(app.handle().clone(), create_test_thread("Branches"))
+        .await
+        .unwrap();
+    let thread_id = thread["id"].as_str().unwrap().to_string();
+
+    let mut ids = Vec::new();
+    for (text, parent) in [("Q", None), ("A1", Some(0)), ("A2", Some(0))] {
+        let mut message = create_test_message(&thread_id, text);
+        if let Some(parent) = parent {
+            message["parent_id"] = json!(ids[parent]);
+        }
+        let message = create_message(app.handle().clone(), message).await.unwrap();
+        ids.push(message["id"].as_str().unwrap().to_string());
+    }
+    set_active_message(app.handle().clone(), thread_id.clone(), ids[1].clone())
+        .await
+        .unwrap();
+
+    let archive = data_dir.join("branches.zip");
+    export_threads(
+        app.handle().clone(),
+        vec![thread_id.clone()],
+        ExportFormat::Archive,
+        archive.to_string_lossy().to_string(),
+    )
+    .await
+    .unwrap();
+    let summary = import_threads(app.handle().clone(), archive.to_string_lossy().to_string())
+        .await
+        .unwrap();
+    let new_id = summary.thread_ids[&thread_id].clone();
+
+    let imported = list_messages(app.handle().clone(), new_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(message_texts(&imported), vec!["Q", "A1", "A2"]);
+    assert!(imported
+        .iter()
+        .all(|m| !ids.contains(&m["id"].as_str().unwrap().to_string())));
+    assert!(imported[0]["parent_id"].is_null());
+    assert_eq!(imported[1]["parent_id"], imported[0]["id"]);
+    assert_eq!(imported[2]["parent_id"], imported[0]["id"]);
+
+    let thread = get_thread(app.handle().clone(), new_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(thread["metadata"]["active_message_id"], imported[1]["id"]);
+    let branch = list_message_branch(app.handle().clone(), new_id)
+        .await
+        .unwrap();
+    assert_eq!(message_texts(&branch.messages), vec!["Q", "A1"]);
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[test]
+fn test_export_text_formats() {
+    let thread = json!({
+        "title": "Greetings",
+        "assistants": [{"id": "jan", "name": "Jan", "instructions": "Be brief."}]
+    });
+    let mut reply = create_test_message("t", "Hi there!");
+    reply["role"] = json!("assistant");
+    let messages = vec![create_test_message("t", "Hello"), reply];
+
+    assert_eq!(
+        thread_markdown(&thread, &messages),
+        "# Greetings\n\n## User\n\nHello\n\n## Jan\n\nHi there!\n"
+    );
+    assert_eq!(
+        thread_chat_example(&thread, &messages).unwrap(),
+        json!({"messages": [
+            {"role": "system", "content": "Be brief."},
+            {"role": "user", "content": "Hello"},
+            {"role": "assistant", "content": "Hi there!"}
+        ]})
+    );
+    // Not a useful training example without a reply
+    assert_eq!(thread_chat_example(&thread, &messages[..1]), None);
+}
+
+// Helper to read the text of converted messages
+fn converted_texts(messages: &[serde_json::Value]) -> Vec<&str> {
+    messages
+        .iter()
+        .map(|m| m["content"][0]["text"]["value"].as_str().unwrap())
+        .collect()
+}
+
+// ChatGPT export where the answer was regenerated: "a2" replaced "a1"
+fn chatgpt_export() -> serde_json::Value {
+    let node = |id: &str, parent: Option<&str>, children: &[&str], message| json!({"id": id, "parent": parent, "children": children, "message": message});
+    let message = |role: &str, parts: serde_json::Value, time: f64| {
+        json!({
+            "author": {"role": role},
+            "create_time": time,
+            "content": {"content_type": "text", "parts": parts},
+            "metadata": {"model_slug": "gpt-4o"}
+        })
+    };
+    json!([{
+        "title": "Regenerated",
+        "conversation_id": "c1",
+        "create_time": 1700000000.5,
+        "update_time": 1700000100.0,
+        "current_node": "a2",
+        "mapping": {
+            "root": node("root", None, &["sys"], serde_json::Value::Null),
+            "sys": node("sys", Some("root"), &["q"], message("system", json!([""]), 1.0)),
+            "q": node("q", Some("sys"), &["a1", "a2"], message("user", json!(["Question"]), 2.0)),
+            "a1": node("a1", Some("q"), &[], message("assistant", json!(["First answer"]), 3.0)),
+            "a2": node("a2", Some("q"), &[], message("assistant", json!(["Better answer", {"asset_pointer": "file"}]), 4.0)),
+        }
+    }])
+}
+
+#[test]
+fn test_convert_chatgpt_follows_current_branch() {
+    let data = chatgpt_export();
+    assert_eq!(detect_source(&data), Some(ImportSource::Chatgpt));
+    let (_, conversion) = convert_history(&data, None).unwrap();
+
+    assert_eq!(conversion.threads.len(), 1);
+    let thread = &conversion.threads[0];
+    assert_eq!(thread.source_id, "c1");
+    assert_eq!(thread.thread["title"], "Regenerated");
+    assert_eq!(thread.thread["metadata"]["imported_from"], "chatgpt");
+    assert_eq!(
+        converted_texts(&thread.messages),
+        vec!["Question", "Better answer"]
+    );
+    assert_eq!(thread.messages[1]["role"], "assistant");
+    assert_eq!(thread.messages[1]["created_at"], 4000);
+    assert_eq!(thread.messages[1]["metadata"]["model"], "gpt-4o");
+    // The empty system message is skipped, the replaced answer is on another branch
+    assert_eq!(conversion.skipped_messages, 1);
+    assert_eq!(conversion.skipped_branch_messages, 1);
+}
+
+#[test]
+fn test_convert_openwebui_and_librechat() {
+    let openwebui = json!([{
+        "id": "w1",
+        "title": "Web UI chat",
+        "created_at": 1700000000,
+        "updated_at": 1700000050,
+        "chat": {"history": {"currentId": "m3", "messages": {
+            "m1": {"id": "m1", "parentId": null, "role": "user", "content": "Hi", "timestamp": 1700000000},
+            "m2": {"id": "m2", "parentId": "m1", "role": "assistant", "content": "Old", "timestamp": 1700000001},
+            "m3": {"id": "m3", "parentId": "m1", "role": "assistant", "content": "New", "timestamp": 1700000002, "model": "llama3"}
+        }}}
+    }, {"id": "w2", "title": "Empty", "chat": {"messages": []}}]);
+    assert_eq!(detect_source(&openwebui), Some(ImportSource::Openwebui));
+    let (_, conversion) = convert_history(&openwebui, None).unwrap();
+    assert_eq!(conversion.threads.len(), 1);
+    assert_eq!(
+        converted_texts(&conversion.threads[0].messages),
+        vec!["Hi", "New"]
+    );
+    assert_eq!(conversion.skipped_branch_messages, 1);
+    assert_eq!(conversion.skipped_conversations.len(), 1);
+    assert_eq!(conversion.skipped_conversations[0].source_id, "w2");
+
+    // Recursive LibreChat export: the newest leaf decides the branch
+    let librechat = json!({
+        "conversationId": "l1",
+        "title": "Libre",
+        "recursive": true,
+        "messages": [{
+            "messageId": "q", "isCreatedByUser": true, "text": "Ask", "createdAt": "2024-01-01T00:00:00Z",
+            "children": [
+                {"messageId": "r1", "isCreatedByUser": false, "text": "Reply one", "createdAt": "2024-01-01T00:00:05Z"},
+                {"messageId": "r2", "isCreatedByUser": false, "text": "", "createdAt": "2024-01-01T00:00:09Z",
+                 "content": [{"type": "text", "text": "Reply two"}]}
+            ]
+        }]
+    });
+    assert_eq!(detect_source(&librechat), Some(ImportSource::Librechat));
+    let (_, conversion) = convert_history(&librechat, None).unwrap();
+    let messages = &conversion.threads[0].messages;
+    assert_eq!(converted_texts(messages), vec!["Ask", "Reply two"]);
+    assert_eq!(messages[0]["role"], "user");
+    assert_eq!(messages[0]["created_at"], 1704067200000i64);
+    assert_eq!(conversion.skipped_branch_messages, 1);
+
+    assert!(convert_history(&json!({"unknown": true}), None).is_err());
+}
+
+#[tokio::test]
+async fn test_import_chat_history_dry_run_and_reimport() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let export = data_dir.join("conversations.json");
+    fs::write(&export, chatgpt_export().to_string()).unwrap();
+    let path = export.to_string_lossy().to_string();
+
+    let report = import_chat_history(app.handle().clone(), path.clone(), None, true)
+        .await
+        .unwrap();
+    assert!(report.dry_run);
+    assert_eq!((report.threads, report.messages), (1, 2));
+    assert!(list_threads(app.handle().clone(), None)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let report = import_chat_history(
+        app.handle().clone(),
+        path.clone(),
+        Some(ImportSource::Chatgpt),
+        false,
+    )
+    .await
+    .unwrap();
+    assert_eq!((report.threads, report.messages), (1, 2));
+    let threads = list_threads(app.handle().clone(), None).await.unwrap();
+    assert_eq!(threads.len(), 1);
+    let thread_id = threads[0]["id"].as_str().unwrap().to_string();
+    let messages = list_messages(app.handle().clone(), thread_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        converted_texts(&messages),
+        vec!["Question", "Better answer"]
+    );
+
+    // Conversations that are already in Jan are not imported twice
+    let report = import_chat_history(app.handle().clone(), path, None, false)
+        .await
+        .unwrap();
+    assert_eq!(report.threads, 0);
+    assert_eq!(report.skipped_conversations[0].reason, "Already imported");
+    assert_eq!(
+        list_threads(app.handle().clone(), None)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+// Helper to read the text of messages created by create_test_message
+fn message_texts(messages: &[serde_json::Value]) -> Vec<&str> {
+    messages
+        .iter()
+        .map(|m| m["content"][0]["text"].as_str().unwrap())
+        .collect()
+}
+
+fn branch_message(id: &str, parent_id: Option<&str>) -> serde_json::Value {
+    let mut message = json!({ "id": id, "role": "user" });
+    if let Some(parent_id) = parent_id {
+        message["parent_id"] = json!(parent_id);
+    }
+    message
+}
+
+#[test]
+fn test_active_branch_follows_newest_replies() {
+    // a - b - c, with d regenerated as an alternative to b and e answering d
+    let messages = vec![
+        branch_message("a", None),
+        branch_message("b", None),
+        branch_message("c", None),
+        branch_message("d", Some("a")),
+        branch_message("e", Some("d")),
+    ];
+
+    let branch = active_branch(messages.clone(), None);
+    assert_eq!(page_ids(&branch.messages), vec!["a", "d", "e"]);
+    assert_eq!(branch.siblings.len(), 1);
+    assert_eq!(branch.siblings["d"], vec!["b", "d"]);
+
+    let branch = active_branch(messages.clone(), Some("b"));
+    assert_eq!(page_ids(&branch.messages), vec!["a", "b", "c"]);
+    assert_eq!(branch.siblings["b"], vec!["b", "d"]);
+
+    // An unknown active message falls back to the newest message
+    let branch = active_branch(messages, Some("missing"));
+    assert_eq!(page_ids(&branch.messages), vec!["a", "d", "e"]);
+}
+
+#[test]
+fn test_reparent_children_keeps_branches_reachable() {
+    let mut messages = vec![
+        branch_message("a", None),
+        branch_message("b", None),
+        branch_message("c", None),
+        branch_message("d", Some("b")),
+    ];
+    assert_eq!(reparent_children(&mut messages, 1), vec![3]);
+    messages.remove(1);
+
+    // c follows a implicitly once b is gone, d is moved explicitly
+    assert!(messages[1].get("parent_id").is_none());
+    assert_eq!(messages[2]["parent_id"], "a");
+    let branch = active_branch(messages, None);
+    assert_eq!(page_ids(&branch.messages), vec!["a", "d"]);
+    assert_eq!(branch.siblings["d"], vec!["c", "d"]);
+}
+
+#[tokio::test]
+async fn test_fork_thread_and_switch_branches() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let thread = create_thread(app.handle().clone(), create_test_thread("Branches"))
+        .await
+        .unwrap();
+    let thread_id = thread["id"].as_str().unwrap().to_string();
+
+    let mut ids = Vec::new();
+    for (text, parent) in [
+        ("Q", None),
+        ("A1", Some(0)),
+        ("A2", Some(0)),
+        ("More", Some(2)),
+    ] {
+        let mut message = create_test_message(&thread_id, text);
+        if let Some(parent) = parent {
+            message["parent_id"] = json!(ids[parent]);
+        }
+        let message = create_message(app.handle().clone(), message).await.unwrap();
+        ids.push(message["id"].as_str().unwrap().to_string());
+    }
+
+    let branch = list_message_branch(app.handle().clone(), thread_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(message_texts(&branch.messages), vec!["Q", "A2", "More"]);
+    assert_eq!(
+        branch.siblings[&ids[2]],
+        vec![ids[1].clone(), ids[2].clone()]
+    );
+
+    let branch = set_active_message(app.handle().clone(), thread_id.clone(), ids[1].clone())
+        .await
+        .unwrap();
+    assert_eq!(message_texts(&branch.messages), vec!["Q", "A1"]);
+    let branch = list_message_branch(app.handle().clone(), thread_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(message_texts(&branch.messages), vec!["Q", "A1"]);
+
+    let fork = fork_thread(app.handle().clone(), thread_id.clone(), ids[3].clone())
+        .await
+        .unwrap();
+    let fork_id = fork["id"].as_str().unwrap().to_string();
+    assert_ne!(fork_id, thread_id);
+    assert_eq!(
+        fork["metadata"]["forked_from"]["thread_id"],
+        thread_id.as_str()
+    );
+    assert!(fork["metadata"].get("active_message_id").is_none());
+    let forked = list_messages(app.handle().clone(), fork_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(message_texts(&forked), vec!["Q", "A2", "More"]);
+    assert!(forked.iter().all(|m| m["thread_id"] == fork_id.as_str()));
+    assert!(forked[0]["parent_id"].is_null());
+    assert_eq!(forked[2]["parent_id"], forked[1]["id"]);
+
+    // Forking from an unknown message creates nothing
+    assert!(fork_thread(
+        app.handle().clone(),
+        thread_id.clone(),
+        "missing".to_string()
+    )
+    .await
+    .is_err());
+    assert_eq!(
+        list_threads(app.handle().clone(), None)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+
+    // Deleting a reply moves its own replies up to its parent
+    delete_message(app.handle().clone(), thread_id.clone(), ids[2].clone())
+        .await
+        .unwrap();
+    let messages = list_messages(app.handle().clone(), thread_id)
+        .await
+        .unwrap();
+    assert_eq!(messages[2]["parent_id"], ids[0].as_str());
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn test_trash_restore_and_empty() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let thread = create_thread(app.handle().clone(), create_test_thread("Research"))
+        .await
+        .unwrap();
+    let thread_id = thread["id"].as_str().unwrap().to_string();
+    let mut ids = Vec::new();
+    for text in ["First", "Second", "Third"] {
+        let message = create_message(app.handle().clone(), create_test_message(&thread_id, text))
+            .await
+            .unwrap();
+        ids.push(message["id"].as_str().unwrap().to_string());
+    }
+    let texts = |thread_id: String| {
+        let app = app.handle().clone();
+        async move {
+            let messages = list_messages(app, thread_id).await.unwrap();
+            message_texts(&messages)
+                .into_iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        }
+    };
+
+    // A deleted message goes back to its place
+    delete_message(app.handle().clone(), thread_id.clone(), ids[1].clone())
+        .await
+        .unwrap();
+    let entries = list_trash(app.handle().clone()).await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].kind, TrashKind::Message);
+    assert_eq!(entries[0].title, "Second");
+    restore_from_trash(app.handle().clone(), entries[0].id.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        texts(thread_id.clone()).await,
+        vec!["First", "Second", "Third"]
+    );
+    assert!(list_trash(app.handle().clone()).await.unwrap().is_empty());
+
+    // A message cannot be restored into a thread that is in the trash
+    delete_message(app.handle().clone(), thread_id.clone(), ids[1].clone())
+        .await
+        .unwrap();
+    delete_thread(app.handle().clone(), thread_id.clone())
+        .await
+        .unwrap();
+    assert!(list_threads(app.handle().clone(), None)
+        .await
+        .unwrap()
+        .is_empty());
+    let entries = list_trash(app.handle().clone()).await.unwrap();
+    assert_eq!(entries.len(), 2);
+    let message_entry = entries
+        .iter()
+        .find(|e| e.kind == TrashKind::Message)
+        .unwrap();
+    let thread_entry = entries
+        .iter()
+        .find(|e| e.kind == TrashKind::Thread)
+        .unwrap();
+    assert_eq!(thread_entry.title, "Research");
+    assert!(
+        restore_from_trash(app.handle().clone(), message_entry.id.clone())
+            .await
+            .is_err()
+    );
+
+    restore_from_trash(app.handle().clone(), thread_entry.id.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        list_threads(app.handle().clone(), None)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(texts(thread_id.clone()).await, vec!["First", "Third"]);
+    restore_from_trash(app.handle().clone(), message_entry.id.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        texts(thread_id.clone()).await,
+        vec!["First", "Second", "Third"]
+    );
+
+    delete_thread(app.handle().clone(), thread_id)
+        .await
+        .unwrap();
+    assert_eq!(empty_trash(app.handle().clone(), None).await.unwrap(), 1);
+    assert!(list_trash(app.handle().clone()).await.unwrap().is_empty());
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn test_trash_purges_expired_entries() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    for title in ["Old", "Recent"] {
+        let thread = create_thread(app.handle().clone(), create_test_thread(title))
+            .await
+            .unwrap();
+        let thread_id = thread["id"].as_str().unwrap().to_string();
+        delete_thread(app.handle().clone(), thread_id)
+            .await
+            .unwrap();
+    }
+
+    // Backdate the first entry by 40 days
+    let mut old = trash::list_entries(app.handle())
+        .unwrap()
+        .into_iter()
+        .find(|e| e.title == "Old")
+        .unwrap();
+    old.deleted_at -= 40 * 24 * 60 * 60;
+    let entry_path = trash::get_entry_dir(app.handle(), &old.id).join("entry.json");
+    fs::write(&entry_path, serde_json::to_string(&old).unwrap()).unwrap();
+
+    assert_eq!(trash::purge_expired(app.handle(), 0).await.unwrap(), 0);
+    assert_eq!(trash::purge_expired(app.handle(), 30).await.unwrap(), 1);
+    let entries = trash::list_entries(app.handle()).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].title, "Recent");
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[test]
+fn test_normalize_message() {
+    let message = json!({
+        "id": "m1",
+        "thread_id": "t1",
+        "role": "assistant",
+        "content": "Hello",
+        "created_at": 1700000000000u64,
+        "tool_call_id": "call_1",
+        "parent_id": null
+    });
+    let normalized = normalize_message(message).unwrap();
+    assert_eq!(normalized["schema_version"], SCHEMA_VERSION);
+    assert_eq!(
+        normalized["content"],
+        json!([{"type": "text", "text": {"value": "Hello", "annotations": []}}])
+    );
+    // Unknown fields and the created_at representation are kept
+    assert_eq!(normalized["tool_call_id"], "call_1");
+    assert!(normalized["parent_id"].is_null());
+    assert_eq!(normalized["created_at"], json!(1700000000000u64));
+    assert_eq!(normalize_message(normalized.clone()).unwrap(), normalized);
+
+    let parts = json!({
+        "id": "m2",
+        "thread_id": "t1",
+        "role": "tool",
+        "content": [
+            {"type": "tool_call", "tool_call": {"id": "call_1", "name": "search", "arguments": "{}"}},
+            {"type": "tool_result", "tool_result": {"tool_call_id": "call_1", "content": "ok"}},
+            {"type": "audio", "audio": {"format": "wav"}}
+        ]
+    });
+    let normalized = normalize_message(parts).unwrap();
+    assert_eq!(normalized["content"][2]["audio"]["format"], "wav");
+    assert_eq!(normalized["content"][0]["type"], "tool_call");
+
+    let invalid = [
+        json!({"id": "m", "thread_id": "t", "role": 42, "content": []}),
+        json!({"id": "m", "thread_id": "", "role": "user", "content": []}),
+        json!({"id": "m", "thread_id": "t", "role": "user", "content": [{"type": "text"}]}),
+        json!({"id": "m", "thread_id": "t", "role": "user", "created_at": "yesterday"}),
+        json!({"id": "m", "thread_id": "t", "role": "tool", "content": [
+            {"type": "tool_call", "tool_call": {"id": "call_1", "name": ""}}
+        ]}),
+        json!({"id": "m", "thread_id": "t", "role": "user", "schema_version": SCHEMA_VERSION + 1}),
+    ];
+    for message in invalid {
+        assert!(normalize_message(message.clone()).is_err(), "{message}");
+    }
+}
+
+#[test]
+fn test_normalize_thread() {
+    let thread = json!({"id": "t1", "title": "Old", "metadata": {"pinned": true}, "order": 2});
+    let normalized = normalize_thread(thread).unwrap();
+    assert_eq!(normalized["assistants"], json!([]));
+    assert_eq!(normalized["metadata"]["pinned"], true);
+    assert_eq!(normalized["order"], 2);
+
+    assert!(normalize_thread(json!({"id": "t1", "metadata": "pinned"})).is_err());
+    assert!(normalize_thread(json!({"id": "t1", "assistants": [{"name": "Jan"}]})).is_err());
+    assert!(normalize_thread(json!({"title": "No id"})).is_err());
+}
+
+#[test]
+fn test_normalize_legacy_records() {
+    // `null` reads as the default, and a null id is still missing
+    let thread = normalize_thread(json!({"id": "t1", "title": null, "assistants": null})).unwrap();
+    assert_eq!(thread["title"], "");
+    assert_eq!(thread["assistants"], json!([]));
+    assert!(normalize_thread(json!({"id": null, "title": "Old"})).is_err());
+
+    // `metadata: null` is kept, a missing one stays missing
+    let thread = normalize_thread(json!({"id": "t1", "metadata": null})).unwrap();
+    assert!(thread.as_object().unwrap().contains_key("metadata"));
+    assert!(thread["metadata"].is_null());
+    let thread = normalize_thread(json!({"id": "t1"})).unwrap();
+    assert!(!thread.as_object().unwrap().contains_key("metadata"));
+
+    // Assistants with only the legacy `assistant_id` get an `id`
+    let thread = normalize_thread(json!({
+        "id": "t1",
+        "assistants": [{"assistant_id": "jan", "name": "Jan"}, {"id": "new", "assistant_id": "old"}]
+    }))
+    .unwrap();
+    assert_eq!(thread["assistants"][0]["id"], "jan");
+    assert_eq!(thread["assistants"][1]["id"], "new");
+
+    let message = |fields: serde_json::Value| {
+        let mut message = json!({"id": "m1", "thread_id": "t1", "role": "user"});
+        for (key, value) in fields.as_object().unwrap() {
+            message[key] = value.clone();
+        }
+        normalize_message(message)
+    };
+    // Null content, in a legacy and in a current record
+    assert_eq!(
+        message(json!({"content": null})).unwrap()["content"],
+        json!([])
+    );
+    let current = json!({"content": null, "schema_version": SCHEMA_VERSION});
+    assert_eq!(message(current).unwrap()["content"], json!([]));
+    assert!(message(json!({"metadata": null})).unwrap()["metadata"].is_null());
+    assert!(message(json!({"id": null})).is_err());
+
+    // Roles this version does not know are kept
+    let developer = message(json!({"role": "developer", "content": "Be brief"})).unwrap();
+    assert_eq!(developer["role"], "developer");
+    assert_eq!(normalize_message(developer.clone()).unwrap(), developer);
+    assert_eq!(message(json!({"role": "tool"})).unwrap()["role"], "tool");
+}
+
+#[tokio::test]
+async fn test_write_commands_validate_records() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let thread = create_thread(app.handle().clone(), create_test_thread("Typed"))
+        .await
+        .unwrap();
+    assert_eq!(thread["schema_version"], SCHEMA_VERSION);
+    let thread_id = thread["id"].as_str().unwrap().to_string();
+
+    let mut message = create_test_message(&thread_id, "Hi");
+    message["role"] = json!(42);
+    assert!(create_message(app.handle().clone(), message).await.is_err());
+    assert!(list_messages(app.handle().clone(), thread_id.clone())
+        .await
+        .unwrap()
+        .is_empty());
+
+    let mut modified = thread.clone();
+    modified["assistants"] = json!("jan");
+    assert!(modify_thread(app.handle().clone(), modified).await.is_err());
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn test_upgrade_files_schema() {
+    let dir = create_temp_dir();
+    let thread_dir = dir.join("old");
+    fs::create_dir_all(&thread_dir).unwrap();
+    fs::write(
+        thread_dir.join("thread.json"),
+        json!({"id": "old", "title": "Old"}).to_string(),
+    )
+    .unwrap();
+    write_messages_to_file(
+        &[json!({"id": "m1", "thread_id": "old", "role": "user", "content": "Legacy"})],
+        &thread_dir.join(MESSAGES_FILE),
+    )
+    .unwrap();
+    let broken_dir = dir.join("broken");
+    fs::create_dir_all(&broken_dir).unwrap();
+    fs::write(
+        broken_dir.join("thread.json"),
+        json!({"id": "broken", "title": "Broken"}).to_string(),
+    )
+    .unwrap();
+    let broken_messages = vec![json!({"id": "m2", "thread_id": "broken", "role": 42})];
+    write_messages_to_file(&broken_messages, &broken_dir.join(MESSAGES_FILE)).unwrap();
+
+    assert!(!files_schema_is_current(&dir));
+    let report = upgrade_files_schema(&dir).await.unwrap();
+    assert_eq!((report.upgraded_threads, report.upgraded_messages), (1, 1));
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].thread_id, "broken");
+
+    let thread: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(thread_dir.join("thread.json")).unwrap()).unwrap();
+    assert_eq!(thread["schema_version"], SCHEMA_VERSION);
+    let messages = read_messages_with_recovery(&thread_dir.join(MESSAGES_FILE))
+        .unwrap()
+        .messages;
+    assert_eq!(messages[0]["content"][0]["text"]["value"], "Legacy");
+    // A thread with a record that cannot be upgraded is left as it was
+    let broken = read_messages_with_recovery(&broken_dir.join(MESSAGES_FILE))
+        .unwrap()
+        .messages;
+    assert_eq!(broken, broken_messages);
+    let thread: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(broken_dir.join("thread.json")).unwrap()).unwrap();
+    assert!(thread.get("schema_version").is_none());
+
+    // Records that fail validation do not keep the storage from being marked as upgraded
+    assert!(files_schema_is_current(&dir));
+    let report = upgrade_files_schema(&dir).await.unwrap();
+    assert_eq!((report.upgraded_threads, report.upgraded_messages), (0, 0));
+
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_upgrade_sqlite_schema() {
+    use sqlx::Row;
+    let dir = create_temp_dir();
+    let pool = db::open_database(&dir.join(db::DB_NAME)).await.unwrap();
+    sqlx::query("INSERT INTO threads (id, data) VALUES ('t', ?1)")
+        .bind(json!({"id": "t", "title": "Old"}).to_string())
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO messages (id, thread_id, data) VALUES ('m1', 't', ?1)")
+        .bind(
+            json!({"id": "m1", "thread_id": "t", "role": "user", "content": "Legacy"}).to_string(),
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    assert!(!sqlite_schema_is_current(&pool).await.unwrap());
+    let report = upgrade_sqlite_schema(&pool, &dir).await.unwrap();
+    assert_eq!((report.upgraded_threads, report.upgraded_messages), (1, 1));
+    assert!(sqlite_schema_is_current(&pool).await.unwrap());
+    let data: String = sqlx::query("SELECT data FROM messages WHERE id = 'm1'")
+        .fetch_one(&pool)
+        .await
+        .unwrap()
+        .get("data");
+    let message: serde_json::Value = serde_json::from_str(&data).unwrap();
+    assert_eq!(message["schema_version"], SCHEMA_VERSION);
+    assert_eq!(message["content"][0]["text"]["value"], "Legacy");
+
+    let report = upgrade_sqlite_schema(&pool, &dir).await.unwrap();
+    assert_eq!((report.upgraded_threads, report.upgraded_messages), (0, 0));
+
+    pool.close().await;
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_thread_encryption() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let thread = create_thread(app.handle().clone(), create_test_thread("Research"))
+        .await
+        .unwrap();
+    let thread_id = thread["id"].as_str().unwrap().to_string();
+    let mut ids = Vec::new();
+    for text in ["First", "Second"] {
+        let message = create_message(app.handle().clone(), create_test_message(&thread_id, text))
+            .await
+            .unwrap();
+        ids.push(message["id"].as_str().unwrap().to_string());
+    }
+    delete_message(app.handle().clone(), thread_id.clone(), ids[1].clone())
+        .await
+        .unwrap();
+    let thread_dir = data_dir.join("threads").join(&thread_id);
+    let raw = |name: &str| fs::read_to_string(thread_dir.join(name)).unwrap();
+
+    let report = enable_thread_encryption(
+        app.handle().clone(),
+        KeySource::Passphrase,
+        Some("correct horse".to_string()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        (
+            report.rewritten_threads,
+            report.rewritten_messages,
+            report.rewritten_trash_entries
+        ),
+        (1, 1, 1)
+    );
+    assert!(raw("thread.json").starts_with("jan-enc1:"));
+    assert!(!raw("thread.json").contains("Research"));
+    assert!(raw(MESSAGES_FILE)
+        .lines()
+        .all(|line| line.starts_with("jan-enc1:")));
+    assert!(!raw(MESSAGES_FILE).contains("First"));
+
+    // Reads, appends and pages work as before
+    create_message(
+        app.handle().clone(),
+        create_test_message(&thread_id, "Third"),
+    )
+    .await
+    .unwrap();
+    let messages = list_messages(app.handle().clone(), thread_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(message_texts(&messages), vec!["First", "Third"]);
+    let page = list_messages_page(
+        app.handle().clone(),
+        thread_id.clone(),
+        None,
+        None,
+        Some(1),
+        Some(MessageOrder::Desc),
+    )
+    .await
+    .unwrap();
+    assert_eq!(message_texts(&page.messages), vec!["Third"]);
+    assert!(
+        search_messages(app.handle().clone(), "First".to_string(), None)
+            .await
+            .is_err()
+    );
+
+    // After a restart the data stays locked until the passphrase is given
+    encryption::load_keys(&data_dir).unwrap();
+    let status = get_thread_encryption_status(app.handle().clone())
+        .await
+        .unwrap();
+    assert!(status.enabled && !status.unlocked);
+    assert_eq!(
+        list_messages(app.handle().clone(), thread_id.clone()).await,
+        Err(LOCKED_ERROR.to_string())
+    );
+    assert!(create_message(
+        app.handle().clone(),
+        create_test_message(&thread_id, "Fourth")
+    )
+    .await
+    .is_err());
+    assert!(!thread_dir.join(MESSAGES_CORRUPT_FILE).exists());
+    assert!(
+        unlock_thread_storage(app.handle().clone(), "wrong".to_string())
+            .await
+            .is_err()
+    );
+    unlock_thread_storage(app.handle().clone(), "correct horse".to_string())
+        .await
+        .unwrap();
+    assert_eq!(
+        list_threads(app.handle().clone(), None).await.unwrap()[0]["title"],
+        "Research"
+    );
+
+    // Re-encrypting with a new passphrase replaces the key
+    let old_line = raw(MESSAGES_FILE).lines().next().unwrap().to_string();
+    let old_key_id = old_line.split(':').nth(1).unwrap().to_string();
+    reencrypt_thread_data(
+        app.handle().clone(),
+        None,
+        Some("battery staple".to_string()),
+    )
+    .await
+    .unwrap();
+    assert!(!raw(MESSAGES_FILE).contains(&old_key_id));
+    encryption::load_keys(&data_dir).unwrap();
+    assert!(
+        unlock_thread_storage(app.handle().clone(), "correct horse".to_string())
+            .await
+            .is_err()
+    );
+    unlock_thread_storage(app.handle().clone(), "battery staple".to_string())
+        .await
+        .unwrap();
+    let entries = list_trash(app.handle().clone()).await.unwrap();
+    assert_eq!(entries[0].title, "Second");
+    restore_from_trash(app.handle().clone(), entries[0].id.clone())
+        .await
+        .unwrap();
+
+    let report = disable_thread_encryption(app.handle().clone())
+        .await
+        .unwrap();
+    assert!(report.failed.is_empty());
+    assert!(raw("thread.json").contains("Research"));
+    assert!(raw(MESSAGES_FILE).lines().all(|line| line.starts_with('{')));
+    let status = get_thread_encryption_status(app.handle().clone())
+        .await
+        .unwrap();
+    assert!(!status.enabled && status.unlocked);
+    let messages = list_messages(app.handle().clone(), thread_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(message_texts(&messages), vec!["First", "Second", "Third"]);
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn test_encrypt_sqlite_records() {
+    use sqlx::Row;
+    let dir = create_temp_dir();
+    let pool = db::open_database(&dir.join(db::DB_NAME)).await.unwrap();
+    sqlx::query("INSERT INTO threads (id, data) VALUES ('t', ?1)")
+        .bind(json!({"id": "t", "title": "Secret"}).to_string())
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO messages (id, thread_id, data) VALUES ('m1', 't', ?1)")
+        .bind(
+            json!({"id": "m1", "thread_id": "t", "role": "user", "content": "Legacy"}).to_string(),
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+    let raw_data = |table: &'static str| {
+        let pool = pool.clone();
+        async move {
+            sqlx::query(&format!("SELECT data FROM {}", table))
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+                .get::<String, _>("data")
+        }
+    };
+
+    encryption::begin_enable(&dir, KeySource::Passphrase, Some("passphrase")).unwrap();
+    let report = encryption::rewrite_sqlite(&pool, &dir).await.unwrap();
+    assert_eq!(
+        (report.rewritten_threads, report.rewritten_messages),
+        (1, 1)
+    );
+    assert!(raw_data("threads").await.starts_with("jan-enc1:"));
+    assert!(!raw_data("messages").await.contains("Legacy"));
+
+    // Encrypted rows are upgraded and stay encrypted
+    let report = upgrade_sqlite_schema(&pool, &dir).await.unwrap();
+    assert_eq!((report.upgraded_threads, report.upgraded_messages), (1, 1));
+    let data = raw_data("messages").await;
+    assert!(data.starts_with("jan-enc1:"));
+    let message: serde_json::Value = encryption::from_record(data.as_bytes()).unwrap();
+    assert_eq!(message["content"][0]["text"]["value"], "Legacy");
+
+    encryption::begin_disable(&dir).unwrap();
+    encryption::rewrite_sqlite(&pool, &dir).await.unwrap();
+    encryption::finish_disable(&dir).unwrap();
+    let thread: serde_json::Value = serde_json::from_str(&raw_data("threads").await).unwrap();
+    assert_eq!(thread["title"], "Secret");
+
+    pool.close().await;
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_message_attachment_ids() {
+    let id = "ab".repeat(32);
+    let message = json!({
+        "content": [
+            {"type": "image_url", "image_url": {"url": format!("attachment://{}", id)}},
+            {"type": "text", "text": {"value": format!("![chart](attachment://{}) twice: attachment://{}", id, id)}},
+            {"type": "text", "text": {"value": "attachment://not-a-hash attachment://AB"}}
+        ]
+    });
+    let ids = message_attachment_ids(&message);
+    assert_eq!(ids.len(), 1);
+    assert!(ids.contains(&id));
+}
+
+#[tokio::test]
+async fn test_attachment_store() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let files = create_temp_dir();
+    let png = [
+        &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A][..],
+        b"\0\0\0\rIHDR image data",
+    ]
+    .concat();
+    fs::write(files.join("chart.png"), &png).unwrap();
+    fs::write(files.join("copy.png"), &png).unwrap();
+    fs::write(files.join("notes.txt"), "Plain notes").unwrap();
+    let put = |name: &str| {
+        put_attachment(
+            app.handle().clone(),
+            files.join(name).to_string_lossy().to_string(),
+            None,
+        )
+    };
+    let ref_count = |id: String| async {
+        get_attachment(app.handle().clone(), id)
+            .await
+            .map(|attachment| attachment.ref_count)
+    };
+
+    // Identical files are stored once, under the name they were first put with
+    let image = put("chart.png").await.unwrap();
+    let copy = put("copy.png").await.unwrap();
+    assert_eq!(image, copy);
+    assert_eq!(image.mime_type, "image/png");
+    assert_eq!(image.size, png.len() as u64);
+    assert_eq!(image.name.as_deref(), Some("chart.png"));
+    assert_eq!(image.url, format!("{}{}", ATTACHMENT_URL_PREFIX, image.id));
+    assert_eq!(fs::read(&image.path).unwrap(), png);
+    let notes = put("notes.txt").await.unwrap();
+    assert_eq!(notes.mime_type, "text/plain");
+    assert!(
+        get_attachment(app.handle().clone(), "../index.json".to_string())
+            .await
+            .is_err()
+    );
+
+    // References are counted per message, across threads
+    let mut thread_ids = Vec::new();
+    for title in ["First", "Second"] {
+        let thread = create_thread(app.handle().clone(), create_test_thread(title))
+            .await
+            .unwrap();
+        thread_ids.push(thread["id"].as_str().unwrap().to_string());
+    }
+    let mut with_image = create_test_message(&thread_ids[0], "Look");
+    with_image["content"]
+        .as_array_mut()
+        .unwrap()
+        .push(json!({"type": "image_url", "image_url": {"url": image.url}}));
+    let first = create_message(app.handle().clone(), with_image)
+        .await
+        .unwrap();
+    let second = create_message(
+        app.handle().clone(),
+        create_test_message(&thread_ids[1], &format!("Same as {}", image.url)),
+    )
+    .await
+    .unwrap();
+    assert_eq!(ref_count(image.id.clone()).await, Ok(2));
+
+    // Saving messages whose references did not change does not write the index
+    let index_path = data_dir.join("attachments").join("index.json");
+    let written_at = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1);
+    fs::File::options()
+        .write(true)
+        .open(&index_path)
+        .unwrap()
+        .set_modified(written_at)
+        .unwrap();
+    modify_message(app.handle().clone(), first.clone())
+        .await
+        .unwrap();
+    create_message(
+        app.handle().clone(),
+        create_test_message(&thread_ids[0], "No attachment"),
+    )
+    .await
+    .unwrap();
+    let modified = fs::metadata(&index_path).unwrap().modified().unwrap();
+    assert_eq!(modified, written_at);
+
+    // Editing a message away from the attachment drops its reference
+    let mut edited = first.clone();
+    edited["content"] = json!([{"type": "text", "text": "No image"}]);
+    modify_message(app.handle().clone(), edited).await.unwrap();
+    assert_eq!(ref_count(image.id.clone()).await, Ok(1));
+
+    // Trashed messages keep their references, so only the notes are collected
+    let second_id = second["id"].as_str().unwrap().to_string();
+    delete_message(app.handle().clone(), thread_ids[1].clone(), second_id)
+        .await
+        .unwrap();
+    assert_eq!(ref_count(image.id.clone()).await, Ok(1));
+    let report = gc_attachments(app.handle().clone(), Some(0)).await.unwrap();
+    assert_eq!((report.removed, report.recounted), (1, 0));
+    assert_eq!(report.freed_bytes, notes.size);
+    assert!(get_attachment(app.handle().clone(), notes.id.clone())
+        .await
+        .is_err());
+    let report = gc_attachments(app.handle().clone(), None).await.unwrap();
+    assert_eq!(report.removed, 0);
+
+    // Deleting the message permanently releases the image
+    empty_trash(app.handle().clone(), None).await.unwrap();
+    assert_eq!(ref_count(image.id.clone()).await, Ok(0));
+
+    // Messages not saved through the commands are found when collecting
+    let mut imported = create_test_message(&thread_ids[0], &image.url);
+    imported["id"] = json!("imported");
+    let messages_path = data_dir
+        .join("threads")
+        .join(&thread_ids[0])
+        .join(MESSAGES_FILE);
+    let mut messages = read_messages_with_recovery(&messages_path)
+        .unwrap()
+        .messages;
+    messages.push(imported);
+    write_messages_to_file(&messages, &messages_path).unwrap();
+    let report = gc_attachments(app.handle().clone(), Some(0)).await.unwrap();
+    assert_eq!((report.removed, report.recounted), (0, 1));
+    assert_eq!(ref_count(image.id.clone()).await, Ok(1));
+
+    delete_thread_permanently(app.handle().clone(), thread_ids[0].clone())
+        .await
+        .unwrap();
+    let report = gc_attachments(app.handle().clone(), Some(0)).await.unwrap();
+    assert_eq!(report.removed, 1);
+    assert!(!std::path::Path::new(&image.path).exists());
+
+    let _ = fs::remove_dir_all(files);
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+// (kind, message id, external) of `threads://changed` events
+type RecordedChanges = Arc<Mutex<Vec<(String, Option<String>, bool)>>>;
+
+// Collects the `threads://changed` events of the given threads
+fn record_thread_changes(
+    app: &tauri::App<MockRuntime>,
+    thread_ids: Arc<Mutex<Vec<String>>>,
+) -> (tauri::EventId, RecordedChanges) {
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let recorded = changes.clone();
+    let id = app
+        .handle()
+        .listen_any(THREADS_CHANGED_EVENT, move |event| {
+            let change: serde_json::Value = serde_json::from_str(event.payload()).unwrap();
+            let thread_id = change["thread_id"].as_str().unwrap_or_default().to_string();
+            if thread_ids.lock().unwrap().contains(&thread_id) {
+                recorded.lock().unwrap().push((
+                    change["kind"].as_str().unwrap().to_string(),
+                    change["message_id"].as_str().map(str::to_string),
+                    change["external"].as_bool().unwrap(),
+                ));
+            }
+        });
+    (id, changes)
+}
+
+#[tokio::test]
+async fn test_thread_change_events() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let watched = Arc::new(Mutex::new(Vec::new()));
+    let (listener, changes) = record_thread_changes(&app, watched.clone());
+
+    let thread = create_thread(app.handle().clone(), create_test_thread("Events"))
+        .await
+        .unwrap();
+    let thread_id = thread["id"].as_str().unwrap().to_string();
+    watched.lock().unwrap().push(thread_id.clone());
+    let mut thread = get_thread(app.handle().clone(), thread_id.clone())
+        .await
+        .unwrap();
+    thread["title"] = json!("Renamed");
+    modify_thread(app.handle().clone(), thread).await.unwrap();
+
+    let message = create_message(
+        app.handle().clone(),
+        create_test_message(&thread_id, "Hello"),
+    )
+    .await
+    .unwrap();
+    let message_id = message["id"].as_str().unwrap().to_string();
+    modify_message(app.handle().clone(), message).await.unwrap();
+    delete_message(app.handle().clone(), thread_id.clone(), message_id.clone())
+        .await
+        .unwrap();
+    let entry = &trash::list_entries(app.handle()).unwrap()[0];
+    restore_from_trash(app.handle().clone(), entry.id.clone())
+        .await
+        .unwrap();
+    delete_thread(app.handle().clone(), thread_id.clone())
+        .await
+        .unwrap();
+    app.handle().unlisten(listener);
+
+    let change =
+        |kind: &str, message: bool| (kind.to_string(), message.then(|| message_id.clone()), false);
+    // The thread was created before its id was known to the listener
+    assert_eq!(
+        *changes.lock().unwrap(),
+        vec![
+            change("modified", false),
+            change("created", true),
+            change("modified", true),
+            change("deleted", true),
+            change("created", true),
+            change("deleted", false),
+        ]
+    );
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn test_external_thread_changes() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let threads_dir = data_dir.join("threads");
+    let thread = create_thread(app.handle().clone(), create_test_thread("Local"))
+        .await
+        .unwrap();
+    let local_id = thread["id"].as_str().unwrap();
+
+    // Changes are grouped by thread; derived and temporary files and the app's own
+    // changes are left out
+    let paths: Vec<PathBuf> = [
+        "external/messages.jsonl",
+        "external/thread.json",
+        "external",
+        "indexed/messages.idx.json",
+        "indexed/.thread.json.1234.tmp",
+        &format!("{}/thread.json", local_id),
+    ]
+    .iter()
+    .map(|path| threads_dir.join(path))
+    .chain([data_dir.join("trash/entry/entry.json")])
+    .collect();
+    let changed = changed_threads(&threads_dir, paths.iter().map(PathBuf::as_path));
+    assert_eq!(changed.into_iter().collect::<Vec<_>>(), vec!["external"]);
+    assert!(changed_threads(&threads_dir, [Path::new("/elsewhere/thread.json")]).is_empty());
+
+    // A thread that is gone is reported as deleted, one that exists as modified
+    let watched = Arc::new(Mutex::new(vec![
+        "external".to_string(),
+        local_id.to_string(),
+    ]));
+    let (listener, changes) = record_thread_changes(&app, watched);
+    report_external_changes(
+        app.handle(),
+        ["external".to_string(), local_id.to_string()].into(),
+    );
+    app.handle().unlisten(listener);
+    let mut changes = changes.lock().unwrap().clone();
+    changes.sort();
+    assert_eq!(
+        changes,
+        vec![
+            ("deleted".to_string(), None, true),
+            ("modified".to_string(), None, true),
+        ]
+    );
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[test]
+fn test_apply_thread_query() {
+    let indexed =
+        |id: &str, title: &str, updated: i64, assistant: &str, count: usize| IndexedThread {
+            thread: json!({
+                "id": id,
+                "title": title,
+                "created": 100 - updated,
+                "updated": updated,
+                "assistants": [{"id": assistant}],
+            }),
+            message_count: count,
+        };
+    let threads = vec![
+        indexed("a", "Rust notes", 10, "jan", 4),
+        indexed("b", "Shopping", 30, "jan", 0),
+        indexed("c", "rust errors", 20, "coder", 9),
+        indexed("d", "Trip", 20, "coder", 2),
+    ];
+    let ids = |query: ThreadQuery| -> Vec<String> {
+        apply_query(threads.clone(), &query)
+            .iter()
+            .map(|thread| thread["id"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    // Most recently updated first by default, ties broken by id
+    assert_eq!(ids(ThreadQuery::default()), vec!["b", "d", "c", "a"]);
+    assert_eq!(
+        ids(ThreadQuery {
+            sort_by: Some(ThreadSortKey::Title),
+            order: Some(SortOrder::Asc),
+            ..Default::default()
+        }),
+        vec!["c", "a", "b", "d"]
+    );
+    assert_eq!(
+        ids(ThreadQuery {
+            sort_by: Some(ThreadSortKey::MessageCount),
+            ..Default::default()
+        }),
+        vec!["c", "a", "d", "b"]
+    );
+    assert_eq!(
+        ids(ThreadQuery {
+            sort_by: Some(ThreadSortKey::Created),
+            order: Some(SortOrder::Asc),
+            ..Default::default()
+        }),
+        vec!["b", "c", "d", "a"]
+    );
+
+    // Filters combine
+    assert_eq!(
+        ids(ThreadQuery {
+            title: Some("RUST".to_string()),
+            ..Default::default()
+        }),
+        vec!["c", "a"]
+    );
+    assert_eq!(
+        ids(ThreadQuery {
+            assistant_id: Some("coder".to_string()),
+            min_messages: Some(3),
+            ..Default::default()
+        }),
+        vec!["c"]
+    );
+    assert_eq!(
+        ids(ThreadQuery {
+            updated_from: Some(15),
+            updated_to: Some(20),
+            ..Default::default()
+        }),
+        vec!["d", "c"]
+    );
+
+    // Pages
+    let page = |offset, limit| ThreadQuery {
+        offset: Some(offset),
+        limit: Some(limit),
+        ..Default::default()
+    };
+    assert_eq!(ids(page(1, 2)), vec!["d", "c"]);
+    assert_eq!(ids(page(3, 2)), vec!["a"]);
+    assert!(ids(page(4, 2)).is_empty());
+
+    // Unknown sort keys are rejected
+    assert!(serde_json::from_value::<ThreadQuery>(json!({"sort_by": "size"})).is_err());
+    let query: ThreadQuery =
+        serde_json::from_value(json!({"sort_by": "message_count", "order": "asc"})).unwrap();
+    assert_eq!(query.sort_by, Some(ThreadSortKey::MessageCount));
+}
+
+#[tokio::test]
+async fn test_thread_index() {
+    if should_use_sqlite() {
+        return;
+    }
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let handle = app.handle().clone();
+    let by_messages = || {
+        Some(ThreadQuery {
+            sort_by: Some(ThreadSortKey::MessageCount),
+            ..Default::default()
+        })
+    };
+    let titles = |threads: Vec<serde_json::Value>| -> Vec<String> {
+        threads
+            .iter()
+            .map(|thread| thread["title"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let first = create_thread(handle.clone(), create_test_thread("First"))
+        .await
+        .unwrap();
+    let first_id = first["id"].as_str().unwrap().to_string();
+    // The index is built on first use, from the thread files
+    assert!(!data_dir.join(THREAD_INDEX_DB_NAME).exists());
+    assert_eq!(list_threads(handle.clone(), None).await.unwrap().len(), 1);
+    assert!(data_dir.join(THREAD_INDEX_DB_NAME).exists());
+
+    // From then on the commands keep it up to date
+    let second = create_thread(handle.clone(), create_test_thread("Second"))
+        .await
+        .unwrap();
+    let second_id = second["id"].as_str().unwrap().to_string();
+    let mut message_ids = Vec::new();
+    for text in ["one", "two"] {
+        let message = create_message(handle.clone(), create_test_message(&second_id, text))
+            .await
+            .unwrap();
+        message_ids.push(message["id"].as_str().unwrap().to_string());
+    }
+    create_message(handle.clone(), create_test_message(&first_id, "three"))
+        .await
+        .unwrap();
+    assert_eq!(
+        titles(list_threads(handle.clone(), by_messages()).await.unwrap()),
+        vec!["Second", "First"]
+    );
+
+    delete_message(handle.clone(), second_id.clone(), message_ids[0].clone())
+        .await
+        .unwrap();
+    delete_message(handle.clone(), second_id.clone(), message_ids[1].clone())
+        .await
+        .unwrap();
+    let mut renamed = first.clone();
+    renamed["title"] = json!("Renamed");
+    modify_thread(handle.clone(), renamed).await.unwrap();
+    assert_eq!(
+        titles(list_threads(handle.clone(), by_messages()).await.unwrap()),
+        vec!["Renamed", "Second"]
+    );
+
+    // A restored message is counted again
+    let entry = trash::list_entries(&handle)
+        .unwrap()
+        .into_iter()
+        .find(|entry| entry.message_id.as_deref() == Some(message_ids[1].as_str()))
+        .unwrap();
+    restore_from_trash(handle.clone(), entry.id).await.unwrap();
+    let query = ThreadQuery {
+        min_messages: Some(1),
+        ..Default::default()
+    };
+    assert_eq!(
+        list_threads(handle.clone(), Some(query))
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+
+    delete_thread(handle.clone(), second_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        titles(list_threads(handle.clone(), None).await.unwrap()),
+        vec!["Renamed"]
+    );
+
+    // Files written outside the app are only seen after a rebuild
+    let external_dir = data_dir.join("threads").join("external");
+    fs::create_dir_all(&external_dir).unwrap();
+    let mut external = create_test_thread("External");
+    external["id"] = json!("external");
+    fs::write(external_dir.join("thread.json"), external.to_string()).unwrap();
+    assert_eq!(list_threads(handle.clone(), None).await.unwrap().len(), 1);
+    assert_eq!(rebuild_thread_index(handle.clone()).await.unwrap(), 2);
+    assert_eq!(list_threads(handle.clone(), None).await.unwrap().len(), 2);
+
+    // or after the watcher reports them
+    fs::remove_dir_all(&external_dir).unwrap();
+    thread_index::refresh_thread(&handle, "external").await;
+    assert_eq!(
+        titles(list_threads(handle.clone(), None).await.unwrap()),
+        vec!["Renamed"]
+    );
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[test]
+fn test_compare_clocks() {
+    let clock = |entries: &[(&str, u64)]| -> Clock {
+        entries
+            .iter()
+            .map(|(device, seq)| (device.to_string(), *seq))
+            .collect()
+    };
+    let a = clock(&[("a", 2), ("b", 1)]);
+    assert_eq!(compare_clocks(&a, &a), Some(Ordering::Equal));
+    assert_eq!(
+        compare_clocks(&a, &clock(&[("a", 1)])),
+        Some(Ordering::Greater)
+    );
+    assert_eq!(
+        compare_clocks(&a, &clock(&[("a", 2), ("b", 1), ("c", 1)])),
+        Some(Ordering::Less)
+    );
+    assert_eq!(compare_clocks(&a, &clock(&[("a", 1), ("b", 2)])), None);
+}
+
+type DeviceJob = Box<dyn FnOnce(&tokio::runtime::Runtime, &tauri::AppHandle<MockRuntime>) + Send>;
+
+/// A second device, on a thread of its own so that it gets a data folder of its own
+struct OtherDevice {
+    jobs: mpsc::Sender<DeviceJob>,
+}
+
+impl OtherDevice {
+    fn spawn() -> Self {
+        let (jobs, received) = mpsc::channel::<DeviceJob>();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let (app, data_dir) = mock_app_with_temp_data_dir();
+            for job in received {
+                job(&runtime, app.handle());
+            }
+            let _ = fs::remove_dir_all(data_dir);
+        });
+        Self { jobs }
+    }
+
+    fn run<T, F>(&self, job: impl FnOnce(tauri::AppHandle<MockRuntime>) -> F + Send + 'static) -> T
+    where
+        T: Send + 'static,
+        F: std::future::Future<Output = T>,
+    {
+        let (result, received) = mpsc::channel();
+        self.jobs
+            .send(Box::new(move |runtime, app| {
+                let _ = result.send(runtime.block_on(job(app.clone())));
+            }))
+            .unwrap();
+        received.recv().unwrap()
+    }
+}
+
+/// Title and message texts of a thread, if it exists
+async fn thread_contents(
+    app: tauri::AppHandle<MockRuntime>,
+    thread_id: String,
+) -> Option<(String, Vec<String>)> {
+    let thread = get_thread(app.clone(), thread_id.clone()).await.ok()?;
+    let messages = list_messages(app, thread_id).await.unwrap();
+    let texts = messages
+        .iter()
+        .map(|message| message["content"][0]["text"].as_str().unwrap().to_string())
+        .collect();
+    Some((thread["title"].as_str().unwrap().to_string(), texts))
+}
+
+#[tokio::test]
+async fn test_sync_threads_between_devices() {
+    let (app, data_dir) = mock_app_with_temp_data_dir();
+    let handle = app.handle().clone();
+    let laptop = OtherDevice::spawn();
+    let folder = data_dir.join("shared");
+    fs::create_dir_all(&folder).unwrap();
+    let sync_laptop = |folder: PathBuf| {
+        laptop.run(move |app| async move { sync_with_folder(&app, &folder).await.unwrap() })
+    };
+
+    // A thread created on the desktop reaches the laptop with the same ids
+    let thread = create_thread(handle.clone(), create_test_thread("Shared"))
+        .await
+        .unwrap();
+    let thread_id = thread["id"].as_str().unwrap().to_string();
+    let mut message_ids = Vec::new();
+    for text in ["one", "two"] {
+        let message = create_message(handle.clone(), create_test_message(&thread_id, text))
+            .await
+            .unwrap();
+        message_ids.push(message["id"].as_str().unwrap().to_string());
+    }
+    let report = sync_with_folder(&handle, &folder).await.unwrap();
+    assert_eq!((report.sent, report.received), (3, 0));
+    let report = sync_laptop(folder.clone());
+    assert_eq!((report.sent, report.received), (0, 3));
+    let id = thread_id.clone();
+    assert_eq!(
+        laptop.run(move |app| thread_contents(app, id)),
+        Some((
+            "Shared".to_string(),
+            vec!["one".to_string(), "two".to_string()]
+        ))
+    );
+
+    // Nothing changed, nothing is exchanged
+    assert_eq!(
+        sync_with_folder(&handle, &folder).await.unwrap(),
+        SyncReport::default()
+    );
+
+    // Concurrent edits: both rename the thread, the laptop adds a message and the desktop
+    // deletes one. The renames conflict and resolve the same way on both devices.
+    let mut renamed = thread.clone();
+    renamed["title"] = json!("Desktop title");
+    modify_thread(handle.clone(), renamed).await.unwrap();
+    delete_message(handle.clone(), thread_id.clone(), message_ids[0].clone())
+        .await
+        .unwrap();
+    let id = thread_id.clone();
+    laptop.run(move |app| async move {
+        let mut thread = get_thread(app.clone(), id.clone()).await.unwrap();
+        thread["title"] = json!("Laptop title");
+        modify_thread(app.clone(), thread).await.unwrap();
+        create_message(app, create_test_message(&id, "three"))
+            .await
+            .unwrap();
+    });
+    sync_with_folder(&handle, &folder).await.unwrap();
+    let report = sync_laptop(folder.clone());
+    assert_eq!(report.conflicts, 1);
+    sync_with_folder(&handle, &folder).await.unwrap();
+
+    let desktop = thread_contents(handle.clone(), thread_id.clone()).await;
+    let id = thread_id.clone();
+    assert_eq!(laptop.run(move |app| thread_contents(app, id)), desktop);
+    let (_, texts) = desktop.unwrap();
+    assert_eq!(texts, vec!["two", "three"]);
+
+    // The desktop deletes the thread while the laptop adds a message to it: the message
+    // keeps the thread alive on both devices
+    delete_thread(handle.clone(), thread_id.clone())
+        .await
+        .unwrap();
+    let id = thread_id.clone();
+    laptop.run(move |app| async move {
+        create_message(app, create_test_message(&id, "four"))
+            .await
+            .unwrap();
+    });
+    sync_with_folder(&handle, &folder).await.unwrap();
+    sync_laptop(folder.clone());
+    sync_with_folder(&handle, &folder).await.unwrap();
+    sync_laptop(folder.clone());
+
+    let desktop = thread_contents(handle.clone(), thread_id.clone()).await;
+    let id = thread_id.clone();
+    assert_eq!(laptop.run(move |app| thread_contents(app, id)), desktop);
+    let (_, texts) = desktop.unwrap();
+    assert_eq!(texts, vec!["four"]);
+
+    // A deletion both devices know all messages of goes through
+    delete_thread(handle.clone(), thread_id.clone())
+        .await
+        .unwrap();
+    sync_with_folder(&handle, &folder).await.unwrap();
+    sync_laptop(folder.clone());
+    let id = thread_id.clone();
+    assert_eq!(laptop.run(move |app| thread_contents(app, id)), None);
+    assert_eq!(
+        sync_with_folder(&handle, &folder).await.unwrap(),
+        SyncReport::default()
+    );
+
+    drop(laptop);
+    let _ = fs::remove_dir_all(data_dir);
+}
@@ -0,0 +1,452 @@
+/*!
+   Thread Metadata Index
+
+   With the file storage, `list_threads` reads `threads_index.db` instead of every thread.json.
+   It holds each thread's metadata together with its message count, is built from the thread
+   files on first use and is kept up to date by the thread and message commands afterwards.
+   Changes made to the files outside the app are picked up by the threads folder watcher, or by
+   `rebuild_thread_index`. The SQLite storage needs no index, as its threads table serves the
+   same purpose.
+
+   Rows are stored as records of the at-rest encryption, so the index holds no plaintext while
+   encryption is enabled. Sorting and filtering happen in memory after the rows are read.
+*/
+
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
+use sqlx::Row;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tauri::{AppHandle, Runtime};
+use tokio::sync::Mutex;
+
+use super::constants::THREADS_FILE;
+use super::db::get_database_dir;
+use super::encryption::{from_record, read_json_file, to_record, LOCKED_ERROR};
+use super::helpers::get_lock_for_thread;
+use super::pagination::count_messages_in_file;
+use super::utils::{ensure_data_dirs, get_data_dir, get_messages_path, get_thread_dir};
+use crate::core::app::commands::get_jan_data_folder_path;
+
+pub const THREAD_INDEX_DB_NAME: &str = "threads_index.db";
+
+/// Open indexes keyed by database path; the path changes with the data folder
+static INDEX_POOLS: OnceLock<Mutex<HashMap<PathBuf, SqlitePool>>> = OnceLock::new();
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThreadSortKey {
+    #[default]
+    Updated,
+    Created,
+    Title,
+    MessageCount,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct ThreadQuery {
+    pub sort_by: Option<ThreadSortKey>,
+    pub order: Option<SortOrder>,
+    /// Case-insensitive part of the title
+    pub title: Option<String>,
+    /// Threads whose first assistant has this id
+    pub assistant_id: Option<String>,
+    /// Inclusive `updated` range, in the unit threads are stored with
+    pub updated_from: Option<i64>,
+    pub updated_to: Option<i64>,
+    pub min_messages: Option<usize>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+/// A thread with its message count
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedThread {
+    pub thread: Value,
+    pub message_count: usize,
+}
+
+impl IndexedThread {
+    fn title(&self) -> &str {
+        self.thread
+            .get("title")
+            .and_then(|title| title.as_str())
+            .unwrap_or_default()
+    }
+
+    fn timestamp(&self, key: &str) -> i64 {
+        self.thread
+            .get(key)
+            .and_then(|value| value.as_i64())
+            .unwrap_or_default()
+    }
+
+    fn assistant_id(&self) -> Option<&str> {
+        let assistant = self.thread.get("assistants")?.get(0)?;
+        assistant
+            .get("id")
+            .or_else(|| assistant.get("assistant_id"))
+            .and_then(|id| id.as_str())
+    }
+
+    fn id(&self) -> &str {
+        self.thread
+            .get("id")
+            .and_then(|id| id.as_str())
+            .unwrap_or_default()
+    }
+}
+
+/// Filter, sort and page threads. Ties are broken by id, so pages are stable.
+pub fn apply_query(mut threads: Vec<IndexedThread>, query: &ThreadQuery) -> Vec<Value> {
+    let title = query.title.as_ref().map(|title| title.to_lowercase());
+    threads.retain(|thread| {
+        let updated = thread.timestamp("updated");
+        title
+            .as_ref()
+            .map_or(true, |title| thread.title().to_lowercase().contains(title))
+            && query
+                .assistant_id
+                .as_ref()
+                .map_or(true, |id| thread.assistant_id() == Some(id.as_str()))
+            && query.updated_from.map_or(true, |from| updated >= from)
+            && query.updated_to.map_or(true, |to| updated <= to)
+            && query
+                .min_messages
+                .map_or(true, |min| thread.message_count >= min)
+    });
+
+    let sort_by = query.sort_by.unwrap_or_default();
+    threads.sort_by(|a, b| {
+        let ordering = match sort_by {
+            ThreadSortKey::Updated => a.timestamp("updated").cmp(&b.timestamp("updated")),
+            ThreadSortKey::Created => a.timestamp("created").cmp(&b.timestamp("created")),
+            ThreadSortKey::Title => a.title().to_lowercase().cmp(&b.title().to_lowercase()),
+            ThreadSortKey::MessageCount => a.message_count.cmp(&b.message_count),
+        };
+        let ordering = ordering.then_with(|| a.id().cmp(b.id()));
+        match query.order.unwrap_or_default() {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    });
+
+    threads
+        .into_iter()
+        .skip(query.offset.unwrap_or(0))
+        .take(query.limit.unwrap_or(usize::MAX))
+        .map(|thread| thread.thread)
+        .collect()
+}
+
+/// Open (creating if missing) the thread index at `path`
+pub async fn open_thread_index(path: &Path) -> Result<SqlitePool, String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create thread index dir: {}", e))?;
+    }
+
+    let connect_options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(2)
+        .connect_with(connect_options)
+        .await
+        .map_err(|e| format!("Failed to open thread index: {}", e))?;
+
+    for statement in [
+        r#"
+        CREATE TABLE IF NOT EXISTS thread_index (
+            id TEXT PRIMARY KEY,
+            data TEXT NOT NULL,
+            message_count INTEGER NOT NULL DEFAULT 0
+        );
+        "#,
+        "CREATE TABLE IF NOT EXISTS thread_index_meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);",
+    ] {
+        sqlx::query(statement)
+            .execute(&pool)
+            .await
+            .map_err(|e| format!("Failed to create thread index: {}", e))?;
+    }
+
+    Ok(pool)
+}
+
+/// The thread index for the current data folder. Without `create`, returns `None` when
+/// there is no index yet.
+async fn get_thread_index<R: Runtime>(
+    app: &AppHandle<R>,
+    create: bool,
+) -> Result<Option<SqlitePool>, String> {
+    let path = get_database_dir(app)?.join(THREAD_INDEX_DB_NAME);
+    let mut pools = INDEX_POOLS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .await;
+    if let Some(pool) = pools.get(&path) {
+        return Ok(Some(pool.clone()));
+    }
+    if !create && !path.exists() {
+        return Ok(None);
+    }
+    let pool = open_thread_index(&path).await?;
+    pools.insert(path, pool.clone());
+    Ok(Some(pool))
+}
+
+async fn ensure_thread_index<R: Runtime>(app: &AppHandle<R>) -> Result<SqlitePool, String> {
+    get_thread_index(app, true)
+        .await?
+        .ok_or_else(|| "Thread index not available".to_string())
+}
+
+async fn is_built(pool: &SqlitePool) -> Result<bool, String> {
+    Ok(
+        sqlx::query("SELECT value FROM thread_index_meta WHERE key = 'built'")
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| e.to_string())?
+            .is_some(),
+    )
+}
+
+/// The index, if it has been built. Updates are skipped until then, as building reads
+/// every thread anyway.
+async fn get_built_index<R: Runtime>(app: &AppHandle<R>) -> Result<Option<SqlitePool>, String> {
+    match get_thread_index(app, false).await? {
+        Some(pool) => Ok(is_built(&pool).await?.then_some(pool)),
+        None => Ok(None),
+    }
+}
+
+/// Read a thread directory's metadata and count its messages
+async fn read_thread_dir<R: Runtime>(
+    app: &AppHandle<R>,
+    thread_id: &str,
+) -> Result<Option<IndexedThread>, String> {
+    let path = get_thread_dir(app.clone(), thread_id).join(THREADS_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let thread = read_json_file(&path)?;
+    let lock = get_lock_for_thread(thread_id).await;
+    let _guard = lock.lock().await;
+    let message_count = count_messages_in_file(&get_messages_path(app.clone(), thread_id))?;
+    Ok(Some(IndexedThread {
+        thread,
+        message_count,
+    }))
+}
+
+async fn upsert(
+    pool: &SqlitePool,
+    data_dir: &Path,
+    thread: &Value,
+    message_count: Option<usize>,
+) -> Result<(), String> {
+    let thread_id = thread
+        .get("id")
+        .and_then(|id| id.as_str())
+        .ok_or("Missing thread id")?;
+    sqlx::query(
+        r#"
+        INSERT INTO thread_index (id, data, message_count) VALUES (?1, ?2, COALESCE(?3, 0))
+        ON CONFLICT(id) DO UPDATE SET
+            data = excluded.data,
+            message_count = COALESCE(?3, thread_index.message_count)
+        "#,
+    )
+    .bind(thread_id)
+    .bind(to_record(data_dir, thread)?)
+    .bind(message_count.map(|count| count as i64))
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to update thread index: {}", e))?;
+    Ok(())
+}
+
+/// Drop the index and index every thread directory again. Returns the number of indexed
+/// threads. Threads that cannot be read are skipped, as `list_threads` always did.
+pub async fn rebuild_thread_index<R: Runtime>(app: &AppHandle<R>) -> Result<usize, String> {
+    let pool = ensure_thread_index(app).await?;
+    for statement in [
+        "DELETE FROM thread_index_meta WHERE key = 'built'",
+        "DELETE FROM thread_index",
+    ] {
+        sqlx::query(statement)
+            .execute(&pool)
+            .await
+            .map_err(|e| format!("Failed to clear thread index: {}", e))?;
+    }
+
+    ensure_data_dirs(app.clone())?;
+    let data_dir = get_jan_data_folder_path(app.clone());
+    let mut count = 0;
+    for entry in fs::read_dir(get_data_dir(app.clone())).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        if !entry.path().is_dir() {
+            continue;
+        }
+        let thread_id = entry.file_name().to_string_lossy().to_string();
+        match read_thread_dir(app, &thread_id).await {
+            Ok(Some(indexed)) => {
+                upsert(
+                    &pool,
+                    &data_dir,
+                    &indexed.thread,
+                    Some(indexed.message_count),
+                )
+                .await?;
+                count += 1;
+            }
+            Ok(None) => {}
+            Err(e) if e == LOCKED_ERROR => return Err(e),
+            Err(e) => log::warn!("Skipping thread {} in the index: {}", thread_id, e),
+        }
+    }
+
+    sqlx::query("INSERT OR REPLACE INTO thread_index_meta (key, value) VALUES ('built', '1')")
+        .execute(&pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    log::info!("Rebuilt thread index with {} threads", count);
+    Ok(count)
+}
+
+/// Every indexed thread, building the index first if needed
+pub async fn list_indexed_threads<R: Runtime>(
+    app: &AppHandle<R>,
+) -> Result<Vec<IndexedThread>, String> {
+    let pool = ensure_thread_index(app).await?;
+    if !is_built(&pool).await? {
+        rebuild_thread_index(app).await?;
+    }
+    let rows = sqlx::query("SELECT data, message_count FROM thread_index")
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| format!("Failed to read thread index: {}", e))?;
+    rows.iter()
+        .map(|row| {
+            let data: String = row.get("data");
+            let message_count: i64 = row.get("message_count");
+            Ok(IndexedThread {
+                thread: from_record(data.as_bytes())?,
+                message_count: message_count.max(0) as usize,
+            })
+        })
+        .collect()
+}
+
+/// Forget the index, e.g. after thread files were rewritten; it is built again on the
+/// next listing
+pub async fn invalidate<R: Runtime>(app: &AppHandle<R>) {
+    let result = async {
+        let Some(pool) = get_thread_index(app, false).await? else {
+            return Ok(());
+        };
+        for statement in [
+            "DELETE FROM thread_index_meta WHERE key = 'built'",
+            "DELETE FROM thread_index",
+        ] {
+            sqlx::query(statement)
+                .execute(&pool)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        Ok::<_, String>(())
+    }
+    .await;
+    if let Err(e) = result {
+        log::warn!("Failed to clear thread index: {}", e);
+    }
+}
+
+/// Keep a built index in sync after a thread was created or modified. `message_count`
+/// replaces the indexed count when given. Failures are only logged, and the index is
+/// cleared so that it is rebuilt rather than left stale.
+pub async fn on_thread_saved<R: Runtime>(
+    app: &AppHandle<R>,
+    thread: &Value,
+    message_count: Option<usize>,
+) {
+    let result = async {
+        if let Some(pool) = get_built_index(app).await? {
+            let data_dir = get_jan_data_folder_path(app.clone());
+            upsert(&pool, &data_dir, thread, message_count).await?;
+        }
+        Ok::<_, String>(())
+    }
+    .await;
+    if let Err(e) = result {
+        log::warn!("Failed to update thread index: {}", e);
+        invalidate(app).await;
+    }
+}
+
+pub async fn on_thread_deleted<R: Runtime>(app: &AppHandle<R>, thread_id: &str) {
+    let result = async {
+        if let Some(pool) = get_built_index(app).await? {
+            sqlx::query("DELETE FROM thread_index WHERE id = ?1")
+                .bind(thread_id)
+                .execute(&pool)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        Ok::<_, String>(())
+    }
+    .await;
+    if let Err(e) = result {
+        log::warn!("Failed to update thread index: {}", e);
+        invalidate(app).await;
+    }
+}
+
+/// Add `delta` to the indexed message count of a thread
+pub async fn on_message_count_changed<R: Runtime>(app: &AppHandle<R>, thread_id: &str, delta: i64) {
+    let result = async {
+        if let Some(pool) = get_built_index(app).await? {
+            sqlx::query(
+                "UPDATE thread_index SET message_count = MAX(message_count + ?1, 0) WHERE id = ?2",
+            )
+            .bind(delta)
+            .bind(thread_id)
+            .execute(&pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        }
+        Ok::<_, String>(())
+    }
+    .await;
+    if let Err(e) = result {
+        log::warn!("Failed to update thread index: {}", e);
+        invalidate(app).await;
+    }
+}
+
+/// Index a thread directory again from its files, or drop it when it is gone
+pub async fn refresh_thread<R: Runtime>(app: &AppHandle<R>, thread_id: &str) {
+    match read_thread_dir(app, thread_id).await {
+        Ok(Some(indexed)) => {
+            on_thread_saved(app, &indexed.thread, Some(indexed.message_count)).await
+        }
+        Ok(None) => on_thread_deleted(app, thread_id).await,
+        Err(e) => {
+            log::warn!("Failed to index thread {}: {}", thread_id, e);
+            on_thread_deleted(app, thread_id).await;
+        }
+    }
+}
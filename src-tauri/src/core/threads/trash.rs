@@ -0,0 +1,312 @@
+/*!
+   Trash for Deleted Threads and Messages
+
+   Deleting a thread or message moves it to `trash/<entry id>/` in the Jan data folder instead
+   of removing it. `entry.json` describes the entry; a thread keeps its directory layout under
+   `thread/` (thread.json, messages.jsonl and attachments), a message is stored as
+   `message.json`. Both backends trash into this layout, so an entry can be restored after
+   switching between file and SQLite storage.
+
+   Entries older than the `trash_retention_days` setting are purged on startup and whenever the
+   trash is listed.
+*/
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::Runtime;
+use uuid::Uuid;
+
+use super::attachments;
+use super::constants::{
+    MESSAGES_FILE, THREADS_FILE, TRASHED_MESSAGE_FILE, TRASHED_THREAD_DIR, TRASH_DIR,
+    TRASH_ENTRY_FILE,
+};
+use super::encryption::{from_record, read_json_file, write_json_file, LOCKED_ERROR};
+use super::helpers::write_messages_to_file;
+use super::search::message_text;
+use crate::core::app::commands::{get_app_configurations, get_jan_data_folder_path};
+
+/// Characters of a message's text kept as the title of its trash entry
+const MESSAGE_PREVIEW_CHARS: usize = 80;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrashKind {
+    Thread,
+    Message,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TrashEntry {
+    pub id: String,
+    pub kind: TrashKind,
+    pub thread_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    /// Thread title, or the beginning of the message text
+    pub title: String,
+    /// Seconds since the epoch
+    pub deleted_at: u64,
+    /// Message stored before a trashed message, to put it back in place
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after_id: Option<String>,
+    /// SQLite `created_at` of a trashed message, which orders it within its thread
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub db_created_at: Option<i64>,
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+impl TrashEntry {
+    pub fn thread(thread_id: &str, thread: Option<&Value>) -> Self {
+        let title = thread
+            .and_then(|thread| thread.get("title"))
+            .and_then(|title| title.as_str())
+            .unwrap_or_default();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            kind: TrashKind::Thread,
+            thread_id: thread_id.to_string(),
+            message_id: None,
+            title: title.to_string(),
+            deleted_at: now_secs(),
+            after_id: None,
+            db_created_at: None,
+        }
+    }
+
+    pub fn message(thread_id: &str, message: &Value, after_id: Option<&str>) -> Self {
+        let message_id = message.get("id").and_then(|id| id.as_str());
+        Self {
+            id: Uuid::new_v4().to_string(),
+            kind: TrashKind::Message,
+            thread_id: thread_id.to_string(),
+            message_id: message_id.map(str::to_string),
+            title: message_text(message)
+                .chars()
+                .take(MESSAGE_PREVIEW_CHARS)
+                .collect(),
+            deleted_at: now_secs(),
+            after_id: after_id.map(str::to_string),
+            db_created_at: None,
+        }
+    }
+}
+
+pub fn get_trash_dir<R: Runtime>(app_handle: &tauri::AppHandle<R>) -> PathBuf {
+    get_jan_data_folder_path(app_handle.clone()).join(TRASH_DIR)
+}
+
+pub fn get_entry_dir<R: Runtime>(app_handle: &tauri::AppHandle<R>, entry_id: &str) -> PathBuf {
+    get_trash_dir(app_handle).join(entry_id)
+}
+
+/// Write `entry.json` once the deleted data is in place; directories without it are
+/// leftovers of a failed deletion and are not listed
+fn commit_entry(dir: &Path, entry: &TrashEntry) -> Result<(), String> {
+    write_json_file(&dir.join(TRASH_ENTRY_FILE), entry)
+}
+
+/// Move a thread directory of the file storage to the trash
+pub fn trash_thread_dir<R: Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    thread_id: &str,
+    thread: Option<&Value>,
+    thread_dir: &Path,
+) -> Result<TrashEntry, String> {
+    let entry = TrashEntry::thread(thread_id, thread);
+    let dir = get_entry_dir(app_handle, &entry.id);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let payload = dir.join(TRASHED_THREAD_DIR);
+    if let Err(e) = fs::rename(thread_dir, &payload) {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(format!("Failed to move thread to trash: {}", e));
+    }
+    if let Err(e) = commit_entry(&dir, &entry) {
+        let _ = fs::rename(&payload, thread_dir);
+        let _ = fs::remove_dir_all(&dir);
+        return Err(e);
+    }
+    Ok(entry)
+}
+
+/// Store a thread read from SQLite in the trash, in the layout of the file storage
+pub fn trash_thread_data<R: Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    thread: &Value,
+    messages: &[Value],
+) -> Result<TrashEntry, String> {
+    let thread_id = thread
+        .get("id")
+        .and_then(|id| id.as_str())
+        .ok_or("Missing thread id")?;
+    let entry = TrashEntry::thread(thread_id, Some(thread));
+    let dir = get_entry_dir(app_handle, &entry.id);
+    let payload = dir.join(TRASHED_THREAD_DIR);
+    let result = (|| {
+        fs::create_dir_all(&payload).map_err(|e| e.to_string())?;
+        write_json_file(&payload.join(THREADS_FILE), thread)?;
+        write_messages_to_file(messages, &payload.join(MESSAGES_FILE))?;
+        commit_entry(&dir, &entry)
+    })();
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(e);
+    }
+    Ok(entry)
+}
+
+/// Store the message at `index` of a thread's messages, in storage order, in the trash
+pub fn trash_message<R: Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    thread_id: &str,
+    messages: &[Value],
+    index: usize,
+    db_created_at: Option<i64>,
+) -> Result<TrashEntry, String> {
+    let after_id = index
+        .checked_sub(1)
+        .and_then(|i| messages[i].get("id"))
+        .and_then(|id| id.as_str());
+    let mut entry = TrashEntry::message(thread_id, &messages[index], after_id);
+    entry.db_created_at = db_created_at;
+    let dir = get_entry_dir(app_handle, &entry.id);
+    let result = (|| {
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        write_json_file(&dir.join(TRASHED_MESSAGE_FILE), &messages[index])?;
+        commit_entry(&dir, &entry)
+    })();
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(e);
+    }
+    Ok(entry)
+}
+
+/// Directory of a trashed thread, laid out like a thread directory of the file storage
+pub fn trashed_thread_dir<R: Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    entry: &TrashEntry,
+) -> PathBuf {
+    get_entry_dir(app_handle, &entry.id).join(TRASHED_THREAD_DIR)
+}
+
+pub fn read_trashed_message<R: Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    entry: &TrashEntry,
+) -> Result<Value, String> {
+    read_json_file(&get_entry_dir(app_handle, &entry.id).join(TRASHED_MESSAGE_FILE))
+}
+
+pub fn load_entry<R: Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    entry_id: &str,
+) -> Result<TrashEntry, String> {
+    let path = get_entry_dir(app_handle, entry_id).join(TRASH_ENTRY_FILE);
+    let data = fs::read(&path).map_err(|_| "Trash entry not found".to_string())?;
+    from_record(&data)
+}
+
+/// Permanently delete an entry, dropping the attachment references of its messages
+pub async fn delete_entry<R: Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    entry_id: &str,
+) -> Result<(), String> {
+    if let Ok(entry) = load_entry(app_handle, entry_id) {
+        release_attachments(app_handle, &entry).await;
+    }
+    remove_entry(app_handle, entry_id)
+}
+
+async fn release_attachments<R: Runtime>(app_handle: &tauri::AppHandle<R>, entry: &TrashEntry) {
+    attachments::on_messages_deleted(app_handle, &entry.thread_id, entry.message_id.as_deref())
+        .await;
+}
+
+/// Remove an entry's directory, e.g. once it is restored
+pub fn remove_entry<R: Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    entry_id: &str,
+) -> Result<(), String> {
+    let dir = get_entry_dir(app_handle, entry_id);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// All entries, most recently deleted first. Directories without a readable `entry.json`,
+/// e.g. left by a failed deletion, are skipped.
+pub fn list_entries<R: Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+) -> Result<Vec<TrashEntry>, String> {
+    let trash_dir = get_trash_dir(app_handle);
+    if !trash_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+    for dir in fs::read_dir(&trash_dir).map_err(|e| e.to_string())? {
+        let path = dir.map_err(|e| e.to_string())?.path();
+        let Ok(data) = fs::read(path.join(TRASH_ENTRY_FILE)) else {
+            continue;
+        };
+        match from_record::<TrashEntry>(&data) {
+            Ok(entry) => entries.push(entry),
+            Err(e) if e == LOCKED_ERROR => return Err(e),
+            Err(e) => log::warn!("Skipping trash entry {}: {}", path.display(), e),
+        }
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.deleted_at));
+    Ok(entries)
+}
+
+/// Permanently delete every entry, including leftovers of failed deletions.
+/// Returns the number of deleted entries.
+pub async fn empty<R: Runtime>(app_handle: &tauri::AppHandle<R>) -> Result<usize, String> {
+    let entries = list_entries(app_handle)?;
+    for entry in &entries {
+        release_attachments(app_handle, entry).await;
+    }
+    let trash_dir = get_trash_dir(app_handle);
+    if trash_dir.exists() {
+        fs::remove_dir_all(&trash_dir).map_err(|e| e.to_string())?;
+    }
+    Ok(entries.len())
+}
+
+/// Delete entries older than `retention_days`. Returns the number of purged entries.
+pub async fn purge_expired<R: Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+    retention_days: u32,
+) -> Result<usize, String> {
+    if retention_days == 0 {
+        return Ok(0);
+    }
+    let cutoff = now_secs().saturating_sub(u64::from(retention_days) * 24 * 60 * 60);
+    let mut purged = 0;
+    for entry in list_entries(app_handle)? {
+        if entry.deleted_at < cutoff {
+            release_attachments(app_handle, &entry).await;
+            remove_entry(app_handle, &entry.id)?;
+            purged += 1;
+        }
+    }
+    Ok(purged)
+}
+
+/// Purge expired entries according to the app configuration
+pub async fn purge_expired_trash<R: Runtime>(
+    app_handle: &tauri::AppHandle<R>,
+) -> Result<usize, String> {
+    let retention_days = get_app_configurations(app_handle.clone()).trash_retention_days;
+    purge_expired(app_handle, retention_days).await
+}
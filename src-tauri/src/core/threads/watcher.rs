@@ -0,0 +1,185 @@
+/*!
+   Threads Folder Watcher
+
+   With the `watch_threads_folder` setting on, changes made to the threads folder outside the app,
+   e.g. by scripts or sync tools, emit `threads://changed` with `external` set, once per changed
+   thread after the folder has been quiet for `DEBOUNCE`. Threads the app changed itself within
+   `OWN_CHANGES_WINDOW` are skipped, as their events are most likely the app's own writes. The
+   thread index entry and the search index of every reported thread are rebuilt.
+
+   Only the file storage can be changed from outside; nothing is reported while SQLite is used.
+*/
+
+use std::collections::BTreeSet;
+use std::path::{Component, Path};
+use std::time::Duration;
+use tauri::Runtime;
+
+use super::commands::list_messages;
+use super::constants::{MESSAGES_CORRUPT_FILE, MESSAGES_INDEX_FILE};
+use super::events::{self, ChangeKind, ThreadsChanged};
+use super::helpers::should_use_sqlite;
+use super::search;
+use super::thread_index;
+use super::utils::get_thread_dir;
+
+pub const DEBOUNCE: Duration = Duration::from_millis(500);
+const OWN_CHANGES_WINDOW: Duration = Duration::from_secs(2);
+
+/// Threads the changed paths belong to, leaving out files the app derives from a thread's
+/// data, temporary files and threads the app changed itself
+pub fn changed_threads<'a>(
+    threads_dir: &Path,
+    paths: impl IntoIterator<Item = &'a Path>,
+) -> BTreeSet<String> {
+    let mut thread_ids = BTreeSet::new();
+    for path in paths {
+        let Ok(relative) = path.strip_prefix(threads_dir) else {
+            continue;
+        };
+        let names: Vec<_> = relative
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => name.to_str(),
+                _ => None,
+            })
+            .collect();
+        let (Some(thread_id), Some(file_name)) = (names.first(), names.last()) else {
+            continue;
+        };
+        if file_name.starts_with('.')
+            || *file_name == MESSAGES_INDEX_FILE
+            || *file_name == MESSAGES_CORRUPT_FILE
+        {
+            continue;
+        }
+        thread_ids.insert(thread_id.to_string());
+    }
+    thread_ids.retain(|thread_id| !events::changed_locally_within(thread_id, OWN_CHANGES_WINDOW));
+    thread_ids
+}
+
+/// Emit an external change for each thread and rebuild its index entries
+pub fn report_external_changes<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    thread_ids: BTreeSet<String>,
+) {
+    if should_use_sqlite() {
+        return;
+    }
+    for thread_id in thread_ids {
+        let exists = get_thread_dir(app.clone(), &thread_id).exists();
+        let kind = if exists {
+            ChangeKind::Modified
+        } else {
+            ChangeKind::Deleted
+        };
+
+        let app_handle = app.clone();
+        let id = thread_id.clone();
+        tauri::async_runtime::spawn(async move {
+            thread_index::refresh_thread(&app_handle, &id).await;
+            search::on_thread_deleted(&app_handle, &id).await;
+            if !exists {
+                return;
+            }
+            match list_messages(app_handle.clone(), id).await {
+                Ok(messages) => {
+                    for message in &messages {
+                        search::on_message_saved(&app_handle, message).await;
+                    }
+                }
+                Err(e) => log::warn!("Failed to read externally changed thread: {}", e),
+            }
+        });
+
+        events::emit(
+            app,
+            ThreadsChanged {
+                kind,
+                thread_id: Some(thread_id),
+                message_id: None,
+                external: true,
+            },
+        );
+    }
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+mod platform {
+    use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+    use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+    use std::sync::{Mutex, PoisonError};
+    use tauri::Runtime;
+
+    use super::super::utils::{ensure_data_dirs, get_data_dir};
+    use super::{changed_threads, report_external_changes, DEBOUNCE};
+
+    static WATCHER: Mutex<Option<Debouncer<RecommendedWatcher>>> = Mutex::new(None);
+
+    /// Start watching the threads folder, replacing a watcher of a previous data folder
+    pub fn start<R: Runtime>(app: &tauri::AppHandle<R>) -> Result<(), String> {
+        ensure_data_dirs(app.clone())?;
+        let threads_dir = get_data_dir(app.clone());
+
+        let app_handle = app.clone();
+        let root = threads_dir.clone();
+        let mut debouncer =
+            new_debouncer(DEBOUNCE, move |result: DebounceEventResult| match result {
+                Ok(events) => {
+                    let thread_ids =
+                        changed_threads(&root, events.iter().map(|event| event.path.as_path()));
+                    report_external_changes(&app_handle, thread_ids);
+                }
+                Err(e) => log::warn!("Threads folder watcher failed: {}", e),
+            })
+            .map_err(|e| format!("Failed to create threads folder watcher: {}", e))?;
+        debouncer
+            .watcher()
+            .watch(&threads_dir, RecursiveMode::Recursive)
+            .map_err(|e| format!("Failed to watch threads folder: {}", e))?;
+
+        *WATCHER.lock().unwrap_or_else(PoisonError::into_inner) = Some(debouncer);
+        log::info!("Watching threads folder {}", threads_dir.display());
+        Ok(())
+    }
+
+    pub fn stop() {
+        WATCHER
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+    }
+
+    pub fn is_running() -> bool {
+        WATCHER
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some()
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+mod platform {
+    use tauri::Runtime;
+
+    pub fn start<R: Runtime>(_app: &tauri::AppHandle<R>) -> Result<(), String> {
+        Err("Watching the threads folder is not supported on mobile".to_string())
+    }
+
+    pub fn stop() {}
+
+    pub fn is_running() -> bool {
+        false
+    }
+}
+
+pub use platform::{is_running, start, stop};
+
+/// Follow the threads folder of a new data folder if the watcher is running
+pub fn restart_if_running<R: Runtime>(app: &tauri::AppHandle<R>) -> Result<(), String> {
+    if is_running() {
+        start(app)?;
+    }
+    Ok(())
+}
@@ -93,6 +93,7 @@ pub fn run() {
             core::mcp::commands::cancel_tool_call,
             core::mcp::commands::restart_mcp_servers,
             core::mcp::commands::get_connected_servers,
+            core::mcp::commands::get_mcp_server_pids,
             core::mcp::commands::save_mcp_configs,
             core::mcp::commands::get_mcp_configs,
             core::mcp::commands::activate_mcp_server,
@@ -100,19 +101,53 @@ pub fn run() {
             core::mcp::commands::check_jan_browser_extension_connected,
             // Threads
             core::threads::commands::list_threads,
+            core::threads::commands::rebuild_thread_index,
             core::threads::commands::create_thread,
             core::threads::commands::modify_thread,
             core::threads::commands::delete_thread,
             core::threads::commands::list_messages,
+            core::threads::commands::list_messages_page,
+            core::threads::commands::count_messages,
+            core::threads::commands::list_message_branch,
+            core::threads::commands::set_active_message,
             core::threads::commands::create_message,
             core::threads::commands::modify_message,
             core::threads::commands::delete_message,
+            core::threads::commands::get_thread,
+            core::threads::commands::fork_thread,
             core::threads::commands::get_thread_assistant,
             core::threads::commands::create_thread_assistant,
             core::threads::commands::modify_thread_assistant,
+            core::threads::commands::get_threads_storage,
+            core::threads::commands::set_threads_storage,
+            core::threads::commands::set_watch_threads_folder,
+            core::threads::commands::set_threads_sync_folder,
+            core::threads::commands::sync_threads,
+            core::threads::commands::migrate_threads_to_sqlite,
+            core::threads::commands::verify_threads_migration,
+            core::threads::commands::export_threads_to_jsonl,
+            core::threads::commands::upgrade_thread_schema,
+            core::threads::commands::search_messages,
+            core::threads::commands::rebuild_search_index,
+            core::threads::commands::export_threads,
+            core::threads::commands::import_threads,
+            core::threads::commands::import_chat_history,
+            core::threads::commands::list_trash,
+            core::threads::commands::restore_from_trash,
+            core::threads::commands::empty_trash,
+            core::threads::commands::get_thread_encryption_status,
+            core::threads::commands::enable_thread_encryption,
+            core::threads::commands::unlock_thread_storage,
+            core::threads::commands::disable_thread_encryption,
+            core::threads::commands::reencrypt_thread_data,
+            core::threads::commands::put_attachment,
+            core::threads::commands::get_attachment,
+            core::threads::commands::gc_attachments,
             // Download
             core::downloads::commands::download_files,
             core::downloads::commands::cancel_download_task,
+            // llama.cpp backends
+            core::backends::commands::install_backend,
         ])
         .manage(AppState {
             app_token: Some(generate_app_token()),
@@ -186,6 +221,16 @@ pub fn run() {
                 app.deep_link().register_all()?;
             }
 
+            // Load the thread encryption keys kept in the OS keyring; passphrase-protected
+            // threads stay locked until unlock_thread_storage is called
+            {
+                let data_dir =
+                    crate::core::app::commands::get_jan_data_folder_path(app.handle().clone());
+                if let Err(e) = crate::core::threads::encryption::load_keys(&data_dir) {
+                    log::error!("Failed to load thread encryption keys: {}", e);
+                }
+            }
+
             // Initialize SQLite database for mobile platforms
             #[cfg(any(target_os = "android", target_os = "ios"))]
             {
@@ -197,6 +242,69 @@ pub fn run() {
                 });
             }
 
+            // Open the desktop threads database before any thread command can run
+            #[cfg(not(any(target_os = "android", target_os = "ios")))]
+            {
+                use crate::core::app::models::ThreadsStorage;
+                use crate::core::threads::helpers::set_desktop_sqlite_enabled;
+
+                let app_handle = app.handle().clone();
+                let configuration =
+                    crate::core::app::commands::get_app_configurations(app_handle.clone());
+                if configuration.threads_storage == ThreadsStorage::Sqlite {
+                    match tauri::async_runtime::block_on(
+                        crate::core::threads::db::init_database(&app_handle),
+                    ) {
+                        Ok(()) => set_desktop_sqlite_enabled(true),
+                        Err(e) => log::error!("Failed to open threads database: {}", e),
+                    }
+                }
+                if configuration.watch_threads_folder {
+                    if let Err(e) = crate::core::threads::watcher::start(&app_handle) {
+                        log::error!("Failed to watch threads folder: {}", e);
+                    }
+                }
+            }
+
+            // Upgrade threads written by older versions to the current schema, once
+            {
+                let app_handle = app.handle().clone();
+                tauri::async_runtime::spawn(async move {
+                    match crate::core::threads::commands::upgrade_thread_schema_if_needed(
+                        app_handle,
+                    )
+                    .await
+                    {
+                        Ok(None) => {}
+                        Ok(Some(report)) => {
+                            if report.upgraded_threads + report.upgraded_messages > 0 {
+                                log::info!("Upgraded thread schema: {:?}", report);
+                            }
+                            for failure in report.failed {
+                                log::warn!(
+                                    "Thread {} not upgraded: {}",
+                                    failure.thread_id,
+                                    failure.error
+                                );
+                            }
+                        }
+                        Err(e) => log::error!("Failed to upgrade thread schema: {}", e),
+                    }
+                });
+            }
+
+            // Purge deleted threads and messages past the trash retention period
+            {
+                let app_handle = app.handle().clone();
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) =
+                        crate::core::threads::trash::purge_expired_trash(&app_handle).await
+                    {
+                        log::error!("Failed to purge trash: {}", e);
+                    }
+                });
+            }
+
             setup_mcp(app);
             setup::setup_theme_listener(app)?;
             Ok(())
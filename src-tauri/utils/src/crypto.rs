@@ -1,3 +1,6 @@
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::Argon2;
 use base64::{engine::general_purpose, Engine as _};
 use hmac::{Hmac, Mac};
 use rand::{distributions::Alphanumeric, Rng};
@@ -29,6 +32,66 @@ pub fn generate_api_key(model_id: String, api_secret: String) -> Result<String,
     Ok(hash)
 }
 
+/// Length of keys used with `encrypt` / `decrypt`
+pub const KEY_LEN: usize = 32;
+/// Length of salts used with `derive_key`
+pub const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Generates a random key for `encrypt` / `decrypt`
+pub fn generate_key() -> [u8; KEY_LEN] {
+    let mut key = [0u8; KEY_LEN];
+    rand::thread_rng().fill(&mut key);
+    key
+}
+
+/// Generates a random salt for `derive_key`
+pub fn generate_salt() -> [u8; SALT_LEN] {
+    let mut salt = [0u8; SALT_LEN];
+    rand::thread_rng().fill(&mut salt);
+    salt
+}
+
+/// Derive a key from a passphrase using Argon2id with its default parameters
+pub fn derive_key(passphrase: &[u8], salt: &[u8]) -> Result<[u8; KEY_LEN], String> {
+    let mut key = [0u8; KEY_LEN];
+    Argon2::default()
+        .hash_password_into(passphrase, salt, &mut key)
+        .map_err(|e| format!("Failed to derive key: {}", e))?;
+    Ok(key)
+}
+
+// `KeyInit` is not imported as its `new_from_slice` would clash with the one of `Mac`
+fn new_cipher(key: &[u8; KEY_LEN]) -> Aes256Gcm {
+    <Aes256Gcm as aes_gcm::KeyInit>::new(key.into())
+}
+
+/// Encrypt with AES-256-GCM. Returns the random nonce followed by the ciphertext and tag.
+/// `aad` is authenticated but not encrypted, and must be passed again to `decrypt`.
+pub fn encrypt(key: &[u8; KEY_LEN], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
+    let cipher = new_cipher(key);
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill(&mut nonce);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+        .map_err(|_| "Encryption failed".to_string())?;
+    let mut output = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+    output.extend_from_slice(&nonce);
+    output.extend_from_slice(&ciphertext);
+    Ok(output)
+}
+
+/// Decrypt the output of `encrypt`. Fails if the key or `aad` is wrong or the data was modified.
+pub fn decrypt(key: &[u8; KEY_LEN], data: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < NONCE_LEN {
+        return Err("Encrypted data is too short".to_string());
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    new_cipher(key)
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+        .map_err(|_| "Decryption failed".to_string())
+}
+
 /// Compute SHA256 hash of a file with cancellation support by chunking the file
 pub async fn compute_file_sha256_with_cancellation(
     file_path: &Path,
@@ -135,6 +198,37 @@ mod tests {
         assert!(result.is_ok()); // Should still work with empty secret
     }
 
+    #[test]
+    fn test_encrypt_decrypt() {
+        let key = generate_key();
+        let encrypted = encrypt(&key, b"secret message", b"aad").unwrap();
+        assert_ne!(&encrypted[12..], b"secret message");
+        assert_eq!(decrypt(&key, &encrypted, b"aad").unwrap(), b"secret message");
+
+        // Nonces are random, so the same plaintext encrypts differently
+        assert_ne!(encrypted, encrypt(&key, b"secret message", b"aad").unwrap());
+
+        // Wrong key, wrong aad and modified data are rejected
+        assert!(decrypt(&generate_key(), &encrypted, b"aad").is_err());
+        assert!(decrypt(&key, &encrypted, b"other").is_err());
+        let mut modified = encrypted.clone();
+        let last = modified.len() - 1;
+        modified[last] ^= 1;
+        assert!(decrypt(&key, &modified, b"aad").is_err());
+        assert!(decrypt(&key, &encrypted[..8], b"aad").is_err());
+    }
+
+    #[test]
+    fn test_derive_key() {
+        let salt = generate_salt();
+        let key1 = derive_key(b"passphrase", &salt).unwrap();
+        let key2 = derive_key(b"passphrase", &salt).unwrap();
+        assert_eq!(key1, key2);
+
+        assert_ne!(key1, derive_key(b"other passphrase", &salt).unwrap());
+        assert_ne!(key1, derive_key(b"passphrase", &generate_salt()).unwrap());
+    }
+
     #[tokio::test]
     async fn test_compute_file_sha256_with_cancellation() {
         use std::io::Write;
@@ -88,6 +88,10 @@ export interface ModelPlan {
   offloadMmproj: boolean
   batchSize: number
   mode: 'GPU' | 'Hybrid' | 'CPU' | 'Unsupported'
+  threads?: number
+  threadsBatch?: number
+  cpuMask?: string
+  numa?: string
 }
 
 export type PreflightReason =